@@ -0,0 +1,54 @@
+// Compare Branch and Bound against greedy selection on random UTXO sets.
+//
+// Run with `cargo bench --bench coin_selection`.
+use bitcoincore_rpc::bitcoin::Amount;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+#[allow(dead_code)]
+#[path = "../src/amount_serde.rs"]
+mod amount_serde;
+#[allow(dead_code)]
+#[path = "../src/coin_selection.rs"]
+mod coin_selection;
+
+use coin_selection::{branch_and_bound, greedy, Utxo};
+
+// Small xorshift generator so the UTXO sets are reproducible between runs.
+fn random_utxos(count: usize, seed: u64) -> Vec<Utxo> {
+    let mut state = seed;
+    (0..count)
+        .map(|i| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            Utxo {
+                txid: format!("{:064x}", i),
+                vout: 0,
+                amount: Amount::from_sat(1_000 + state % 10_000_000),
+            }
+        })
+        .collect()
+}
+
+fn bench_selection(c: &mut Criterion) {
+    let target = Amount::from_sat(20_000_000);
+    let cost_of_change = Amount::from_sat(5_000);
+
+    let mut group = c.benchmark_group("coin_selection");
+    for count in [10, 50, 200] {
+        let utxos = random_utxos(count, 0x5eed + count as u64);
+        group.bench_with_input(BenchmarkId::new("greedy", count), &utxos, |b, utxos| {
+            b.iter(|| greedy(black_box(utxos), target))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("branch_and_bound", count),
+            &utxos,
+            |b, utxos| b.iter(|| branch_and_bound(black_box(utxos), target, cost_of_change)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_selection);
+criterion_main!(benches);
@@ -0,0 +1,313 @@
+// Generating many labeled addresses in one round trip.
+//
+// Split payments, stress runs and address book warm-ups each want a run of
+// fresh addresses, and one `getnewaddress` per address costs a round trip
+// each. `new_addresses` sends them all as a single JSON-RPC batch, labels them
+// `<prefix>-0001`, `<prefix>-0002`, ..., and records them in the address book,
+// if the run keeps one, in one write. A batch can partly fail: the addresses
+// that were made are still recorded, and the error names which labels
+// succeeded and which did not.
+use crate::address_book::{AddressBook, AddressBookEntry};
+use crate::node_rpc::NodeRpc;
+use bitcoincore_rpc::bitcoin::Address;
+use bitcoincore_rpc::json::AddressType;
+use bitcoincore_rpc::jsonrpc::Response;
+use bitcoincore_rpc::{Error, RpcApi};
+use serde_json::value::{to_raw_value, RawValue};
+use std::path::Path;
+
+// The label of the `index`th address of a batch, counting from zero
+pub fn batch_label(prefix: &str, index: usize) -> String {
+    format!("{}-{:04}", prefix, index + 1)
+}
+
+// The `getnewaddress` parameters for each address of a batch
+pub fn batch_params(
+    count: usize,
+    label_prefix: &str,
+    kind: Option<AddressType>,
+) -> serde_json::Result<Vec<Vec<Box<RawValue>>>> {
+    (0..count)
+        .map(|index| {
+            let mut params = vec![to_raw_value(&batch_label(label_prefix, index))?];
+            if let Some(kind) = &kind {
+                params.push(to_raw_value(kind)?);
+            }
+            Ok(params)
+        })
+        .collect()
+}
+
+// The address each response of a batch carries, in request order
+pub fn parse_responses(responses: Vec<Option<Response>>) -> Vec<Result<Address, String>> {
+    responses
+        .into_iter()
+        .map(|response| {
+            let response = response.ok_or_else(|| "no response".to_string())?;
+            let address: String = response.result().map_err(|e| e.to_string())?;
+            address
+                .parse::<Address<_>>()
+                .map(Address::assume_checked)
+                .map_err(|e| format!("invalid address '{}': {}", address, e))
+        })
+        .collect()
+}
+
+// All the addresses of a batch, or an error naming which labels succeeded and
+// which failed
+pub fn collect_batch(
+    label_prefix: &str,
+    results: Vec<Result<Address, String>>,
+) -> Result<Vec<Address>, String> {
+    let mut succeeded = Vec::new();
+    let mut failed = Vec::new();
+    for (index, result) in results.iter().enumerate() {
+        match result {
+            Ok(_) => succeeded.push(batch_label(label_prefix, index)),
+            Err(e) => failed.push(format!("{} ({})", batch_label(label_prefix, index), e)),
+        }
+    }
+    if !failed.is_empty() {
+        return Err(format!(
+            "getnewaddress failed for {} of {} address(es): {}; succeeded: {}",
+            failed.len(),
+            results.len(),
+            failed.join(", "),
+            if succeeded.is_empty() {
+                "none".to_string()
+            } else {
+                succeeded.join(", ")
+            }
+        ));
+    }
+    Ok(results.into_iter().flatten().collect())
+}
+
+// Helper function to generate `count` addresses of `kind` (the wallet's
+// default when `None`) from `wallet` in one batch, record them under
+// `wallet_name` in the address book at `book` if given, and return them in
+// label order
+pub fn new_addresses(
+    wallet: &(impl NodeRpc + RpcApi),
+    book: Option<&Path>,
+    wallet_name: &str,
+    count: usize,
+    label_prefix: &str,
+    kind: Option<AddressType>,
+) -> bitcoincore_rpc::Result<Vec<Address>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let params = batch_params(count, label_prefix, kind)?;
+    let results = parse_responses(wallet.send_batch("getnewaddress", &params)?);
+
+    if let Some(book) = book {
+        let height = wallet.get_block_count()?;
+        let entries = results
+            .iter()
+            .enumerate()
+            .filter_map(|(index, result)| {
+                result.as_ref().ok().map(|address| AddressBookEntry {
+                    address: address.to_string(),
+                    wallet: wallet_name.to_string(),
+                    label: batch_label(label_prefix, index),
+                    purpose: "receive".to_string(),
+                    first_seen_height: height,
+                })
+            })
+            .collect();
+        AddressBook::record_all(book, entries)?;
+    }
+    collect_batch(label_prefix, results).map_err(Error::ReturnedError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::{Network, Script};
+    use bitcoincore_rpc::jsonrpc::{self, Request, Transport};
+    use std::fmt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // The address a fake wallet hands out for `label`
+    fn fake_address(label: &str) -> String {
+        Address::p2wsh(Script::from_bytes(label.as_bytes()), Network::Regtest).to_string()
+    }
+
+    // Stands in for a wallet: answers `getnewaddress` with an address derived
+    // from the label, except for the labels in `failing`, and counts round trips
+    struct FakeWallet {
+        round_trips: Arc<AtomicUsize>,
+        failing: Vec<&'static str>,
+    }
+
+    impl FakeWallet {
+        fn answer(&self, request: &Request) -> Response {
+            let label: String = serde_json::from_str(request.params[0].get()).unwrap();
+            let (result, error) = if self.failing.contains(&label.as_str()) {
+                let error = jsonrpc::error::RpcError {
+                    code: -12,
+                    message: "Error: Keypool ran out".to_string(),
+                    data: None,
+                };
+                (None, Some(error))
+            } else {
+                (Some(to_raw_value(&fake_address(&label)).unwrap()), None)
+            };
+            Response {
+                result,
+                error,
+                id: request.id.clone(),
+                jsonrpc: Some("2.0".to_string()),
+            }
+        }
+    }
+
+    impl Transport for FakeWallet {
+        fn send_request(&self, request: Request) -> Result<Response, jsonrpc::Error> {
+            self.round_trips.fetch_add(1, Ordering::SeqCst);
+            Ok(self.answer(&request))
+        }
+
+        fn send_batch(&self, requests: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+            self.round_trips.fetch_add(1, Ordering::SeqCst);
+            // Out of order, as a server may answer a batch
+            Ok(requests.iter().rev().map(|r| self.answer(r)).collect())
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("fake wallet")
+        }
+    }
+
+    fn fake_wallet(failing: Vec<&'static str>) -> (jsonrpc::Client, Arc<AtomicUsize>) {
+        let round_trips = Arc::new(AtomicUsize::new(0));
+        let transport = FakeWallet {
+            round_trips: Arc::clone(&round_trips),
+            failing,
+        };
+        (jsonrpc::Client::with_transport(transport), round_trips)
+    }
+
+    // What `new_addresses` does before touching the address book
+    fn batch(client: &jsonrpc::Client, count: usize) -> Vec<Result<Address, String>> {
+        let params = batch_params(count, "stress", None).unwrap();
+        let requests: Vec<_> = params
+            .iter()
+            .map(|params| client.build_request("getnewaddress", params))
+            .collect();
+        parse_responses(client.send_batch(&requests).unwrap())
+    }
+
+    #[test]
+    fn labels_count_from_one_with_four_digits() {
+        assert_eq!(batch_label("stress", 0), "stress-0001");
+        assert_eq!(batch_label("split", 41), "split-0042");
+        assert_eq!(batch_label("warmup", 12_344), "warmup-12345");
+    }
+
+    #[test]
+    fn the_address_type_is_passed_when_set() {
+        let params = batch_params(2, "stress", Some(AddressType::Bech32)).unwrap();
+        let params: Vec<Vec<&str>> = params
+            .iter()
+            .map(|p| p.iter().map(|value| value.get()).collect())
+            .collect();
+        assert_eq!(
+            params,
+            vec![
+                vec![r#""stress-0001""#, r#""bech32""#],
+                vec![r#""stress-0002""#, r#""bech32""#],
+            ]
+        );
+        assert_eq!(batch_params(1, "stress", None).unwrap()[0].len(), 1);
+    }
+
+    #[test]
+    fn a_batch_is_one_round_trip_instead_of_one_per_address() {
+        let count = 50;
+        let (client, round_trips) = fake_wallet(Vec::new());
+        for index in 0..count {
+            let label = to_raw_value(&batch_label("stress", index)).unwrap();
+            let _: String = client.call("getnewaddress", &[label]).unwrap();
+        }
+        assert_eq!(round_trips.swap(0, Ordering::SeqCst), count);
+
+        let addresses = collect_batch("stress", batch(&client, count)).unwrap();
+        assert_eq!(round_trips.load(Ordering::SeqCst), 1);
+        assert_eq!(addresses.len(), count);
+    }
+
+    #[test]
+    fn addresses_come_back_in_label_order() {
+        let (client, _) = fake_wallet(Vec::new());
+        let addresses = collect_batch("stress", batch(&client, 3)).unwrap();
+        let expected: Vec<String> = (0..3)
+            .map(|index| fake_address(&batch_label("stress", index)))
+            .collect();
+        let addresses: Vec<String> = addresses.iter().map(Address::to_string).collect();
+        assert_eq!(addresses, expected);
+    }
+
+    #[test]
+    fn partial_failures_name_the_labels_that_succeeded() {
+        let (client, _) = fake_wallet(vec!["stress-0002", "stress-0004"]);
+        let results = batch(&client, 4);
+        assert!(results[0].is_ok() && results[2].is_ok());
+        let err = collect_batch("stress", results).unwrap_err();
+        assert!(
+            err.starts_with("getnewaddress failed for 2 of 4 address(es): stress-0002 ("),
+            "{}",
+            err
+        );
+        assert!(err.contains("Keypool ran out"), "{}", err);
+        assert!(
+            err.ends_with("; succeeded: stress-0001, stress-0003"),
+            "{}",
+            err
+        );
+        let none = vec![Err("no response".to_string())];
+        assert!(collect_batch("stress", none)
+            .unwrap_err()
+            .ends_with("succeeded: none"));
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn batched_addresses_are_labeled_in_the_wallet() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Trader", true).unwrap();
+        let trader = get_wallet_client(&config, "Trader").unwrap();
+        let prefix = format!("batch{}", std::process::id());
+        let path = std::env::temp_dir().join(format!("{prefix}-addressbook.json"));
+        let addresses = new_addresses(
+            &trader,
+            Some(&path),
+            "Trader",
+            5,
+            &prefix,
+            Some(AddressType::Bech32),
+        )
+        .unwrap();
+        assert_eq!(addresses.len(), 5);
+        let book = AddressBook::load(&path).unwrap();
+        for (index, address) in addresses.iter().enumerate() {
+            let info = crate::rpc_call::<serde_json::Value>(
+                &trader,
+                "getaddressinfo",
+                &[serde_json::json!(address)],
+            )
+            .unwrap();
+            assert_eq!(
+                info["labels"],
+                serde_json::json!([batch_label(&prefix, index)])
+            );
+            assert_eq!(book.find(&address.to_string()).unwrap().wallet, "Trader");
+        }
+    }
+}
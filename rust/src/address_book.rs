@@ -0,0 +1,236 @@
+// Persistent address book: every address the tool generates, across runs.
+//
+// Stored as versioned JSON. Updates are a locked read-modify-write: an
+// exclusive lock on a sibling `.lock` file serializes concurrent runs, and the
+// new book is written to a temporary file and renamed over the old one so a
+// crash never leaves a half-written book behind.
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const ADDRESS_BOOK_PATH: &str = "addressbook.json";
+pub const ADDRESS_BOOK_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressBookEntry {
+    pub address: String,
+    pub wallet: String,
+    pub label: String,
+    pub purpose: String,
+    pub first_seen_height: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressBook {
+    pub version: u32,
+    pub entries: Vec<AddressBookEntry>,
+}
+
+impl Default for AddressBook {
+    fn default() -> Self {
+        AddressBook {
+            version: ADDRESS_BOOK_VERSION,
+            entries: Vec::new(),
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+impl AddressBook {
+    // Read the book at `path`; a missing file is an empty book.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let book: AddressBook = serde_json::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if book.version != ADDRESS_BOOK_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} has address book version {}, this build reads version {}",
+                    path.display(),
+                    book.version,
+                    ADDRESS_BOOK_VERSION
+                ),
+            ));
+        }
+        Ok(book)
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = with_suffix(path, ".tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)? + "\n")?;
+        fs::rename(&tmp, path)
+    }
+
+    // Add `entry` to the book at `path` unless its address is already there.
+    // Returns whether the book changed.
+    pub fn record(path: &Path, entry: AddressBookEntry) -> io::Result<bool> {
+        Ok(Self::record_all(path, vec![entry])? == 1)
+    }
+
+    // Add each of `entries` whose address is not there yet, under one lock and
+    // in one write. Returns how many were added.
+    pub fn record_all(path: &Path, entries: Vec<AddressBookEntry>) -> io::Result<usize> {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(with_suffix(path, ".lock"))?;
+        lock.lock()?;
+
+        let mut book = Self::load(path)?;
+        let mut added = 0;
+        for entry in entries {
+            if book.find(&entry.address).is_none() {
+                book.entries.push(entry);
+                added += 1;
+            }
+        }
+        if added > 0 {
+            book.save(path)?;
+        }
+        Ok(added)
+    }
+
+    pub fn find(&self, address: &str) -> Option<&AddressBookEntry> {
+        self.entries.iter().find(|e| e.address == address)
+    }
+}
+
+fn print_entry(entry: &AddressBookEntry) {
+    info!(
+        "{}\t{}\t{}\t{}\t{}",
+        entry.address, entry.wallet, entry.label, entry.purpose, entry.first_seen_height
+    );
+}
+
+// `addressbook list` and `addressbook find <addr>`; `args` excludes "addressbook".
+pub fn run_command(path: &Path, args: &[String]) -> io::Result<()> {
+    let book = AddressBook::load(path)?;
+    match args.first().map(String::as_str) {
+        Some("list") => {
+            book.entries.iter().for_each(print_entry);
+            Ok(())
+        }
+        Some("find") => {
+            let address = args.get(1).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "usage: addressbook find <addr>",
+                )
+            })?;
+            let entry = book.find(address).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in the address book", address),
+                )
+            })?;
+            print_entry(entry);
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: addressbook list | addressbook find <addr>",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_book(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("addressbook-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(ADDRESS_BOOK_PATH);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn entry(address: &str, wallet: &str) -> AddressBookEntry {
+        AddressBookEntry {
+            address: address.to_string(),
+            wallet: wallet.to_string(),
+            label: "Received".to_string(),
+            purpose: "receive".to_string(),
+            first_seen_height: 101,
+        }
+    }
+
+    #[test]
+    fn missing_book_is_empty() {
+        let path = temp_book("missing");
+        assert_eq!(AddressBook::load(&path).unwrap(), AddressBook::default());
+    }
+
+    #[test]
+    fn entries_persist_across_loads() {
+        let path = temp_book("persist");
+        assert!(AddressBook::record(&path, entry("bcrt1qa", "Trader")).unwrap());
+        assert!(AddressBook::record(&path, entry("bcrt1qb", "Miner")).unwrap());
+        assert!(!AddressBook::record(&path, entry("bcrt1qa", "Trader")).unwrap());
+
+        // A fresh load sees exactly what an earlier run wrote
+        let book = AddressBook::load(&path).unwrap();
+        assert_eq!(book.version, ADDRESS_BOOK_VERSION);
+        assert_eq!(
+            book.entries,
+            vec![entry("bcrt1qa", "Trader"), entry("bcrt1qb", "Miner")]
+        );
+    }
+
+    #[test]
+    fn batches_are_recorded_in_one_write() {
+        let path = temp_book("batch");
+        assert!(AddressBook::record(&path, entry("bcrt1qa", "Trader")).unwrap());
+        let batch = vec![
+            entry("bcrt1qb", "Miner"),
+            entry("bcrt1qa", "Trader"),
+            entry("bcrt1qc", "Miner"),
+        ];
+        assert_eq!(AddressBook::record_all(&path, batch).unwrap(), 2);
+        let addresses: Vec<String> = AddressBook::load(&path)
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|e| e.address)
+            .collect();
+        assert_eq!(addresses, vec!["bcrt1qa", "bcrt1qb", "bcrt1qc"]);
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let path = temp_book("version");
+        fs::write(&path, r#"{ "version": 99, "entries": [] }"#).unwrap();
+        let err = AddressBook::load(&path).unwrap_err();
+        assert!(err.to_string().contains("version 99"));
+    }
+
+    #[test]
+    fn concurrent_records_are_not_lost() {
+        let path = temp_book("concurrent");
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    AddressBook::record(&path, entry(&format!("bcrt1q{}", i), "Trader")).unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+        assert_eq!(AddressBook::load(&path).unwrap().entries.len(), 8);
+    }
+}
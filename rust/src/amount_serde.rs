@@ -0,0 +1,93 @@
+// Lenient deserialization of amounts found in RPC responses.
+//
+// Bitcoin Core writes amounts as BTC numbers (`0.00001000`), but some calls and
+// tools hand them back as BTC strings (`"0.00001000"`) or as integer satoshis.
+// `deserialize_amount` accepts all three: floats and strings are BTC, integers
+// are satoshis.
+use bitcoincore_rpc::bitcoin::{Amount, Denomination};
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+
+struct AmountVisitor;
+
+impl Visitor<'_> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a BTC amount as a number or string, or an integer number of satoshis")
+    }
+
+    fn visit_f64<E: de::Error>(self, btc: f64) -> Result<Amount, E> {
+        Amount::from_btc(btc).map_err(|e| E::custom(format!("invalid BTC amount {}: {}", btc, e)))
+    }
+
+    fn visit_u64<E: de::Error>(self, sats: u64) -> Result<Amount, E> {
+        Ok(Amount::from_sat(sats))
+    }
+
+    fn visit_i64<E: de::Error>(self, sats: i64) -> Result<Amount, E> {
+        u64::try_from(sats)
+            .map(Amount::from_sat)
+            .map_err(|_| E::custom(format!("negative satoshi amount {}", sats)))
+    }
+
+    fn visit_str<E: de::Error>(self, btc: &str) -> Result<Amount, E> {
+        Amount::from_str_in(btc.trim(), Denomination::Bitcoin)
+            .map_err(|e| E::custom(format!("invalid BTC amount '{}': {}", btc, e)))
+    }
+}
+
+// Use with `#[serde(deserialize_with = "deserialize_amount")]` on `Amount` fields.
+pub fn deserialize_amount<'de, D: Deserializer<'de>>(d: D) -> Result<Amount, D::Error> {
+    d.deserialize_any(AmountVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    struct Wrapper {
+        #[serde(deserialize_with = "deserialize_amount")]
+        amount: Amount,
+    }
+
+    fn amount(value: serde_json::Value) -> Result<Amount, serde_json::Error> {
+        serde_json::from_value::<Wrapper>(json!({ "amount": value })).map(|w| w.amount)
+    }
+
+    #[test]
+    fn btc_number() {
+        assert_eq!(amount(json!(0.00001)).unwrap(), Amount::from_sat(1_000));
+        assert_eq!(
+            amount(json!(29.99999859)).unwrap(),
+            Amount::from_sat(2_999_999_859)
+        );
+    }
+
+    #[test]
+    fn btc_string() {
+        assert_eq!(
+            amount(json!("0.00001000")).unwrap(),
+            Amount::from_sat(1_000)
+        );
+        assert_eq!(amount(json!(" 20 ")).unwrap(), Amount::from_int_btc(20));
+    }
+
+    #[test]
+    fn satoshi_integer() {
+        assert_eq!(amount(json!(1000)).unwrap(), Amount::from_sat(1_000));
+        assert_eq!(amount(json!(0)).unwrap(), Amount::ZERO);
+    }
+
+    #[test]
+    fn rejects_bad_amounts() {
+        assert!(amount(json!(-1)).is_err());
+        assert!(amount(json!(-0.5)).is_err());
+        assert!(amount(json!("0.000000001")).is_err());
+        assert!(amount(json!("lots")).is_err());
+        assert!(amount(json!(null)).is_err());
+    }
+}
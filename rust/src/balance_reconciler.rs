@@ -0,0 +1,113 @@
+// Cross-check a wallet's balance as reported by `getbalances`, `listunspent`
+// and `getwalletinfo`.
+//
+// The three RPCs are computed independently inside Core, so on a quiet wallet
+// they must agree; any difference points at a bug in our flow or the node.
+// `listunspent` never returns immature coinbase outputs, so it is compared
+// against the spendable part of `getbalances` (trusted + untrusted pending)
+// while the two balance RPCs are compared on their full totals.
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::RpcApi;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconciliationResult {
+    pub getbalances_total: Amount,
+    pub listunspent_total: Amount,
+    pub getwalletinfo_total: Amount,
+    pub all_agree: bool,
+    pub discrepancies: Vec<String>,
+}
+
+impl ReconciliationResult {
+    fn new(
+        getbalances_spendable: Amount,
+        getbalances_immature: Amount,
+        listunspent_total: Amount,
+        getwalletinfo_total: Amount,
+    ) -> Self {
+        let getbalances_total = getbalances_spendable + getbalances_immature;
+        let mut discrepancies = Vec::new();
+        if listunspent_total != getbalances_spendable {
+            discrepancies.push(format!(
+                "listunspent sums to {} but getbalances trusted + untrusted_pending is {}",
+                listunspent_total, getbalances_spendable
+            ));
+        }
+        if getwalletinfo_total != getbalances_total {
+            discrepancies.push(format!(
+                "getwalletinfo totals {} but getbalances totals {}",
+                getwalletinfo_total, getbalances_total
+            ));
+        }
+        ReconciliationResult {
+            getbalances_total,
+            listunspent_total,
+            getwalletinfo_total,
+            all_agree: discrepancies.is_empty(),
+            discrepancies,
+        }
+    }
+}
+
+// Helper function to reconcile the balance of the wallet behind `rpc`
+pub fn reconcile_wallet_balance(
+    rpc: &impl RpcApi,
+) -> bitcoincore_rpc::Result<ReconciliationResult> {
+    let balances = rpc.get_balances()?.mine;
+    // minconf 0 and include_unsafe so unconfirmed outputs count like in getbalances
+    let unspent = rpc.list_unspent(Some(0), None, None, Some(true), None)?;
+    let info = rpc.get_wallet_info()?;
+
+    let listunspent_total = unspent
+        .iter()
+        .fold(Amount::ZERO, |sum, utxo| sum + utxo.amount);
+    Ok(ReconciliationResult::new(
+        balances.trusted + balances.untrusted_pending,
+        balances.immature,
+        listunspent_total,
+        info.balance + info.unconfirmed_balance + info.immature_balance,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_totals_agree() {
+        let result = ReconciliationResult::new(
+            Amount::from_int_btc(30),
+            Amount::from_int_btc(5000),
+            Amount::from_int_btc(30),
+            Amount::from_int_btc(5030),
+        );
+        assert!(result.all_agree);
+        assert_eq!(result.getbalances_total, Amount::from_int_btc(5030));
+        assert!(result.discrepancies.is_empty());
+    }
+
+    #[test]
+    fn listunspent_mismatch_is_reported() {
+        let result = ReconciliationResult::new(
+            Amount::from_int_btc(30),
+            Amount::ZERO,
+            Amount::from_sat(2_999_999_859),
+            Amount::from_int_btc(30),
+        );
+        assert!(!result.all_agree);
+        assert_eq!(result.discrepancies.len(), 1);
+        assert!(result.discrepancies[0].starts_with("listunspent"));
+    }
+
+    #[test]
+    fn getwalletinfo_mismatch_is_reported() {
+        let result = ReconciliationResult::new(
+            Amount::from_int_btc(30),
+            Amount::from_int_btc(50),
+            Amount::from_int_btc(30),
+            Amount::from_int_btc(30),
+        );
+        assert!(!result.all_agree);
+        assert!(result.discrepancies[0].starts_with("getwalletinfo"));
+    }
+}
@@ -0,0 +1,253 @@
+// `export-blocks`: every output of every transaction in a range of blocks, as CSV.
+//
+// Each block is read with `getblock` into `BlockDetails`, and each of its
+// transactions with `getrawtransaction` into `RawTx`. The block hash is passed
+// along, so the node finds the transactions without `-txindex`. One row is
+// written per output. Outputs without an address, such as the coinbase's
+// witness commitment, get an empty address column.
+use crate::rpc_helpers::{BlockDetails, RawTx};
+use crate::{arg_value, get_block_details, rpc_call};
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+pub const DEFAULT_EXPORT_PATH: &str = "transactions.csv";
+
+pub const EXPORT_COLUMNS: &str =
+    "block_height,block_hash,txid,output_index,address,amount_sat,is_coinbase";
+
+// The CSV rows of `block`, whose transactions are `txs`
+pub fn block_rows(block: &BlockDetails, txs: &[RawTx]) -> Vec<String> {
+    txs.iter()
+        .flat_map(|tx| {
+            tx.vout.iter().map(move |output| {
+                format!(
+                    "{},{},{},{},{},{},{}",
+                    block.height,
+                    block.hash,
+                    tx.txid,
+                    output.n,
+                    output.address().unwrap_or(""),
+                    output.value.to_sat(),
+                    tx.is_coinbase()
+                )
+            })
+        })
+        .collect()
+}
+
+// Check that `start..=end` is a range of blocks the node has
+pub fn check_range(start: u64, end: u64, tip: u64) -> Result<(), String> {
+    if start > end {
+        return Err(format!(
+            "--start-height {} is above --end-height {}",
+            start, end
+        ));
+    }
+    if end > tip {
+        return Err(format!(
+            "--end-height {} is above the chain tip at height {}",
+            end, tip
+        ));
+    }
+    Ok(())
+}
+
+// Helper function to write the header and one row per output of the blocks
+// `start..=end` to `writer`. Returns the number of rows.
+pub fn export_block_range(
+    rpc: &impl RpcApi,
+    start: u64,
+    end: u64,
+    writer: &mut impl Write,
+) -> bitcoincore_rpc::Result<u64> {
+    check_range(start, end, rpc.get_block_count()?).map_err(Error::ReturnedError)?;
+    writeln!(writer, "{}", EXPORT_COLUMNS)?;
+    let mut rows = 0;
+    for height in start..=end {
+        let block = get_block_details(rpc, &rpc.get_block_hash(height)?)?;
+        let txs = block
+            .tx
+            .iter()
+            .map(|txid| {
+                rpc_call::<RawTx>(
+                    rpc,
+                    "getrawtransaction",
+                    &[json!(txid), json!(true), json!(block.hash)],
+                )
+            })
+            .collect::<bitcoincore_rpc::Result<Vec<_>>>()?;
+        for row in block_rows(&block, &txs) {
+            writeln!(writer, "{}", row)?;
+            rows += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+// Helper function to parse a required height flag
+fn height_arg(args: &[String], flag: &str) -> bitcoincore_rpc::Result<u64> {
+    let value = arg_value(args, flag).ok_or_else(|| {
+        Error::ReturnedError(format!(
+            "usage: export-blocks --start-height <N> --end-height <M> [--out <file.csv>]; \
+             {} is missing",
+            flag
+        ))
+    })?;
+    value.parse().map_err(|_| {
+        Error::ReturnedError(format!(
+            "invalid {} value '{}': expected a block height",
+            flag, value
+        ))
+    })
+}
+
+// `export-blocks --start-height N --end-height M [--out transactions.csv]`
+pub fn run_command(rpc: &impl RpcApi, args: &[String]) -> bitcoincore_rpc::Result<()> {
+    let start = height_arg(args, "--start-height")?;
+    let end = height_arg(args, "--end-height")?;
+    let path = Path::new(arg_value(args, "--out").unwrap_or(DEFAULT_EXPORT_PATH));
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    let rows = export_block_range(rpc, start, end, &mut file)?;
+    info!(
+        "Exported {} output(s) of blocks {} to {} to {}",
+        rows,
+        start,
+        end,
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block() -> BlockDetails {
+        serde_json::from_value(json!({
+            "hash": "cc".repeat(32),
+            "height": 102,
+            "time": 1_700_000_600,
+            "mediantime": 1_700_000_000,
+            "nonce": 0,
+            "difficulty": 4.6e-10,
+            "tx": ["aa".repeat(32), "bb".repeat(32)],
+            "size": 400,
+            "weight": 1_200,
+        }))
+        .unwrap()
+    }
+
+    fn tx(txid: &str, vin: serde_json::Value, vout: serde_json::Value) -> RawTx {
+        serde_json::from_value(json!({
+            "txid": txid,
+            "vin": vin,
+            "vout": vout,
+            "size": 200,
+            "vsize": 141,
+            "weight": 561,
+            "locktime": 0,
+        }))
+        .unwrap()
+    }
+
+    fn output(n: u32, btc: f64, address: Option<&str>) -> serde_json::Value {
+        let script = match address {
+            Some(address) => json!({ "type": "witness_v0_keyhash", "address": address }),
+            None => json!({ "type": "nulldata" }),
+        };
+        json!({ "n": n, "value": btc, "scriptPubKey": script })
+    }
+
+    #[test]
+    fn one_row_per_output_flagging_the_coinbase() {
+        let coinbase = tx(
+            &"aa".repeat(32),
+            json!([{ "coinbase": "0166" }]),
+            json!([
+                output(0, 50.00001410, Some("bcrt1qminer")),
+                output(1, 0.0, None)
+            ]),
+        );
+        let payment = tx(
+            &"bb".repeat(32),
+            json!([{ "txid": "dd".repeat(32), "vout": 0 }]),
+            json!([
+                output(0, 20.0, Some("bcrt1qtrader")),
+                output(1, 29.9999859, Some("bcrt1qchange")),
+            ]),
+        );
+        let block = block();
+        let rows = block_rows(&block, &[coinbase, payment]);
+        let hash = "cc".repeat(32);
+        let (cb, pay) = ("aa".repeat(32), "bb".repeat(32));
+        assert_eq!(
+            rows,
+            vec![
+                format!("102,{},{},0,bcrt1qminer,5000001410,true", hash, cb),
+                format!("102,{},{},1,,0,true", hash, cb),
+                format!("102,{},{},0,bcrt1qtrader,2000000000,false", hash, pay),
+                format!("102,{},{},1,bcrt1qchange,2999998590,false", hash, pay),
+            ]
+        );
+        assert_eq!(EXPORT_COLUMNS.split(',').count(), 7);
+        assert!(rows.iter().all(|row| row.split(',').count() == 7));
+    }
+
+    #[test]
+    fn the_range_must_be_on_the_chain() {
+        assert!(check_range(1, 102, 102).is_ok());
+        assert!(check_range(5, 5, 102).is_ok());
+        assert_eq!(
+            check_range(10, 9, 102).unwrap_err(),
+            "--start-height 10 is above --end-height 9"
+        );
+        assert_eq!(
+            check_range(1, 103, 102).unwrap_err(),
+            "--end-height 103 is above the chain tip at height 102"
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn the_first_102_blocks_export_with_their_coinbases() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let tip = rpc.get_block_count().unwrap();
+        if tip < 102 {
+            create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+            let miner = get_wallet_client(&config, "Miner").unwrap();
+            let address = miner.get_new_address(None, None).unwrap().assume_checked();
+            mine_blocks(&rpc, &address, 102 - tip).unwrap();
+        }
+
+        let mut csv = Vec::new();
+        let rows = export_block_range(&rpc, 1, 102, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(EXPORT_COLUMNS));
+        let rows_read: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows_read.len() as u64, rows);
+        // Every block's first transaction is its coinbase
+        for height in 1..=102u64 {
+            let hash = rpc.get_block_hash(height).unwrap().to_string();
+            let coinbase = rpc_call::<serde_json::Value>(&rpc, "getblock", &[json!(hash)]).unwrap()
+                ["tx"][0]
+                .as_str()
+                .unwrap()
+                .to_string();
+            let coinbase_rows: Vec<_> = rows_read.iter().filter(|row| row[2] == coinbase).collect();
+            assert!(!coinbase_rows.is_empty(), "block {}", height);
+            assert!(coinbase_rows
+                .iter()
+                .all(|row| row[0] == height.to_string() && row[6] == "true"));
+        }
+    }
+}
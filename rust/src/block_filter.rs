@@ -0,0 +1,266 @@
+// BIP-157/158 compact block filter client.
+//
+// Lets us ask "does this block touch my script?" from the node's basic block
+// filter instead of scanning every transaction, which would need `txindex`.
+// The node must run with `-blockfilterindex=1` for `getblockfilter` to work.
+// `block-filter <block-hash> <address>` asks it from the command line.
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::hashes::siphash24;
+use bitcoincore_rpc::bitcoin::Address;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+
+// Golomb-Rice parameters of the BIP-158 basic filter.
+pub const BASIC_FILTER_P: u8 = 19;
+pub const BASIC_FILTER_M: u64 = 784_931;
+
+// A Golomb-coded set: `n` sorted, delta-encoded items in the range [0, n * m).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcsFilter {
+    pub data: Vec<u8>,
+    pub n: u32,
+    pub m: u64,
+}
+
+impl GcsFilter {
+    // Build a basic filter from its full serialization, as returned by
+    // `getblockfilter`: a CompactSize item count followed by the bitstream.
+    pub fn from_serialized_hex(filter_hex: &str) -> bitcoincore_rpc::Result<Self> {
+        let bytes = Vec::<u8>::from_hex(filter_hex)?;
+        let (n, prefix_len) = read_compact_size(&bytes)
+            .ok_or_else(|| Error::ReturnedError("truncated block filter".to_string()))?;
+        let n = u32::try_from(n).map_err(|_| {
+            Error::ReturnedError(format!("block filter item count {} too large", n))
+        })?;
+        Ok(GcsFilter {
+            data: bytes[prefix_len..].to_vec(),
+            n,
+            m: BASIC_FILTER_M,
+        })
+    }
+}
+
+fn read_compact_size(bytes: &[u8]) -> Option<(u64, usize)> {
+    let width = match *bytes.first()? {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        n => return Some((u64::from(n), 1)),
+    };
+    let raw = bytes.get(1..1 + width)?;
+    let mut buf = [0u8; 8];
+    buf[..width].copy_from_slice(raw);
+    Some((u64::from_le_bytes(buf), 1 + width))
+}
+
+// Reads bits most-significant first, as BIP-158 writes them.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Option<u64> {
+        (0..count).try_fold(0u64, |acc, _| {
+            Some((acc << 1) | u64::from(self.read_bit()?))
+        })
+    }
+
+    fn read_golomb_rice(&mut self, p: u8) -> Option<u64> {
+        let mut quotient = 0u64;
+        while self.read_bit()? {
+            quotient += 1;
+        }
+        Some((quotient << p) | self.read_bits(p)?)
+    }
+}
+
+// Map an element onto [0, f) the way BIP-158 does: SipHash-2-4 keyed with
+// `key`, then a 64x64 -> 128 bit multiply to avoid a modulo.
+fn hash_to_range(element: &[u8], f: u64, k0: u64, k1: u64) -> u64 {
+    let hash = siphash24::Hash::hash_with_keys(k0, k1, element).as_u64();
+    ((u128::from(hash) * u128::from(f)) >> 64) as u64
+}
+
+// Check whether any of `elements` is (probably) in the filter. `key` is the
+// first 16 bytes of the block hash in internal byte order.
+pub fn match_any(filter: &GcsFilter, key: &[u8; 16], elements: &[Vec<u8>]) -> bool {
+    if filter.n == 0 || elements.is_empty() {
+        return false;
+    }
+    let k0 = u64::from_le_bytes(key[..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(key[8..].try_into().unwrap());
+    let f = u64::from(filter.n) * filter.m;
+
+    let mut queries: Vec<u64> = elements
+        .iter()
+        .map(|e| hash_to_range(e, f, k0, k1))
+        .collect();
+    queries.sort_unstable();
+
+    // Walk the decoded set and the sorted queries together.
+    let mut reader = BitReader {
+        data: &filter.data,
+        pos: 0,
+    };
+    let mut value = 0u64;
+    let mut query = queries.iter().peekable();
+    for _ in 0..filter.n {
+        let Some(delta) = reader.read_golomb_rice(BASIC_FILTER_P) else {
+            return false;
+        };
+        value += delta;
+        while let Some(&&q) = query.peek() {
+            if q == value {
+                return true;
+            }
+            if q > value {
+                break;
+            }
+            query.next();
+        }
+        if query.peek().is_none() {
+            return false;
+        }
+    }
+    false
+}
+
+// Helper function to check via the block's basic filter whether `address_script`
+// (a scriptPubKey) is paid or spent in the block.
+pub fn find_tx_in_block_via_filter(
+    rpc: &impl RpcApi,
+    block_hash: &str,
+    address_script: &[u8],
+) -> bitcoincore_rpc::Result<bool> {
+    #[derive(Deserialize)]
+    struct BlockFilterResult {
+        filter: String,
+    }
+    let result =
+        rpc.call::<BlockFilterResult>("getblockfilter", &[json!(block_hash), json!("basic")])?;
+    let filter = GcsFilter::from_serialized_hex(&result.filter)?;
+
+    // The key uses internal byte order, i.e. the reverse of the displayed hex.
+    let mut hash_bytes = Vec::<u8>::from_hex(block_hash)?;
+    hash_bytes.reverse();
+    let key: [u8; 16] = hash_bytes
+        .get(..16)
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| Error::ReturnedError(format!("invalid block hash '{}'", block_hash)))?;
+
+    Ok(match_any(&filter, &key, &[address_script.to_vec()]))
+}
+
+// `block-filter <block-hash> <address>`; `args` excludes "block-filter".
+pub fn run_command(rpc: &impl RpcApi, args: &[String]) -> bitcoincore_rpc::Result<()> {
+    let (block_hash, address) = match args {
+        [block_hash, address, ..] => (block_hash, address),
+        _ => {
+            return Err(Error::ReturnedError(
+                "usage: block-filter <block-hash> <address>".to_string(),
+            ))
+        }
+    };
+    // Only the address's script is matched, whatever network it is for
+    let script = Address::from_str(address)
+        .map_err(|e| Error::ReturnedError(format!("invalid address '{}': {}", address, e)))?
+        .assume_checked()
+        .script_pubkey();
+    if find_tx_in_block_via_filter(rpc, block_hash, script.as_bytes())? {
+        // Filters have false positives, never false negatives
+        info!("Block {} probably pays or spends {}", block_hash, address);
+    } else {
+        info!("Block {} neither pays nor spends {}", block_hash, address);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::bip158::GcsFilterWriter;
+
+    // Testnet genesis block, from the BIP-158 test vectors.
+    const GENESIS_HASH: &str = "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943";
+    const GENESIS_FILTER: &str = "019dfca8";
+    const GENESIS_SCRIPT: &str = "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac";
+
+    fn key_for(block_hash: &str) -> [u8; 16] {
+        let mut bytes = Vec::<u8>::from_hex(block_hash).unwrap();
+        bytes.reverse();
+        bytes[..16].try_into().unwrap()
+    }
+
+    #[test]
+    fn genesis_filter_matches_coinbase_script() {
+        let filter = GcsFilter::from_serialized_hex(GENESIS_FILTER).unwrap();
+        assert_eq!(filter.n, 1);
+        let script = Vec::<u8>::from_hex(GENESIS_SCRIPT).unwrap();
+        assert!(match_any(&filter, &key_for(GENESIS_HASH), &[script]));
+    }
+
+    #[test]
+    fn genesis_filter_rejects_other_script() {
+        let filter = GcsFilter::from_serialized_hex(GENESIS_FILTER).unwrap();
+        let other = Vec::<u8>::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert!(!match_any(&filter, &key_for(GENESIS_HASH), &[other]));
+    }
+
+    #[test]
+    fn matches_filter_built_by_reference_writer() {
+        let key = [7u8; 16];
+        let k0 = u64::from_le_bytes(key[..8].try_into().unwrap());
+        let k1 = u64::from_le_bytes(key[8..].try_into().unwrap());
+        let members: Vec<Vec<u8>> = (0u8..20).map(|i| vec![i; 22]).collect();
+
+        let mut data = Vec::new();
+        let mut writer = GcsFilterWriter::new(&mut data, k0, k1, BASIC_FILTER_M, BASIC_FILTER_P);
+        for member in &members {
+            writer.add_element(member);
+        }
+        writer.finish().unwrap();
+
+        let filter = GcsFilter::from_serialized_hex(&hex_string(&data)).unwrap();
+        assert_eq!(filter.n, 20);
+        assert!(match_any(&filter, &key, &[members[13].clone()]));
+        assert!(match_any(
+            &filter,
+            &key,
+            &[vec![0xaa; 22], members[0].clone()]
+        ));
+        assert!(!match_any(&filter, &key, &[vec![0xaa; 22], vec![0xbb; 22]]));
+        assert!(!match_any(&filter, &key, &[]));
+    }
+
+    fn hex_string(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind with -blockfilterindex=1"]
+    fn finds_mined_address_in_block_filter() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{get_wallet_client, mine_blocks_to_address};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner.get_new_address(None, None).unwrap().assume_checked();
+        let hashes = mine_blocks_to_address(&rpc, &address, 1).unwrap();
+
+        let script = address.script_pubkey();
+        assert!(find_tx_in_block_via_filter(&rpc, &hashes[0], script.as_bytes()).unwrap());
+        assert!(!find_tx_in_block_via_filter(&rpc, &hashes[0], &[0x51]).unwrap());
+    }
+}
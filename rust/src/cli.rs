@@ -0,0 +1,625 @@
+// Command-line settings of the main flow, parsed with clap.
+//
+// Every flag of the run is a field of `Config`, with the assignment's values
+// as defaults. Flags that pick a different way to send the payment only apply
+// to the default single payment, so clap rejects them together
+// (`conflicts_with_all`); the checks that depend on the chain, such as the
+// mining extensions on a chain the run cannot mine, follow in `parse_config`.
+// The RPC settings are layered over the environment and the defaults by
+// `Config::rpc_config`.
+use crate::confirm_target::ConfirmTarget;
+use crate::network::ChainNetwork;
+use crate::report::{self, ReportFormat};
+use crate::rpc_config::RpcConfig;
+use crate::{rpc_retry, DEFAULT_CSV_OUT_PATH, DEFAULT_JSON_OUT_PATH, DEFAULT_OUT_PATH};
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::{Amount, ScriptBuf};
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// `Wallet=BTC,...` pairs of `--distribute` and `--also-pay`
+pub type Payments = Vec<(String, Amount)>;
+
+/// Bitcoin Core RPC capstone: mine, pay the Trader and report the transaction
+#[derive(Parser)]
+#[command(name = "rust")]
+pub struct Config {
+    /// Node RPC URL [env: BITCOIN_RPC_URL]
+    #[arg(long)]
+    pub rpc_url: Option<String>,
+    /// RPC user [env: BITCOIN_RPC_USER]
+    #[arg(long)]
+    pub rpc_user: Option<String>,
+    /// RPC password [env: BITCOIN_RPC_PASS]
+    #[arg(long)]
+    pub rpc_pass: Option<String>,
+    /// Authenticate with the node's .cookie file instead of a user and password
+    #[arg(long, value_name = "PATH")]
+    pub cookie_file: Option<PathBuf>,
+    /// TOML file supplying any flag not given on the command line
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// bitcoin.conf to check against the regtest settings [default: ../bitcoin.conf]
+    #[arg(long, value_name = "PATH")]
+    pub conf: Option<PathBuf>,
+    /// Also write the log to this file
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Time every RPC call and print a table at the end
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Chain the node runs: regtest or signet
+    #[arg(long, default_value = "regtest", value_parser = ChainNetwork::parse)]
+    pub network: ChainNetwork,
+    /// Wallet that mines and sends the payment
+    #[arg(long, default_value = "Miner", value_parser = parse_wallet_name)]
+    pub miner_wallet: String,
+    /// Wallet that receives the payment
+    #[arg(long, default_value = "Trader", value_parser = parse_wallet_name)]
+    pub trader_wallet: String,
+    /// BTC sent to the Trader
+    #[arg(
+        long = "amount",
+        visible_alias = "send-amount",
+        value_name = "BTC",
+        default_value = "20",
+        value_parser = parse_send_amount
+    )]
+    pub send_amount_btc: Amount,
+    /// Report path [default: ../out.txt, ../out.json or ../out.csv by --format]
+    #[arg(long = "out", value_name = "PATH")]
+    pub out_file: Option<PathBuf>,
+    /// Report format: text, json or csv
+    #[arg(long, default_value = "text", value_parser = ReportFormat::parse)]
+    pub format: ReportFormat,
+    /// Also write the report as versioned JSON to this path
+    #[arg(long, value_name = "PATH")]
+    pub json_report: Option<PathBuf>,
+    /// Grade the run against an instructor's expected.toml
+    #[arg(long, value_name = "PATH")]
+    pub expect: Option<PathBuf>,
+
+    /// Blocks before a block reward can be spent [default: 100]
+    #[arg(long, value_name = "BLOCKS")]
+    pub maturity: Option<u32>,
+    /// Most blocks Step 3 mines before giving up
+    #[arg(
+        long,
+        value_name = "BLOCKS",
+        default_value_t = crate::DEFAULT_MAX_MINE_BLOCKS,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub max_mine_blocks: u64,
+    /// Mine exactly this many blocks in Step 3 instead of until spendable
+    #[arg(
+        long,
+        value_name = "BLOCKS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "max_mine_blocks"
+    )]
+    pub blocks: Option<u64>,
+    /// Seconds to wait for a node that is still starting [default: 60]
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub node_timeout: Option<u64>,
+    /// Mine to this output descriptor instead of a Miner address
+    #[arg(long, value_name = "DESCRIPTOR")]
+    pub mine_to_descriptor: Option<String>,
+    /// Who the confirmation block is mined to: miner, burn or thirdwallet [default: burn]
+    #[arg(long, value_parser = ConfirmTarget::parse)]
+    pub confirm_to: Option<ConfirmTarget>,
+    /// Create the wallets from this BIP-39 mnemonic (regtest only)
+    #[arg(long, value_name = "WORDS", conflicts_with = "seed_file")]
+    pub seed_mnemonic: Option<String>,
+    /// Read the BIP-39 mnemonic from this file (regtest only)
+    #[arg(long, value_name = "PATH")]
+    pub seed_file: Option<PathBuf>,
+    /// Record the addresses the run generates in this address book [default path: addressbook.json]
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = crate::address_book::ADDRESS_BOOK_PATH
+    )]
+    pub address_book: Option<PathBuf>,
+    /// Attach a note to the payment in this SQLite note store [default path: txnotes.db]
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = crate::tx_notes::TX_NOTES_PATH
+    )]
+    pub tx_notes: Option<PathBuf>,
+
+    /// Send without recording the payment in the Miner wallet
+    #[arg(long, conflicts_with_all = [
+        "split_recipient", "pay_to_script", "require_coinbase_input", "change_to_descriptor",
+        "also_pay", "rbf", "send_raw", "psbt", "psbt_in", "min_input_confirmations",
+    ])]
+    pub no_wallet_record: bool,
+    /// Spread the amount over N fresh Trader addresses
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub split_recipient: Option<u32>,
+    /// Pay the amount to this scriptPubKey (hex) instead of the Trader
+    #[arg(long, value_name = "HEX", value_parser = parse_script_hex, conflicts_with_all = [
+        "split_recipient", "require_coinbase_input", "also_pay", "rbf", "send_raw", "psbt",
+        "psbt_in", "min_input_confirmations",
+    ])]
+    pub pay_to_script: Option<ScriptBuf>,
+    /// Fund the payment from block rewards only
+    #[arg(long, conflicts_with_all = [
+        "split_recipient", "change_to_descriptor", "also_pay", "rbf", "send_raw", "psbt",
+        "psbt_in", "min_input_confirmations",
+    ])]
+    pub require_coinbase_input: bool,
+    /// Send the change to the next unused address of this descriptor
+    #[arg(long, value_name = "DESCRIPTOR", conflicts_with_all = [
+        "split_recipient", "also_pay", "rbf", "send_raw", "psbt", "psbt_in",
+        "min_input_confirmations",
+    ])]
+    pub change_to_descriptor: Option<String>,
+    /// Also pay these wallets in the same transaction
+    #[arg(long, value_name = "WALLET=BTC,...", value_parser = parse_distributions,
+        conflicts_with_all = ["rbf", "send_raw", "psbt", "psbt_in", "min_input_confirmations"])]
+    pub also_pay: Option<Payments>,
+    /// Send the payment replaceable and bump its fee before confirming it
+    #[arg(long, conflicts_with_all = [
+        "split_recipient", "send_raw", "psbt", "psbt_in", "min_input_confirmations",
+    ])]
+    pub rbf: bool,
+    /// Fee rate --rbf bumps the payment to [default: the node's choice]
+    #[arg(long, value_name = "SAT/VB", requires = "rbf", value_parser = parse_fee_rate)]
+    pub bump_fee_rate: Option<f64>,
+    /// Build, sign and broadcast the payment by hand
+    #[arg(long, conflicts_with_all = [
+        "split_recipient", "psbt", "psbt_in", "min_input_confirmations",
+    ])]
+    pub send_raw: bool,
+    /// Fund, sign and finalize the payment as a PSBT
+    #[arg(long, conflicts_with_all = ["split_recipient", "min_input_confirmations"])]
+    pub psbt: bool,
+    /// Finalize and broadcast a PSBT already signed elsewhere
+    #[arg(long, value_name = "PATH", conflicts_with_all = [
+        "split_recipient", "min_input_confirmations",
+    ])]
+    pub psbt_in: Option<PathBuf>,
+    /// Spend only inputs with at least this many confirmations
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with = "split_recipient"
+    )]
+    pub min_input_confirmations: Option<u32>,
+
+    /// Add a 2-of-2 multisig between the Miner and the Trader
+    #[arg(long)]
+    pub multisig: bool,
+    /// Lock 1 BTC with OP_CHECKLOCKTIMEVERIFY for N blocks, then spend it back
+    #[arg(long, value_name = "BLOCKS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub cltv_lock: Option<u64>,
+    /// Lock 1 BTC in a 2-of-3 multisig and take its 30-block CSV recovery path
+    #[arg(long)]
+    pub timelocked_multisig: bool,
+    /// Check the payment's input signatures offline against the Miner's keys
+    #[arg(long)]
+    pub verify_signatures: bool,
+    /// After the payment, pay these wallets in one more transaction
+    #[arg(long, value_name = "WALLET=BTC,...", value_parser = parse_distributions)]
+    pub distribute: Option<Payments>,
+    /// Sweep the Miner's UTXOs into one output at the end
+    #[arg(long)]
+    pub consolidate: bool,
+}
+
+impl Config {
+    // Where the report goes: --out, or the default of the --format
+    pub fn out_path(&self) -> PathBuf {
+        match (&self.out_file, self.format) {
+            (Some(path), _) => path.clone(),
+            (None, ReportFormat::Text) => DEFAULT_OUT_PATH.into(),
+            (None, ReportFormat::Json) => DEFAULT_JSON_OUT_PATH.into(),
+            (None, ReportFormat::Csv) => DEFAULT_CSV_OUT_PATH.into(),
+        }
+    }
+
+    pub fn node_timeout(&self) -> Duration {
+        self.node_timeout
+            .map_or(rpc_retry::DEFAULT_NODE_TIMEOUT, Duration::from_secs)
+    }
+
+    // With --psbt-in the PSBT path is taken too
+    pub fn uses_psbt(&self) -> bool {
+        self.psbt || self.psbt_in.is_some()
+    }
+
+    // The RPC settings: the flags over the environment and the network's defaults
+    pub fn rpc_config(&self) -> Result<RpcConfig, String> {
+        let mut config = RpcConfig::load_with_defaults(RpcConfig {
+            url: self.network.rpc_url().to_string(),
+            ..RpcConfig::default()
+        })?;
+        for (value, field) in [
+            (&self.rpc_url, &mut config.url),
+            (&self.rpc_user, &mut config.user),
+            (&self.rpc_pass, &mut config.pass),
+        ] {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+        if let Some(path) = &self.cookie_file {
+            config.cookie_file = Some(path.clone());
+        }
+        Ok(config)
+    }
+
+    // The checks clap cannot express: the wallets differ, and the extensions
+    // that mine or seed wallets run on a chain that allows it
+    fn validate(&self) -> Result<(), String> {
+        if self.miner_wallet == self.trader_wallet {
+            return Err(format!(
+                "the Miner and Trader wallets must differ, both are '{}'",
+                self.miner_wallet
+            ));
+        }
+        if !self.network.can_mine() {
+            for (flag, given) in [
+                ("--blocks", self.blocks.is_some()),
+                ("--mine-to-descriptor", self.mine_to_descriptor.is_some()),
+                ("--confirm-to", self.confirm_to.is_some()),
+                ("--multisig", self.multisig),
+                ("--cltv-lock", self.cltv_lock.is_some()),
+                ("--timelocked-multisig", self.timelocked_multisig),
+                ("--distribute", self.distribute.is_some()),
+            ] {
+                if given {
+                    return Err(format!(
+                        "{} needs a chain the run can mine on, not {}",
+                        flag,
+                        self.network.chain_name()
+                    ));
+                }
+            }
+        }
+        let seeded = self.seed_mnemonic.is_some() || self.seed_file.is_some();
+        if seeded && self.network != ChainNetwork::Regtest {
+            return Err("--seed-mnemonic and --seed-file only apply to regtest".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Parse the main flow's flags (`args` without the program name). `--help`
+// prints the usage and exits.
+pub fn parse_config(args: &[String]) -> Result<Config, String> {
+    let argv = std::iter::once("rust").chain(args.iter().map(String::as_str));
+    let config = match Config::try_parse_from(argv) {
+        Ok(config) => config,
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => return Err(e.to_string().trim_end().to_string()),
+    };
+    config.validate()?;
+    Ok(config)
+}
+
+// Helper function to check a --miner-wallet or --trader-wallet name
+fn parse_wallet_name(value: &str) -> Result<String, String> {
+    match value.trim() {
+        "" => Err("needs a wallet name".to_string()),
+        name => Ok(name.to_string()),
+    }
+}
+
+// Helper function to parse a positive BTC amount
+fn parse_send_amount(value: &str) -> Result<Amount, String> {
+    report::parse_btc(value)
+        .ok()
+        .and_then(|amount| amount.to_unsigned().ok())
+        .filter(|amount| *amount > Amount::ZERO)
+        .ok_or_else(|| "expected a positive BTC amount".to_string())
+}
+
+// Helper function to parse a positive sat/vB fee rate
+fn parse_fee_rate(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| rate.is_finite() && *rate > 0.0)
+        .ok_or_else(|| "expected a positive sat/vB rate".to_string())
+}
+
+// Helper function to parse a hex scriptPubKey
+fn parse_script_hex(value: &str) -> Result<ScriptBuf, String> {
+    Vec::<u8>::from_hex(value)
+        .map(ScriptBuf::from_bytes)
+        .map_err(|e| e.to_string())
+}
+
+// Helper function to parse `Wallet=BTC,Wallet=BTC,...`
+fn parse_distributions(value: &str) -> Result<Payments, String> {
+    value
+        .split(',')
+        .map(|entry| {
+            let invalid = || format!("invalid entry '{}': expected <wallet>=<BTC amount>", entry);
+            let (wallet, btc) = entry.split_once('=').ok_or_else(invalid)?;
+            let amount = parse_send_amount(btc).map_err(|_| invalid())?;
+            if wallet.trim().is_empty() {
+                return Err(invalid());
+            }
+            Ok((wallet.trim().to_string(), amount))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_config(&args)
+    }
+
+    #[test]
+    fn defaults_are_the_assignment_values() {
+        let config = parse(&["--network", "regtest"]).unwrap();
+        assert_eq!(config.miner_wallet, "Miner");
+        assert_eq!(config.trader_wallet, "Trader");
+        assert_eq!(config.send_amount_btc, Amount::from_int_btc(20));
+        assert_eq!(config.out_path(), Path::new("../out.txt"));
+        assert_eq!(config.format, ReportFormat::Text);
+        assert_eq!(config.max_mine_blocks, crate::DEFAULT_MAX_MINE_BLOCKS);
+        assert_eq!(config.node_timeout(), Duration::from_secs(60));
+        assert_eq!(config.maturity, None);
+        assert!(config.confirm_to.is_none() && config.also_pay.is_none());
+        assert!(config.address_book.is_none() && config.tx_notes.is_none());
+    }
+
+    #[test]
+    fn address_book_path_is_optional() {
+        let config = parse(&["--address-book"]).unwrap();
+        assert_eq!(
+            config.address_book.as_deref(),
+            Some(Path::new(crate::address_book::ADDRESS_BOOK_PATH))
+        );
+        let config = parse(&["--address-book", "/tmp/book.json"]).unwrap();
+        assert_eq!(
+            config.address_book.as_deref(),
+            Some(Path::new("/tmp/book.json"))
+        );
+    }
+
+    #[test]
+    fn tx_notes_path_is_optional() {
+        let config = parse(&["--tx-notes"]).unwrap();
+        assert_eq!(
+            config.tx_notes.as_deref(),
+            Some(Path::new(crate::tx_notes::TX_NOTES_PATH))
+        );
+        let config = parse(&["--tx-notes=/tmp/notes.db"]).unwrap();
+        assert_eq!(config.tx_notes.as_deref(), Some(Path::new("/tmp/notes.db")));
+    }
+
+    #[test]
+    fn flags_override_the_defaults() {
+        let config = parse(&[
+            "--miner-wallet",
+            "Alice",
+            "--trader-wallet= Bob ",
+            "--amount",
+            "1.5",
+            "--out",
+            "/tmp/report.txt",
+            "--maturity=20",
+            "--max-mine-blocks",
+            "25",
+            "--node-timeout",
+            "5",
+            "--confirm-to",
+            "thirdwallet",
+        ])
+        .unwrap();
+        assert_eq!(config.miner_wallet, "Alice");
+        assert_eq!(config.trader_wallet, "Bob");
+        assert_eq!(config.send_amount_btc, Amount::from_sat(150_000_000));
+        assert_eq!(config.out_path(), Path::new("/tmp/report.txt"));
+        assert_eq!(config.maturity, Some(20));
+        assert_eq!(config.max_mine_blocks, 25);
+        assert_eq!(config.node_timeout(), Duration::from_secs(5));
+        assert_eq!(config.confirm_to, Some(ConfirmTarget::ThirdWallet));
+    }
+
+    #[test]
+    fn send_amount_and_blocks_are_read() {
+        let config = parse(&[
+            "--miner-wallet",
+            "Alice",
+            "--trader-wallet",
+            "Bob",
+            "--send-amount",
+            "0.5",
+            "--blocks",
+            "150",
+        ])
+        .unwrap();
+        assert_eq!(config.miner_wallet, "Alice");
+        assert_eq!(config.trader_wallet, "Bob");
+        assert_eq!(config.send_amount_btc, Amount::from_sat(50_000_000));
+        assert_eq!(config.blocks, Some(150));
+        assert_eq!(parse(&[]).unwrap().blocks, None);
+        for bad in [
+            &["--send-amount", "-2"][..],
+            &["--send-amount", "lots"],
+            &["--blocks", "0"],
+        ] {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+        let err = parse(&["--blocks", "10", "--max-mine-blocks", "20"])
+            .err()
+            .unwrap();
+        assert!(err.contains("cannot be used with"), "{}", err);
+        assert!(parse(&["--network", "signet", "--blocks", "10"]).is_err());
+    }
+
+    #[test]
+    fn format_picks_the_default_out_path() {
+        let config = parse(&["--format", "json"]).unwrap();
+        assert_eq!(config.format, ReportFormat::Json);
+        assert_eq!(config.out_path(), Path::new("../out.json"));
+        let config = parse(&["--out=/tmp/r.json", "--format=json"]).unwrap();
+        assert_eq!(config.out_path(), Path::new("/tmp/r.json"));
+        let config = parse(&["--format", "csv"]).unwrap();
+        assert_eq!(config.out_path(), Path::new("../out.csv"));
+        let err = parse(&["--format", "xml"]).err().unwrap();
+        assert!(err.contains("expected text, json or csv"), "{}", err);
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        for amount in ["0", "-1", "abc", "0.000000001"] {
+            assert!(parse(&["--amount", amount]).is_err(), "{}", amount);
+        }
+        assert!(parse(&["--trader-wallet", "Miner"]).is_err());
+        assert!(parse(&["--miner-wallet", " "]).is_err());
+        for (flag, bad) in [
+            ("--max-mine-blocks", "0"),
+            ("--node-timeout", "0"),
+            ("--node-timeout", "1.5"),
+            ("--node-timeout", "soon"),
+            ("--maturity", "-1"),
+            ("--split-recipient", "0"),
+            ("--min-input-confirmations", "0"),
+            ("--min-input-confirmations", "2.5"),
+            ("--pay-to-script", "zz"),
+            ("--network", "mainnet"),
+            ("--confirm-to", "nobody"),
+        ] {
+            assert!(parse(&[flag, bad]).is_err(), "{} {}", flag, bad);
+        }
+        for bad in ["0", "-3", "inf", "fast"] {
+            assert!(
+                parse(&["--rbf", "--bump-fee-rate", bad]).is_err(),
+                "{}",
+                bad
+            );
+        }
+        let err = parse(&["--no-such-flag"]).err().unwrap();
+        assert!(err.contains("--no-such-flag"), "{}", err);
+    }
+
+    #[test]
+    fn pay_to_script_is_read_as_hex() {
+        let script = "0020a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90";
+        let config = parse(&["--pay-to-script", script]).unwrap();
+        assert_eq!(config.pay_to_script.unwrap().to_hex_string(), script);
+    }
+
+    #[test]
+    fn distributions_read_wallet_amounts() {
+        assert_eq!(
+            parse_distributions("Trader=20, Alice=1.5,Bob=0.00000001").unwrap(),
+            vec![
+                ("Trader".to_string(), Amount::from_int_btc(20)),
+                ("Alice".to_string(), Amount::from_sat(150_000_000)),
+                ("Bob".to_string(), Amount::from_sat(1)),
+            ]
+        );
+        for value in ["Trader", "=1", "Trader=0", "Trader=-1", "Trader=x"] {
+            assert!(parse_distributions(value).is_err(), "{}", value);
+        }
+        for value in ["Trader=1,", "Trader=1,,Alice=2"] {
+            assert!(parse_distributions(value).is_err(), "{}", value);
+        }
+        let err = parse(&["--also-pay", "Alice"]).err().unwrap();
+        assert!(err.contains("--also-pay"), "{}", err);
+        assert!(err.contains("invalid entry 'Alice'"), "{}", err);
+    }
+
+    #[test]
+    fn send_paths_exclude_each_other() {
+        let exclusive = [
+            vec!["--no-wallet-record"],
+            vec!["--split-recipient", "2"],
+            vec!["--pay-to-script", "51"],
+            vec!["--require-coinbase-input"],
+            vec!["--rbf"],
+            vec!["--send-raw"],
+            vec!["--psbt"],
+            vec!["--min-input-confirmations", "6"],
+        ];
+        for (i, first) in exclusive.iter().enumerate() {
+            for second in &exclusive[i + 1..] {
+                let args: Vec<&str> = first.iter().chain(second).copied().collect();
+                let err = parse(&args).err();
+                assert!(err.is_some(), "{:?}", args);
+                assert!(err.unwrap().contains("cannot be used with"), "{:?}", args);
+            }
+        }
+    }
+
+    #[test]
+    fn change_descriptor_and_also_pay_combine_where_they_apply() {
+        let descriptor = ["--change-to-descriptor", "wpkh(02aa)"];
+        let also_pay = ["--also-pay", "Alice=1"];
+        assert!(parse(&[&descriptor[..], &["--pay-to-script", "51"]].concat()).is_ok());
+        assert!(parse(&[&also_pay[..], &["--split-recipient", "2"]].concat()).is_ok());
+        for other in [&["--require-coinbase-input"][..], &["--rbf"], &also_pay] {
+            assert!(parse(&[&descriptor[..], other].concat()).is_err());
+        }
+        for other in [
+            &["--no-wallet-record"][..],
+            &["--pay-to-script", "51"],
+            &["--send-raw"],
+        ] {
+            assert!(parse(&[&also_pay[..], other].concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn bump_fee_rate_needs_rbf() {
+        let err = parse(&["--bump-fee-rate", "12.5"]).err().unwrap();
+        assert!(err.contains("--rbf"), "{}", err);
+        let config = parse(&["--rbf", "--bump-fee-rate", "12.5"]).unwrap();
+        assert_eq!(config.bump_fee_rate, Some(12.5));
+    }
+
+    #[test]
+    fn mining_extensions_need_a_mineable_chain() {
+        for args in [
+            &["--mine-to-descriptor", "raw(51)"][..],
+            &["--confirm-to", "miner"],
+            &["--multisig"],
+            &["--cltv-lock", "5"],
+            &["--timelocked-multisig"],
+            &["--distribute", "Alice=1"],
+        ] {
+            let err = parse(&[&["--network", "signet"], args].concat())
+                .err()
+                .unwrap();
+            assert!(err.contains("not signet"), "{}", err);
+            assert!(parse(args).is_ok(), "{:?}", args);
+        }
+        let err = parse(&["--network", "signet", "--seed-file", "words.txt"])
+            .err()
+            .unwrap();
+        assert!(err.contains("only apply to regtest"), "{}", err);
+        assert!(parse(&["--seed-file", "a", "--seed-mnemonic", "b"]).is_err());
+    }
+
+    #[test]
+    fn rpc_flags_override_the_network_defaults() {
+        let config = parse(&["--network", "signet", "--rpc-user", "bob"]).unwrap();
+        let rpc = config.rpc_config().unwrap();
+        assert_eq!(rpc.user, "bob");
+        if std::env::var(crate::rpc_config::URL_VAR).is_err() {
+            assert_eq!(rpc.url, ChainNetwork::Signet.rpc_url());
+        }
+        let config = parse(&["--rpc-url", "http://lab:18443", "--cookie-file", "/c"]).unwrap();
+        let rpc = config.rpc_config().unwrap();
+        assert_eq!(rpc.url, "http://lab:18443");
+        assert!(rpc.uses_cookie());
+    }
+}
@@ -0,0 +1,266 @@
+// Funds locking contract built on OP_CHECKLOCKTIMEVERIFY (BIP-65).
+//
+// The locked output is a P2SH whose redeem script is
+// `<unlock_height> OP_CHECKLOCKTIMEVERIFY OP_DROP OP_DUP OP_HASH160 <pubkey_hash> OP_EQUALVERIFY OP_CHECKSIG`,
+// so it can only be spent by the recipient key, and only by a transaction
+// whose nLockTime is at or beyond `unlock_height`.
+// `demonstrate` (`--cltv-lock <blocks>`) locks coins of the Miner to a
+// throwaway key and spends them back once the lock has expired.
+use crate::mine_blocks_to_address;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use bitcoincore_rpc::bitcoin::absolute::LockTime;
+use bitcoincore_rpc::bitcoin::blockdata::opcodes::all::{
+    OP_CHECKSIG, OP_CLTV, OP_DROP, OP_DUP, OP_EQUALVERIFY, OP_HASH160,
+};
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::script::{Builder, PushBytesBuf};
+use bitcoincore_rpc::bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoincore_rpc::bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoincore_rpc::bitcoin::{
+    ecdsa, transaction, Address, Amount, Network, OutPoint, PrivateKey, PublicKey, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use std::str::FromStr;
+
+// Heights at or above this value are interpreted as UNIX timestamps by nLockTime.
+const LOCKTIME_THRESHOLD: u64 = 500_000_000;
+
+// What `demonstrate` locks, and the fee its spend pays out of it
+const DEMO_AMOUNT: Amount = Amount::from_sat(100_000_000);
+const DEMO_SPEND_FEE: Amount = Amount::from_sat(10_000);
+
+// A P2SH output locked until `unlock_height`, as created by `create_cltv_output`.
+#[derive(Debug, Clone)]
+pub struct FundsLockingContract {
+    pub txid: String,
+    pub vout: u32,
+    pub address: String,
+    pub redeem_script: ScriptBuf,
+    pub amount: Amount,
+    pub unlock_height: u64,
+}
+
+// Build the CLTV redeem script paying to the hash of `pubkey`.
+pub fn cltv_redeem_script(pubkey: &PublicKey, unlock_height: u64) -> ScriptBuf {
+    Builder::new()
+        .push_int(unlock_height as i64)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_DROP)
+        .push_opcode(OP_DUP)
+        .push_opcode(OP_HASH160)
+        .push_slice(pubkey.pubkey_hash().to_byte_array())
+        .push_opcode(OP_EQUALVERIFY)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+// Helper function to lock `amount` from `wallet` into a CLTV P2SH output.
+// `rpc` is the node client used to look the funding transaction up again.
+pub fn create_cltv_output(
+    rpc: &impl RpcApi,
+    wallet: &impl RpcApi,
+    recipient_pubkey: &str,
+    amount: Amount,
+    unlock_height: u64,
+) -> bitcoincore_rpc::Result<FundsLockingContract> {
+    if unlock_height >= LOCKTIME_THRESHOLD {
+        return Err(Error::ReturnedError(format!(
+            "unlock height {} is not a block height (must be below {})",
+            unlock_height, LOCKTIME_THRESHOLD
+        )));
+    }
+    let pubkey = PublicKey::from_str(recipient_pubkey).map_err(|e| {
+        Error::ReturnedError(format!("invalid public key '{}': {}", recipient_pubkey, e))
+    })?;
+
+    let redeem_script = cltv_redeem_script(&pubkey, unlock_height);
+    let address = Address::p2sh(&redeem_script, Network::Regtest)
+        .map_err(|e| Error::ReturnedError(format!("cannot build P2SH address: {}", e)))?;
+
+    let txid = wallet.send_to_address(&address, amount, None, None, None, None, None, None)?;
+
+    // Locate the output paying to the P2SH script; the wallet may place change first.
+    let tx = rpc.get_raw_transaction(&txid, None)?;
+    let script_pubkey = address.script_pubkey();
+    let vout = tx
+        .output
+        .iter()
+        .position(|out| out.script_pubkey == script_pubkey)
+        .ok_or_else(|| {
+            Error::ReturnedError(format!("transaction {} has no output to {}", txid, address))
+        })?;
+
+    Ok(FundsLockingContract {
+        txid: txid.to_string(),
+        vout: vout as u32,
+        address: address.to_string(),
+        redeem_script,
+        amount,
+        unlock_height,
+    })
+}
+
+// Helper function to spend a CLTV output once the chain has reached its unlock
+// height. The transaction is signed locally with `key` and broadcast through `rpc`.
+pub fn spend_cltv_output(
+    rpc: &impl RpcApi,
+    contract: &FundsLockingContract,
+    key: &PrivateKey,
+    destination: &Address,
+    fee: Amount,
+) -> bitcoincore_rpc::Result<String> {
+    let prev_txid = Txid::from_str(&contract.txid)
+        .map_err(|e| Error::ReturnedError(format!("invalid txid '{}': {}", contract.txid, e)))?;
+    let value = contract.amount.checked_sub(fee).ok_or_else(|| {
+        Error::ReturnedError(format!(
+            "fee {} exceeds locked amount {}",
+            fee, contract.amount
+        ))
+    })?;
+    let lock_time = LockTime::from_height(contract.unlock_height as u32)
+        .map_err(|e| Error::ReturnedError(format!("invalid unlock height: {}", e)))?;
+
+    let mut tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(prev_txid, contract.vout),
+            script_sig: ScriptBuf::new(),
+            // A non-final sequence is required for nLockTime (and therefore CLTV) to be enforced.
+            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: destination.script_pubkey(),
+        }],
+    };
+
+    let sighash = SighashCache::new(&tx)
+        .legacy_signature_hash(0, &contract.redeem_script, EcdsaSighashType::All.to_u32())
+        .map_err(|e| Error::ReturnedError(format!("cannot compute sighash: {}", e)))?;
+    let secp = Secp256k1::new();
+    let signature = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &key.inner);
+    let signature = ecdsa::Signature::sighash_all(signature);
+
+    let redeem_script = PushBytesBuf::try_from(contract.redeem_script.to_bytes())
+        .map_err(|e| Error::ReturnedError(format!("redeem script too large: {}", e)))?;
+    tx.input[0].script_sig = Builder::new()
+        .push_slice(signature.serialize())
+        .push_key(&key.public_key(&secp))
+        .push_slice(redeem_script)
+        .into_script();
+
+    Ok(rpc.send_raw_transaction(&tx)?.to_string())
+}
+
+// A fresh random key, for outputs the run locks and spends itself
+pub(crate) fn random_key(network: Network) -> PrivateKey {
+    let mut bytes = [0u8; 32];
+    loop {
+        OsRng.fill_bytes(&mut bytes);
+        // All but a negligible fraction of 32-byte strings are valid keys
+        if let Ok(secret) = SecretKey::from_slice(&bytes) {
+            return PrivateKey::new(secret, network);
+        }
+    }
+}
+
+// Helper function to lock 1 BTC of `miner` until `lock_blocks` blocks past the
+// tip, check that the node refuses the spend before then, mine up to the
+// unlock height and spend the output back to `miner`. Blocks are mined to
+// `mining_address`. Returns the locking and the spending txid.
+pub fn demonstrate(
+    rpc: &impl RpcApi,
+    miner: &impl RpcApi,
+    mining_address: &Address,
+    lock_blocks: u64,
+) -> bitcoincore_rpc::Result<(String, String)> {
+    let key = random_key(Network::Regtest);
+    let pubkey = key.public_key(&Secp256k1::new());
+    let unlock_height = rpc.get_block_count()? + lock_blocks;
+    let contract = create_cltv_output(rpc, miner, &pubkey.to_string(), DEMO_AMOUNT, unlock_height)?;
+    info!(
+        "Locked {} BTC at {} until height {}",
+        DEMO_AMOUNT.to_btc(),
+        contract.address,
+        unlock_height
+    );
+
+    let destination = miner.get_new_address(None, None)?.assume_checked();
+    if spend_cltv_output(rpc, &contract, &key, &destination, DEMO_SPEND_FEE).is_ok() {
+        return Err(Error::ReturnedError(format!(
+            "the node accepted a spend of {} before height {}",
+            contract.address, unlock_height
+        )));
+    }
+    info!("Spending before height {} is rejected", unlock_height);
+
+    let remaining = unlock_height.saturating_sub(rpc.get_block_count()?);
+    mine_blocks_to_address(rpc, mining_address, remaining)?;
+    let spend_txid = spend_cltv_output(rpc, &contract, &key, &destination, DEMO_SPEND_FEE)?;
+    mine_blocks_to_address(rpc, mining_address, 1)?;
+    info!("Locked output spent back to the Miner: {}", spend_txid);
+    Ok((contract.txid, spend_txid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_wallet_client;
+    use crate::rpc_config::RpcConfig;
+
+    fn test_key() -> PrivateKey {
+        let secret = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        PrivateKey::new(secret, Network::Regtest)
+    }
+
+    #[test]
+    fn redeem_script_layout() {
+        let secp = Secp256k1::new();
+        let pubkey = test_key().public_key(&secp);
+        let script = cltv_redeem_script(&pubkey, 200);
+        let asm = script.to_asm_string();
+        assert!(asm.starts_with("OP_PUSHBYTES_2 c800 OP_CLTV OP_DROP OP_DUP OP_HASH160"));
+        assert!(asm.ends_with("OP_EQUALVERIFY OP_CHECKSIG"));
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn cltv_output_spendable_after_unlock_height() {
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let mining_address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &mining_address, 101).unwrap();
+
+        let secp = Secp256k1::new();
+        let key = test_key();
+        let unlock_height = rpc.get_block_count().unwrap() + 5;
+        let contract = create_cltv_output(
+            &rpc,
+            &miner,
+            &key.public_key(&secp).to_string(),
+            Amount::from_btc(1.0).unwrap(),
+            unlock_height,
+        )
+        .unwrap();
+        mine_blocks_to_address(&rpc, &mining_address, 1).unwrap();
+
+        let destination = miner.get_new_address(None, None).unwrap().assume_checked();
+        let fee = Amount::from_sat(10_000);
+
+        // Still locked: the next block would be below the unlock height.
+        assert!(spend_cltv_output(&rpc, &contract, &key, &destination, fee).is_err());
+
+        let remaining = unlock_height - rpc.get_block_count().unwrap();
+        mine_blocks_to_address(&rpc, &mining_address, remaining).unwrap();
+        let spend_txid = spend_cltv_output(&rpc, &contract, &key, &destination, fee).unwrap();
+        assert!(rpc
+            .get_mempool_entry(&Txid::from_str(&spend_txid).unwrap())
+            .is_ok());
+    }
+}
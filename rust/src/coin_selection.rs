@@ -0,0 +1,215 @@
+// Coin selection strategies for picking which wallet UTXOs fund a payment.
+//
+// `greedy` is the simple largest-first approach. `branch_and_bound` searches
+// for an input set whose total lands within `cost_of_change` of the target,
+// so that no change output is needed at all; `select_coins` tries it first
+// and falls back to greedy when no such set exists.
+use crate::amount_serde::deserialize_amount;
+use bitcoincore_rpc::bitcoin::Amount;
+use serde::Deserialize;
+
+// Upper bound on the number of search nodes visited by `branch_and_bound`,
+// mirroring the iteration cap Bitcoin Core uses for the same algorithm.
+const BNB_MAX_TRIES: u32 = 100_000;
+
+// An unspent output as returned by `listunspent`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub amount: Amount,
+}
+
+// Largest-first selection: keep adding the biggest UTXO until the target is met.
+pub fn greedy(utxos: &[Utxo], target: Amount) -> Option<Vec<Utxo>> {
+    let mut sorted = utxos.to_vec();
+    sorted.sort_by_key(|u| std::cmp::Reverse(u.amount));
+
+    let mut selected = Vec::new();
+    let mut total = Amount::ZERO;
+    for utxo in sorted {
+        if total >= target {
+            break;
+        }
+        total += utxo.amount;
+        selected.push(utxo);
+    }
+    (total >= target).then_some(selected)
+}
+
+// Branch and Bound selection: find the input set whose total is in
+// `[target, target + cost_of_change]` with the least excess. Returns `None`
+// when no such set is found within the search budget.
+pub fn branch_and_bound(
+    utxos: &[Utxo],
+    target: Amount,
+    cost_of_change: Amount,
+) -> Option<Vec<Utxo>> {
+    let mut sorted = utxos.to_vec();
+    sorted.sort_by_key(|u| std::cmp::Reverse(u.amount));
+    let amounts: Vec<u64> = sorted.iter().map(|u| u.amount.to_sat()).collect();
+
+    // remaining[i] is the sum of every UTXO from index i onwards, used to
+    // prune branches that can no longer reach the target.
+    let mut remaining = vec![0u64; amounts.len() + 1];
+    for i in (0..amounts.len()).rev() {
+        remaining[i] = remaining[i + 1] + amounts[i];
+    }
+
+    let mut search = BnbSearch {
+        amounts: &amounts,
+        remaining: &remaining,
+        target: target.to_sat(),
+        upper_bound: target.to_sat() + cost_of_change.to_sat(),
+        tries: 0,
+        current: Vec::new(),
+        best: None,
+    };
+    search.explore();
+
+    search
+        .best
+        .map(|(indexes, _)| indexes.into_iter().map(|i| sorted[i].clone()).collect())
+}
+
+// Try an exact-ish Branch and Bound match first, then fall back to greedy.
+pub fn select_coins(utxos: &[Utxo], target: Amount, cost_of_change: Amount) -> Option<Vec<Utxo>> {
+    branch_and_bound(utxos, target, cost_of_change).or_else(|| greedy(utxos, target))
+}
+
+struct BnbSearch<'a> {
+    amounts: &'a [u64],
+    remaining: &'a [u64],
+    target: u64,
+    upper_bound: u64,
+    tries: u32,
+    current: Vec<usize>,
+    // Best selection so far with its excess over the target.
+    best: Option<(Vec<usize>, u64)>,
+}
+
+impl BnbSearch<'_> {
+    // Depth-first search over include/exclude decisions for each UTXO, with
+    // the inclusion branch first so large UTXOs are tried before small ones.
+    // `current` doubles as the backtracking stack: undoing the last inclusion
+    // moves on to its exclusion branch. Iterative rather than recursive, since
+    // the depth can reach the number of UTXOs in the wallet.
+    fn explore(&mut self) {
+        let mut depth = 0;
+        let mut total = 0;
+        loop {
+            self.tries += 1;
+            if self.tries > BNB_MAX_TRIES {
+                return;
+            }
+
+            let backtrack = if total > self.upper_bound {
+                true
+            } else if total >= self.target {
+                let excess = total - self.target;
+                if self.best.as_ref().is_none_or(|(_, best)| excess < *best) {
+                    self.best = Some((self.current.clone(), excess));
+                }
+                if excess == 0 {
+                    return;
+                }
+                true
+            } else {
+                depth == self.amounts.len() || total + self.remaining[depth] < self.target
+            };
+
+            if backtrack {
+                let Some(last) = self.current.pop() else {
+                    return;
+                };
+                total -= self.amounts[last];
+                depth = last + 1;
+            } else {
+                self.current.push(depth);
+                total += self.amounts[depth];
+                depth += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxos(sats: &[u64]) -> Vec<Utxo> {
+        sats.iter()
+            .enumerate()
+            .map(|(i, &sat)| Utxo {
+                txid: format!("{:064x}", i),
+                vout: 0,
+                amount: Amount::from_sat(sat),
+            })
+            .collect()
+    }
+
+    fn total(selection: &[Utxo]) -> u64 {
+        selection.iter().map(|u| u.amount.to_sat()).sum()
+    }
+
+    #[test]
+    fn bnb_finds_exact_match() {
+        let set = utxos(&[5_000, 3_000, 2_000, 1_000]);
+        let selection = branch_and_bound(&set, Amount::from_sat(4_000), Amount::ZERO).unwrap();
+        assert_eq!(total(&selection), 4_000);
+    }
+
+    #[test]
+    fn bnb_accepts_match_within_cost_of_change() {
+        let set = utxos(&[7_000, 4_100, 2_500]);
+        let selection =
+            branch_and_bound(&set, Amount::from_sat(4_000), Amount::from_sat(200)).unwrap();
+        assert_eq!(total(&selection), 4_100);
+    }
+
+    #[test]
+    fn bnb_returns_none_without_changeless_solution() {
+        let set = utxos(&[7_000, 5_000]);
+        assert!(branch_and_bound(&set, Amount::from_sat(4_000), Amount::from_sat(100)).is_none());
+    }
+
+    #[test]
+    fn bnb_searches_deep_utxo_sets_without_overflowing() {
+        let set = utxos(&vec![1; 50_000]);
+        let selection = branch_and_bound(&set, Amount::from_sat(50_000), Amount::ZERO).unwrap();
+        assert_eq!(selection.len(), 50_000);
+    }
+
+    #[test]
+    fn select_coins_falls_back_to_greedy() {
+        let set = utxos(&[7_000, 5_000]);
+        let selection = select_coins(&set, Amount::from_sat(4_000), Amount::from_sat(100)).unwrap();
+        assert_eq!(total(&selection), 7_000);
+    }
+
+    #[test]
+    fn selection_fails_when_funds_are_insufficient() {
+        let set = utxos(&[1_000, 2_000]);
+        assert!(select_coins(&set, Amount::from_sat(5_000), Amount::from_sat(100)).is_none());
+    }
+
+    #[test]
+    fn greedy_picks_largest_first() {
+        let set = utxos(&[1_000, 9_000, 3_000]);
+        let selection = greedy(&set, Amount::from_sat(10_000)).unwrap();
+        assert_eq!(total(&selection), 12_000);
+        assert_eq!(selection[0].amount, Amount::from_sat(9_000));
+    }
+
+    #[test]
+    fn utxo_amount_accepts_btc_and_satoshis() {
+        let entries = serde_json::json!([
+            { "txid": "a", "vout": 0, "amount": 0.00001 },
+            { "txid": "b", "vout": 1, "amount": "0.00001000" },
+            { "txid": "c", "vout": 2, "amount": 1000 },
+        ]);
+        let parsed: Vec<Utxo> = serde_json::from_value(entries).unwrap();
+        assert!(parsed.iter().all(|u| u.amount == Amount::from_sat(1_000)));
+    }
+}
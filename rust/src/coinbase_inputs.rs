@@ -0,0 +1,371 @@
+// Funding a payment from block rewards only (`--require-coinbase-input`).
+//
+// The assignment's story is that the Miner spends a block reward, but the
+// wallet's own coin selection happily picks change from an earlier run
+// instead. Whether a UTXO's parent is a coinbase is answered by the wallet's
+// `gettransaction` (`"generated": true`); `CoinbaseResolver` caches the answer
+// per txid, since every output of a transaction shares it.
+use crate::coin_selection::{select_coins, Utxo};
+use crate::tx_category::TxCategory;
+use crate::wallet_lock::Wallet;
+use bitcoincore_rpc::bitcoin::{Address, Amount};
+use bitcoincore_rpc::{Error, RpcApi};
+use serde_json::json;
+use std::collections::HashMap;
+
+// Headroom over the payment left for the fee when choosing inputs
+pub const COINBASE_FEE_MARGIN: Amount = Amount::from_sat(10_000);
+// Selections within this of the target need no change output
+pub const COST_OF_CHANGE: Amount = Amount::from_sat(1_000);
+
+// What the wallet knows about the transaction that created an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrevoutOrigin {
+    pub coinbase: bool,
+    pub block_height: Option<u64>,
+}
+
+impl PrevoutOrigin {
+    // From a wallet `gettransaction` result
+    pub fn from_wallet_tx(tx: &serde_json::Value) -> Self {
+        let category = TxCategory::normalize(
+            tx["details"][0]["category"].as_str().unwrap_or(""),
+            tx["generated"].as_bool().unwrap_or(false),
+            tx["trusted"].as_bool(),
+        );
+        PrevoutOrigin {
+            coinbase: category.is_coinbase(),
+            block_height: tx["blockheight"].as_u64(),
+        }
+    }
+}
+
+// Looks up and caches the origin of each parent transaction.
+pub struct CoinbaseResolver<F> {
+    fetch: F,
+    cache: HashMap<String, PrevoutOrigin>,
+}
+
+impl<F> CoinbaseResolver<F>
+where
+    F: FnMut(&str) -> bitcoincore_rpc::Result<PrevoutOrigin>,
+{
+    pub fn new(fetch: F) -> Self {
+        CoinbaseResolver {
+            fetch,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn origin(&mut self, txid: &str) -> bitcoincore_rpc::Result<PrevoutOrigin> {
+        if let Some(origin) = self.cache.get(txid) {
+            return Ok(*origin);
+        }
+        let origin = (self.fetch)(txid)?;
+        self.cache.insert(txid.to_string(), origin);
+        Ok(origin)
+    }
+
+    // The coinbase UTXOs among `utxos`, with the height of the block that
+    // created each.
+    pub fn coinbase_utxos(&mut self, utxos: &[Utxo]) -> bitcoincore_rpc::Result<Vec<(Utxo, u64)>> {
+        let mut coinbases = Vec::new();
+        for utxo in utxos {
+            let origin = self.origin(&utxo.txid)?;
+            if let (true, Some(height)) = (origin.coinbase, origin.block_height) {
+                coinbases.push((utxo.clone(), height));
+            }
+        }
+        Ok(coinbases)
+    }
+}
+
+// A coinbase-funded input set for a payment of `amount`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinbaseSelection {
+    pub inputs: Vec<(Utxo, u64)>,
+}
+
+impl CoinbaseSelection {
+    // Height of the oldest block reward spent
+    pub fn oldest_height(&self) -> Option<u64> {
+        self.inputs.iter().map(|&(_, height)| height).min()
+    }
+}
+
+// Pick coinbase outputs covering `amount` plus the fee margin. `spendable`
+// counts every spendable UTXO, to tell the user what is there instead.
+pub fn select_coinbase_inputs(
+    coinbases: &[(Utxo, u64)],
+    spendable: usize,
+    amount: Amount,
+) -> Result<CoinbaseSelection, String> {
+    if coinbases.is_empty() {
+        return Err(format!(
+            "--require-coinbase-input: none of the {} spendable UTXO(s) is a block reward; \
+             mine more blocks and let them mature, or drop the flag to spend change",
+            spendable
+        ));
+    }
+    let target = amount + COINBASE_FEE_MARGIN;
+    let utxos: Vec<Utxo> = coinbases.iter().map(|(utxo, _)| utxo.clone()).collect();
+    let selected = select_coins(&utxos, target, COST_OF_CHANGE).ok_or_else(|| {
+        let total = utxos
+            .iter()
+            .fold(Amount::ZERO, |sum, utxo| sum + utxo.amount);
+        format!(
+            "--require-coinbase-input: mature block rewards total {} BTC, but {} BTC \
+             (payment plus fee margin) is needed",
+            total.to_btc(),
+            target.to_btc()
+        )
+    })?;
+    let inputs = selected
+        .into_iter()
+        .filter_map(|utxo| {
+            coinbases
+                .iter()
+                .find(|(candidate, _)| candidate.txid == utxo.txid && candidate.vout == utxo.vout)
+                .cloned()
+        })
+        .collect();
+    Ok(CoinbaseSelection { inputs })
+}
+
+// Helper function to list the block-reward UTXOs of `wallet`, with the number
+// of spendable UTXOs of any kind
+pub fn wallet_coinbase_utxos(
+    wallet: &impl RpcApi,
+) -> bitcoincore_rpc::Result<(Vec<(Utxo, u64)>, usize)> {
+    let utxos: Vec<Utxo> = wallet.call("listunspent", &[])?;
+    let mut resolver = CoinbaseResolver::new(|txid: &str| {
+        let tx = wallet.call::<serde_json::Value>("gettransaction", &[json!(txid)])?;
+        Ok(PrevoutOrigin::from_wallet_tx(&tx))
+    });
+    let coinbases = resolver.coinbase_utxos(&utxos)?;
+    Ok((coinbases, utxos.len()))
+}
+
+// Helper function to choose block-reward inputs from `wallet` for `amount`
+pub fn choose_coinbase_inputs(
+    wallet: &impl RpcApi,
+    amount: Amount,
+) -> bitcoincore_rpc::Result<CoinbaseSelection> {
+    let (coinbases, spendable) = wallet_coinbase_utxos(wallet)?;
+    select_coinbase_inputs(&coinbases, spendable, amount).map_err(Error::ReturnedError)
+}
+
+// Helper function to pay `amount` to `address` spending exactly the selected
+// inputs; the `send` RPC adds change but no further inputs
+pub fn send_from_inputs(
+    wallet: &Wallet<impl RpcApi>,
+    address: &Address,
+    amount: Amount,
+    selection: &CoinbaseSelection,
+) -> bitcoincore_rpc::Result<String> {
+    let inputs: Vec<_> = selection
+        .inputs
+        .iter()
+        .map(|(utxo, _)| json!({ "txid": utxo.txid, "vout": utxo.vout }))
+        .collect();
+    send_with_options(
+        wallet,
+        &address.to_string(),
+        amount,
+        json!({ "inputs": inputs, "add_inputs": false }),
+    )
+}
+
+// Helper function to pay `amount` to `address` with the `send` RPC and the
+// given funding `options`
+pub fn send_with_options(
+    wallet: &Wallet<impl RpcApi>,
+    address: &str,
+    amount: Amount,
+    options: serde_json::Value,
+) -> bitcoincore_rpc::Result<String> {
+    let args = [
+        json!([{ address: amount.to_btc() }]), // outputs
+        json!(null),                           // conf target
+        json!(null),                           // estimate mode
+        json!(null),                           // fee rate
+        options,
+    ];
+    let result = wallet.call::<serde_json::Value>("send", &args)?;
+    result["txid"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| Error::ReturnedError(format!("send returned no txid: {}", result)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(txid: &str, vout: u32, btc: u64) -> Utxo {
+        Utxo {
+            txid: txid.to_string(),
+            vout,
+            amount: Amount::from_int_btc(btc),
+        }
+    }
+
+    // A Miner wallet after one earlier run: two matured rewards, plus change
+    // and a received payment from a regular transaction
+    fn wallet() -> (Vec<Utxo>, HashMap<&'static str, PrevoutOrigin>) {
+        let utxos = vec![
+            utxo("change", 1, 29),
+            utxo("reward1", 0, 50),
+            utxo("received", 0, 5),
+            utxo("reward2", 0, 50),
+            utxo("change", 2, 1),
+        ];
+        let coinbase = |height| PrevoutOrigin {
+            coinbase: true,
+            block_height: Some(height),
+        };
+        let regular = PrevoutOrigin {
+            coinbase: false,
+            block_height: Some(103),
+        };
+        let origins = HashMap::from([
+            ("reward1", coinbase(1)),
+            ("reward2", coinbase(2)),
+            ("change", regular),
+            ("received", regular),
+        ]);
+        (utxos, origins)
+    }
+
+    #[test]
+    fn only_coinbase_outputs_are_kept() {
+        let (utxos, origins) = wallet();
+        let mut resolver = CoinbaseResolver::new(|txid: &str| Ok(origins[txid]));
+        let coinbases = resolver.coinbase_utxos(&utxos).unwrap();
+        let kept: Vec<(&str, u64)> = coinbases
+            .iter()
+            .map(|(utxo, height)| (utxo.txid.as_str(), *height))
+            .collect();
+        assert_eq!(kept, vec![("reward1", 1), ("reward2", 2)]);
+    }
+
+    #[test]
+    fn resolver_caches_per_txid() {
+        let (utxos, origins) = wallet();
+        let mut lookups = Vec::new();
+        let mut resolver = CoinbaseResolver::new(|txid: &str| {
+            lookups.push(txid.to_string());
+            Ok(origins[txid])
+        });
+        resolver.coinbase_utxos(&utxos).unwrap();
+        resolver.coinbase_utxos(&utxos).unwrap();
+        drop(resolver);
+        assert_eq!(lookups, vec!["change", "reward1", "received", "reward2"]);
+    }
+
+    #[test]
+    fn selection_spends_a_block_reward_not_change() {
+        let (utxos, origins) = wallet();
+        let coinbases = CoinbaseResolver::new(|txid: &str| Ok(origins[txid]))
+            .coinbase_utxos(&utxos)
+            .unwrap();
+        let selection =
+            select_coinbase_inputs(&coinbases, utxos.len(), Amount::from_int_btc(20)).unwrap();
+        assert_eq!(selection.inputs.len(), 1);
+        assert!(selection.inputs[0].0.txid.starts_with("reward"));
+        assert!(selection.oldest_height().unwrap() <= 2);
+    }
+
+    #[test]
+    fn large_payment_combines_rewards() {
+        let (utxos, origins) = wallet();
+        let coinbases = CoinbaseResolver::new(|txid: &str| Ok(origins[txid]))
+            .coinbase_utxos(&utxos)
+            .unwrap();
+        let selection =
+            select_coinbase_inputs(&coinbases, utxos.len(), Amount::from_int_btc(80)).unwrap();
+        assert_eq!(selection.inputs.len(), 2);
+        assert_eq!(selection.oldest_height(), Some(1));
+
+        let err =
+            select_coinbase_inputs(&coinbases, utxos.len(), Amount::from_int_btc(100)).unwrap_err();
+        assert!(err.contains("block rewards total 100 BTC"), "{}", err);
+    }
+
+    #[test]
+    fn no_coinbase_outputs_is_explained() {
+        let err = select_coinbase_inputs(&[], 3, Amount::from_int_btc(20)).unwrap_err();
+        assert!(err.contains("none of the 3 spendable UTXO(s)"), "{}", err);
+    }
+
+    #[test]
+    fn origin_from_gettransaction() {
+        let generated = json!({ "generated": true, "blockheight": 7, "amount": 50.0 });
+        assert_eq!(
+            PrevoutOrigin::from_wallet_tx(&generated),
+            PrevoutOrigin {
+                coinbase: true,
+                block_height: Some(7)
+            }
+        );
+        let immature = json!({ "details": [{ "category": "immature" }], "blockheight": 8 });
+        assert!(PrevoutOrigin::from_wallet_tx(&immature).coinbase);
+        let unconfirmed = json!({ "amount": -20.0, "confirmations": 0, "trusted": true });
+        assert_eq!(
+            PrevoutOrigin::from_wallet_tx(&unconfirmed),
+            PrevoutOrigin {
+                coinbase: false,
+                block_height: None
+            }
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn spends_a_reward_with_change_in_the_wallet() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let name = format!("Coinbase-test-{}", std::process::id());
+        create_or_load_wallet(&rpc, &config, &name, true).unwrap();
+        let wallet = get_wallet_client(&config, &name).unwrap();
+        let address = wallet.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &address, 101).unwrap();
+        // Leave a change output behind, then confirm it
+        let own = wallet.get_new_address(None, None).unwrap().assume_checked();
+        wallet
+            .send_to_address(
+                &own,
+                Amount::from_int_btc(1),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        mine_blocks_to_address(&rpc, &address, 1).unwrap();
+
+        let selection = choose_coinbase_inputs(&wallet, Amount::from_int_btc(20)).unwrap();
+        let txid = send_from_inputs(
+            &Wallet::new(&name, &wallet),
+            &own,
+            Amount::from_int_btc(20),
+            &selection,
+        )
+        .unwrap();
+        let tx = wallet
+            .call::<serde_json::Value>("gettransaction", &[json!(txid), json!(false), json!(true)])
+            .unwrap();
+        for input in tx["decoded"]["vin"].as_array().unwrap() {
+            let parent = wallet
+                .call::<serde_json::Value>("gettransaction", &[input["txid"].clone()])
+                .unwrap();
+            assert_eq!(parent["generated"], true);
+        }
+        rpc.unload_wallet(Some(&name)).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+// Sending the payment's change to cold storage: `--change-to-descriptor`.
+//
+// Instead of going back to the Miner wallet, the change is paid to an address
+// derived from an external descriptor, as if it were swept to a cold wallet.
+// A ranged descriptor (one with a `*`) gives a fresh address every run: the
+// next unused index of each descriptor is kept in a state file and only
+// advanced once a payment has been sent to it. The node derives the address
+// with `deriveaddresses`, which imports nothing into any wallet, so neither
+// the Miner nor the Trader may own it; `check_cold_change` verifies that.
+use crate::descriptor::with_valid_checksum;
+use crate::rpc_call;
+use bitcoincore_rpc::{Error, RpcApi};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const CHANGE_STATE_PATH: &str = "changestate.json";
+
+// The next unused index of every ranged descriptor, keyed by the descriptor
+// with its checksum
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct ChangeState {
+    next_indexes: BTreeMap<String, u32>,
+}
+
+fn load_state(path: &Path) -> Result<ChangeState, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ChangeState::default()),
+        Err(e) => Err(format!("cannot read {}: {}", path.display(), e)),
+    }
+}
+
+// Whether `descriptor` derives a range of addresses rather than a single one
+pub fn is_ranged(descriptor: &str) -> bool {
+    descriptor.contains('*')
+}
+
+// The index the change of this run is derived at: the next unused one for a
+// ranged descriptor, 0 for any other
+pub fn next_index(path: &Path, descriptor: &str) -> Result<u32, String> {
+    if !is_ranged(descriptor) {
+        return Ok(0);
+    }
+    let descriptor = with_valid_checksum(descriptor)?;
+    Ok(load_state(path)?
+        .next_indexes
+        .get(&descriptor)
+        .copied()
+        .unwrap_or(0))
+}
+
+// Record that `index` of a ranged `descriptor` has received change, so the
+// next run derives past it
+pub fn mark_used(path: &Path, descriptor: &str, index: u32) -> Result<(), String> {
+    if !is_ranged(descriptor) {
+        return Ok(());
+    }
+    let descriptor = with_valid_checksum(descriptor)?;
+    let mut state = load_state(path)?;
+    let next = state.next_indexes.entry(descriptor).or_insert(0);
+    *next = (*next).max(index + 1);
+    let json = serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?;
+    fs::write(path, json + "\n").map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+// Helper function to derive the address at `index` of `descriptor`
+pub fn derive_change_address(
+    rpc: &impl RpcApi,
+    descriptor: &str,
+    index: u32,
+) -> bitcoincore_rpc::Result<String> {
+    let descriptor = with_valid_checksum(descriptor).map_err(Error::ReturnedError)?;
+    let mut args = vec![json!(descriptor)];
+    if is_ranged(&descriptor) {
+        args.push(json!([index, index]));
+    }
+    let addresses = rpc_call::<Vec<String>>(rpc, "deriveaddresses", &args)?;
+    addresses.into_iter().next().ok_or_else(|| {
+        Error::ReturnedError(format!(
+            "descriptor {} derives no address at index {}",
+            descriptor, index
+        ))
+    })
+}
+
+// Check that the change went to the `expected` cold-storage address and that
+// none of `owners`, (wallet name, whether it owns the change address) pairs,
+// owns it
+pub fn check_cold_change(
+    change_address: &str,
+    expected: &str,
+    owners: &[(&str, bool)],
+) -> Result<(), String> {
+    if change_address != expected {
+        return Err(format!(
+            "the change went to {} instead of the cold-storage address {}",
+            change_address, expected
+        ));
+    }
+    let owning: Vec<&str> = owners
+        .iter()
+        .filter(|(_, owns)| *owns)
+        .map(|(wallet, _)| *wallet)
+        .collect();
+    if !owning.is_empty() {
+        return Err(format!(
+            "the cold-storage change address {} is owned by wallet(s) {}",
+            change_address,
+            owning.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RANGED: &str = "wpkh(tpubD6NzVbkrYhZ4WaWSyoBvQwbpLkojyoTZPRsgXELWz3Popb3qkjcJyJUGLnL4qHHoQvao8ESaAstxYSnhyswJ76uZPStJRJCTKvosUCJZL5B/0/*)";
+    const SINGLE: &str = "wpkh(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798)";
+
+    fn state_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn ranged_indexes_advance_across_runs() {
+        let path = state_path("change-state");
+        // First run
+        assert_eq!(next_index(&path, RANGED).unwrap(), 0);
+        mark_used(&path, RANGED, 0).unwrap();
+        // Second run, reading the state file the first one wrote
+        assert_eq!(next_index(&path, RANGED).unwrap(), 1);
+        mark_used(&path, RANGED, 1).unwrap();
+        assert_eq!(next_index(&path, RANGED).unwrap(), 2);
+        // With or without its checksum it is the same descriptor
+        let checksummed = with_valid_checksum(RANGED).unwrap();
+        assert_eq!(next_index(&path, &checksummed).unwrap(), 2);
+        // Marking an older index never moves the next one back
+        mark_used(&path, RANGED, 0).unwrap();
+        assert_eq!(next_index(&path, RANGED).unwrap(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unranged_descriptors_always_use_index_zero() {
+        let path = state_path("change-state-single");
+        assert!(!is_ranged(SINGLE));
+        mark_used(&path, SINGLE, 0).unwrap();
+        assert_eq!(next_index(&path, SINGLE).unwrap(), 0);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_broken_state_file_is_an_error() {
+        let path = state_path("change-state-broken");
+        fs::write(&path, "{ not json").unwrap();
+        assert!(next_index(&path, RANGED).is_err());
+        assert!(mark_used(&path, RANGED, 0).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cold_change_is_owned_by_no_wallet() {
+        let owners = [("Miner", false), ("Trader", false)];
+        assert!(check_cold_change("bcrt1qcold", "bcrt1qcold", &owners).is_ok());
+
+        let err = check_cold_change("bcrt1qchange", "bcrt1qcold", &owners).unwrap_err();
+        assert!(
+            err.contains("instead of the cold-storage address"),
+            "{}",
+            err
+        );
+
+        let owned = [("Miner", true), ("Trader", false)];
+        let err = check_cold_change("bcrt1qcold", "bcrt1qcold", &owned).unwrap_err();
+        assert!(err.ends_with("owned by wallet(s) Miner"), "{}", err);
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn each_index_derives_its_own_address() {
+        use crate::rpc_config::RpcConfig;
+
+        let rpc = RpcConfig::load().unwrap().client().unwrap();
+        let first = derive_change_address(&rpc, RANGED, 0).unwrap();
+        let second = derive_change_address(&rpc, RANGED, 1).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(derive_change_address(&rpc, RANGED, 0).unwrap(), first);
+        assert_eq!(
+            derive_change_address(&rpc, SINGLE, 7).unwrap(),
+            derive_change_address(&rpc, SINGLE, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,173 @@
+// Run settings from a TOML file (`--config <path>`).
+//
+// Every key of the file stands for the command-line flag of the same name, so
+// the file is merged by appending those flags after the real command line,
+// leaving out any flag the command line already gives. Flags given on the
+// command line thus win over the file, and the file wins over the environment
+// and the defaults.
+use crate::arg_value;
+use serde::Deserialize;
+use std::fs;
+
+/// Settings file for the capstone run, e.g.
+///
+/// ```toml
+/// # RPC access, as --rpc-url, --rpc-user, --rpc-pass and --cookie-file
+/// rpc_url = "http://127.0.0.1:18443"
+/// rpc_user = "alice"
+/// rpc_pass = "password"
+/// cookie_file = "/path/to/.cookie"  # instead of rpc_user and rpc_pass
+///
+/// # Workflow, as the flags of the same name
+/// network = "regtest"           # regtest or signet
+/// miner_wallet = "Miner"
+/// trader_wallet = "Trader"
+/// amount = 20                   # BTC; an integer, a float or a string
+/// confirm_to = "burn"           # miner, burn or thirdwallet
+/// out = "../out.txt"
+/// format = "text"               # text, json or csv
+/// ```
+///
+/// Every key is optional and unknown keys are rejected.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub rpc_url: Option<String>,
+    pub rpc_user: Option<String>,
+    pub rpc_pass: Option<String>,
+    pub cookie_file: Option<String>,
+    pub network: Option<String>,
+    pub miner_wallet: Option<String>,
+    pub trader_wallet: Option<String>,
+    pub amount: Option<toml::Value>,
+    pub confirm_to: Option<String>,
+    pub out: Option<String>,
+    pub format: Option<String>,
+}
+
+impl ConfigFile {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.message().to_string())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config '{}': {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // The settings as `--flag=value` arguments
+    pub fn to_args(&self) -> Result<Vec<String>, String> {
+        let amount = match &self.amount {
+            None => None,
+            Some(toml::Value::String(s)) => Some(s.clone()),
+            Some(toml::Value::Integer(n)) => Some(n.to_string()),
+            Some(toml::Value::Float(x)) => Some(x.to_string()),
+            Some(other) => return Err(format!("amount must be a number, found {}", other)),
+        };
+        let settings = [
+            ("--rpc-url", self.rpc_url.clone()),
+            ("--rpc-user", self.rpc_user.clone()),
+            ("--rpc-pass", self.rpc_pass.clone()),
+            ("--cookie-file", self.cookie_file.clone()),
+            ("--network", self.network.clone()),
+            ("--miner-wallet", self.miner_wallet.clone()),
+            ("--trader-wallet", self.trader_wallet.clone()),
+            ("--amount", amount),
+            ("--confirm-to", self.confirm_to.clone()),
+            ("--out", self.out.clone()),
+            ("--format", self.format.clone()),
+        ];
+        Ok(settings
+            .into_iter()
+            .filter_map(|(flag, value)| value.map(|value| format!("{}={}", flag, value)))
+            .collect())
+    }
+}
+
+// Append the settings of `file` that `args` does not give to the command line
+pub fn merge_args(args: &[String], file: &ConfigFile) -> Result<Vec<String>, String> {
+    let given = |setting: &str| {
+        let flag = setting.split_once('=').map_or(setting, |(flag, _)| flag);
+        // --send-amount is another name for --amount
+        let names: &[&str] = match flag {
+            "--amount" => &["--amount", "--send-amount"],
+            _ => &[flag],
+        };
+        args.iter().any(|arg| {
+            names
+                .iter()
+                .any(|name| arg == name || arg.starts_with(&format!("{}=", name)))
+        })
+    };
+    let mut merged = args.to_vec();
+    merged.extend(
+        file.to_args()?
+            .into_iter()
+            .filter(|setting| !given(setting)),
+    );
+    Ok(merged)
+}
+
+// Helper function to apply the `--config <path>` file, if any, to `args`
+pub fn apply_config_file(args: &[String]) -> Result<Vec<String>, String> {
+    match arg_value(args, "--config") {
+        Some(path) => merge_args(args, &ConfigFile::load(path)?),
+        None => Ok(args.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_config::RpcConfig;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn command_line_wins_over_the_file() {
+        let file =
+            ConfigFile::parse("rpc_user = \"bob\"\nrpc_url = \"http://lab:18443\"\n").unwrap();
+        let args = merge_args(&argv(&["--rpc-user", "carol"]), &file).unwrap();
+        let config = RpcConfig::default().with_args(&args);
+        assert_eq!(config.user, "carol");
+        assert_eq!(config.url, "http://lab:18443");
+        assert_eq!(config.pass, RpcConfig::default().pass);
+    }
+
+    #[test]
+    fn workflow_settings_reach_the_run_options() {
+        let file =
+            ConfigFile::parse("miner_wallet = \"Alice\"\namount = 2.5\nout = \"/tmp/run.txt\"\n")
+                .unwrap();
+        let args = merge_args(&argv(&["--amount=3"]), &file).unwrap();
+        assert_eq!(args.len(), 3);
+        assert_eq!(
+            merge_args(&argv(&["--send-amount", "3"]), &file)
+                .unwrap()
+                .len(),
+            4
+        );
+        let config = crate::cli::parse_config(&args).unwrap();
+        assert_eq!(config.miner_wallet, "Alice");
+        assert_eq!(config.trader_wallet, "Trader");
+        assert_eq!(config.send_amount_btc.to_btc(), 3.0);
+        assert_eq!(config.out_path(), std::path::PathBuf::from("/tmp/run.txt"));
+    }
+
+    #[test]
+    fn bad_files_are_errors() {
+        let err = ConfigFile::parse("rpc_usr = \"bob\"\n").unwrap_err();
+        assert!(err.contains("rpc_usr"), "{}", err);
+        let file = ConfigFile::parse("amount = [1]\n").unwrap();
+        assert!(file.to_args().is_err());
+        let err = apply_config_file(&argv(&["--config", "/nonexistent/run.toml"])).unwrap_err();
+        assert!(err.contains("/nonexistent/run.toml"), "{}", err);
+        assert_eq!(
+            apply_config_file(&argv(&["report"])).unwrap(),
+            argv(&["report"])
+        );
+    }
+}
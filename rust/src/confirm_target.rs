@@ -0,0 +1,217 @@
+// Where the block that confirms the payment is mined to (`--confirm-to`).
+//
+// Mining it to the Miner adds one more immature block reward to the wallet
+// whose balance the run checks. By default it goes to an OP_TRUE output
+// instead (`burn`); `thirdwallet` pays it to the dedicated Fee-Sink wallet
+// and `miner` keeps the old behaviour. Either way the confirmation block also
+// matures the Miner's reward from `maturity` blocks earlier, so the expected
+// balances account for that too.
+use bitcoincore_rpc::bitcoin::Amount;
+
+pub const FEE_SINK_WALLET: &str = "Fee-Sink";
+// Anyone-can-spend output script: OP_TRUE
+pub const BURN_DESCRIPTOR: &str = "raw(51)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConfirmTarget {
+    Miner,
+    #[default]
+    Burn,
+    ThirdWallet,
+}
+
+impl ConfirmTarget {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "miner" => Ok(ConfirmTarget::Miner),
+            "burn" => Ok(ConfirmTarget::Burn),
+            "thirdwallet" => Ok(ConfirmTarget::ThirdWallet),
+            other => Err(format!(
+                "invalid --confirm-to value '{}': expected miner, burn or thirdwallet",
+                other
+            )),
+        }
+    }
+
+    // Who receives the confirmation block reward
+    pub fn reward_owner<'a>(&self, miner_wallet: &'a str) -> &'a str {
+        match self {
+            ConfirmTarget::Miner => miner_wallet,
+            ConfirmTarget::Burn => "OP_TRUE output (burned)",
+            ConfirmTarget::ThirdWallet => FEE_SINK_WALLET,
+        }
+    }
+}
+
+// The Miner wallet's `getbalances` figures the check compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinerBalances {
+    pub trusted: Amount,
+    pub immature: Amount,
+}
+
+// The Miner's balances once the payment is confirmed, from those just before
+// it was sent: the payment and fee leave the trusted balance (the change comes
+// back), the reward `matured` by the confirmation block moves from immature
+// to trusted, and `confirm_reward` is new immature income only when the Miner
+// mined the confirmation block.
+pub fn expected_miner_balances(
+    before: MinerBalances,
+    sent: Amount,
+    fee: Amount,
+    matured: Amount,
+    confirm_reward: Amount,
+    target: ConfirmTarget,
+) -> Result<MinerBalances, String> {
+    let trusted = (before.trusted + matured)
+        .checked_sub(sent + fee)
+        .ok_or_else(|| {
+            format!(
+                "the Miner sent {} plus a {} fee from a trusted balance of {}",
+                sent, fee, before.trusted
+            )
+        })?;
+    let mut immature = before.immature.checked_sub(matured).ok_or_else(|| {
+        format!(
+            "{} matured but only {} was immature",
+            matured, before.immature
+        )
+    })?;
+    if target == ConfirmTarget::Miner {
+        immature += confirm_reward;
+    }
+    Ok(MinerBalances { trusted, immature })
+}
+
+// Differences between the expected and actual balances, empty when they agree
+pub fn balance_discrepancies(expected: MinerBalances, actual: MinerBalances) -> Vec<String> {
+    [
+        ("trusted", expected.trusted, actual.trusted),
+        ("immature", expected.immature, actual.immature),
+    ]
+    .into_iter()
+    .filter(|(_, expected, actual)| expected != actual)
+    .map(|(name, expected, actual)| {
+        format!(
+            "{} balance is {} BTC, expected {} BTC",
+            name,
+            actual.to_btc(),
+            expected.to_btc()
+        )
+    })
+    .collect()
+}
+
+// Block rewards earned during the run, by recipient: the `mined` funding
+// blocks go to the Miner, the confirmation block to the target's owner.
+pub fn mining_income(miner_wallet: &str, mined: u64, target: ConfirmTarget) -> Vec<(&str, u64)> {
+    match target {
+        ConfirmTarget::Miner => vec![(miner_wallet, mined + 1)],
+        _ => vec![
+            (miner_wallet, mined),
+            (target.reward_owner(miner_wallet), 1),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn btc(value: u64) -> Amount {
+        Amount::from_int_btc(value)
+    }
+
+    // After mining 101 blocks: block 1 is mature, blocks 2-101 are not
+    fn before() -> MinerBalances {
+        MinerBalances {
+            trusted: btc(50),
+            immature: btc(5000),
+        }
+    }
+
+    const FEE: Amount = Amount::from_sat(1_410);
+
+    #[test]
+    fn parses_the_three_targets() {
+        assert_eq!(ConfirmTarget::parse("miner"), Ok(ConfirmTarget::Miner));
+        assert_eq!(ConfirmTarget::parse("burn"), Ok(ConfirmTarget::Burn));
+        assert_eq!(
+            ConfirmTarget::parse("thirdwallet"),
+            Ok(ConfirmTarget::ThirdWallet)
+        );
+        assert!(ConfirmTarget::parse("Miner").is_err());
+        assert_eq!(ConfirmTarget::default(), ConfirmTarget::Burn);
+    }
+
+    #[test]
+    fn mining_to_the_miner_adds_an_immature_reward() {
+        let reward = btc(50) + FEE;
+        let expected = expected_miner_balances(
+            before(),
+            btc(20),
+            FEE,
+            btc(50),
+            reward,
+            ConfirmTarget::Miner,
+        )
+        .unwrap();
+        assert_eq!(expected.trusted, btc(80) - FEE);
+        assert_eq!(expected.immature, btc(5000) + FEE);
+    }
+
+    #[test]
+    fn neutral_targets_only_mature_the_old_reward() {
+        let reward = btc(50) + FEE;
+        for target in [ConfirmTarget::Burn, ConfirmTarget::ThirdWallet] {
+            let expected =
+                expected_miner_balances(before(), btc(20), FEE, btc(50), reward, target).unwrap();
+            assert_eq!(expected.trusted, btc(80) - FEE);
+            assert_eq!(expected.immature, btc(4950));
+        }
+    }
+
+    #[test]
+    fn overspending_is_an_error() {
+        let err = expected_miner_balances(
+            before(),
+            btc(50),
+            FEE,
+            Amount::ZERO,
+            btc(50),
+            ConfirmTarget::Burn,
+        )
+        .unwrap_err();
+        assert!(err.contains("trusted balance"), "{}", err);
+    }
+
+    #[test]
+    fn discrepancies_name_the_balance() {
+        let expected = before();
+        assert!(balance_discrepancies(expected, expected).is_empty());
+        let actual = MinerBalances {
+            immature: btc(5050),
+            ..expected
+        };
+        assert_eq!(
+            balance_discrepancies(expected, actual),
+            vec!["immature balance is 5050 BTC, expected 5000 BTC".to_string()]
+        );
+    }
+
+    #[test]
+    fn confirmation_reward_is_attributed_to_its_owner() {
+        assert_eq!(
+            mining_income("Miner", 101, ConfirmTarget::Miner),
+            vec![("Miner", 102)]
+        );
+        assert_eq!(
+            mining_income("Miner", 101, ConfirmTarget::ThirdWallet),
+            vec![("Miner", 101), ("Fee-Sink", 1)]
+        );
+        assert_eq!(
+            mining_income("Alice", 101, ConfirmTarget::Burn),
+            vec![("Alice", 101), ("OP_TRUE output (burned)", 1)]
+        );
+    }
+}
@@ -0,0 +1,455 @@
+// Child-pays-for-parent with an exact fee.
+//
+// Miners judge an unconfirmed parent and its child as one package, at the
+// package's combined fee rate. `calculate_cpfp_fee` gives the child fee that
+// lifts that rate to a target, and `create_exact_cpfp` spends one of the
+// parent's outputs to a recipient paying exactly that fee.
+//
+// `build_chain` extends this to more generations, e.g. a low-fee grandparent,
+// a medium-fee parent and a high-fee child, each spending its predecessor's
+// unconfirmed change. The mempool must then list every older generation as an
+// ancestor and every newer one as a descendant (`check_chain_relatives`), and
+// mining takes the whole package into one block (`check_confirmed_together`).
+use crate::get_mempool_entry;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Error, RpcApi};
+use serde_json::json;
+
+// The fee (sat) a child of `child_vsize` must pay so that it and its parent
+// together pay `target_rate_sat_vb`: (parent_vsize + child_vsize) * target
+// rate, rounded up, less what the parent already pays. 0 when the parent's fee
+// alone already covers the package at the target rate.
+pub fn calculate_cpfp_fee(
+    parent_vsize: u32,
+    parent_fee_sat: u64,
+    child_vsize: u32,
+    target_rate_sat_vb: f64,
+) -> u64 {
+    let package_vsize = f64::from(parent_vsize) + f64::from(child_vsize);
+    let required_total_fee = (package_vsize * target_rate_sat_vb).ceil() as u64;
+    required_total_fee.saturating_sub(parent_fee_sat)
+}
+
+// Helper function to build and sign a child spending `parent_txid:vout` and
+// paying `amount` to `recipient`, returning the signed hex and its vsize
+fn sign_child(
+    rpc: &impl RpcApi,
+    parent_txid: &str,
+    vout: u32,
+    recipient: &str,
+    amount: Amount,
+) -> bitcoincore_rpc::Result<(String, u32)> {
+    sign_spend(
+        rpc,
+        parent_txid,
+        vout,
+        json!([{ recipient: amount.to_btc() }]),
+    )
+}
+
+// Helper function to build and sign a transaction spending `txid:vout` to
+// `outputs`, in `createrawtransaction` form, returning the signed hex and its
+// vsize
+fn sign_spend(
+    rpc: &impl RpcApi,
+    txid: &str,
+    vout: u32,
+    outputs: serde_json::Value,
+) -> bitcoincore_rpc::Result<(String, u32)> {
+    let inputs = json!([{ "txid": txid, "vout": vout }]);
+    let raw = rpc.call::<String>("createrawtransaction", &[inputs, outputs])?;
+    let signed = rpc.call::<serde_json::Value>("signrawtransactionwithwallet", &[json!(raw)])?;
+    if signed["complete"].as_bool() != Some(true) {
+        return Err(Error::ReturnedError(format!(
+            "wallet could not sign the spend of {}:{}: {}",
+            txid, vout, signed["errors"]
+        )));
+    }
+    let hex = signed["hex"]
+        .as_str()
+        .ok_or_else(|| Error::ReturnedError("the signed transaction has no hex".to_string()))?
+        .to_string();
+    let decoded = rpc.call::<serde_json::Value>("decoderawtransaction", &[json!(hex)])?;
+    let vsize = decoded["vsize"]
+        .as_u64()
+        .ok_or_else(|| Error::ReturnedError("the decoded transaction has no vsize".to_string()))?;
+    Ok((hex, vsize as u32))
+}
+
+// Helper function to spend output `vout` of the unconfirmed `parent_txid` to
+// `recipient`, with the child fee that brings the parent and child package to
+// `target_rate` sat/vB. `rpc` must be a wallet client that can sign the output.
+// Returns the child's txid.
+pub fn create_exact_cpfp(
+    rpc: &impl RpcApi,
+    parent_txid: &str,
+    vout: u32,
+    recipient: &str,
+    target_rate: f64,
+) -> bitcoincore_rpc::Result<String> {
+    let entry = get_mempool_entry(rpc, parent_txid)?;
+    let (parent_vsize, parent_fee) = (entry.vsize, entry.fees.base);
+    let txout =
+        rpc.call::<serde_json::Value>("gettxout", &[json!(parent_txid), json!(vout), json!(true)])?;
+    if txout.is_null() {
+        return Err(Error::ReturnedError(format!(
+            "{}:{} is not an unspent output",
+            parent_txid, vout
+        )));
+    }
+    let value = Amount::from_btc(txout["value"].as_f64().unwrap_or(0.0))?;
+
+    // Measure the child without a fee, then again with it, until the signature
+    // no longer comes out larger than the size the fee was computed for
+    let (_, mut child_vsize) = sign_child(rpc, parent_txid, vout, recipient, value)?;
+    loop {
+        let fee = Amount::from_sat(calculate_cpfp_fee(
+            parent_vsize as u32,
+            parent_fee.to_sat(),
+            child_vsize,
+            target_rate,
+        ));
+        let amount = value.checked_sub(fee).ok_or_else(|| {
+            Error::ReturnedError(format!(
+                "output {}:{} holds {} but the child fee is {}",
+                parent_txid, vout, value, fee
+            ))
+        })?;
+        let (hex, vsize) = sign_child(rpc, parent_txid, vout, recipient, amount)?;
+        if vsize <= child_vsize {
+            return rpc.call::<String>("sendrawtransaction", &[json!(hex)]);
+        }
+        child_vsize = vsize;
+    }
+}
+
+// One transaction of an ancestor package
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageTx {
+    pub txid: String,
+    pub vsize: u64,
+    pub fee: Amount,
+}
+
+impl PackageTx {
+    // sat/vB
+    pub fn fee_rate(&self) -> f64 {
+        self.fee.to_sat() as f64 / self.vsize as f64
+    }
+}
+
+// Fee rate in sat/vB of `txs` taken together: their total fee over their
+// total vsize
+pub fn package_fee_rate(txs: &[PackageTx]) -> f64 {
+    let fee: u64 = txs.iter().map(|tx| tx.fee.to_sat()).sum();
+    let vsize: u64 = txs.iter().map(|tx| tx.vsize).sum();
+    if vsize == 0 {
+        return 0.0;
+    }
+    fee as f64 / vsize as f64
+}
+
+// The fee rate of each generation, oldest first, and of the whole package
+pub fn render_package(txs: &[PackageTx]) -> String {
+    let mut lines = vec![format!(
+        "{:>3}  {:<64}  {:>6}  {:>10}  {:>8}",
+        "gen", "txid", "vsize", "fee sat", "sat/vB"
+    )];
+    for (generation, tx) in txs.iter().enumerate() {
+        lines.push(format!(
+            "{:>3}  {:<64}  {:>6}  {:>10}  {:>8.3}",
+            generation + 1,
+            tx.txid,
+            tx.vsize,
+            tx.fee.to_sat(),
+            tx.fee_rate()
+        ));
+    }
+    lines.push(format!(
+        "{:>3}  {:<64}  {:>6}  {:>10}  {:>8.3}",
+        "all",
+        "package",
+        txs.iter().map(|tx| tx.vsize).sum::<u64>(),
+        txs.iter().map(|tx| tx.fee.to_sat()).sum::<u64>(),
+        package_fee_rate(txs)
+    ));
+    lines.join("\n")
+}
+
+// Helper function to sign a spend of `txid:vout`, which holds `value`, paying
+// `payment` to `recipient` and the rest to `change` less a fee of `fee_rate`
+// sat/vB. The payment is output 0 and the change output 1. Returns the signed
+// hex, its vsize and its fee.
+pub(crate) fn sign_at_rate(
+    rpc: &impl RpcApi,
+    (txid, vout): (&str, u32),
+    value: Amount,
+    (recipient, payment): (&str, Amount),
+    change: &str,
+    fee_rate: f64,
+) -> bitcoincore_rpc::Result<(String, u64, Amount)> {
+    let outputs = |fee: Amount| -> bitcoincore_rpc::Result<serde_json::Value> {
+        let rest = value
+            .checked_sub(payment)
+            .and_then(|rest| rest.checked_sub(fee))
+            .ok_or_else(|| {
+                Error::ReturnedError(format!(
+                    "{}:{} holds {} but pays {} with a fee of {}",
+                    txid, vout, value, payment, fee
+                ))
+            })?;
+        Ok(json!([{ recipient: payment.to_btc() }, { change: rest.to_btc() }]))
+    };
+
+    // As in `create_exact_cpfp`: sign until the signature no longer comes out
+    // larger than the size the fee was computed for
+    let (_, mut vsize) = sign_spend(rpc, txid, vout, outputs(Amount::ZERO)?)?;
+    loop {
+        let fee = Amount::from_sat((f64::from(vsize) * fee_rate).ceil() as u64);
+        let (hex, signed_vsize) = sign_spend(rpc, txid, vout, outputs(fee)?)?;
+        if signed_vsize <= vsize {
+            return Ok((hex, u64::from(signed_vsize), fee));
+        }
+        vsize = signed_vsize;
+    }
+}
+
+// Helper function to build a chain of unconfirmed transactions from wallet
+// client `rpc`, one per rate in `fee_rates` (sat/vB), oldest first. The first
+// spends the outpoint `funding`; each pays `payment` to `recipient` and the
+// rest to a fresh change address, and the next one spends that change.
+pub fn build_chain(
+    rpc: &impl RpcApi,
+    funding: (&str, u32),
+    (recipient, payment): (&str, Amount),
+    fee_rates: &[f64],
+) -> bitcoincore_rpc::Result<Vec<PackageTx>> {
+    let (txid, vout) = funding;
+    let txout =
+        rpc.call::<serde_json::Value>("gettxout", &[json!(txid), json!(vout), json!(true)])?;
+    if txout.is_null() {
+        return Err(Error::ReturnedError(format!(
+            "{}:{} is not an unspent output",
+            txid, vout
+        )));
+    }
+    let mut value = Amount::from_btc(txout["value"].as_f64().unwrap_or(0.0))?;
+    let mut spending = (txid.to_string(), vout);
+
+    let mut chain = Vec::new();
+    for &fee_rate in fee_rates {
+        let change = rpc.call::<String>("getrawchangeaddress", &[])?;
+        let (hex, vsize, fee) = sign_at_rate(
+            rpc,
+            (&spending.0, spending.1),
+            value,
+            (recipient, payment),
+            &change,
+            fee_rate,
+        )?;
+        let txid = rpc.call::<String>("sendrawtransaction", &[json!(hex)])?;
+        chain.push(PackageTx {
+            txid: txid.clone(),
+            vsize,
+            fee,
+        });
+        // The change, output 1, funds the next generation
+        value = value - payment - fee;
+        spending = (txid, 1);
+    }
+    Ok(chain)
+}
+
+// Helper function to count the in-mempool ancestors and descendants of `txid`,
+// itself excluded
+pub fn mempool_relatives(rpc: &impl RpcApi, txid: &str) -> bitcoincore_rpc::Result<(usize, usize)> {
+    let ancestors = rpc.call::<Vec<String>>("getmempoolancestors", &[json!(txid)])?;
+    let descendants = rpc.call::<Vec<String>>("getmempooldescendants", &[json!(txid)])?;
+    Ok((ancestors.len(), descendants.len()))
+}
+
+// Check the (ancestor, descendant) counts of a chain, oldest first: every
+// older generation is an ancestor and every newer one a descendant
+pub fn check_chain_relatives(counts: &[(usize, usize)]) -> Result<(), String> {
+    for (generation, &(ancestors, descendants)) in counts.iter().enumerate() {
+        let expected = (generation, counts.len() - 1 - generation);
+        if (ancestors, descendants) != expected {
+            return Err(format!(
+                "generation {} has {} ancestor(s) and {} descendant(s), expected {} and {}",
+                generation + 1,
+                ancestors,
+                descendants,
+                expected.0,
+                expected.1
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Check that every (txid, confirming block) pair names the same block, and
+// return it
+pub fn check_confirmed_together(blocks: &[(&str, Option<&str>)]) -> Result<String, String> {
+    let mut confirmed_in: Option<&str> = None;
+    for &(txid, block) in blocks {
+        let block = block.ok_or_else(|| format!("{} is unconfirmed", txid))?;
+        match confirmed_in {
+            Some(first) if first != block => {
+                return Err(format!(
+                    "{} confirmed in block {}, not with the rest of the package in {}",
+                    txid, block, first
+                ))
+            }
+            _ => confirmed_in = Some(block),
+        }
+    }
+    confirmed_in
+        .map(str::to_string)
+        .ok_or_else(|| "the package is empty".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_pays_the_package_shortfall() {
+        // (200 + 100) vB at 10 sat/vB is 3000 sat, the parent pays 200
+        assert_eq!(calculate_cpfp_fee(200, 200, 100, 10.0), 2800);
+        assert_eq!(calculate_cpfp_fee(141, 0, 110, 1.0), 251);
+    }
+
+    #[test]
+    fn fractional_totals_round_up() {
+        // 250 vB at 2.5 sat/vB is 625 sat; 251 vB is 627.5, so 628
+        assert_eq!(calculate_cpfp_fee(150, 0, 100, 2.5), 625);
+        assert_eq!(calculate_cpfp_fee(150, 0, 101, 2.5), 628);
+    }
+
+    #[test]
+    fn parent_already_at_target_needs_no_child_fee() {
+        assert_eq!(calculate_cpfp_fee(200, 3000, 100, 10.0), 0);
+        assert_eq!(calculate_cpfp_fee(200, 5000, 100, 10.0), 0);
+        assert_eq!(calculate_cpfp_fee(200, 2999, 100, 10.0), 1);
+    }
+
+    fn tx(txid: &str, vsize: u64, fee_sat: u64) -> PackageTx {
+        PackageTx {
+            txid: txid.to_string(),
+            vsize,
+            fee: Amount::from_sat(fee_sat),
+        }
+    }
+
+    #[test]
+    fn package_rate_is_total_fee_over_total_vsize() {
+        let chain = [tx("aa", 141, 141), tx("bb", 110, 550), tx("cc", 110, 2750)];
+        assert_eq!(chain[1].fee_rate(), 5.0);
+        // 3441 sat over 361 vB
+        assert!((package_fee_rate(&chain) - 9.532).abs() < 0.001);
+        assert_eq!(package_fee_rate(&[]), 0.0);
+    }
+
+    #[test]
+    fn rendered_package_lists_each_generation_then_the_total() {
+        let chain = [
+            tx(&"aa".repeat(32), 100, 100),
+            tx(&"bb".repeat(32), 100, 300),
+        ];
+        let rendered = render_package(&chain);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].ends_with("sat/vB"));
+        assert!(lines[1].starts_with("  1  aaaa"), "{}", lines[1]);
+        assert!(lines[2].ends_with("300     3.000"), "{}", lines[2]);
+        assert!(lines[3].starts_with("all  package"), "{}", lines[3]);
+        assert!(
+            lines[3].ends_with("200         400     2.000"),
+            "{}",
+            lines[3]
+        );
+    }
+
+    #[test]
+    fn chain_relatives_grow_with_each_generation() {
+        assert!(check_chain_relatives(&[(0, 2), (1, 1), (2, 0)]).is_ok());
+        assert!(check_chain_relatives(&[(0, 0)]).is_ok());
+        let err = check_chain_relatives(&[(0, 2), (1, 0), (2, 0)]).unwrap_err();
+        assert_eq!(
+            err,
+            "generation 2 has 1 ancestor(s) and 0 descendant(s), expected 1 and 1"
+        );
+    }
+
+    #[test]
+    fn a_package_confirms_in_one_block() {
+        let together = [("aa", Some("b1")), ("bb", Some("b1")), ("cc", Some("b1"))];
+        assert_eq!(check_confirmed_together(&together).unwrap(), "b1");
+        let split = [("aa", Some("b1")), ("bb", Some("b2"))];
+        assert!(check_confirmed_together(&split)
+            .unwrap_err()
+            .starts_with("bb confirmed in block b2"));
+        let pending = [("aa", Some("b1")), ("bb", None)];
+        assert_eq!(
+            check_confirmed_together(&pending).unwrap_err(),
+            "bb is unconfirmed"
+        );
+        assert!(check_confirmed_together(&[]).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn child_lifts_package_to_target_rate() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &address, 101).unwrap();
+
+        // A parent at 1 sat/vB paying one of the Miner's own addresses
+        let target = miner.get_new_address(None, None).unwrap().assume_checked();
+        let parent_txid = miner
+            .call::<String>(
+                "sendtoaddress",
+                &[
+                    json!(target.to_string()),
+                    json!(1.0),
+                    json!(""),
+                    json!(""),
+                    json!(false),
+                    json!(true),
+                    json!(null),
+                    json!("unset"),
+                    json!(false),
+                    json!(1),
+                ],
+            )
+            .unwrap();
+        let parent = miner
+            .get_raw_transaction(&parent_txid.parse().unwrap(), None)
+            .unwrap();
+        let vout = parent
+            .output
+            .iter()
+            .position(|output| output.script_pubkey == target.script_pubkey())
+            .unwrap() as u32;
+
+        let recipient = miner.get_new_address(None, None).unwrap().assume_checked();
+        let child_txid =
+            create_exact_cpfp(&miner, &parent_txid, vout, &recipient.to_string(), 20.0).unwrap();
+        let entry = rpc
+            .call::<serde_json::Value>("getmempoolentry", &[json!(child_txid)])
+            .unwrap();
+        let package_fee = Amount::from_btc(entry["fees"]["ancestor"].as_f64().unwrap()).unwrap();
+        let package_rate = package_fee.to_sat() as f64 / entry["ancestorsize"].as_f64().unwrap();
+        assert!(
+            (20.0..20.1).contains(&package_rate),
+            "rate {}",
+            package_rate
+        );
+    }
+}
@@ -0,0 +1,126 @@
+// Output descriptor checksums (BIP-380), checked locally before a descriptor
+// is handed to the node.
+//
+// The checksum is an 8-character BCH code over the descriptor text, appended
+// after a '#'. This is a direct port of `DescriptorChecksum` in Bitcoin Core's
+// `src/script/descriptor.cpp`.
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const CHECKSUM_LEN: usize = 8;
+
+fn poly_mod(c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ val;
+    for (bit, generator) in [
+        0xf5_dee5_1989,
+        0xa9_fdca_3312,
+        0x1b_ab10_e32d,
+        0x37_06b1_677a,
+        0x64_4d62_6ffd,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if c0 & (1 << bit) != 0 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+// Compute the checksum of a descriptor without its `#checksum` suffix. Returns
+// None if the descriptor contains characters outside the descriptor charset.
+pub fn descriptor_checksum(descriptor: &str) -> Option<String> {
+    let mut c = 1u64;
+    let mut cls = 0u64;
+    let mut cls_count = 0;
+    for ch in descriptor.chars() {
+        let pos = INPUT_CHARSET.find(ch)? as u64;
+        // Emit a symbol for the position inside the group, for every character
+        c = poly_mod(c, pos & 31);
+        // Accumulate the group numbers, three characters at a time
+        cls = cls * 3 + (pos >> 5);
+        cls_count += 1;
+        if cls_count == 3 {
+            c = poly_mod(c, cls);
+            cls = 0;
+            cls_count = 0;
+        }
+    }
+    if cls_count > 0 {
+        c = poly_mod(c, cls);
+    }
+    for _ in 0..CHECKSUM_LEN {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+    Some(
+        (0..CHECKSUM_LEN)
+            .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+            .collect(),
+    )
+}
+
+// Check a descriptor's checksum and return the descriptor with its checksum.
+// A descriptor without one gets it appended; a wrong one is rejected.
+pub fn with_valid_checksum(descriptor: &str) -> Result<String, String> {
+    let (body, given) = match descriptor.split_once('#') {
+        Some((body, checksum)) => (body, Some(checksum)),
+        None => (descriptor, None),
+    };
+    let expected = descriptor_checksum(body)
+        .ok_or_else(|| format!("descriptor '{}' contains invalid characters", body))?;
+    match given {
+        Some(checksum) if checksum != expected => Err(format!(
+            "descriptor checksum '{}' is invalid, expected '{}'",
+            checksum, expected
+        )),
+        _ => Ok(format!("{}#{}", body, expected)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    #[test]
+    fn bip380_raw_vector() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+    }
+
+    #[test]
+    fn pkh_descriptor_checksum() {
+        let desc = format!("pkh({})", PUBKEY);
+        assert_eq!(
+            with_valid_checksum(&format!("{}#e48zzw02", desc)).unwrap(),
+            format!("{}#e48zzw02", desc)
+        );
+    }
+
+    #[test]
+    fn wpkh_descriptor_gets_checksum_appended() {
+        let desc = format!("wpkh({})", PUBKEY);
+        assert_eq!(
+            with_valid_checksum(&desc).unwrap(),
+            format!("{}#ucxz0gak", desc)
+        );
+    }
+
+    #[test]
+    fn invalid_checksum_is_rejected() {
+        let err = with_valid_checksum(&format!("wpkh({})#ucxz0gal", PUBKEY)).unwrap_err();
+        assert!(err.contains("expected 'ucxz0gak'"));
+        assert!(with_valid_checksum("raw(deadbeef)#").is_err());
+        assert!(with_valid_checksum("raw(deadbeef)#89f8spxmx").is_err());
+    }
+
+    #[test]
+    fn invalid_character_is_rejected() {
+        assert!(descriptor_checksum("raw(deadbeef)\u{e9}").is_none());
+        assert!(with_valid_checksum("raw(\n)").is_err());
+    }
+}
@@ -0,0 +1,283 @@
+// Encrypted backups of a wallet's descriptors.
+//
+// `backup_descriptors` takes the wallet's `listdescriptors true` output, which
+// holds the private keys, and writes it encrypted with AES-256-GCM under a key
+// derived from a passphrase with Argon2id. The file is JSON holding the KDF
+// parameters, the salt, the nonce and the ciphertext, so it can be decrypted
+// with the same passphrase even if the defaults change.
+// `restore_from_descriptor_backup` decrypts it into a new blank wallet with
+// `importdescriptors`. A wrong passphrase and a modified file are both caught
+// by the GCM tag.
+use crate::rpc_config::RpcConfig;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::hex::DisplayHex;
+use bitcoincore_rpc::{Error, RpcApi};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const BACKUP_VERSION: u32 = 1;
+const KDF_ALGORITHM: &str = "argon2id";
+
+// Argon2id settings, stored in the backup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+// The backup file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedBackup {
+    pub version: u32,
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+// Helper function to derive the AES-256 key from the passphrase
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: KdfParams,
+) -> Result<Zeroizing<[u8; 32]>, String> {
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| format!("invalid Argon2 parameters: {}", e))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+        .map_err(|e| format!("cannot derive the backup key: {}", e))?;
+    Ok(key)
+}
+
+// Encrypt `plaintext` under `passphrase` with a fresh salt and nonce
+pub fn encrypt_backup(
+    plaintext: &[u8],
+    passphrase: &str,
+    params: KdfParams,
+) -> Result<EncryptedBackup, String> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt, params)?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&*key))
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| "encryption failed".to_string())?;
+    Ok(EncryptedBackup {
+        version: BACKUP_VERSION,
+        kdf: KDF_ALGORITHM.to_string(),
+        kdf_params: params,
+        salt: salt.to_lower_hex_string(),
+        nonce: nonce.to_lower_hex_string(),
+        ciphertext: ciphertext.to_lower_hex_string(),
+    })
+}
+
+// Decrypt a backup; fails on a wrong passphrase or a modified backup
+pub fn decrypt_backup(
+    backup: &EncryptedBackup,
+    passphrase: &str,
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    if backup.version != BACKUP_VERSION || backup.kdf != KDF_ALGORITHM {
+        return Err(format!(
+            "unsupported backup version {} with KDF '{}'",
+            backup.version, backup.kdf
+        ));
+    }
+    let hex = |name: &str, value: &str| {
+        Vec::<u8>::from_hex(value).map_err(|e| format!("invalid {} in backup: {}", name, e))
+    };
+    let salt = hex("salt", &backup.salt)?;
+    let nonce = hex("nonce", &backup.nonce)?;
+    let ciphertext = hex("ciphertext", &backup.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err(format!("invalid nonce in backup: {} bytes", nonce.len()));
+    }
+    let key = derive_key(passphrase, &salt, backup.kdf_params)?;
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&*key))
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map(Zeroizing::new)
+        .map_err(|_| "cannot decrypt backup: wrong passphrase or modified file".to_string())
+}
+
+// Helper function to write the descriptors of wallet client `rpc`, private
+// keys included, to `output_path` encrypted under `passphrase`. Returns the
+// number of descriptors backed up.
+pub fn backup_descriptors(
+    rpc: &impl RpcApi,
+    output_path: &Path,
+    passphrase: &str,
+) -> bitcoincore_rpc::Result<usize> {
+    let listed = rpc.call::<serde_json::Value>("listdescriptors", &[json!(true)])?;
+    let count = listed["descriptors"].as_array().map_or(0, Vec::len);
+    let plaintext = Zeroizing::new(serde_json::to_vec(&listed)?);
+    let backup = encrypt_backup(&plaintext, passphrase, KdfParams::default())
+        .map_err(Error::ReturnedError)?;
+    fs::write(output_path, serde_json::to_string_pretty(&backup)? + "\n")?;
+    Ok(count)
+}
+
+// The `importdescriptors` request restoring one `listdescriptors` entry. Older
+// nodes name the next index `next`, newer ones `next_index`.
+fn import_request(descriptor: &serde_json::Value) -> serde_json::Value {
+    let mut request = json!({
+        "desc": descriptor["desc"],
+        "timestamp": descriptor["timestamp"],
+        "active": descriptor["active"].as_bool().unwrap_or(false),
+    });
+    for (from, to) in [
+        ("internal", "internal"),
+        ("range", "range"),
+        ("next", "next_index"),
+        ("next_index", "next_index"),
+    ] {
+        if !descriptor[from].is_null() {
+            request[to] = descriptor[from].clone();
+        }
+    }
+    request
+}
+
+// Helper function to decrypt the backup at `backup_path`, create the blank
+// descriptor wallet `wallet_name` and import the backed-up descriptors into it
+pub fn restore_from_descriptor_backup(
+    rpc: &impl RpcApi,
+    config: &RpcConfig,
+    backup_path: &Path,
+    passphrase: &str,
+    wallet_name: &str,
+) -> bitcoincore_rpc::Result<()> {
+    let backup: EncryptedBackup = serde_json::from_str(&fs::read_to_string(backup_path)?)?;
+    let plaintext = decrypt_backup(&backup, passphrase).map_err(Error::ReturnedError)?;
+    let listed: serde_json::Value = serde_json::from_slice(&plaintext)?;
+    let requests: Vec<_> = listed["descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(import_request)
+        .collect();
+    if requests.is_empty() {
+        return Err(Error::ReturnedError(
+            "the backup holds no descriptors".to_string(),
+        ));
+    }
+
+    // name, disable_private_keys, blank, passphrase, avoid_reuse, descriptors
+    let args = [
+        json!(wallet_name),
+        json!(false),
+        json!(true),
+        json!(""),
+        json!(false),
+        json!(true),
+    ];
+    rpc.call::<serde_json::Value>("createwallet", &args)?;
+    let wallet = crate::get_wallet_client(config, wallet_name)?;
+    let results = wallet.call::<Vec<serde_json::Value>>("importdescriptors", &[json!(requests)])?;
+    if let Some(failed) = results
+        .iter()
+        .find(|result| result["success"].as_bool() != Some(true))
+    {
+        return Err(Error::ReturnedError(format!(
+            "importdescriptors failed for wallet '{}': {}",
+            wallet_name, failed["error"]
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap Argon2 settings so the tests stay fast
+    const FAST: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn backup_decrypts_with_its_passphrase_only() {
+        let backup = encrypt_backup(b"{\"descriptors\":[]}", "correct horse", FAST).unwrap();
+        assert_eq!(
+            decrypt_backup(&backup, "correct horse").unwrap().as_slice(),
+            b"{\"descriptors\":[]}"
+        );
+        let err = decrypt_backup(&backup, "wrong horse").unwrap_err();
+        assert!(err.contains("wrong passphrase"), "{}", err);
+    }
+
+    #[test]
+    fn each_backup_gets_a_fresh_salt_and_nonce() {
+        let first = encrypt_backup(b"same", "pass", FAST).unwrap();
+        let second = encrypt_backup(b"same", "pass", FAST).unwrap();
+        assert_ne!(first.salt, second.salt);
+        assert_ne!(first.nonce, second.nonce);
+        assert_ne!(first.ciphertext, second.ciphertext);
+    }
+
+    #[test]
+    fn modified_or_unknown_backups_are_rejected() {
+        let backup = encrypt_backup(b"secret", "pass", FAST).unwrap();
+        let mut tampered = backup.clone();
+        let flipped = if tampered.ciphertext.starts_with('0') {
+            "1"
+        } else {
+            "0"
+        };
+        tampered.ciphertext.replace_range(0..1, flipped);
+        assert!(decrypt_backup(&tampered, "pass").is_err());
+        let newer = EncryptedBackup {
+            version: 2,
+            ..backup
+        };
+        assert!(decrypt_backup(&newer, "pass")
+            .unwrap_err()
+            .contains("version 2"));
+    }
+
+    #[test]
+    fn import_requests_keep_range_and_next_index() {
+        let listed = json!({
+            "desc": "wpkh(tprv.../84h/1h/0h/0/*)#abcd",
+            "timestamp": 1700000000,
+            "active": true,
+            "internal": false,
+            "range": [0, 999],
+            "next": 3,
+        });
+        assert_eq!(
+            import_request(&listed),
+            json!({
+                "desc": "wpkh(tprv.../84h/1h/0h/0/*)#abcd",
+                "timestamp": 1700000000,
+                "active": true,
+                "internal": false,
+                "range": [0, 999],
+                "next_index": 3,
+            })
+        );
+        let single = json!({ "desc": "addr(bcrt1q...)#efgh", "timestamp": 1, "active": false });
+        assert_eq!(import_request(&single), single);
+    }
+}
@@ -0,0 +1,281 @@
+// Descriptor wallets restored from an HD seed.
+//
+// `create_bip84_wallet` derives the BIP-32 master key from the seed locally,
+// builds the standard BIP-84 (native segwit) descriptors
+// `wpkh(xprv/84h/<coin>h/<account>h/<change>/*)` with their checksums, and
+// imports them into a new blank descriptor wallet. `verify_address_derivation` then
+// checks that the node derives the addresses the caller expects, so a
+// restored wallet can be confirmed to match the original before it is used.
+use crate::descriptor::with_valid_checksum;
+use crate::node_rpc::NodeRpc;
+use crate::rpc_config::RpcConfig;
+use bitcoincore_rpc::bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
+use bitcoincore_rpc::bitcoin::secp256k1::Secp256k1;
+use bitcoincore_rpc::bitcoin::{Address, Network};
+use bitcoincore_rpc::{Error, RpcApi};
+use serde_json::json;
+use zeroize::Zeroizing;
+
+// BIP-44 coin type: 0 on mainnet, 1 on every test network including regtest
+fn coin_type(network: Network) -> u32 {
+    match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    }
+}
+
+// Account-level path m/84h/<coin>h/<account>h
+fn bip84_account_path(network: Network, account: u32) -> String {
+    format!("84h/{}h/{}h", coin_type(network), account)
+}
+
+// BIP-32 master key; seeds must be 128 to 512 bits long
+fn master_key(seed: &[u8], network: Network) -> Result<Xpriv, String> {
+    if !(16..=64).contains(&seed.len()) {
+        return Err(format!(
+            "invalid seed: {} bytes, expected 16 to 64",
+            seed.len()
+        ));
+    }
+    Xpriv::new_master(network, seed).map_err(|e| format!("invalid seed: {}", e))
+}
+
+// BIP-84 descriptor (with checksum) for the external (`change == false`) or
+// internal chain of `account`, holding the private key.
+pub fn bip84_descriptor(
+    seed: &[u8],
+    network: Network,
+    account: u32,
+    change: bool,
+) -> Result<String, String> {
+    let master = master_key(seed, network)?;
+    let desc = Zeroizing::new(format!(
+        "wpkh({}/{}/{}/*)",
+        master,
+        bip84_account_path(network, account),
+        u8::from(change)
+    ));
+    with_valid_checksum(&desc)
+}
+
+// Address at `index` on the external or internal chain of BIP-84 `account`,
+// derived locally.
+pub fn bip84_address(
+    seed: &[u8],
+    network: Network,
+    account: u32,
+    change: bool,
+    index: u32,
+) -> Result<String, String> {
+    let secp = Secp256k1::new();
+    let master = master_key(seed, network)?;
+    let path: DerivationPath = format!(
+        "m/{}/{}/{}",
+        bip84_account_path(network, account),
+        u8::from(change),
+        index
+    )
+    .replace('h', "'")
+    .parse()
+    .map_err(|e| format!("invalid derivation path: {}", e))?;
+    let child = master
+        .derive_priv(&secp, &path)
+        .map_err(|e| format!("cannot derive {}: {}", path, e))?;
+    let pubkey = Xpub::from_priv(&secp, &child).to_pub();
+    Address::p2wpkh(&pubkey, network)
+        .map(|address| address.to_string())
+        .map_err(|e| e.to_string())
+}
+
+// Helper function to create a blank descriptor wallet `name` and import the
+// receive and change descriptors of BIP-84 `account` of `seed`
+pub fn create_bip84_wallet(
+    rpc: &(impl NodeRpc + RpcApi),
+    config: &RpcConfig,
+    name: &str,
+    seed: &[u8],
+    network: Network,
+    account: u32,
+) -> bitcoincore_rpc::Result<()> {
+    let external = Zeroizing::new(
+        bip84_descriptor(seed, network, account, false).map_err(Error::ReturnedError)?,
+    );
+    let internal = Zeroizing::new(
+        bip84_descriptor(seed, network, account, true).map_err(Error::ReturnedError)?,
+    );
+
+    // name, disable_private_keys, blank, passphrase, avoid_reuse, descriptors
+    let args = [
+        json!(name),
+        json!(false),
+        json!(true),
+        json!(""),
+        json!(false),
+        json!(true),
+    ];
+    rpc.call::<serde_json::Value>("createwallet", &args)?;
+
+    let requests: Vec<_> = [(&external, false), (&internal, true)]
+        .into_iter()
+        .map(|(desc, internal)| {
+            json!({ "desc": desc.as_str(), "active": true, "internal": internal, "timestamp": "now" })
+        })
+        .collect();
+    let wallet = rpc.wallet(config, name)?;
+    let results = wallet.call::<Vec<serde_json::Value>>("importdescriptors", &[json!(requests)])?;
+    if let Some(failed) = results
+        .iter()
+        .find(|result| result["success"].as_bool() != Some(true))
+    {
+        return Err(Error::ReturnedError(format!(
+            "importdescriptors failed for wallet '{}': {}",
+            name, failed["error"]
+        )));
+    }
+    Ok(())
+}
+
+// Helper function to check that the wallet's active receive descriptor derives
+// `expected_addresses`, in order, starting at index 0
+pub fn verify_address_derivation(
+    rpc: &impl RpcApi,
+    wallet: &impl RpcApi,
+    expected_addresses: &[&str],
+) -> bitcoincore_rpc::Result<bool> {
+    if expected_addresses.is_empty() {
+        return Ok(true);
+    }
+    let listed = wallet.call::<serde_json::Value>("listdescriptors", &[])?;
+    let receive = listed["descriptors"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|desc| {
+            desc["active"].as_bool() == Some(true)
+                && desc["internal"].as_bool() == Some(false)
+                && desc["desc"]
+                    .as_str()
+                    .is_some_and(|d| d.starts_with("wpkh("))
+        })
+        .and_then(|desc| desc["desc"].as_str())
+        .ok_or_else(|| {
+            Error::ReturnedError("wallet has no active wpkh receive descriptor".to_string())
+        })?;
+    let range = json!([0, expected_addresses.len() - 1]);
+    let derived = rpc.call::<Vec<String>>("deriveaddresses", &[json!(receive), range])?;
+    Ok(derived == expected_addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::descriptor_checksum;
+    use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+
+    // BIP-39 seed of "abandon abandon ... abandon about" with no passphrase,
+    // the mnemonic used by the BIP-84 test vectors
+    const SEED_HEX: &str = "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1\
+                            9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4";
+
+    fn seed() -> Vec<u8> {
+        Vec::<u8>::from_hex(SEED_HEX).unwrap()
+    }
+
+    #[test]
+    fn bip84_mainnet_test_vectors() {
+        let seed = seed();
+        assert_eq!(
+            bip84_address(&seed, Network::Bitcoin, 0, false, 0).unwrap(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            bip84_address(&seed, Network::Bitcoin, 0, false, 1).unwrap(),
+            "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+        );
+        assert_eq!(
+            bip84_address(&seed, Network::Bitcoin, 0, true, 0).unwrap(),
+            "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"
+        );
+    }
+
+    #[test]
+    fn regtest_addresses_use_coin_type_one() {
+        let seed = seed();
+        let receive = bip84_address(&seed, Network::Regtest, 0, false, 0).unwrap();
+        assert!(receive.starts_with("bcrt1q"), "{}", receive);
+        // Same key path on testnet gives the same key, only the encoding differs
+        let testnet = bip84_address(&seed, Network::Testnet, 0, false, 0).unwrap();
+        let script = |address: &str| {
+            address
+                .parse::<Address<_>>()
+                .unwrap()
+                .assume_checked()
+                .script_pubkey()
+        };
+        assert_eq!(script(&receive), script(&testnet));
+        assert_ne!(
+            receive,
+            bip84_address(&seed, Network::Regtest, 0, true, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn regtest_descriptor_shape_and_checksum() {
+        let desc = bip84_descriptor(&seed(), Network::Regtest, 0, false).unwrap();
+        let (body, checksum) = desc.split_once('#').unwrap();
+        assert!(body.starts_with("wpkh(tprv"), "{}", body);
+        assert!(body.ends_with("/84h/1h/0h/0/*)"), "{}", body);
+        assert_eq!(descriptor_checksum(body).unwrap(), checksum);
+        let change = bip84_descriptor(&seed(), Network::Regtest, 0, true).unwrap();
+        assert!(change.contains("/84h/1h/0h/1/*)#"));
+    }
+
+    #[test]
+    fn accounts_derive_separate_keys() {
+        let seed = seed();
+        let desc = bip84_descriptor(&seed, Network::Regtest, 1, false).unwrap();
+        assert!(desc.contains("/84h/1h/1h/0/*)#"), "{}", desc);
+        assert_ne!(
+            bip84_address(&seed, Network::Regtest, 0, false, 0).unwrap(),
+            bip84_address(&seed, Network::Regtest, 1, false, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_short_seed() {
+        // BIP-32 seeds must be at least 128 bits
+        assert!(bip84_descriptor(&[0u8; 8], Network::Regtest, 0, false).is_err());
+        assert!(bip84_address(&[0u8; 65], Network::Regtest, 0, false, 0).is_err());
+        assert!(bip84_descriptor(&[0u8; 16], Network::Regtest, 0, false).is_ok());
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn restored_wallet_derives_bip84_addresses() {
+        use crate::get_wallet_client;
+        use crate::rpc_config::RpcConfig;
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let name = format!("Seed-test-{}", std::process::id());
+        create_bip84_wallet(&rpc, &config, &name, &seed(), Network::Regtest, 0).unwrap();
+        let wallet = get_wallet_client(&config, &name).unwrap();
+
+        let expected: Vec<String> = (0..5)
+            .map(|i| bip84_address(&seed(), Network::Regtest, 0, false, i).unwrap())
+            .collect();
+        let expected: Vec<&str> = expected.iter().map(String::as_str).collect();
+        assert!(verify_address_derivation(&rpc, &wallet, &expected).unwrap());
+        assert!(!verify_address_derivation(&rpc, &wallet, &expected[1..]).unwrap());
+
+        let first = RpcApi::get_new_address(
+            &wallet,
+            None,
+            Some(bitcoincore_rpc::json::AddressType::Bech32),
+        )
+        .unwrap()
+        .assume_checked();
+        assert_eq!(first.to_string(), expected[0]);
+        rpc.unload_wallet(Some(&name)).unwrap();
+    }
+}
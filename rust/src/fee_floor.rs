@@ -0,0 +1,79 @@
+// Mempool minimum fee tracking for sends with a fixed fee rate.
+//
+// When the mempool fills up (it is capped by `-maxmempool`), the node raises
+// `mempoolminfee` and rejects anything below it with "mempool min fee not met".
+// The sends that pick their own rate (`--send-raw`, `--consolidate`) sample
+// `getmempoolinfo` first and go above that floor when it exceeds their rate;
+// `sendtoaddress` needs none of this, as the wallet already respects it. The
+// rate logic is a pure function of the configured rate and the sampled floor
+// so it can be tested without a node.
+use crate::rpc_call;
+use bitcoincore_rpc::RpcApi;
+use log::info;
+
+// Fee rates are in sat/vB; `getmempoolinfo` reports BTC/kvB.
+const SAT_VB_PER_BTC_KVB: f64 = 100_000_000.0 / 1_000.0;
+
+// Fee rate to use given our configured rate and the current mempool minimum
+// fee: the configured rate, unless the floor exceeds it, in which case one
+// sat/vB above the floor.
+pub fn effective_fee_rate(configured: f64, mempool_min_fee: f64) -> f64 {
+    if mempool_min_fee > configured {
+        configured.max(mempool_min_fee + 1.0)
+    } else {
+        configured
+    }
+}
+
+// Helper function to sample the node's current `mempoolminfee` in sat/vB
+pub fn sample_mempool_min_fee(rpc: &impl RpcApi) -> bitcoincore_rpc::Result<f64> {
+    let info = rpc_call::<serde_json::Value>(rpc, "getmempoolinfo", &[])?;
+    Ok(info["mempoolminfee"].as_f64().unwrap_or(0.0) * SAT_VB_PER_BTC_KVB)
+}
+
+// Helper function to get the rate to send with instead of `configured`, raised
+// above the node's current mempool floor when that is higher
+pub fn floored_fee_rate(rpc: &impl RpcApi, configured: f64) -> bitcoincore_rpc::Result<f64> {
+    let floor = sample_mempool_min_fee(rpc)?;
+    let rate = effective_fee_rate(configured, floor);
+    if rate != configured {
+        info!(
+            "Mempool minimum fee is {:.3} sat/vB, sending at {:.3} instead of {:.3} sat/vB",
+            floor, rate, configured
+        );
+    }
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floor_below_configured_rate_keeps_it() {
+        assert_eq!(effective_fee_rate(5.0, 1.0), 5.0);
+        assert_eq!(effective_fee_rate(5.0, 5.0), 5.0);
+    }
+
+    #[test]
+    fn floor_above_configured_rate_raises_it() {
+        assert_eq!(effective_fee_rate(1.0, 3.5), 4.5);
+        assert_eq!(effective_fee_rate(5.0, 5.5), 6.5);
+    }
+
+    #[test]
+    fn btc_per_kvb_converts_to_sat_per_vb() {
+        assert_eq!(0.00001 * SAT_VB_PER_BTC_KVB, 1.0);
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn an_empty_mempool_keeps_the_configured_rate() {
+        use crate::rpc_config::RpcConfig;
+
+        let rpc = RpcConfig::load().unwrap().client().unwrap();
+        // Default -minrelaytxfee is 1 sat/vB, the floor of a mempool with room
+        assert_eq!(sample_mempool_min_fee(&rpc).unwrap(), 1.0);
+        assert_eq!(floored_fee_rate(&rpc, 2.0).unwrap(), 2.0);
+    }
+}
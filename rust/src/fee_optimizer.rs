@@ -0,0 +1,289 @@
+// MinerFeeOptimizer: find the lowest fee rate the node's mempool accepts for a
+// transaction, by bisecting over fee rates with `testmempoolaccept`.
+//
+// Each probe builds the template with the probed fee taken out of the change
+// output, has the wallet sign it and asks the node whether it would accept it.
+// Nothing is broadcast. `fees min-accepted` runs it for a payment funded
+// largest-first from the wallet.
+use crate::coin_selection::{greedy, Utxo};
+use crate::fee_preview::PREVIEW_RECIPIENT;
+use crate::report::{format_btc, parse_btc};
+use crate::spend_simulator::simulate_batch_acceptance;
+use crate::{arg_value, rpc_call};
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+
+// The fee rate range `fees min-accepted` searches by default, in sat/vB, and
+// the number of bisections
+const DEFAULT_MIN_RATE: f64 = 0.1;
+const DEFAULT_MAX_RATE: f64 = 100.0;
+const SEARCH_STEPS: u32 = 12;
+// The inputs are picked to cover a transaction of this many vbytes at the
+// maximum rate on top of the amount
+const FEE_ALLOWANCE_VSIZE: f64 = 1_000.0;
+
+// Pre-selected inputs and outputs; whatever the inputs leave over after the
+// outputs and the fee goes to `change_address`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawTxTemplate {
+    pub inputs: Vec<Utxo>,
+    pub outputs: Vec<(String, Amount)>,
+    pub change_address: String,
+}
+
+// Bisect `[min_rate, max_rate]` for the lowest rate `accepts` approves, probing
+// at most `steps` times inside the range. Assumes acceptance is monotonic in
+// the fee rate. Returns the lowest accepted rate found.
+fn bisect_min_accepted<F>(
+    min_rate: f64,
+    max_rate: f64,
+    steps: u32,
+    mut accepts: F,
+) -> bitcoincore_rpc::Result<f64>
+where
+    F: FnMut(f64) -> bitcoincore_rpc::Result<bool>,
+{
+    if !(min_rate > 0.0 && min_rate <= max_rate) {
+        return Err(Error::ReturnedError(format!(
+            "invalid fee rate range [{}, {}]",
+            min_rate, max_rate
+        )));
+    }
+    if !accepts(max_rate)? {
+        return Err(Error::ReturnedError(format!(
+            "not accepted even at the maximum fee rate of {} sat/vB",
+            max_rate
+        )));
+    }
+    if accepts(min_rate)? {
+        return Ok(min_rate);
+    }
+    let (mut rejected, mut accepted) = (min_rate, max_rate);
+    for _ in 0..steps {
+        let mid = (rejected + accepted) / 2.0;
+        if accepts(mid)? {
+            accepted = mid;
+        } else {
+            rejected = mid;
+        }
+    }
+    Ok(accepted)
+}
+
+impl RawTxTemplate {
+    fn input_total(&self) -> Amount {
+        self.inputs
+            .iter()
+            .fold(Amount::ZERO, |sum, utxo| sum + utxo.amount)
+    }
+
+    fn output_total(&self) -> Amount {
+        self.outputs
+            .iter()
+            .fold(Amount::ZERO, |sum, (_, amount)| sum + *amount)
+    }
+
+    // Build and sign the template paying `fee`, returning the signed hex
+    fn sign_with_fee(&self, wallet: &impl RpcApi, fee: Amount) -> bitcoincore_rpc::Result<String> {
+        let change = self
+            .input_total()
+            .checked_sub(self.output_total() + fee)
+            .ok_or_else(|| {
+                Error::ReturnedError(format!("inputs cannot cover the outputs and a {} fee", fee))
+            })?;
+        let inputs: Vec<_> = self
+            .inputs
+            .iter()
+            .map(|utxo| json!({ "txid": utxo.txid, "vout": utxo.vout }))
+            .collect();
+        let mut outputs: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|(address, amount)| json!({ address.as_str(): amount.to_btc() }))
+            .collect();
+        outputs.push(json!({ self.change_address.as_str(): change.to_btc() }));
+
+        let raw =
+            wallet.call::<String>("createrawtransaction", &[json!(inputs), json!(outputs)])?;
+        let signed =
+            wallet.call::<serde_json::Value>("signrawtransactionwithwallet", &[json!(raw)])?;
+        if signed["complete"].as_bool() != Some(true) {
+            return Err(Error::ReturnedError(format!(
+                "wallet could not sign the template: {}",
+                signed["errors"]
+            )));
+        }
+        signed["hex"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| Error::ReturnedError("signed transaction has no hex".to_string()))
+    }
+}
+
+// Helper function to find the minimum fee rate (sat/vB) within `[min_rate, max_rate]`
+// at which `testmempoolaccept` accepts the template, in at most `steps` bisections.
+// `rpc` must be a wallet client that can sign the template's inputs.
+pub fn find_minimum_accepted_fee_rate(
+    rpc: &impl RpcApi,
+    tx_template: &RawTxTemplate,
+    min_rate: f64,
+    max_rate: f64,
+    steps: u32,
+) -> bitcoincore_rpc::Result<f64> {
+    // The size barely depends on the fee, so measure it once from a zero-fee draft
+    let draft = tx_template.sign_with_fee(rpc, Amount::ZERO)?;
+    let decoded = rpc.call::<serde_json::Value>("decoderawtransaction", &[json!(draft)])?;
+    let vsize = decoded["vsize"]
+        .as_u64()
+        .ok_or_else(|| Error::ReturnedError("decoded draft has no vsize".to_string()))?;
+
+    bisect_min_accepted(min_rate, max_rate, steps, |rate| {
+        let fee = Amount::from_sat((rate * vsize as f64).ceil() as u64);
+        let hex = tx_template.sign_with_fee(rpc, fee)?;
+        Ok(simulate_batch_acceptance(rpc, &[&hex])?.all_accepted())
+    })
+}
+
+// Parse the sat/vB value of `flag`, `default` when it is not given
+fn rate_arg(args: &[String], flag: &str, default: f64) -> bitcoincore_rpc::Result<f64> {
+    let Some(value) = arg_value(args, flag) else {
+        return Ok(default);
+    };
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| rate.is_finite() && *rate > 0.0)
+        .ok_or_else(|| {
+            Error::ReturnedError(format!(
+                "invalid {} value '{}': expected a positive fee rate in sat/vB",
+                flag, value
+            ))
+        })
+}
+
+// `fees min-accepted --amount <BTC> [--to <address>] [--min-rate R] [--max-rate R]`
+pub fn run_command(wallet: &impl RpcApi, args: &[String]) -> bitcoincore_rpc::Result<()> {
+    let usage = || {
+        Error::ReturnedError(
+            "usage: fees min-accepted --amount <BTC> [--to <address>] \
+             [--min-rate <sat/vB>] [--max-rate <sat/vB>]"
+                .into(),
+        )
+    };
+    let btc = arg_value(args, "--amount").ok_or_else(usage)?;
+    let amount = parse_btc(btc)
+        .ok()
+        .and_then(|amount| amount.to_unsigned().ok())
+        .filter(|amount| *amount > Amount::ZERO)
+        .ok_or_else(|| {
+            Error::ReturnedError(format!(
+                "invalid --amount value '{}': expected a positive BTC amount",
+                btc
+            ))
+        })?;
+    let recipient = arg_value(args, "--to").unwrap_or(PREVIEW_RECIPIENT);
+    let min_rate = rate_arg(args, "--min-rate", DEFAULT_MIN_RATE)?;
+    let max_rate = rate_arg(args, "--max-rate", DEFAULT_MAX_RATE)?;
+
+    let utxos = rpc_call::<Vec<Utxo>>(wallet, "listunspent", &[])?;
+    let allowance = Amount::from_sat((max_rate * FEE_ALLOWANCE_VSIZE).ceil() as u64);
+    let inputs = greedy(&utxos, amount + allowance).ok_or_else(|| {
+        Error::ReturnedError(format!(
+            "the wallet cannot fund {} BTC plus fees",
+            format_btc(amount)
+        ))
+    })?;
+    let template = RawTxTemplate {
+        inputs,
+        outputs: vec![(recipient.to_string(), amount)],
+        change_address: rpc_call(wallet, "getrawchangeaddress", &[])?,
+    };
+    let rate = find_minimum_accepted_fee_rate(wallet, &template, min_rate, max_rate, SEARCH_STEPS)?;
+    info!(
+        "Paying {} BTC to {} from {} input(s) is accepted from {:.3} sat/vB",
+        format_btc(amount),
+        recipient,
+        template.inputs.len(),
+        rate
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converges_on_threshold() {
+        let mut probes = 0;
+        let rate = bisect_min_accepted(0.1, 100.0, 30, |rate| {
+            probes += 1;
+            Ok(rate >= 1.0)
+        })
+        .unwrap();
+        assert!((1.0..1.000_001).contains(&rate), "rate {}", rate);
+        assert_eq!(probes, 32);
+    }
+
+    #[test]
+    fn minimum_accepted_returns_immediately() {
+        let rate = bisect_min_accepted(2.0, 10.0, 30, |_| Ok(true)).unwrap();
+        assert_eq!(rate, 2.0);
+    }
+
+    #[test]
+    fn maximum_rejected_is_an_error() {
+        let err = bisect_min_accepted(1.0, 10.0, 30, |rate| Ok(rate > 10.0)).unwrap_err();
+        assert!(err.to_string().contains("maximum fee rate"));
+    }
+
+    #[test]
+    fn zero_steps_returns_maximum() {
+        let rate = bisect_min_accepted(1.0, 8.0, 0, |rate| Ok(rate >= 3.0)).unwrap();
+        assert_eq!(rate, 8.0);
+    }
+
+    #[test]
+    fn invalid_range_is_rejected() {
+        assert!(bisect_min_accepted(5.0, 1.0, 10, |_| Ok(true)).is_err());
+        assert!(bisect_min_accepted(0.0, 1.0, 10, |_| Ok(true)).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn converges_on_relay_fee_floor() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &address, 101).unwrap();
+
+        let utxo = miner
+            .list_unspent(Some(1), None, None, None, None)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        let recipient = miner.get_new_address(None, None).unwrap().assume_checked();
+        let change = miner.get_new_address(None, None).unwrap().assume_checked();
+        let template = RawTxTemplate {
+            inputs: vec![Utxo {
+                txid: utxo.txid.to_string(),
+                vout: utxo.vout,
+                amount: utxo.amount,
+            }],
+            outputs: vec![(recipient.to_string(), Amount::from_int_btc(1))],
+            change_address: change.to_string(),
+        };
+
+        // Default -minrelaytxfee is 1 sat/vB
+        let rate = find_minimum_accepted_fee_rate(&miner, &template, 0.1, 50.0, 20).unwrap();
+        assert!((1.0..1.1).contains(&rate), "rate {}", rate);
+    }
+}
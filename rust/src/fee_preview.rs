@@ -0,0 +1,184 @@
+// `fees preview`: what a payment would cost at several fee rates.
+//
+// For each rate the wallet funds the payment with `walletcreatefundedpsbt`
+// and an explicit `fee_rate`, so the fee, change and input count come from
+// the wallet's real coin selection. The PSBTs are decoded and thrown away:
+// nothing is signed, broadcast or added to the wallet, and `lockUnspents` is
+// off so no coins stay reserved. `preview_command` checks `listlockunspent`
+// before and after to make sure of the latter.
+use crate::arg_value;
+use crate::report::{format_btc, parse_btc};
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+
+// Stands in for the recipient when no --to is given (BIP 173's regtest
+// P2WPKH example), so the preview has a payment output of the usual size
+pub const PREVIEW_RECIPIENT: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+
+// The funded payment at one fee rate
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeePreview {
+    // sat/vB
+    pub fee_rate: f64,
+    pub fee: Amount,
+    // None when the inputs leave too little over for a change output
+    pub change: Option<Amount>,
+    pub inputs: usize,
+}
+
+// Parse `--rates 1,5,10.5` into positive fee rates in sat/vB
+pub fn parse_rates(value: &str) -> Result<Vec<f64>, String> {
+    value
+        .split(',')
+        .map(|rate| {
+            rate.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|rate| rate.is_finite() && *rate > 0.0)
+                .ok_or_else(|| {
+                    format!(
+                        "invalid fee rate '{}' in --rates: expected positive sat/vB values",
+                        rate.trim()
+                    )
+                })
+        })
+        .collect()
+}
+
+// The preview at `fee_rate` from a `walletcreatefundedpsbt` result and the
+// `decodepsbt` of its PSBT
+pub fn preview_from_funded(
+    fee_rate: f64,
+    funded: &serde_json::Value,
+    decoded: &serde_json::Value,
+) -> Result<FeePreview, String> {
+    let btc = |value: &serde_json::Value, what: &str| {
+        value
+            .as_f64()
+            .and_then(|btc| Amount::from_btc(btc).ok())
+            .ok_or_else(|| format!("funded PSBT has no valid {}", what))
+    };
+    let fee = btc(&funded["fee"], "fee")?;
+    let change = match funded["changepos"].as_i64() {
+        Some(-1) => None,
+        Some(pos) => Some(btc(
+            &decoded["tx"]["vout"][pos as usize]["value"],
+            "change output",
+        )?),
+        None => return Err("funded PSBT has no changepos".to_string()),
+    };
+    let inputs = decoded["tx"]["vin"]
+        .as_array()
+        .map(Vec::len)
+        .ok_or("decoded PSBT has no inputs")?;
+    Ok(FeePreview {
+        fee_rate,
+        fee,
+        change,
+        inputs,
+    })
+}
+
+// One row per fee rate
+pub fn render_table(previews: &[FeePreview]) -> String {
+    let mut lines = vec![format!(
+        "{:>10}  {:>12}  {:>14}  {:>6}",
+        "sat/vB", "fee BTC", "change BTC", "inputs"
+    )];
+    for preview in previews {
+        lines.push(format!(
+            "{:>10}  {:>12}  {:>14}  {:>6}",
+            preview.fee_rate,
+            format_btc(preview.fee),
+            preview
+                .change
+                .map(format_btc)
+                .unwrap_or_else(|| "-".to_string()),
+            preview.inputs
+        ));
+    }
+    lines.join("\n")
+}
+
+// Helper function to fund a payment of `amount` to `recipient` from `wallet`
+// at each of `rates`, without keeping any of the PSBTs
+pub fn preview_fees(
+    wallet: &impl RpcApi,
+    recipient: &str,
+    amount: Amount,
+    rates: &[f64],
+) -> bitcoincore_rpc::Result<Vec<FeePreview>> {
+    rates
+        .iter()
+        .map(|&rate| {
+            // inputs, outputs, locktime, options
+            let args = [
+                json!([]),
+                json!([{ recipient: amount.to_btc() }]),
+                json!(0),
+                json!({ "fee_rate": rate, "lockUnspents": false }),
+            ];
+            let funded = wallet.call::<serde_json::Value>("walletcreatefundedpsbt", &args)?;
+            let decoded =
+                wallet.call::<serde_json::Value>("decodepsbt", &[funded["psbt"].clone()])?;
+            preview_from_funded(rate, &funded, &decoded).map_err(Error::ReturnedError)
+        })
+        .collect()
+}
+
+// Helper function to list the wallet's locked outputs as (txid, vout), sorted
+pub fn locked_outputs(wallet: &impl RpcApi) -> bitcoincore_rpc::Result<Vec<(String, u64)>> {
+    let locked = wallet.call::<Vec<serde_json::Value>>("listlockunspent", &[])?;
+    let mut outpoints: Vec<_> = locked
+        .iter()
+        .map(|outpoint| {
+            (
+                outpoint["txid"].as_str().unwrap_or_default().to_string(),
+                outpoint["vout"].as_u64().unwrap_or_default(),
+            )
+        })
+        .collect();
+    outpoints.sort();
+    Ok(outpoints)
+}
+
+// `fees preview --amount <BTC> --rates <r1,r2,...> [--to <address>]`; `args`
+// excludes "fees"
+pub fn preview_command(wallet: &impl RpcApi, args: &[String]) -> bitcoincore_rpc::Result<()> {
+    let usage =
+        || Error::ReturnedError("usage: fees preview --amount <BTC> --rates <sat/vB,...>".into());
+    if args.first().map(String::as_str) != Some("preview") {
+        return Err(usage());
+    }
+    let btc = arg_value(args, "--amount").ok_or_else(usage)?;
+    let amount = parse_btc(btc)
+        .ok()
+        .and_then(|amount| amount.to_unsigned().ok())
+        .filter(|amount| *amount > Amount::ZERO)
+        .ok_or_else(|| {
+            Error::ReturnedError(format!(
+                "invalid --amount value '{}': expected a positive BTC amount",
+                btc
+            ))
+        })?;
+    let rates =
+        parse_rates(arg_value(args, "--rates").ok_or_else(usage)?).map_err(Error::ReturnedError)?;
+    let recipient = arg_value(args, "--to").unwrap_or(PREVIEW_RECIPIENT);
+
+    let locked_before = locked_outputs(wallet)?;
+    let previews = preview_fees(wallet, recipient, amount, &rates)?;
+    if locked_outputs(wallet)? != locked_before {
+        return Err(Error::ReturnedError(
+            "the fee preview changed the wallet's locked outputs".to_string(),
+        ));
+    }
+    info!(
+        "Paying {} BTC to {}:\n{}",
+        format_btc(amount),
+        recipient,
+        render_table(&previews)
+    );
+    Ok(())
+}
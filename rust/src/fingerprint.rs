@@ -0,0 +1,287 @@
+// A fingerprint of the chain state a run ended in, for settling grading
+// disputes.
+//
+// It records the chain tip, the block that confirmed the payment, the number
+// of transactions in each wallet and a sha256 over the Miner's UTXO outpoints.
+// `out.json` carries it in its `fingerprint` section; `fingerprint verify
+// <out.json>` recomputes it against the live node and lists every field that
+// differs, so an instructor can check that a report came from the chain state
+// submitted with it. Wallets are keyed by name and the outpoints are sorted, so
+// the same chain state always gives the same fingerprint.
+use crate::coin_selection::Utxo;
+use crate::rpc_call;
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::BlockHash;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub tip_hash: BlockHash,
+    pub tip_height: u64,
+    pub confirmation_block_hash: BlockHash,
+    // `getwalletinfo.txcount` by wallet name
+    pub wallet_txcounts: BTreeMap<String, u64>,
+    // sha256 of the Miner's confirmed UTXOs, one sorted `txid:vout` line each
+    pub miner_utxos_sha256: String,
+}
+
+// The digest of a set of outpoints, whatever order they are listed in
+pub fn outpoints_digest(outpoints: &[(String, u32)]) -> String {
+    let mut sorted = outpoints.to_vec();
+    sorted.sort();
+    let lines: String = sorted
+        .iter()
+        .map(|(txid, vout)| format!("{}:{}\n", txid, vout))
+        .collect();
+    sha256::Hash::hash(lines.as_bytes()).to_string()
+}
+
+// Helper function to fingerprint the chain state. `wallets` are (name, client)
+// pairs and the first is the Miner, whose UTXOs are digested and whose record
+// of the payment `txid` names the confirmation block.
+pub fn chain_fingerprint<R: RpcApi>(
+    rpc: &impl RpcApi,
+    wallets: &[(&str, &R)],
+    txid: &str,
+) -> bitcoincore_rpc::Result<Fingerprint> {
+    let &(_, miner) = wallets
+        .first()
+        .ok_or_else(|| Error::ReturnedError("no wallets to fingerprint".to_string()))?;
+    let chain = rpc_call::<serde_json::Value>(rpc, "getblockchaininfo", &[])?;
+    let payment = rpc_call::<serde_json::Value>(miner, "gettransaction", &[json!(txid)])?;
+    let mut wallet_txcounts = BTreeMap::new();
+    for &(name, wallet) in wallets {
+        let info = rpc_call::<serde_json::Value>(wallet, "getwalletinfo", &[])?;
+        wallet_txcounts.insert(name.to_string(), info["txcount"].as_u64().unwrap_or(0));
+    }
+    let utxos = rpc_call::<Vec<Utxo>>(miner, "listunspent", &[json!(1)])?;
+    let outpoints: Vec<_> = utxos
+        .into_iter()
+        .map(|utxo| (utxo.txid, utxo.vout))
+        .collect();
+
+    let hash = |value: &serde_json::Value, what: &str| {
+        serde_json::from_value::<BlockHash>(value.clone())
+            .map_err(|_| Error::ReturnedError(format!("the node returned no {}", what)))
+    };
+    Ok(Fingerprint {
+        tip_hash: hash(&chain["bestblockhash"], "chain tip")?,
+        tip_height: chain["blocks"].as_u64().unwrap_or(0),
+        confirmation_block_hash: hash(&payment["blockhash"], "confirmation block")?,
+        wallet_txcounts,
+        miner_utxos_sha256: outpoints_digest(&outpoints),
+    })
+}
+
+// One line per field that differs between a submitted and a live fingerprint
+pub fn diff(submitted: &Fingerprint, live: &Fingerprint) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut compare = |field: &str, submitted: String, live: String| {
+        if submitted != live {
+            lines.push(format!("{}: submitted {}, live {}", field, submitted, live));
+        }
+    };
+    compare(
+        "tip_hash",
+        submitted.tip_hash.to_string(),
+        live.tip_hash.to_string(),
+    );
+    compare(
+        "tip_height",
+        submitted.tip_height.to_string(),
+        live.tip_height.to_string(),
+    );
+    compare(
+        "confirmation_block_hash",
+        submitted.confirmation_block_hash.to_string(),
+        live.confirmation_block_hash.to_string(),
+    );
+    let wallets: BTreeSet<_> = submitted
+        .wallet_txcounts
+        .keys()
+        .chain(live.wallet_txcounts.keys())
+        .collect();
+    let txcount = |counts: &BTreeMap<String, u64>, wallet: &str| {
+        counts
+            .get(wallet)
+            .map_or_else(|| "none".to_string(), u64::to_string)
+    };
+    for wallet in wallets {
+        compare(
+            &format!("wallet_txcounts.{}", wallet),
+            txcount(&submitted.wallet_txcounts, wallet),
+            txcount(&live.wallet_txcounts, wallet),
+        );
+    }
+    compare(
+        "miner_utxos_sha256",
+        submitted.miner_utxos_sha256.clone(),
+        live.miner_utxos_sha256.clone(),
+    );
+    lines
+}
+
+// The payment txid and fingerprint of a submitted out.json
+pub fn read_submitted(text: &str) -> Result<(String, Fingerprint), String> {
+    let report: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("not valid JSON: {}", e))?;
+    let txid = report["txid"]
+        .as_str()
+        .ok_or("the report has no txid")?
+        .to_string();
+    if report["fingerprint"].is_null() {
+        return Err("the report has no fingerprint section".to_string());
+    }
+    let fingerprint = serde_json::from_value(report["fingerprint"].clone())
+        .map_err(|e| format!("invalid fingerprint section: {}", e))?;
+    Ok((txid, fingerprint))
+}
+
+// `fingerprint verify <out.json>`; `args` excludes "fingerprint"
+pub fn verify_command<R: RpcApi>(
+    rpc: &impl RpcApi,
+    wallets: &[(&str, &R)],
+    args: &[String],
+) -> bitcoincore_rpc::Result<()> {
+    let path = match (args.first().map(String::as_str), args.get(1)) {
+        (Some("verify"), Some(path)) => path,
+        _ => {
+            return Err(Error::ReturnedError(
+                "usage: fingerprint verify <out.json>".to_string(),
+            ))
+        }
+    };
+    let text = std::fs::read_to_string(Path::new(path))?;
+    let (txid, submitted) =
+        read_submitted(&text).map_err(|e| Error::ReturnedError(format!("{}: {}", path, e)))?;
+    let live = chain_fingerprint(rpc, wallets, &txid)?;
+    let differences = diff(&submitted, &live);
+    if differences.is_empty() {
+        info!("{} matches the live chain state", path);
+        return Ok(());
+    }
+    for difference in &differences {
+        error!("{}", difference);
+    }
+    Err(Error::ReturnedError(format!(
+        "{} differs from the live chain state in {} field(s)",
+        path,
+        differences.len()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_HASH: &str = "044f4aa26f7b069f4e78dd53eb8952e29b3714eedc55f35d1f3325cb4abd0912";
+
+    fn sample() -> Fingerprint {
+        Fingerprint {
+            tip_hash: BLOCK_HASH.parse().unwrap(),
+            tip_height: 102,
+            confirmation_block_hash: BLOCK_HASH.parse().unwrap(),
+            wallet_txcounts: BTreeMap::from([
+                ("Miner".to_string(), 103),
+                ("Trader".to_string(), 1),
+            ]),
+            miner_utxos_sha256: outpoints_digest(&[("aa".repeat(32), 1)]),
+        }
+    }
+
+    #[test]
+    fn outpoint_order_does_not_matter() {
+        let a = ("aa".repeat(32), 0);
+        let b = ("aa".repeat(32), 1);
+        let c = ("bb".repeat(32), 0);
+        assert_eq!(
+            outpoints_digest(&[a.clone(), b.clone(), c.clone()]),
+            outpoints_digest(&[c.clone(), a.clone(), b.clone()])
+        );
+        assert_ne!(outpoints_digest(&[a.clone(), b]), outpoints_digest(&[a, c]));
+        // sha256 of the empty string
+        assert_eq!(
+            outpoints_digest(&[]),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn identical_fingerprints_have_no_diff() {
+        assert!(diff(&sample(), &sample()).is_empty());
+    }
+
+    #[test]
+    fn every_differing_field_is_listed() {
+        let mut live = sample();
+        live.tip_height = 110;
+        live.wallet_txcounts.insert("Miner".to_string(), 111);
+        live.wallet_txcounts.remove("Trader");
+        live.miner_utxos_sha256 = outpoints_digest(&[]);
+        let lines = diff(&sample(), &live);
+        assert_eq!(lines.len(), 4, "{:?}", lines);
+        assert_eq!(lines[0], "tip_height: submitted 102, live 110");
+        assert_eq!(lines[1], "wallet_txcounts.Miner: submitted 103, live 111");
+        assert_eq!(lines[2], "wallet_txcounts.Trader: submitted 1, live none");
+        assert!(lines[3].starts_with("miner_utxos_sha256: "));
+    }
+
+    #[test]
+    fn submitted_reports_round_trip() {
+        let report = json!({ "txid": "cc".repeat(32), "fingerprint": sample() });
+        let (txid, fingerprint) = read_submitted(&report.to_string()).unwrap();
+        assert_eq!(txid, "cc".repeat(32));
+        assert_eq!(fingerprint, sample());
+
+        let unfingerprinted = json!({ "txid": "cc".repeat(32), "fingerprint": null });
+        let err = read_submitted(&unfingerprinted.to_string()).unwrap_err();
+        assert!(err.contains("no fingerprint"), "{}", err);
+        assert!(read_submitted("{ not json").is_err());
+        assert!(read_submitted(r#"{ "fingerprint": {} }"#).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn fingerprints_are_reproducible() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks};
+        use bitcoincore_rpc::RpcApi;
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks(&rpc, &address, 101).unwrap();
+        let txid = miner
+            .send_to_address(
+                &address,
+                bitcoincore_rpc::bitcoin::Amount::ONE_BTC,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+            .to_string();
+        let confirmed_in = mine_blocks(&rpc, &address, 1).unwrap()[0];
+
+        let wallets = [("Miner", &miner)];
+        let first = chain_fingerprint(&rpc, &wallets, &txid).unwrap();
+        assert_eq!(first.confirmation_block_hash, confirmed_in);
+        assert_eq!(first.tip_hash, confirmed_in);
+        assert!(diff(&first, &chain_fingerprint(&rpc, &wallets, &txid).unwrap()).is_empty());
+
+        mine_blocks(&rpc, &address, 1).unwrap();
+        let after = chain_fingerprint(&rpc, &wallets, &txid).unwrap();
+        assert_eq!(after.tip_height, first.tip_height + 1);
+        assert_ne!(after.miner_utxos_sha256, first.miner_utxos_sha256);
+    }
+}
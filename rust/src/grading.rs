@@ -0,0 +1,478 @@
+// Autograder support: compare a finished run against an instructor's
+// `expected.toml` and produce a pass/fail grading section.
+//
+// An expectations file looks like:
+//
+//     amount_sent = "20"                  # BTC, must match exactly
+//     blocks_mined = { min = 101, max = 102 }
+//     fee_rate = { min = 1, max = 10, max_exclusive = true }   # sat/vB
+//     confirmation_depth = { min = 1 }
+//
+// Every entry is optional. Bounds are inclusive unless `min_exclusive` or
+// `max_exclusive` is set. Loading and grading are pure so they can be tested
+// without a node.
+use crate::report::{format_btc, parse_btc, Report};
+use bitcoincore_rpc::bitcoin::Amount;
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+// A range a numeric result must fall in; either end may be left open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bounds {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub min_exclusive: bool,
+    #[serde(default)]
+    pub max_exclusive: bool,
+}
+
+impl Bounds {
+    pub fn contains(&self, value: f64) -> bool {
+        if value.is_nan() {
+            return false;
+        }
+        let above_min = match self.min {
+            Some(min) if self.min_exclusive => value > min,
+            Some(min) => value >= min,
+            None => true,
+        };
+        let below_max = match self.max {
+            Some(max) if self.max_exclusive => value < max,
+            Some(max) => value <= max,
+            None => true,
+        };
+        above_min && below_max
+    }
+}
+
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lower = match self.min {
+            Some(min) => format!("{}{}", if self.min_exclusive { "(" } else { "[" }, min),
+            None => "(-inf".to_string(),
+        };
+        let upper = match self.max {
+            Some(max) => format!("{}{}", max, if self.max_exclusive { ")" } else { "]" }),
+            None => "inf)".to_string(),
+        };
+        write!(f, "{}, {}", lower, upper)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawExpectations {
+    amount_sent: Option<toml::Value>,
+    blocks_mined: Option<Bounds>,
+    fee_rate: Option<Bounds>,
+    confirmation_depth: Option<Bounds>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Expectations {
+    pub amount_sent: Option<Amount>,
+    pub blocks_mined: Option<Bounds>,
+    pub fee_rate: Option<Bounds>,
+    pub confirmation_depth: Option<Bounds>,
+}
+
+impl Expectations {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read expectations {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let raw: RawExpectations = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        // Amounts may be written as a TOML number or a string; strings keep
+        // exact precision
+        let amount_sent = match raw.amount_sent {
+            None => None,
+            Some(toml::Value::String(s)) => Some(parse_btc(&s)?),
+            Some(toml::Value::Integer(n)) => Some(parse_btc(&n.to_string())?),
+            Some(toml::Value::Float(x)) => Some(parse_btc(&x.to_string())?),
+            Some(other) => return Err(format!("amount_sent: invalid amount {}", other)),
+        }
+        .map(|amount| {
+            amount
+                .to_unsigned()
+                .map_err(|_| "amount_sent: amount must not be negative".to_string())
+        })
+        .transpose()?;
+        for (name, bounds) in [
+            ("blocks_mined", raw.blocks_mined),
+            ("fee_rate", raw.fee_rate),
+            ("confirmation_depth", raw.confirmation_depth),
+        ] {
+            if let Some(Bounds {
+                min: Some(min),
+                max: Some(max),
+                ..
+            }) = bounds
+            {
+                if min > max {
+                    return Err(format!("{}: min {} is above max {}", name, min, max));
+                }
+            }
+        }
+        Ok(Expectations {
+            amount_sent,
+            blocks_mined: raw.blocks_mined,
+            fee_rate: raw.fee_rate,
+            confirmation_depth: raw.confirmation_depth,
+        })
+    }
+}
+
+// What the run observed beyond the ten lines of out.txt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunFacts {
+    pub blocks_mined: u64,
+    pub vsize: u64,
+    pub confirmations: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeCheck {
+    pub name: &'static str,
+    pub actual: String,
+    pub expected: String,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GradeReport {
+    pub checks: Vec<GradeCheck>,
+}
+
+impl GradeReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| !check.passed).count()
+    }
+}
+
+impl fmt::Display for GradeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(
+                f,
+                "{} {}: actual {}, expected {}",
+                if check.passed { "PASS" } else { "FAIL" },
+                check.name,
+                check.actual,
+                check.expected
+            )?;
+        }
+        write!(
+            f,
+            "Overall: {} ({} of {} checks passed)",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.checks.len() - self.failures(),
+            self.checks.len()
+        )
+    }
+}
+
+fn bounds_check(name: &'static str, actual: f64, bounds: &Bounds) -> GradeCheck {
+    GradeCheck {
+        name,
+        actual: actual.to_string(),
+        expected: bounds.to_string(),
+        passed: bounds.contains(actual),
+    }
+}
+
+// Grade a run: one check per expectation that was given.
+pub fn grade(report: &Report, facts: &RunFacts, expectations: &Expectations) -> GradeReport {
+    let mut checks = Vec::new();
+    if let Some(amount) = expectations.amount_sent {
+        checks.push(GradeCheck {
+            name: "amount_sent",
+            actual: format_btc(report.trader_output_amount),
+            expected: format_btc(amount),
+            passed: report.trader_output_amount == amount,
+        });
+    }
+    if let Some(bounds) = &expectations.blocks_mined {
+        checks.push(bounds_check(
+            "blocks_mined",
+            facts.blocks_mined as f64,
+            bounds,
+        ));
+    }
+    if let Some(bounds) = &expectations.fee_rate {
+        let fee_rate = if facts.vsize == 0 {
+            f64::NAN
+        } else {
+            report.fee.to_sat() as f64 / facts.vsize as f64
+        };
+        checks.push(bounds_check("fee_rate", fee_rate, bounds));
+    }
+    if let Some(bounds) = &expectations.confirmation_depth {
+        checks.push(bounds_check(
+            "confirmation_depth",
+            facts.confirmations as f64,
+            bounds,
+        ));
+    }
+    GradeReport { checks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(fee_sats: u64) -> Report {
+        Report {
+            txid: "ab".repeat(32),
+            miner_input_address: "bcrt1qminer".to_string(),
+            miner_input_amount: Amount::from_int_btc(50),
+            trader_output_address: "bcrt1qtrader".to_string(),
+            trader_output_amount: Amount::from_int_btc(20),
+            miner_change_address: "bcrt1qchange".to_string(),
+            miner_change_amount: Amount::from_sat(3_000_000_000 - fee_sats),
+            fee: Amount::from_sat(fee_sats),
+            block_height: 102,
+            block_hash: "cd".repeat(32),
+            confirmation_time: None,
+            vsize: None,
+            recipients: Vec::new(),
+            fee_bump: None,
+            multisig: None,
+            fingerprint: None,
+        }
+    }
+
+    const FACTS: RunFacts = RunFacts {
+        blocks_mined: 102,
+        vsize: 141,
+        confirmations: 1,
+    };
+
+    fn bounds(min: Option<f64>, max: Option<f64>) -> Bounds {
+        Bounds {
+            min,
+            max,
+            ..Bounds::default()
+        }
+    }
+
+    #[test]
+    fn parses_full_expectations() {
+        let expectations = Expectations::parse(
+            r#"
+            amount_sent = "20"
+            blocks_mined = { min = 101, max = 102 }
+            fee_rate = { min = 1, max = 10.5, max_exclusive = true }
+            confirmation_depth = { min = 1 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(expectations.amount_sent, Some(Amount::from_int_btc(20)));
+        assert_eq!(
+            expectations.blocks_mined,
+            Some(bounds(Some(101.0), Some(102.0)))
+        );
+        assert_eq!(
+            expectations.fee_rate,
+            Some(Bounds {
+                max_exclusive: true,
+                ..bounds(Some(1.0), Some(10.5))
+            })
+        );
+        assert_eq!(
+            expectations.confirmation_depth,
+            Some(bounds(Some(1.0), None))
+        );
+    }
+
+    #[test]
+    fn amount_accepts_numbers_and_strings() {
+        for text in [
+            "amount_sent = 20",
+            "amount_sent = 20.0",
+            "amount_sent = \"20.00000000\"",
+        ] {
+            assert_eq!(
+                Expectations::parse(text).unwrap().amount_sent,
+                Some(Amount::from_int_btc(20)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_expectations() {
+        assert!(Expectations::parse("amount_sent = -1").is_err());
+        assert!(Expectations::parse("amount_sent = \"0.000000001\"").is_err());
+        assert!(Expectations::parse("amount_sent = true").is_err());
+        assert!(Expectations::parse("fee_rate = { min = 5, max = 1 }").is_err());
+        assert!(Expectations::parse("fee_rate = { minimum = 1 }").is_err());
+        assert!(Expectations::parse("blocks = { min = 1 }").is_err());
+        assert!(Expectations::parse("amount_sent = ").is_err());
+    }
+
+    #[test]
+    fn empty_file_grades_nothing() {
+        let expectations = Expectations::parse("").unwrap();
+        let grade = grade(&report(141), &FACTS, &expectations);
+        assert!(grade.checks.is_empty());
+        assert!(grade.passed());
+    }
+
+    #[test]
+    fn inclusive_bounds_accept_their_ends() {
+        let range = bounds(Some(1.0), Some(10.0));
+        assert!(range.contains(1.0));
+        assert!(range.contains(10.0));
+        assert!(!range.contains(0.999));
+        assert!(!range.contains(10.001));
+    }
+
+    #[test]
+    fn exclusive_bounds_reject_their_ends() {
+        let range = Bounds {
+            min: Some(1.0),
+            max: Some(10.0),
+            min_exclusive: true,
+            max_exclusive: true,
+        };
+        assert!(!range.contains(1.0));
+        assert!(!range.contains(10.0));
+        assert!(range.contains(1.001));
+        assert!(range.contains(9.999));
+    }
+
+    #[test]
+    fn open_bounds_and_nan() {
+        assert!(bounds(None, None).contains(1e12));
+        assert!(bounds(Some(1.0), None).contains(1e12));
+        assert!(bounds(None, Some(1.0)).contains(-1e12));
+        assert!(!bounds(None, None).contains(f64::NAN));
+    }
+
+    #[test]
+    fn bounds_display_marks_exclusive_ends() {
+        assert_eq!(bounds(Some(1.0), Some(10.0)).to_string(), "[1, 10]");
+        let half_open = Bounds {
+            max_exclusive: true,
+            ..bounds(Some(1.5), Some(10.0))
+        };
+        assert_eq!(half_open.to_string(), "[1.5, 10)");
+        assert_eq!(
+            Bounds {
+                min_exclusive: true,
+                ..bounds(Some(0.0), None)
+            }
+            .to_string(),
+            "(0, inf)"
+        );
+    }
+
+    #[test]
+    fn fee_rate_at_the_boundary() {
+        // 141 sats over 141 vB is exactly 1 sat/vB
+        let inclusive = Expectations {
+            fee_rate: Some(bounds(Some(1.0), Some(1.0))),
+            ..Expectations::default()
+        };
+        assert!(grade(&report(141), &FACTS, &inclusive).passed());
+        let exclusive = Expectations {
+            fee_rate: Some(Bounds {
+                min_exclusive: true,
+                ..bounds(Some(1.0), None)
+            }),
+            ..Expectations::default()
+        };
+        assert!(!grade(&report(141), &FACTS, &exclusive).passed());
+        assert!(grade(&report(142), &FACTS, &exclusive).passed());
+    }
+
+    #[test]
+    fn zero_vsize_fails_fee_rate() {
+        let expectations = Expectations {
+            fee_rate: Some(bounds(None, None)),
+            ..Expectations::default()
+        };
+        let facts = RunFacts { vsize: 0, ..FACTS };
+        assert!(!grade(&report(141), &facts, &expectations).passed());
+    }
+
+    #[test]
+    fn grades_every_check_with_actual_and_expected() {
+        let expectations = Expectations::parse(
+            r#"
+            amount_sent = 20
+            blocks_mined = { min = 101, max = 102, max_exclusive = true }
+            fee_rate = { min = 1, max = 10 }
+            confirmation_depth = { min = 1, max = 1 }
+            "#,
+        )
+        .unwrap();
+        let grade = grade(&report(282), &FACTS, &expectations);
+        assert_eq!(
+            grade.checks,
+            vec![
+                GradeCheck {
+                    name: "amount_sent",
+                    actual: "20.00000000".to_string(),
+                    expected: "20.00000000".to_string(),
+                    passed: true,
+                },
+                GradeCheck {
+                    name: "blocks_mined",
+                    actual: "102".to_string(),
+                    expected: "[101, 102)".to_string(),
+                    passed: false,
+                },
+                GradeCheck {
+                    name: "fee_rate",
+                    actual: "2".to_string(),
+                    expected: "[1, 10]".to_string(),
+                    passed: true,
+                },
+                GradeCheck {
+                    name: "confirmation_depth",
+                    actual: "1".to_string(),
+                    expected: "[1, 1]".to_string(),
+                    passed: true,
+                },
+            ]
+        );
+        assert!(!grade.passed());
+        assert_eq!(grade.failures(), 1);
+        assert_eq!(
+            grade.to_string().lines().last().unwrap(),
+            "Overall: FAIL (3 of 4 checks passed)"
+        );
+        assert!(grade
+            .to_string()
+            .contains("FAIL blocks_mined: actual 102, expected [101, 102)"));
+    }
+
+    #[test]
+    fn wrong_amount_fails() {
+        let expectations = Expectations {
+            amount_sent: Some(Amount::from_sat(2_000_000_001)),
+            ..Expectations::default()
+        };
+        let grade = grade(&report(141), &FACTS, &expectations);
+        assert!(!grade.passed());
+        assert_eq!(grade.checks[0].expected, "20.00000001");
+    }
+
+    #[test]
+    fn load_reports_missing_file() {
+        let err = Expectations::load(Path::new("/nonexistent/expected.toml")).unwrap_err();
+        assert!(err.contains("/nonexistent/expected.toml"));
+    }
+}
@@ -0,0 +1,318 @@
+// The machine-readable JSON form of the out.txt report, for downstream tools.
+//
+// Every report carries a `schema_version`. The JSON Schema generated from
+// `JsonReport` is checked in at `schemas/report.schema.json` and embedded in
+// the binary, so `report validate-json <file>` can check any file against it.
+// `report verify <out.txt>` is its counterpart for the text report.
+//
+// Compatibility rules: new fields must have a serde default so reports from
+// older versions still deserialize, and bump `SCHEMA_VERSION`. Removing or
+// renaming a field is a breaking change; the fixtures in
+// `testdata/json-reports/` (one per released version) make the tests fail if
+// that happens. After changing the types, regenerate the schema with
+// `UPDATE_REPORT_SCHEMA=1 cargo test`.
+use crate::report::Report;
+use crate::rpc_profile::MethodProfile;
+use bitcoincore_rpc::bitcoin::Amount;
+use jsonschema::JSONSchema;
+use log::{error, info};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+pub const SCHEMA_VERSION: u32 = 2;
+pub const SCHEMA: &str = include_str!("../schemas/report.schema.json");
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonReport {
+    pub schema_version: u32,
+    pub txid: String,
+    pub miner_input_address: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    #[schemars(with = "f64")]
+    pub miner_input_amount: Amount,
+    pub trader_output_address: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    #[schemars(with = "f64")]
+    pub trader_output_amount: Amount,
+    pub miner_change_address: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    #[schemars(with = "f64")]
+    pub miner_change_amount: Amount,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    #[schemars(with = "f64")]
+    pub fee: Amount,
+    pub block_height: u64,
+    pub block_hash: String,
+    // Added in version 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ReportMeta>,
+}
+
+// How the report was produced, rather than what it reports
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ReportMeta {
+    // Per-method RPC timings, present with --profile
+    #[serde(default)]
+    pub rpc_profile: Vec<MethodProfile>,
+}
+
+impl From<&Report> for JsonReport {
+    fn from(report: &Report) -> Self {
+        JsonReport {
+            schema_version: SCHEMA_VERSION,
+            txid: report.txid.clone(),
+            miner_input_address: report.miner_input_address.clone(),
+            miner_input_amount: report.miner_input_amount,
+            trader_output_address: report.trader_output_address.clone(),
+            trader_output_amount: report.trader_output_amount,
+            miner_change_address: report.miner_change_address.clone(),
+            miner_change_amount: report.miner_change_amount,
+            fee: report.fee,
+            block_height: report.block_height,
+            block_hash: report.block_hash.clone(),
+            meta: None,
+        }
+    }
+}
+
+impl JsonReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report serializes to JSON")
+    }
+}
+
+// The schema as generated from the current types, checked against the
+// embedded one by the tests.
+#[cfg(test)]
+pub fn generate_schema() -> String {
+    let schema = schemars::schema_for!(JsonReport);
+    serde_json::to_string_pretty(&schema).expect("schema serializes to JSON") + "\n"
+}
+
+// Validate a report against the embedded schema. Returns one line per
+// violation, empty if the report is valid.
+pub fn validate_json(text: &str) -> Result<Vec<String>, String> {
+    let instance: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("not valid JSON: {}", e))?;
+    let schema: serde_json::Value =
+        serde_json::from_str(SCHEMA).expect("embedded schema is valid JSON");
+    let compiled = JSONSchema::compile(&schema).expect("embedded schema compiles");
+    let violations = match compiled.validate(&instance) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                let path = if path.is_empty() { "/" } else { &path };
+                format!("{}: {}", path, e)
+            })
+            .collect(),
+    };
+    Ok(violations)
+}
+
+// `report validate-json <file>` or `report verify <out.txt>`; `args` excludes
+// "report".
+pub fn run_command(args: &[String]) -> io::Result<()> {
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("verify"), Some(path)) => {
+            let text = std::fs::read_to_string(Path::new(path))?;
+            let report = Report::parse_lenient(&text).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e))
+            })?;
+            info!(
+                "{} is a valid report; normalized:\n{}",
+                path,
+                report.to_text()
+            );
+            Ok(())
+        }
+        (Some("validate-json"), Some(path)) => {
+            let text = std::fs::read_to_string(Path::new(path))?;
+            let violations =
+                validate_json(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if violations.is_empty() {
+                info!(
+                    "{} is a valid schema version {} report",
+                    path, SCHEMA_VERSION
+                );
+                return Ok(());
+            }
+            for violation in &violations {
+                error!("{}", violation);
+            }
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has {} schema violation(s)", path, violations.len()),
+            ))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: report validate-json <file> | report verify <out.txt>",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::fs;
+
+    const FIXTURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/json-reports");
+
+    fn sample() -> Report {
+        Report {
+            txid: "a".repeat(64),
+            miner_input_address: "bcrt1qminer".to_string(),
+            miner_input_amount: Amount::from_int_btc(50),
+            trader_output_address: "bcrt1qtrader".to_string(),
+            trader_output_amount: Amount::from_int_btc(20),
+            miner_change_address: "bcrt1qchange".to_string(),
+            miner_change_amount: Amount::from_sat(2_999_998_590),
+            fee: Amount::from_sat(1_410),
+            block_height: 102,
+            block_hash: "b".repeat(64),
+            confirmation_time: None,
+            vsize: None,
+            recipients: Vec::new(),
+            fee_bump: None,
+            multisig: None,
+            fingerprint: None,
+        }
+    }
+
+    // (file name, fixture contents) of every released schema version
+    fn fixtures() -> Vec<(String, String)> {
+        let mut files: Vec<_> = fs::read_dir(FIXTURE_DIR)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+        assert!(!files.is_empty(), "no fixtures in {}", FIXTURE_DIR);
+        files
+            .into_iter()
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, fs::read_to_string(&path).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn checked_in_schema_is_up_to_date() {
+        let generated = generate_schema();
+        if std::env::var_os("UPDATE_REPORT_SCHEMA").is_some() {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/schemas/report.schema.json");
+            fs::write(path, &generated).unwrap();
+            return;
+        }
+        assert!(
+            generated == SCHEMA,
+            "schemas/report.schema.json is stale; regenerate it with UPDATE_REPORT_SCHEMA=1 cargo test"
+        );
+    }
+
+    #[test]
+    fn current_report_round_trips_and_validates() {
+        let report = JsonReport::from(&sample());
+        let json = report.to_json();
+        assert_eq!(serde_json::from_str::<JsonReport>(&json).unwrap(), report);
+        assert_eq!(validate_json(&json).unwrap(), Vec::<String>::new());
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["fee"], 0.0000141);
+    }
+
+    #[test]
+    fn profile_is_embedded_in_meta() {
+        let mut report = JsonReport::from(&sample());
+        assert!(!report.to_json().contains("meta"));
+        report.meta = Some(ReportMeta {
+            rpc_profile: vec![MethodProfile {
+                method: "getblock".to_string(),
+                calls: 2,
+                total_us: 1_500,
+                max_us: 1_000,
+            }],
+        });
+        let json = report.to_json();
+        assert_eq!(validate_json(&json).unwrap(), Vec::<String>::new());
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["meta"]["rpc_profile"][0]["method"], "getblock");
+        assert_eq!(serde_json::from_str::<JsonReport>(&json).unwrap(), report);
+    }
+
+    #[test]
+    fn previous_versions_deserialize_into_current_types() {
+        for (name, text) in fixtures() {
+            let report: JsonReport = serde_json::from_str(&text)
+                .unwrap_or_else(|e| panic!("{} no longer deserializes: {}", name, e));
+            assert!(report.schema_version <= SCHEMA_VERSION, "{}", name);
+            assert_eq!(
+                validate_json(&text).unwrap(),
+                Vec::<String>::new(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn no_released_field_was_removed() {
+        // With every optional field present
+        let mut report = JsonReport::from(&sample());
+        report.meta = Some(ReportMeta::default());
+        let current: BTreeSet<String> = serde_json::to_value(report)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        for (name, text) in fixtures() {
+            let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+            for field in value.as_object().unwrap().keys() {
+                assert!(
+                    current.contains(field),
+                    "field '{}' of {} was removed or renamed",
+                    field,
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn violations_name_the_offending_fields() {
+        let mut value = serde_json::to_value(JsonReport::from(&sample())).unwrap();
+        value["block_height"] = serde_json::json!(-1);
+        value["fee"] = serde_json::json!("0.0000141");
+        value.as_object_mut().unwrap().remove("txid");
+        let violations = validate_json(&value.to_string()).unwrap();
+        assert_eq!(violations.len(), 3, "{:?}", violations);
+        assert!(violations.iter().any(|v| v.starts_with("/block_height:")));
+        assert!(violations.iter().any(|v| v.starts_with("/fee:")));
+        assert!(violations
+            .iter()
+            .any(|v| v.starts_with("/:") && v.contains("txid")));
+    }
+
+    #[test]
+    fn invalid_json_is_an_error() {
+        assert!(validate_json("{ not json").is_err());
+    }
+
+    #[test]
+    fn verify_accepts_messy_reports_leniently() {
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/reports/");
+        let verify =
+            |name: &str| run_command(&["verify".to_string(), format!("{}{}", corpus, name)]);
+        assert!(verify("integer-amounts.txt").is_ok());
+        assert!(verify("crlf.txt").is_ok());
+        let err = verify("missing-line.txt").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
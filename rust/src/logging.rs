@@ -0,0 +1,69 @@
+// Logger setup: console output through env_logger, optionally teed to a file.
+//
+// The console honours `RUST_LOG` (default `info`) and prints bare messages so
+// the run looks the same as plain `println!` output. When a log file is given,
+// every record down to `debug` is also written there with its level, giving a
+// run record for debugging grading issues.
+use env_logger::{Builder, Env, Logger, Target};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+const FILE_LEVEL: LevelFilter = LevelFilter::Debug;
+
+struct TeeLogger {
+    console: Logger,
+    file: Option<Mutex<BufWriter<File>>>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata) || (self.file.is_some() && metadata.level() <= FILE_LEVEL)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+        if let Some(file) = &self.file {
+            if record.level() <= FILE_LEVEL {
+                if let Ok(mut file) = file.lock() {
+                    // A failing log file must not take the run down with it.
+                    let _ = writeln!(file, "[{}] {}", record.level(), record.args());
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+// Install the global logger, teeing to `log_file` when one is given.
+pub fn init(log_file: Option<&Path>) -> io::Result<()> {
+    let console = Builder::from_env(Env::default().default_filter_or("info"))
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .target(Target::Stdout)
+        .build();
+    let file = log_file
+        .map(|path| File::create(path).map(|f| Mutex::new(BufWriter::new(f))))
+        .transpose()?;
+
+    let max_level = if file.is_some() {
+        console.filter().max(FILE_LEVEL)
+    } else {
+        console.filter()
+    };
+    log::set_boxed_logger(Box::new(TeeLogger { console, file }))
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
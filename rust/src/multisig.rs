@@ -0,0 +1,288 @@
+// A 2-of-2 multisig between the Miner and the Trader (`--multisig`).
+//
+// Each wallet gives up the public key behind a fresh address of its own, with
+// the key's origin (master fingerprint and derivation path) from
+// `getaddressinfo`. The `wsh(multi(2,...))` descriptor over the two keys is
+// checksummed with `getdescriptorinfo` and imported into a watch-only
+// `Multisig` wallet, whose address the Miner then funds. The spend is a PSBT
+// made by the watch wallet: the key origins in it let each of the two wallets
+// find its key and sign its part with `walletprocesspsbt`. After the Miner's
+// signature alone the PSBT is incomplete and cannot be finalized; only the
+// Trader's signature makes it broadcastable.
+use crate::node_rpc::NodeRpc;
+use crate::psbt_send::{finalize_and_send, FinalizedPsbt, FundedPsbt, ProcessedPsbt};
+use crate::rpc_config::RpcConfig;
+use crate::wallet_lock::Wallet;
+use crate::{get_transaction, mine_blocks, rpc_call, wallet_ready, WALLET_SCAN_TIMEOUT};
+use bitcoincore_rpc::bitcoin::{Address, Amount};
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde::Serialize;
+use serde_json::json;
+
+pub const MULTISIG_WALLET: &str = "Multisig";
+pub const MULTISIG_AMOUNT: Amount = Amount::ONE_BTC;
+
+// What the report records of the multisig round trip
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultisigDemo {
+    pub address: String,
+    pub descriptor: String,
+    pub funding_txid: String,
+    pub spend_txid: String,
+}
+
+// The descriptor key expression `[fingerprint/path]pubkey` of a
+// `getaddressinfo` result
+pub fn key_expression(info: &serde_json::Value) -> Result<String, String> {
+    let address = info["address"].as_str().unwrap_or("?");
+    let field = |name: &str| {
+        info[name]
+            .as_str()
+            .ok_or_else(|| format!("getaddressinfo for {} has no {}", address, name))
+    };
+    let pubkey = field("pubkey")?;
+    let fingerprint = field("hdmasterfingerprint")?;
+    let path = field("hdkeypath")?;
+    let steps = path
+        .strip_prefix('m')
+        .ok_or_else(|| format!("unexpected key path '{}' for {}", path, address))?;
+    Ok(format!("[{}{}]{}", fingerprint, steps, pubkey))
+}
+
+// The 2-of-2 P2WSH descriptor over `keys`, without a checksum
+pub fn multisig_descriptor(keys: &[String; 2]) -> String {
+    format!("wsh(multi(2,{},{}))", keys[0], keys[1])
+}
+
+// Check that a PSBT signed by one wallet only is neither complete nor
+// extractable
+pub fn check_half_signed(
+    processed: &ProcessedPsbt,
+    finalized: FinalizedPsbt,
+) -> Result<(), String> {
+    if processed.complete {
+        return Err("the PSBT was complete after the first signature of a 2-of-2".to_string());
+    }
+    if finalized.into_hex().is_ok() {
+        return Err("finalizepsbt extracted a transaction with one signature of two".to_string());
+    }
+    Ok(())
+}
+
+// Helper function to take the key behind a fresh address of `wallet`
+fn wallet_key(wallet: &impl RpcApi) -> bitcoincore_rpc::Result<String> {
+    let address = rpc_call::<String>(wallet, "getnewaddress", &[json!("Multisig Key")])?;
+    let info = rpc_call::<serde_json::Value>(wallet, "getaddressinfo", &[json!(address)])?;
+    key_expression(&info).map_err(Error::ReturnedError)
+}
+
+// Helper function to open the watch-only Multisig wallet, creating it blank and
+// without private keys the first time
+fn open_watch_wallet<R: NodeRpc + RpcApi>(
+    rpc: &R,
+    config: &RpcConfig,
+) -> bitcoincore_rpc::Result<R> {
+    let loaded = rpc_call::<Vec<String>>(rpc, "listwallets", &[])?;
+    if !loaded.iter().any(|name| name == MULTISIG_WALLET)
+        && rpc_call::<serde_json::Value>(rpc, "loadwallet", &[json!(MULTISIG_WALLET)]).is_err()
+    {
+        // name, disable_private_keys, blank, passphrase, avoid_reuse, descriptors
+        let args = [
+            json!(MULTISIG_WALLET),
+            json!(true),
+            json!(true),
+            json!(""),
+            json!(false),
+            json!(true),
+        ];
+        rpc_call::<serde_json::Value>(rpc, "createwallet", &args)?;
+        info!("Wallet '{}' created (watch-only)", MULTISIG_WALLET);
+    }
+    let wallet = rpc.wallet(config, MULTISIG_WALLET)?;
+    wallet_ready::wait_for_wallet_ready(&wallet, WALLET_SCAN_TIMEOUT)?;
+    Ok(wallet)
+}
+
+// Helper function to build the multisig over a key from `miner` and one from
+// `trader`, fund it from `miner`, and spend it back to `miner` with both
+// signatures. Blocks are mined to `mining_address`.
+pub fn demonstrate<R: NodeRpc + RpcApi>(
+    rpc: &R,
+    config: &RpcConfig,
+    (miner_name, miner): (&str, &R),
+    trader: &R,
+    mining_address: &Address,
+) -> bitcoincore_rpc::Result<MultisigDemo> {
+    let keys = [wallet_key(miner)?, wallet_key(trader)?];
+    let info = rpc_call::<serde_json::Value>(
+        rpc,
+        "getdescriptorinfo",
+        &[json!(multisig_descriptor(&keys))],
+    )?;
+    let descriptor = info["descriptor"]
+        .as_str()
+        .ok_or_else(|| Error::ReturnedError(format!("getdescriptorinfo returned {}", info)))?
+        .to_string();
+    let address = rpc_call::<Vec<String>>(rpc, "deriveaddresses", &[json!(descriptor)])?
+        .pop()
+        .ok_or_else(|| Error::ReturnedError(format!("{} derives no address", descriptor)))?;
+    info!("Multisig descriptor: {}", descriptor);
+    info!("Multisig address: {}", address);
+
+    let watch = open_watch_wallet(rpc, config)?;
+    let request = json!([{ "desc": descriptor, "timestamp": "now", "label": "Multisig" }]);
+    let results = rpc_call::<Vec<serde_json::Value>>(&watch, "importdescriptors", &[request])?;
+    if let Some(failed) = results
+        .iter()
+        .find(|result| result["success"].as_bool() != Some(true))
+    {
+        return Err(Error::ReturnedError(format!(
+            "importdescriptors failed for wallet '{}': {}",
+            MULTISIG_WALLET, failed["error"]
+        )));
+    }
+
+    let funding_txid = Wallet::new(miner_name, miner).call::<String>(
+        "sendtoaddress",
+        &[json!(address), json!(MULTISIG_AMOUNT.to_btc())],
+    )?;
+    mine_blocks(rpc, mining_address, 1)?;
+    let funding = get_transaction(rpc, &funding_txid)?;
+    let vout = funding
+        .vout
+        .iter()
+        .find(|output| output.address() == Some(address.as_str()))
+        .map(|output| output.n)
+        .ok_or_else(|| {
+            Error::ReturnedError(format!("{} does not pay {}", funding_txid, address))
+        })?;
+    info!("Multisig funded by {}:{}", funding_txid, vout);
+
+    // The whole output goes back to the Miner, less the fee, so the watch
+    // wallet needs no change address
+    let destination = rpc_call::<String>(miner, "getnewaddress", &[json!("Multisig Return")])?;
+    let args = [
+        json!([{ "txid": funding_txid, "vout": vout }]),
+        json!([{ destination: MULTISIG_AMOUNT.to_btc() }]),
+        json!(0),
+        json!({ "subtractFeeFromOutputs": [0] }),
+    ];
+    let funded = rpc_call::<FundedPsbt>(&watch, "walletcreatefundedpsbt", &args)?;
+
+    let half = rpc_call::<ProcessedPsbt>(miner, "walletprocesspsbt", &[json!(funded.psbt)])?;
+    let finalized = rpc_call::<FinalizedPsbt>(rpc, "finalizepsbt", &[json!(half.psbt)])?;
+    check_half_signed(&half, finalized).map_err(Error::ReturnedError)?;
+    info!("Signed by the Miner only: incomplete, cannot be broadcast");
+
+    let both = rpc_call::<ProcessedPsbt>(trader, "walletprocesspsbt", &[json!(half.psbt)])?;
+    if !both.complete {
+        return Err(Error::ReturnedError(
+            "the PSBT is still incomplete after both signatures".to_string(),
+        ));
+    }
+    let spend_txid = finalize_and_send(rpc, &both.psbt)?;
+    mine_blocks(rpc, mining_address, 1)?;
+    info!("Signed by the Trader too: spent by {}", spend_txid);
+
+    Ok(MultisigDemo {
+        address,
+        descriptor,
+        funding_txid,
+        spend_txid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINER_KEY: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+    const TRADER_KEY: &str = "03e60fce93b59e9ec53011aabc21c23e97b2a31369b87a5ae9c44ee89e2a6dec0a";
+
+    #[test]
+    fn keys_carry_their_origin() {
+        let info = json!({
+            "address": "bcrt1qexample",
+            "pubkey": MINER_KEY,
+            "hdmasterfingerprint": "d34db33f",
+            "hdkeypath": "m/84h/1h/0h/0/7",
+        });
+        assert_eq!(
+            key_expression(&info).unwrap(),
+            format!("[d34db33f/84h/1h/0h/0/7]{}", MINER_KEY)
+        );
+        // A key without HD origin cannot be found again when signing
+        let bare = json!({ "address": "bcrt1qexample", "pubkey": MINER_KEY });
+        assert_eq!(
+            key_expression(&bare).unwrap_err(),
+            "getaddressinfo for bcrt1qexample has no hdmasterfingerprint"
+        );
+    }
+
+    #[test]
+    fn the_descriptor_needs_both_keys() {
+        let keys = [
+            format!("[d34db33f/84h/1h/0h/0/7]{}", MINER_KEY),
+            format!("[0badcafe/84h/1h/0h/0/2]{}", TRADER_KEY),
+        ];
+        assert_eq!(
+            multisig_descriptor(&keys),
+            format!(
+                "wsh(multi(2,[d34db33f/84h/1h/0h/0/7]{},[0badcafe/84h/1h/0h/0/2]{}))",
+                MINER_KEY, TRADER_KEY
+            )
+        );
+    }
+
+    #[test]
+    fn one_signature_of_two_is_not_enough() {
+        let half = ProcessedPsbt {
+            psbt: "cHNidP8B".to_string(),
+            complete: false,
+        };
+        let stuck = FinalizedPsbt {
+            complete: false,
+            hex: None,
+            psbt: Some("cHNidP8B".to_string()),
+        };
+        assert!(check_half_signed(&half, stuck.clone()).is_ok());
+
+        let complete = ProcessedPsbt {
+            complete: true,
+            ..half.clone()
+        };
+        assert!(check_half_signed(&complete, stuck).is_err());
+        let extracted = FinalizedPsbt {
+            complete: true,
+            hex: Some("0200".to_string()),
+            psbt: None,
+        };
+        assert!(check_half_signed(&half, extracted).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn both_wallets_sign_the_multisig_spend() {
+        use crate::{create_or_load_wallet, get_wallet_client};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        create_or_load_wallet(&rpc, &config, "Trader", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let trader = get_wallet_client(&config, "Trader").unwrap();
+        let address = RpcApi::get_new_address(&miner, None, None)
+            .unwrap()
+            .assume_checked();
+        mine_blocks(&rpc, &address, 101).unwrap();
+
+        let demo = demonstrate(&rpc, &config, ("Miner", &miner), &trader, &address).unwrap();
+        let spend = get_transaction(&rpc, &demo.spend_txid).unwrap();
+        assert_eq!(
+            spend.vin[0].txid.as_deref(),
+            Some(demo.funding_txid.as_str())
+        );
+        assert!(spend.confirmations.is_some());
+    }
+}
@@ -0,0 +1,362 @@
+// Which chain the run talks to, and what changes when it cannot mine.
+//
+// On regtest the run mines its own funds and confirmations. On a public signet
+// blocks are signed by the network's operators, so instead the Miner wallet
+// must be funded beforehand, confirmations are waited for rather than mined,
+// and fees come from the node's real `estimatesmartfee`. While waiting, the
+// node's own view of the chain (`getblockchaininfo`, `getmininginfo`) is
+// reported so a slow or still-syncing node is visible.
+use crate::report::format_btc;
+use crate::rpc_helpers::parse_block_hash;
+use bitcoincore_rpc::bitcoin::{Amount, BlockHash, Network};
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+// Default signet RPC port, the signet counterpart of 18443 on regtest
+const SIGNET_RPC_URL: &str = "http://127.0.0.1:38332";
+// How often to poll the node while waiting for funds or confirmations
+pub const POLL_INTERVAL: Duration = Duration::from_secs(10);
+// Fee rates are in sat/vB; the node's fee estimates are in BTC/kvB
+const SAT_VB_PER_BTC_KVB: f64 = 100_000_000.0 / 1_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainNetwork {
+    Regtest,
+    Signet,
+}
+
+impl ChainNetwork {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "regtest" => Ok(ChainNetwork::Regtest),
+            "signet" => Ok(ChainNetwork::Signet),
+            other => Err(format!(
+                "unsupported network '{}': expected regtest or signet",
+                other
+            )),
+        }
+    }
+
+    // The name `getblockchaininfo` reports as `chain`
+    pub fn chain_name(self) -> &'static str {
+        match self {
+            ChainNetwork::Regtest => "regtest",
+            ChainNetwork::Signet => "signet",
+        }
+    }
+
+    // The network addresses on this chain are encoded for
+    pub fn bitcoin_network(self) -> Network {
+        match self {
+            ChainNetwork::Regtest => Network::Regtest,
+            ChainNetwork::Signet => Network::Signet,
+        }
+    }
+
+    pub fn rpc_url(self) -> &'static str {
+        match self {
+            ChainNetwork::Regtest => crate::RPC_URL,
+            ChainNetwork::Signet => SIGNET_RPC_URL,
+        }
+    }
+
+    // Whether the run can mine its own blocks on this chain
+    pub fn can_mine(self) -> bool {
+        self == ChainNetwork::Regtest
+    }
+
+    // Refuse to run against a node on a different chain than requested.
+    pub fn check_chain(self, chain: &str) -> Result<(), String> {
+        if chain == self.chain_name() {
+            Ok(())
+        } else {
+            Err(format!(
+                "--network {} was requested but the node is on {}",
+                self.chain_name(),
+                chain
+            ))
+        }
+    }
+}
+
+// How to fund the Miner wallet when there is nothing to mine with.
+pub fn funding_guidance(address: &str, needed: Amount) -> Vec<String> {
+    vec![
+        format!(
+            "The Miner wallet needs at least {} BTC on signet before the run can continue.",
+            format_btc(needed)
+        ),
+        format!(
+            "Send signet coins to {} from any wallet you control,",
+            address
+        ),
+        "or ask someone in your class or team to send some: no public faucet is required."
+            .to_string(),
+        "On a custom signet you operate, mine to this address with the signet miner script."
+            .to_string(),
+    ]
+}
+
+// One progress line while waiting for a transaction to confirm.
+pub fn confirmation_progress(
+    confirmations: u64,
+    depth: u64,
+    tip_height: u64,
+    verification_progress: f64,
+) -> String {
+    format!(
+        "{}/{} confirmations, tip at height {}, node {:.2}% verified",
+        confirmations,
+        depth,
+        tip_height,
+        verification_progress * 100.0
+    )
+}
+
+// Fee rate in sat/vB from an `estimatesmartfee` result, if the node has one.
+pub fn fee_rate_from_estimate(estimate: &serde_json::Value) -> Option<f64> {
+    estimate["feerate"]
+        .as_f64()
+        .map(|btc_per_kvb| btc_per_kvb * SAT_VB_PER_BTC_KVB)
+}
+
+// Helper function to ask the node for a fee rate (sat/vB) confirming within
+// `conf_target` blocks
+pub fn estimate_fee_rate(
+    rpc: &impl RpcApi,
+    conf_target: u16,
+) -> bitcoincore_rpc::Result<Option<f64>> {
+    let estimate = rpc.call::<serde_json::Value>("estimatesmartfee", &[json!(conf_target)])?;
+    Ok(fee_rate_from_estimate(&estimate))
+}
+
+// Poll `balance` every `interval` until it reaches `needed`. Fails once
+// `timeout` has passed; progress is logged whenever the balance changes.
+fn poll_balance<F>(
+    needed: Amount,
+    timeout: Duration,
+    interval: Duration,
+    mut balance: F,
+) -> bitcoincore_rpc::Result<Amount>
+where
+    F: FnMut() -> bitcoincore_rpc::Result<Amount>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut last_logged = None;
+    loop {
+        let balance = balance()?;
+        if balance >= needed {
+            return Ok(balance);
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::ReturnedError(format!(
+                "wallet balance is {} BTC after {:?}, {} BTC needed",
+                format_btc(balance),
+                timeout,
+                format_btc(needed)
+            )));
+        }
+        if last_logged != Some(balance) {
+            info!(
+                "Waiting for funds: {} of {} BTC",
+                format_btc(balance),
+                format_btc(needed)
+            );
+            last_logged = Some(balance);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+// Helper function to wait until the wallet's trusted balance reaches `needed`,
+// checking every `interval`
+pub fn wait_for_balance(
+    wallet: &impl RpcApi,
+    needed: Amount,
+    timeout: Duration,
+    interval: Duration,
+) -> bitcoincore_rpc::Result<Amount> {
+    poll_balance(needed, timeout, interval, || wallet.get_balance(None, None))
+}
+
+// Helper function to wait until `txid` has `depth` confirmations in `wallet`,
+// reporting progress from the node. Returns the hash of the block that
+// confirmed it, or None if the wait timed out.
+pub fn wait_for_confirmations(
+    rpc: &impl RpcApi,
+    wallet: &impl RpcApi,
+    txid: &str,
+    depth: u64,
+    timeout: Duration,
+) -> bitcoincore_rpc::Result<Option<BlockHash>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let wallet_tx = wallet.call::<serde_json::Value>("gettransaction", &[json!(txid)])?;
+        let confirmations = wallet_tx["confirmations"].as_i64().unwrap_or(0).max(0) as u64;
+        if confirmations >= depth {
+            return wallet_tx["blockhash"]
+                .as_str()
+                .map(parse_block_hash)
+                .transpose();
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        let chain = rpc.call::<serde_json::Value>("getblockchaininfo", &[])?;
+        let mining = rpc.call::<serde_json::Value>("getmininginfo", &[])?;
+        info!(
+            "{}",
+            confirmation_progress(
+                confirmations,
+                depth,
+                mining["blocks"].as_u64().unwrap_or(0),
+                chain["verificationprogress"].as_f64().unwrap_or(0.0),
+            )
+        );
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balance_is_polled_until_it_is_enough() {
+        let mut balances = [0, 0, 50, 100].into_iter().map(Amount::from_int_btc);
+        let mut polls = 0;
+        let balance = poll_balance(
+            Amount::from_int_btc(20),
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+            || {
+                polls += 1;
+                Ok(balances.next().unwrap())
+            },
+        )
+        .unwrap();
+        assert_eq!(balance, Amount::from_int_btc(50));
+        assert_eq!(polls, 3);
+    }
+
+    #[test]
+    fn balance_wait_times_out() {
+        let started = Instant::now();
+        let err = poll_balance(
+            Amount::from_int_btc(20),
+            Duration::from_millis(30),
+            Duration::from_millis(5),
+            || Ok(Amount::from_int_btc(1)),
+        )
+        .unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert!(
+            err.to_string().contains("1.00000000 BTC after 30ms"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn parses_supported_networks() {
+        assert_eq!(
+            ChainNetwork::parse("regtest").unwrap(),
+            ChainNetwork::Regtest
+        );
+        assert_eq!(ChainNetwork::parse("signet").unwrap(), ChainNetwork::Signet);
+        assert!(ChainNetwork::parse("main").is_err());
+        assert!(ChainNetwork::parse("Signet").is_err());
+    }
+
+    #[test]
+    fn only_regtest_can_mine() {
+        assert!(ChainNetwork::Regtest.can_mine());
+        assert!(!ChainNetwork::Signet.can_mine());
+        assert_eq!(ChainNetwork::Signet.rpc_url(), "http://127.0.0.1:38332");
+        assert_eq!(ChainNetwork::Signet.bitcoin_network(), Network::Signet);
+    }
+
+    #[test]
+    fn chain_mismatch_is_rejected() {
+        assert!(ChainNetwork::Signet.check_chain("signet").is_ok());
+        let err = ChainNetwork::Signet.check_chain("regtest").unwrap_err();
+        assert!(err.contains("node is on regtest"));
+    }
+
+    #[test]
+    fn progress_line() {
+        assert_eq!(
+            confirmation_progress(0, 1, 212_345, 0.9999),
+            "0/1 confirmations, tip at height 212345, node 99.99% verified"
+        );
+    }
+
+    #[test]
+    fn fee_rate_from_smart_fee_estimate() {
+        let estimate = json!({ "feerate": 0.00002, "blocks": 6 });
+        assert_eq!(fee_rate_from_estimate(&estimate), Some(2.0));
+        let no_data = json!({ "errors": ["Insufficient data or no feerate found"], "blocks": 0 });
+        assert_eq!(fee_rate_from_estimate(&no_data), None);
+    }
+
+    #[test]
+    fn guidance_names_address_and_amount() {
+        let lines = funding_guidance("tb1qexample", Amount::from_sat(2_000_100_000));
+        assert!(lines[0].contains("20.00100000 BTC"));
+        assert!(lines[1].contains("tb1qexample"));
+    }
+
+    // Needs a local custom signet whose challenge is OP_TRUE, so anyone can
+    // produce valid blocks, e.g.
+    // `bitcoind -signet -signetchallenge=51 -rpcport=38332 -txindex -fallbackfee=0.0001`
+    #[test]
+    #[ignore = "requires a custom signet bitcoind with -signetchallenge=51"]
+    fn confirms_on_custom_signet_with_generateblock() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client};
+
+        let signet = ChainNetwork::Signet;
+        let config = RpcConfig::load_with_defaults(RpcConfig {
+            url: signet.rpc_url().to_string(),
+            ..RpcConfig::default()
+        })
+        .unwrap();
+        let rpc = config.client().unwrap();
+        signet
+            .check_chain(&rpc.get_blockchain_info().unwrap().chain.to_string())
+            .unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked()
+            .to_string();
+
+        // Fund the wallet with signed (here: trivially signed) blocks
+        for _ in 0..101 {
+            rpc.call::<serde_json::Value>("generateblock", &[json!(address), json!([])])
+                .unwrap();
+        }
+        wait_for_balance(
+            &miner,
+            Amount::from_int_btc(1),
+            Duration::from_secs(30),
+            Duration::from_millis(100),
+        )
+        .unwrap();
+
+        let txid = miner
+            .call::<String>("sendtoaddress", &[json!(address), json!(1)])
+            .unwrap();
+        let block = rpc
+            .call::<serde_json::Value>("generateblock", &[json!(address), json!([txid])])
+            .unwrap();
+        let confirmed_in =
+            wait_for_confirmations(&rpc, &miner, &txid, 1, Duration::from_secs(30)).unwrap();
+        let block_hash = block["hash"].as_str().unwrap();
+        assert_eq!(confirmed_in.unwrap().to_string(), block_hash);
+    }
+}
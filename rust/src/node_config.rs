@@ -0,0 +1,279 @@
+// NodeConfigReader: read `bitcoin.conf` and check it matches what this program
+// expects from the regtest node, before any RPC call is made.
+//
+// Only the settings that apply to regtest are kept: top-level options, options
+// in the `[regtest]` section (which take precedence over top-level ones) and
+// top-level `regtest.<option>` lines. Like Bitcoin Core, the first occurrence
+// of an option wins within the same scope.
+use crate::rpc_config::RpcConfig;
+use bitcoincore_rpc::bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const NETWORK_SECTION: &str = "regtest";
+
+// Parse `bitcoin.conf` text into the effective regtest settings.
+fn parse_bitcoin_conf(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut top_level = HashMap::new();
+    let mut network = HashMap::new();
+    let mut section: Option<String> = None;
+
+    for (i, raw) in text.lines().enumerate() {
+        // Everything after '#' is a comment
+        let line = raw.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = Some(name.trim().to_string());
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected 'key=value', found '{}'", i + 1, line))?;
+        let (key, value) = (key.trim(), value.trim().to_string());
+
+        let target = match (section.as_deref(), key.split_once('.')) {
+            (Some(NETWORK_SECTION), _) => Some((&mut network, key)),
+            (Some(_), _) => None,
+            (None, Some((NETWORK_SECTION, key))) => Some((&mut network, key)),
+            (None, Some(_)) => None,
+            (None, None) => Some((&mut top_level, key)),
+        };
+        if let Some((map, key)) = target {
+            map.entry(key.to_string()).or_insert(value);
+        }
+    }
+
+    top_level.extend(network);
+    Ok(top_level)
+}
+
+// Helper function to read the effective regtest settings from a `bitcoin.conf`
+pub fn read_bitcoin_conf(path: &Path) -> io::Result<HashMap<String, String>> {
+    let text = fs::read_to_string(path)?;
+    parse_bitcoin_conf(&text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+// Check an `rpcauth=<user>:<salt>$<hmac>` line against our credentials. The
+// hmac is HMAC-SHA256 keyed with the salt over the password, as written by
+// Bitcoin Core's `share/rpcauth/rpcauth.py`.
+fn rpcauth_matches(rpcauth: &str, user: &str, password: &str) -> bool {
+    let Some((auth_user, rest)) = rpcauth.split_once(':') else {
+        return false;
+    };
+    let Some((salt, expected)) = rest.split_once('$') else {
+        return false;
+    };
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(salt.as_bytes());
+    engine.input(password.as_bytes());
+    let hmac = hmac::Hmac::<sha256::Hash>::from_engine(engine);
+    auth_user == user && format!("{:x}", hmac) == expected.to_ascii_lowercase()
+}
+
+// Check the settings this program relies on. Settings that are present but
+// wrong are errors; missing recommended ones are returned as warnings.
+pub fn validate_regtest_config(
+    conf: &HashMap<String, String>,
+    credentials: &RpcConfig,
+) -> Result<Vec<String>, String> {
+    let mut warnings = Vec::new();
+
+    if conf.get("regtest").map(String::as_str) != Some("1") {
+        return Err("regtest=1 is not set, the node would not run on regtest".to_string());
+    }
+
+    match (
+        conf.get("rpcuser"),
+        conf.get("rpcpassword"),
+        conf.get("rpcauth"),
+    ) {
+        // Cookie authentication: the node only writes a cookie without rpcpassword
+        (_, Some(_), _) if credentials.uses_cookie() => warnings.push(
+            "rpcpassword is set, so the node writes no .cookie; pass --rpc-user and --rpc-pass"
+                .to_string(),
+        ),
+        _ if credentials.uses_cookie() => {}
+        (Some(user), _, _) if *user != credentials.user => {
+            return Err(format!(
+                "rpcuser is '{}', expected '{}'",
+                user, credentials.user
+            ));
+        }
+        (Some(_), Some(password), _) if *password != credentials.pass => {
+            return Err("rpcpassword does not match the password this program uses".to_string());
+        }
+        (Some(_), Some(_), _) => {}
+        (Some(_), None, _) => warnings.push("rpcuser is set without rpcpassword".to_string()),
+        (None, _, Some(rpcauth))
+            if !rpcauth_matches(rpcauth, &credentials.user, &credentials.pass) =>
+        {
+            return Err(format!(
+                "rpcauth does not match user '{}' and the password this program uses",
+                credentials.user
+            ));
+        }
+        (None, _, Some(_)) => {}
+        (None, _, None) => warnings.push(format!(
+            "neither rpcuser/rpcpassword nor rpcauth is set, expected user '{}'",
+            credentials.user
+        )),
+    }
+
+    match conf.get("txindex").map(String::as_str) {
+        Some("1") => {}
+        Some(other) => {
+            return Err(format!(
+                "txindex is '{}', getrawtransaction needs txindex=1",
+                other
+            ))
+        }
+        None => warnings.push("txindex=1 is not set, getrawtransaction may fail".to_string()),
+    }
+
+    if !conf.contains_key("fallbackfee") {
+        warnings.push(
+            "fallbackfee is not set, sends fail on regtest without fee estimates".to_string(),
+        );
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPO_CONF: &str = include_str!("../../bitcoin.conf");
+
+    #[test]
+    fn parses_repo_conf() {
+        let conf = parse_bitcoin_conf(REPO_CONF).unwrap();
+        assert_eq!(conf["regtest"], "1");
+        assert_eq!(conf["rpcuser"], "alice");
+        assert_eq!(conf["rpcbind"], "0.0.0.0:18443");
+        // The commented-out rpcauth line is ignored
+        assert!(!conf.contains_key("rpcauth"));
+        assert_eq!(
+            validate_regtest_config(&conf, &RpcConfig::default()).unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn blank_lines_comments_and_whitespace() {
+        let conf = parse_bitcoin_conf(
+            "\n# a comment\n   \n  txindex = 1  # trailing comment\nserver=1\r\n",
+        )
+        .unwrap();
+        assert_eq!(conf.len(), 2);
+        assert_eq!(conf["txindex"], "1");
+        assert_eq!(conf["server"], "1");
+    }
+
+    #[test]
+    fn regtest_section_overrides_top_level_and_other_sections_are_ignored() {
+        let conf = parse_bitcoin_conf(
+            "rpcuser=bob\n\
+             main.rpcuser=carol\n\
+             regtest.rpcpassword=password\n\
+             [main]\n\
+             txindex=0\n\
+             [regtest]\n\
+             rpcuser=alice\n\
+             rpcuser=mallory\n\
+             [test]\n\
+             rpcuser=dave\n",
+        )
+        .unwrap();
+        assert_eq!(conf["rpcuser"], "alice");
+        assert_eq!(conf["rpcpassword"], "password");
+        assert!(!conf.contains_key("txindex"));
+    }
+
+    #[test]
+    fn rejects_line_without_equals() {
+        let err = parse_bitcoin_conf("regtest=1\nserver\n").unwrap_err();
+        assert!(err.starts_with("line 2"));
+    }
+
+    fn conf(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn validation_errors_on_wrong_settings() {
+        assert!(
+            validate_regtest_config(&conf(&[("txindex", "1")]), &RpcConfig::default()).is_err()
+        );
+        assert!(validate_regtest_config(
+            &conf(&[("regtest", "1"), ("rpcuser", "bob")]),
+            &RpcConfig::default()
+        )
+        .is_err());
+        assert!(validate_regtest_config(
+            &conf(&[
+                ("regtest", "1"),
+                ("rpcuser", "alice"),
+                ("rpcpassword", "hunter2")
+            ]),
+            &RpcConfig::default()
+        )
+        .is_err());
+        assert!(validate_regtest_config(
+            &conf(&[("regtest", "1"), ("txindex", "0")]),
+            &RpcConfig::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn validation_warns_on_missing_settings() {
+        let warnings =
+            validate_regtest_config(&conf(&[("regtest", "1")]), &RpcConfig::default()).unwrap();
+        assert_eq!(warnings.len(), 3);
+    }
+
+    #[test]
+    fn cookie_auth_skips_the_credential_check() {
+        let cookie = RpcConfig {
+            user: String::new(),
+            pass: String::new(),
+            ..RpcConfig::default()
+        };
+        let settings = conf(&[("regtest", "1"), ("rpcauth", "bob:00$00"), ("txindex", "1")]);
+        assert_eq!(
+            validate_regtest_config(&settings, &cookie).unwrap().len(),
+            1
+        );
+        let settings = conf(&[("regtest", "1"), ("rpcuser", "bob"), ("rpcpassword", "x")]);
+        let warnings = validate_regtest_config(&settings, &cookie).unwrap();
+        assert!(warnings[0].contains("no .cookie"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn validates_rpcauth_from_docker_compose() {
+        let rpcauth = "alice:88cae77e34048eff8b9f0be35527dd91$d5c4e7ff4dfe771808e9c00a1393b90d498f54dcab0ee74a2d77bd01230cd4cc";
+        assert!(rpcauth_matches(rpcauth, "alice", "password"));
+        assert!(!rpcauth_matches(rpcauth, "alice", "wrong"));
+        assert!(!rpcauth_matches(rpcauth, "bob", "password"));
+        let settings = conf(&[
+            ("regtest", "1"),
+            ("rpcauth", rpcauth),
+            ("txindex", "1"),
+            ("fallbackfee", "0.00001"),
+        ]);
+        assert!(validate_regtest_config(&settings, &RpcConfig::default())
+            .unwrap()
+            .is_empty());
+    }
+}
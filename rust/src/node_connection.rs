@@ -0,0 +1,114 @@
+// A validated way to reach the node: its base URL and how to authenticate.
+//
+// Every client is made here, either for the node itself (`client`) or for one
+// of its wallets (`wallet_client`), so callers never build wallet URLs by hand
+// and cannot hand a wallet URL where the node URL is expected. `RpcConfig`
+// resolves the settings and builds its connection with `NodeConnection::builder`.
+use crate::rpc_helpers::wallet_url;
+use crate::rpc_profile;
+use bitcoincore_rpc::{Auth, Client};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeConnection {
+    url: String,
+    auth: Auth,
+}
+
+impl NodeConnection {
+    pub fn builder() -> NodeConnectionBuilder {
+        NodeConnectionBuilder::default()
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn auth(&self) -> &Auth {
+        &self.auth
+    }
+
+    // Helper function to connect to the node. Wallet RPCs on this client are
+    // refused; they need `wallet_client`.
+    pub fn client(&self) -> bitcoincore_rpc::Result<Client> {
+        rpc_profile::profiled_client(&self.url, self.auth.clone(), false)
+    }
+
+    // Helper function to connect to wallet `wallet` of the node
+    pub fn wallet_client(&self, wallet: &str) -> bitcoincore_rpc::Result<Client> {
+        rpc_profile::profiled_client(&wallet_url(&self.url, wallet)?, self.auth.clone(), true)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NodeConnectionBuilder {
+    url: Option<String>,
+    auth: Option<Auth>,
+}
+
+impl NodeConnectionBuilder {
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    // Without one the node is called unauthenticated
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn build(self) -> Result<NodeConnection, String> {
+        let url = self.url.ok_or_else(|| "no node URL given".to_string())?;
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!(
+                "invalid node URL '{}': it must start with http:// or https://",
+                url
+            ));
+        }
+        Ok(NodeConnection {
+            url,
+            auth: self.auth.unwrap_or(Auth::None),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_http_and_https_connections() {
+        let auth = Auth::UserPass("alice".to_string(), "password".to_string());
+        let connection = NodeConnection::builder()
+            .url("http://127.0.0.1:18443")
+            .auth(auth.clone())
+            .build()
+            .unwrap();
+        assert_eq!(connection.url(), "http://127.0.0.1:18443");
+        assert_eq!(connection.auth(), &auth);
+        let tls = NodeConnection::builder()
+            .url("https://node.example.com/btc/")
+            .build()
+            .unwrap();
+        assert_eq!(tls.auth(), &Auth::None);
+    }
+
+    #[test]
+    fn rejects_urls_without_an_http_scheme() {
+        for url in ["127.0.0.1:18443", "ftp://127.0.0.1", "HTTP//x", ""] {
+            let err = NodeConnection::builder().url(url).build().unwrap_err();
+            assert!(err.contains("http://"), "{}", err);
+        }
+        assert!(NodeConnection::builder().build().is_err());
+    }
+
+    #[test]
+    fn wallet_clients_need_a_valid_wallet_name() {
+        let connection = NodeConnection::builder()
+            .url("http://127.0.0.1:18443")
+            .build()
+            .unwrap();
+        assert!(connection.wallet_client("Miner").is_ok());
+        assert!(connection.wallet_client("a/b").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+// The node calls the capstone flow relies on, as a trait.
+//
+// `Client` implements it with the real RPCs; tests implement it over canned
+// JSON, so the parts of the flow that read the payment back from the node can
+// run without a bitcoind. Wallet calls go to whichever wallet the client is
+// for, as with `RpcApi`; `wallet` opens a client for another wallet of the
+// same node. The method names clash with `RpcApi`'s, so where a bound has both
+// traits the `NodeRpc` methods are called by path (`NodeRpc::get_block(..)`).
+use crate::rpc_config::RpcConfig;
+use crate::rpc_helpers::{get_wallet_client, BlockDetails, MempoolEntry, RawTx};
+use crate::{get_block_details, get_mempool_entry, get_transaction, rpc_call};
+use bitcoincore_rpc::bitcoin::{Amount, BlockHash};
+use bitcoincore_rpc::jsonrpc::Response;
+use bitcoincore_rpc::Client;
+use serde_json::json;
+use serde_json::value::RawValue;
+
+pub trait NodeRpc {
+    // A client for wallet `name` of the node `config` points at
+    fn wallet(&self, config: &RpcConfig, name: &str) -> bitcoincore_rpc::Result<Self>
+    where
+        Self: Sized;
+    // `method` once per entry of `params`, sent as one JSON-RPC batch
+    fn send_batch(
+        &self,
+        method: &str,
+        params: &[Vec<Box<RawValue>>],
+    ) -> bitcoincore_rpc::Result<Vec<Option<Response>>>;
+    // `descriptor` asks for a descriptor wallet explicitly; without it the
+    // node picks the wallet type
+    fn create_wallet(&self, name: &str, descriptor: bool) -> bitcoincore_rpc::Result<()>;
+    fn load_wallet(&self, name: &str) -> bitcoincore_rpc::Result<()>;
+    fn get_new_address(&self, label: &str) -> bitcoincore_rpc::Result<String>;
+    // Block hashes of the mined blocks
+    fn generate_to_address(
+        &self,
+        blocks: u64,
+        address: &str,
+    ) -> bitcoincore_rpc::Result<Vec<String>>;
+    // The txid of the payment
+    fn send_to_address(&self, address: &str, amount: Amount) -> bitcoincore_rpc::Result<String>;
+    // Verbose `getrawtransaction` output
+    fn get_raw_transaction(&self, txid: &str) -> bitcoincore_rpc::Result<RawTx>;
+    fn get_block(&self, block_hash: &BlockHash) -> bitcoincore_rpc::Result<BlockDetails>;
+    fn get_mempool_entry(&self, txid: &str) -> bitcoincore_rpc::Result<MempoolEntry>;
+    // What the wallet knows about `address`, `ismine` included
+    fn get_address_info(&self, address: &str) -> bitcoincore_rpc::Result<serde_json::Value>;
+}
+
+impl NodeRpc for Client {
+    fn wallet(&self, config: &RpcConfig, name: &str) -> bitcoincore_rpc::Result<Self> {
+        get_wallet_client(config, name)
+    }
+
+    fn send_batch(
+        &self,
+        method: &str,
+        params: &[Vec<Box<RawValue>>],
+    ) -> bitcoincore_rpc::Result<Vec<Option<Response>>> {
+        let client = self.get_jsonrpc_client();
+        let requests: Vec<_> = params
+            .iter()
+            .map(|params| client.build_request(method, params))
+            .collect();
+        Ok(client.send_batch(&requests)?)
+    }
+
+    fn create_wallet(&self, name: &str, descriptor: bool) -> bitcoincore_rpc::Result<()> {
+        let args = if descriptor {
+            // name, disable_private_keys, blank, passphrase, avoid_reuse, descriptors
+            vec![
+                json!(name),
+                json!(false),
+                json!(false),
+                json!(""),
+                json!(false),
+                json!(true),
+            ]
+        } else {
+            vec![json!(name)]
+        };
+        rpc_call::<serde_json::Value>(self, "createwallet", &args).map(|_| ())
+    }
+
+    fn load_wallet(&self, name: &str) -> bitcoincore_rpc::Result<()> {
+        rpc_call::<serde_json::Value>(self, "loadwallet", &[json!(name)]).map(|_| ())
+    }
+
+    fn get_new_address(&self, label: &str) -> bitcoincore_rpc::Result<String> {
+        rpc_call(self, "getnewaddress", &[json!(label)])
+    }
+
+    fn generate_to_address(
+        &self,
+        blocks: u64,
+        address: &str,
+    ) -> bitcoincore_rpc::Result<Vec<String>> {
+        rpc_call(self, "generatetoaddress", &[json!(blocks), json!(address)])
+    }
+
+    fn send_to_address(&self, address: &str, amount: Amount) -> bitcoincore_rpc::Result<String> {
+        rpc_call(
+            self,
+            "sendtoaddress",
+            &[json!(address), json!(amount.to_btc())],
+        )
+    }
+
+    fn get_raw_transaction(&self, txid: &str) -> bitcoincore_rpc::Result<RawTx> {
+        get_transaction(self, txid)
+    }
+
+    fn get_block(&self, block_hash: &BlockHash) -> bitcoincore_rpc::Result<BlockDetails> {
+        get_block_details(self, block_hash)
+    }
+
+    fn get_mempool_entry(&self, txid: &str) -> bitcoincore_rpc::Result<MempoolEntry> {
+        get_mempool_entry(self, txid)
+    }
+
+    fn get_address_info(&self, address: &str) -> bitcoincore_rpc::Result<serde_json::Value> {
+        rpc_call(self, "getaddressinfo", &[json!(address)])
+    }
+}
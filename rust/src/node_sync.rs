@@ -0,0 +1,237 @@
+// Keeping several regtest nodes on the same chain tip.
+//
+// When a test connects two or more nodes, a block mined on one of them only
+// reaches the others after it has been relayed. `NodeSynchronizer` polls
+// `getbestblockhash` on every node until they all agree, so later checks
+// (balances, confirmations) on any node see the same chain.
+// `sync-nodes <peer-url>...` waits for the peers and the configured node.
+use crate::arg_value;
+use crate::rpc_config::RpcConfig;
+use bitcoincore_rpc::{Client, Error, RpcApi};
+use log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// How long `sync-nodes` waits unless --timeout says otherwise
+const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct NodeSynchronizer {
+    nodes: Vec<Arc<Client>>,
+}
+
+// The tip reported by most nodes; on a tie, the one the earliest node reports
+fn majority_tip(tips: &[String]) -> Option<&str> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for tip in tips {
+        *counts.entry(tip).or_default() += 1;
+    }
+    tips.iter()
+        .map(String::as_str)
+        .rev()
+        .max_by_key(|tip| counts[tip])
+}
+
+// Indices of the nodes whose tip differs from the majority tip
+fn diverged(tips: &[String]) -> Vec<usize> {
+    let Some(majority) = majority_tip(tips) else {
+        return Vec::new();
+    };
+    tips.iter()
+        .enumerate()
+        .filter(|(_, tip)| *tip != majority)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// Poll `fetch` every `interval` until all tips agree and return that tip.
+fn poll_until_synced<F>(
+    timeout: Duration,
+    interval: Duration,
+    mut fetch: F,
+) -> bitcoincore_rpc::Result<String>
+where
+    F: FnMut() -> bitcoincore_rpc::Result<Vec<String>>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let tips = fetch()?;
+        let lagging = diverged(&tips);
+        if lagging.is_empty() {
+            return majority_tip(&tips)
+                .map(str::to_string)
+                .ok_or_else(|| Error::ReturnedError("no nodes to synchronize".to_string()));
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::ReturnedError(format!(
+                "nodes {:?} still differ from tip {} after {}s",
+                lagging,
+                majority_tip(&tips).unwrap_or_default(),
+                timeout.as_secs()
+            )));
+        }
+        info!("Waiting for nodes {:?} to reach the common tip", lagging);
+        std::thread::sleep(interval);
+    }
+}
+
+impl NodeSynchronizer {
+    pub fn new(nodes: Vec<Arc<Client>>) -> Self {
+        NodeSynchronizer { nodes }
+    }
+
+    // Helper function to read every node's best block hash, in node order
+    fn tips(&self) -> bitcoincore_rpc::Result<Vec<String>> {
+        self.nodes
+            .iter()
+            .map(|node| Ok(node.get_best_block_hash()?.to_string()))
+            .collect()
+    }
+
+    // Wait until every node reports the same best block and return its hash.
+    pub fn wait_all_synced(&self, timeout: Duration) -> bitcoincore_rpc::Result<String> {
+        poll_until_synced(timeout, POLL_INTERVAL, || self.tips())
+    }
+
+    // Indices of the nodes whose tip differs from the majority right now.
+    pub fn find_diverged_nodes(&self) -> bitcoincore_rpc::Result<Vec<usize>> {
+        Ok(diverged(&self.tips()?))
+    }
+}
+
+// `sync-nodes <peer-url>... [--timeout <secs>]`; `args` excludes "sync-nodes".
+// The peers use the same credentials as `config`, the first node.
+pub fn run_command(config: &RpcConfig, args: &[String]) -> bitcoincore_rpc::Result<()> {
+    let peers: Vec<&String> = args
+        .iter()
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    if peers.is_empty() {
+        return Err(Error::ReturnedError(
+            "usage: sync-nodes <peer-url>... [--timeout <secs>]".to_string(),
+        ));
+    }
+    let timeout = match arg_value(args, "--timeout") {
+        Some(secs) => secs.parse().map(Duration::from_secs).map_err(|_| {
+            Error::ReturnedError(format!(
+                "invalid --timeout value '{}': expected seconds",
+                secs
+            ))
+        })?,
+        None => DEFAULT_SYNC_TIMEOUT,
+    };
+    let mut nodes = vec![Arc::new(config.client()?)];
+    for url in peers {
+        let peer = RpcConfig {
+            url: url.clone(),
+            ..config.clone()
+        };
+        nodes.push(Arc::new(peer.client()?));
+    }
+    let sync = NodeSynchronizer::new(nodes);
+    let lagging = sync.find_diverged_nodes()?;
+    if !lagging.is_empty() {
+        info!("Nodes {:?} are not at the majority tip yet", lagging);
+    }
+    let tip = sync.wait_all_synced(timeout)?;
+    info!("All {} nodes are at tip {}", sync.nodes.len(), tip);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tips(hashes: &[&str]) -> Vec<String> {
+        hashes.iter().map(|hash| hash.to_string()).collect()
+    }
+
+    #[test]
+    fn agreeing_nodes_have_no_divergence() {
+        assert!(diverged(&tips(&["aa", "aa", "aa"])).is_empty());
+        assert_eq!(majority_tip(&tips(&["aa", "aa"])), Some("aa"));
+    }
+
+    #[test]
+    fn minority_nodes_are_diverged() {
+        assert_eq!(diverged(&tips(&["aa", "bb", "aa"])), vec![1]);
+        assert_eq!(diverged(&tips(&["cc", "bb", "bb", "dd"])), vec![0, 3]);
+    }
+
+    #[test]
+    fn tie_goes_to_the_earliest_node() {
+        assert_eq!(majority_tip(&tips(&["bb", "aa"])), Some("bb"));
+        assert_eq!(diverged(&tips(&["bb", "aa"])), vec![1]);
+        assert_eq!(majority_tip(&[]), None);
+    }
+
+    #[test]
+    fn polls_until_the_tips_agree() {
+        let mut states = vec![tips(&["bb", "bb"]), tips(&["bb", "aa"])];
+        let mut polls = 0;
+        let tip = poll_until_synced(Duration::from_secs(60), Duration::ZERO, || {
+            polls += 1;
+            Ok(states.pop().unwrap())
+        })
+        .unwrap();
+        assert_eq!(tip, "bb");
+        assert_eq!(polls, 2);
+    }
+
+    #[test]
+    fn times_out_naming_the_lagging_nodes() {
+        let err = poll_until_synced(Duration::ZERO, Duration::ZERO, || {
+            Ok(tips(&["aa", "aa", "bb"]))
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("[2]"), "{}", err);
+        let empty = poll_until_synced(Duration::ZERO, Duration::ZERO, || Ok(Vec::new()));
+        assert!(empty.is_err());
+    }
+
+    // Relay a payment from the Miner wallet on the main node to a wallet on a
+    // second regtest node (`BITCOIN_RPC_PEER_URL`, same credentials) and check
+    // its balance only once both nodes share the tip.
+    #[test]
+    #[ignore = "requires two connected regtest bitcoinds"]
+    fn tx_relay_confirms_on_both_nodes() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+        use bitcoincore_rpc::bitcoin::Amount;
+
+        let config = RpcConfig::load().unwrap();
+        let peer_config = RpcConfig {
+            url: std::env::var("BITCOIN_RPC_PEER_URL")
+                .unwrap_or_else(|_| "http://127.0.0.1:18453".to_string()),
+            ..config.clone()
+        };
+        let node = Arc::new(config.client().unwrap());
+        let peer = Arc::new(peer_config.client().unwrap());
+        let sync = NodeSynchronizer::new(vec![Arc::clone(&node), Arc::clone(&peer)]);
+
+        create_or_load_wallet(&*node, &config, "Miner", true).unwrap();
+        let relay_wallet = format!("Relay-{}", std::process::id());
+        create_or_load_wallet(&*peer, &peer_config, &relay_wallet, true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let receiver = get_wallet_client(&peer_config, &relay_wallet).unwrap();
+        let mining_address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&*node, &mining_address, 101).unwrap();
+        sync.wait_all_synced(Duration::from_secs(30)).unwrap();
+
+        let amount = Amount::from_int_btc(3);
+        let address = receiver
+            .get_new_address(None, None)
+            .unwrap()
+            .assume_checked();
+        miner
+            .send_to_address(&address, amount, None, None, None, None, None, None)
+            .unwrap();
+        let hashes = mine_blocks_to_address(&*node, &mining_address, 1).unwrap();
+        let tip = sync.wait_all_synced(Duration::from_secs(30)).unwrap();
+        assert_eq!(tip, hashes[0]);
+        assert!(sync.find_diverged_nodes().unwrap().is_empty());
+        assert_eq!(receiver.get_balance(None, None).unwrap(), amount);
+        peer.unload_wallet(Some(&relay_wallet)).unwrap();
+    }
+}
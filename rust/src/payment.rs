@@ -0,0 +1,495 @@
+// Reading the confirmed payment back from the node into the out.txt report.
+//
+// Steps 8 and 9 of the run: the fee comes from the outputs the payment
+// spends, the change is its first output to an address the Miner wallet owns
+// that is not one of the recipients', and the block height from the confirming
+// block. Everything is read through `NodeRpc`, so the tests feed canned node
+// JSON instead of a bitcoind.
+use crate::node_rpc::NodeRpc;
+use crate::report::{Recipient, Report, ReportFormat};
+use crate::rpc_helpers::RawTx;
+use crate::{compute_fee, fetch_input_total};
+use bitcoincore_rpc::bitcoin::{Amount, BlockHash};
+use bitcoincore_rpc::Error;
+use std::fs;
+use std::path::Path;
+
+// What the run knows about the payment it made
+#[derive(Debug, Clone)]
+pub struct PaymentFacts<'a> {
+    pub txid: &'a str,
+    pub block_hash: &'a BlockHash,
+    pub miner_input_address: String,
+    // Every recipient output and its amount: the Trader's first, which out.txt
+    // reports, then any --also-pay wallet's
+    pub trader_payments: &'a [(String, Amount)],
+    pub amount: Amount,
+    // Reported as the change address when the payment has no change output
+    pub fallback_change_address: &'a str,
+    // With --change-to-descriptor the change goes to this address, which no
+    // wallet owns, instead of back to the Miner
+    pub external_change_address: Option<&'a str>,
+}
+
+// The outputs of a decoded transaction that pay an address, with their values
+fn output_addresses(tx: &RawTx) -> Vec<(&str, Amount)> {
+    tx.vout
+        .iter()
+        .filter_map(|output| Some((output.address()?, output.value)))
+        .collect()
+}
+
+// Helper function to find the change of a decoded transaction: the first
+// output to an address `wallet` owns that is not one of `recipients`, with its
+// value. Outputs to anyone else are skipped however many there are, and so is
+// a payment the wallet made to itself.
+pub fn find_change_output(
+    wallet: &impl NodeRpc,
+    tx: &RawTx,
+    recipients: &[&str],
+) -> bitcoincore_rpc::Result<Option<(String, Amount)>> {
+    for (address, amount) in output_addresses(tx) {
+        if recipients.contains(&address) {
+            continue;
+        }
+        if wallet.get_address_info(address)?["ismine"].as_bool() == Some(true) {
+            return Ok(Some((address.to_string(), amount)));
+        }
+    }
+    Ok(None)
+}
+
+// Helper function to build the report of the confirmed payment, with the
+// change identified by `wallet`, the wallet that paid
+pub fn build_report(
+    rpc: &impl NodeRpc,
+    wallet: &impl NodeRpc,
+    facts: &PaymentFacts,
+) -> bitcoincore_rpc::Result<Report> {
+    let tx = rpc.get_raw_transaction(facts.txid)?;
+    let block = rpc.get_block(facts.block_hash)?;
+    let (trader_output_address, _) = facts
+        .trader_payments
+        .first()
+        .ok_or_else(|| Error::ReturnedError("the payment has no Trader output".to_string()))?;
+    let recipients: Vec<&str> = facts
+        .trader_payments
+        .iter()
+        .map(|(address, _)| address.as_str())
+        .collect();
+
+    let inputs = fetch_input_total(rpc, &tx)?;
+    let fee = compute_fee(rpc, &tx)?;
+    // Without a change output, report what would have been change: the inputs
+    // less the payments and the fee
+    let change = match facts.external_change_address {
+        Some(external) => output_addresses(&tx)
+            .into_iter()
+            .find(|(address, _)| *address == external)
+            .map(|(address, amount)| (address.to_string(), amount)),
+        None => find_change_output(wallet, &tx, &recipients)?,
+    };
+    let (miner_change_address, miner_change_amount) = match change {
+        Some(change) => change,
+        None => {
+            let paid = facts
+                .trader_payments
+                .iter()
+                .fold(Amount::ZERO, |sum, (_, amount)| sum + *amount);
+            (
+                facts.fallback_change_address.to_string(),
+                inputs.checked_sub(paid + fee).unwrap_or(Amount::ZERO),
+            )
+        }
+    };
+
+    Ok(Report {
+        txid: facts.txid.to_string(),
+        miner_input_address: facts.miner_input_address.clone(),
+        miner_input_amount: inputs,
+        trader_output_address: trader_output_address.clone(),
+        trader_output_amount: facts.amount,
+        miner_change_address,
+        miner_change_amount,
+        fee,
+        block_height: block.height,
+        block_hash: facts.block_hash.to_string(),
+        confirmation_time: Some(block.time),
+        vsize: Some(tx.vsize),
+        recipients: facts
+            .trader_payments
+            .iter()
+            .map(|(address, amount)| Recipient {
+                address: address.clone(),
+                amount: *amount,
+            })
+            .collect(),
+        fee_bump: None,
+        multisig: None,
+        fingerprint: None,
+    })
+}
+
+// Check that the report can be created at `path`: a relative path is taken
+// from the working directory, whose `..` may not be the repository root
+pub fn check_out_path(path: &Path) -> Result<(), String> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        let cwd = std::env::current_dir()
+            .map(|cwd| cwd.display().to_string())
+            .unwrap_or_else(|_| "?".to_string());
+        return Err(format!(
+            "cannot write the report to {}: directory {} does not exist (relative to {}); \
+             pass --out with a path in an existing directory",
+            path.display(),
+            dir.display(),
+            cwd
+        ));
+    }
+    Ok(())
+}
+
+// Helper function to write the report to `path` in `format` and check that
+// what was written reads back: text in the exact canonical layout, JSON as
+// the same document, CSV as the same rows
+pub fn write_report(
+    path: &Path,
+    report: &Report,
+    format: ReportFormat,
+) -> bitcoincore_rpc::Result<()> {
+    check_out_path(path).map_err(Error::ReturnedError)?;
+    report.write(format, &mut fs::File::create(path)?)?;
+    let written = fs::read_to_string(path)?;
+    let valid = match format {
+        ReportFormat::Text => Report::parse_strict(&written).map(|_| ()),
+        ReportFormat::Json => match serde_json::from_str::<serde_json::Value>(&written) {
+            Ok(json) if json == serde_json::to_value(report)? => Ok(()),
+            Ok(_) => Err("the JSON read back differs from the report".to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        ReportFormat::Csv if written == report.to_csv() => Ok(()),
+        ReportFormat::Csv => Err("the CSV read back differs from the report".to_string()),
+    };
+    valid.map_err(|e| Error::ReturnedError(format!("{} failed validation: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_config::RpcConfig;
+    use crate::rpc_helpers::{BlockDetails, MempoolEntry};
+    use crate::tests::raw_tx;
+    use bitcoincore_rpc::jsonrpc::Response;
+    use serde_json::json;
+    use serde_json::value::RawValue;
+    use std::collections::HashMap;
+
+    const FUNDING_TXID: &str = "aa00000000000000000000000000000000000000000000000000000000000000";
+    const PAYMENT_TXID: &str = "bb00000000000000000000000000000000000000000000000000000000000000";
+    const BLOCK_HASH: &str = "cc00000000000000000000000000000000000000000000000000000000000000";
+    const TRADER: &str = "bcrt1qtrader";
+    const CHANGE: &str = "bcrt1qchange";
+
+    // A node answering from canned `getrawtransaction` and `getblock` JSON,
+    // and a wallet that owns the addresses in `mine`
+    #[derive(Default)]
+    struct MockRpc {
+        transactions: HashMap<String, RawTx>,
+        blocks: HashMap<String, BlockDetails>,
+        mine: Vec<&'static str>,
+    }
+
+    fn unexpected(call: &str) -> Error {
+        Error::ReturnedError(format!("unexpected call to {}", call))
+    }
+
+    impl NodeRpc for MockRpc {
+        fn wallet(&self, _: &RpcConfig, _: &str) -> bitcoincore_rpc::Result<Self> {
+            Err(unexpected("wallet"))
+        }
+        fn send_batch(
+            &self,
+            method: &str,
+            _: &[Vec<Box<RawValue>>],
+        ) -> bitcoincore_rpc::Result<Vec<Option<Response>>> {
+            Err(unexpected(method))
+        }
+        fn create_wallet(&self, _: &str, _: bool) -> bitcoincore_rpc::Result<()> {
+            Err(unexpected("createwallet"))
+        }
+        fn load_wallet(&self, _: &str) -> bitcoincore_rpc::Result<()> {
+            Err(unexpected("loadwallet"))
+        }
+        fn get_new_address(&self, _: &str) -> bitcoincore_rpc::Result<String> {
+            Err(unexpected("getnewaddress"))
+        }
+        fn generate_to_address(&self, _: u64, _: &str) -> bitcoincore_rpc::Result<Vec<String>> {
+            Err(unexpected("generatetoaddress"))
+        }
+        fn send_to_address(&self, _: &str, _: Amount) -> bitcoincore_rpc::Result<String> {
+            Err(unexpected("sendtoaddress"))
+        }
+        fn get_raw_transaction(&self, txid: &str) -> bitcoincore_rpc::Result<RawTx> {
+            self.transactions
+                .get(txid)
+                .cloned()
+                .ok_or_else(|| Error::ReturnedError(format!("no transaction {}", txid)))
+        }
+        fn get_block(&self, block_hash: &BlockHash) -> bitcoincore_rpc::Result<BlockDetails> {
+            self.blocks
+                .get(&block_hash.to_string())
+                .cloned()
+                .ok_or_else(|| Error::ReturnedError(format!("no block {}", block_hash)))
+        }
+        fn get_mempool_entry(&self, _: &str) -> bitcoincore_rpc::Result<MempoolEntry> {
+            Err(unexpected("getmempoolentry"))
+        }
+        fn get_address_info(&self, address: &str) -> bitcoincore_rpc::Result<serde_json::Value> {
+            Ok(json!({ "address": address, "ismine": self.mine.contains(&address) }))
+        }
+    }
+
+    fn output(n: u32, address: &str, btc: f64) -> serde_json::Value {
+        json!({ "n": n, "value": btc, "scriptPubKey": { "address": address } })
+    }
+
+    // The capstone payment: a 50 BTC block reward pays 20 BTC to the Trader
+    // and `outputs` more
+    fn node(outputs: Vec<serde_json::Value>) -> MockRpc {
+        let mut vout = vec![output(0, TRADER, 20.0)];
+        vout.extend(outputs);
+        let mut rpc = MockRpc {
+            mine: vec!["bcrt1qminer", CHANGE],
+            ..MockRpc::default()
+        };
+        rpc.transactions.insert(
+            FUNDING_TXID.to_string(),
+            raw_tx(
+                json!({ "vin": [{ "coinbase": "01" }], "vout": [output(0, "bcrt1qminer", 50.0)] }),
+            ),
+        );
+        rpc.transactions.insert(
+            PAYMENT_TXID.to_string(),
+            raw_tx(
+                json!({ "vin": [{ "txid": FUNDING_TXID, "vout": 0 }], "vout": vout, "vsize": 141 }),
+            ),
+        );
+        rpc.blocks.insert(
+            BLOCK_HASH.to_string(),
+            BlockDetails {
+                hash: BLOCK_HASH.to_string(),
+                height: 102,
+                time: 1_700_000_600,
+                mediantime: 1_700_000_000,
+                nonce: 0,
+                difficulty: 4.656542373906925e-10,
+                tx: vec![PAYMENT_TXID.to_string()],
+                size: 389,
+                weight: 1_448,
+                previousblockhash: None,
+            },
+        );
+        rpc
+    }
+
+    fn block_hash() -> BlockHash {
+        BLOCK_HASH.parse().unwrap()
+    }
+
+    fn facts<'a>(payments: &'a [(String, Amount)], block_hash: &'a BlockHash) -> PaymentFacts<'a> {
+        PaymentFacts {
+            txid: PAYMENT_TXID,
+            block_hash,
+            miner_input_address: "bcrt1qminer".to_string(),
+            trader_payments: payments,
+            amount: Amount::from_int_btc(20),
+            fallback_change_address: "bcrt1qminer",
+            external_change_address: None,
+        }
+    }
+
+    #[test]
+    fn fee_and_change_come_from_the_node() {
+        let rpc = node(vec![output(1, CHANGE, 29.9999859)]);
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        let report = build_report(&rpc, &rpc, &facts(&payments, &block_hash())).unwrap();
+        assert_eq!(report.miner_input_amount, Amount::from_int_btc(50));
+        assert_eq!(report.miner_change_address, CHANGE);
+        assert_eq!(report.miner_change_amount, Amount::from_sat(2_999_998_590));
+        assert_eq!(report.fee, Amount::from_sat(1_410));
+        assert_eq!(report.block_height, 102);
+        assert_eq!(report.confirmation_time, Some(1_700_000_600));
+        assert_eq!(report.vsize, Some(141));
+    }
+
+    #[test]
+    fn change_skips_every_trader_output() {
+        let wallet = node(vec![]);
+        let tx = raw_tx(json!({ "vout": [
+            output(0, "bcrt1qsplit0", 10.0),
+            { "n": 1, "value": 10.0, "scriptPubKey": { "addresses": ["bcrt1qsplit1"] } },
+            output(2, CHANGE, 29.9),
+        ]}));
+        assert_eq!(
+            find_change_output(&wallet, &tx, &["bcrt1qsplit0", "bcrt1qsplit1"]).unwrap(),
+            Some((CHANGE.to_string(), Amount::from_btc(29.9).unwrap()))
+        );
+        assert_eq!(
+            find_change_output(&wallet, &tx, &["bcrt1qsplit0", "bcrt1qsplit1", CHANGE]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn change_is_the_output_the_wallet_owns() {
+        // A third output to someone else, ahead of the change
+        let rpc = node(vec![
+            output(1, "bcrt1qother", 5.0),
+            output(2, CHANGE, 24.9999859),
+        ]);
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        let report = build_report(&rpc, &rpc, &facts(&payments, &block_hash())).unwrap();
+        assert_eq!(report.miner_change_address, CHANGE);
+        assert_eq!(report.miner_change_amount, Amount::from_sat(2_499_998_590));
+
+        // A Miner paying itself: its own address is a recipient, not change
+        let tx =
+            raw_tx(json!({ "vout": [output(0, "bcrt1qminer", 20.0), output(1, CHANGE, 29.9)] }));
+        assert_eq!(
+            find_change_output(&rpc, &tx, &["bcrt1qminer"]).unwrap(),
+            Some((CHANGE.to_string(), Amount::from_btc(29.9).unwrap()))
+        );
+    }
+
+    #[test]
+    fn every_recipient_is_reported_and_none_is_change() {
+        // --also-pay Miner=5: the second recipient is a Miner-owned address
+        let rpc = node(vec![
+            output(1, "bcrt1qminer", 5.0),
+            output(2, CHANGE, 24.9999859),
+        ]);
+        let payments = [
+            (TRADER.to_string(), Amount::from_int_btc(20)),
+            ("bcrt1qminer".to_string(), Amount::from_int_btc(5)),
+        ];
+        let report = build_report(&rpc, &rpc, &facts(&payments, &block_hash())).unwrap();
+        assert_eq!(report.trader_output_address, TRADER);
+        assert_eq!(report.miner_change_address, CHANGE);
+        assert_eq!(report.miner_change_amount, Amount::from_sat(2_499_998_590));
+        assert_eq!(report.fee, Amount::from_sat(1_410));
+        let recipients: Vec<(&str, Amount)> = report
+            .recipients
+            .iter()
+            .map(|recipient| (recipient.address.as_str(), recipient.amount))
+            .collect();
+        assert_eq!(
+            recipients,
+            [
+                (TRADER, Amount::from_int_btc(20)),
+                ("bcrt1qminer", Amount::from_int_btc(5))
+            ]
+        );
+    }
+
+    #[test]
+    fn external_change_is_the_output_to_its_address() {
+        // The Miner owns CHANGE, but the change went to the cold address
+        let rpc = node(vec![
+            output(1, CHANGE, 5.0),
+            output(2, "bcrt1qcold", 24.9999859),
+        ]);
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        let block_hash = block_hash();
+        let mut facts = facts(&payments, &block_hash);
+        facts.external_change_address = Some("bcrt1qcold");
+        let report = build_report(&rpc, &rpc, &facts).unwrap();
+        assert_eq!(report.miner_change_address, "bcrt1qcold");
+        assert_eq!(report.miner_change_amount, Amount::from_sat(2_499_998_590));
+    }
+
+    #[test]
+    fn without_change_the_remainder_is_reported() {
+        let rpc = node(vec![]);
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        let report = build_report(&rpc, &rpc, &facts(&payments, &block_hash())).unwrap();
+        assert_eq!(report.miner_change_address, "bcrt1qminer");
+        // Everything not paid to the Trader went to the fee
+        assert_eq!(report.fee, Amount::from_int_btc(30));
+        assert_eq!(report.miner_change_amount, Amount::ZERO);
+    }
+
+    #[test]
+    fn out_txt_holds_the_canonical_report() {
+        let rpc = node(vec![output(1, CHANGE, 29.9999859)]);
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        let report = build_report(&rpc, &rpc, &facts(&payments, &block_hash())).unwrap();
+        let path = std::env::temp_dir().join(format!("out-{}.txt", std::process::id()));
+        write_report(&path, &report, ReportFormat::Text).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                PAYMENT_TXID,
+                "bcrt1qminer",
+                "50.00000000",
+                TRADER,
+                "20.00000000",
+                CHANGE,
+                "29.99998590",
+                "0.00001410",
+                "102",
+                BLOCK_HASH,
+            ]
+        );
+    }
+
+    #[test]
+    fn json_out_file_reads_back_as_the_report() {
+        let rpc = node(vec![output(1, CHANGE, 29.9999859)]);
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        let report = build_report(&rpc, &rpc, &facts(&payments, &block_hash())).unwrap();
+        let path = std::env::temp_dir().join(format!("out-{}.json", std::process::id()));
+        write_report(&path, &report, ReportFormat::Json).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(json["txid"], PAYMENT_TXID);
+        assert_eq!(json["miner_change_amount"]["btc"], "29.99998590");
+        assert_eq!(json["fee"]["btc"], "0.00001410");
+        assert_eq!(json["fee"]["sat"], 1_410);
+        assert_eq!(json["vsize"], 141);
+    }
+
+    #[test]
+    fn a_missing_out_directory_is_named() {
+        let rpc = node(vec![output(1, CHANGE, 29.9999859)]);
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        let report = build_report(&rpc, &rpc, &facts(&payments, &block_hash())).unwrap();
+        let dir = std::env::temp_dir().join(format!("no-such-dir-{}", std::process::id()));
+        let path = dir.join("out.txt");
+        let err = write_report(&path, &report, ReportFormat::Text)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(&format!("directory {} does not exist", dir.display())),
+            "{}",
+            err
+        );
+        assert!(!dir.exists());
+        // A bare file name is in the working directory, which exists
+        assert!(check_out_path(Path::new("out.txt")).is_ok());
+    }
+
+    #[test]
+    fn missing_node_data_is_an_error() {
+        let rpc = MockRpc::default();
+        let payments = [(TRADER.to_string(), Amount::from_int_btc(20))];
+        assert!(build_report(&rpc, &rpc, &facts(&payments, &block_hash())).is_err());
+        assert!(build_report(&node(vec![]), &rpc, &facts(&[], &block_hash())).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// Sending the payment through the PSBT RPCs: `--psbt` and `--psbt-in`.
+//
+// With `--psbt` the Miner funds the payment with `walletcreatefundedpsbt` and
+// the unsigned PSBT is written, base64, to `psbt.b64`, from where it could be
+// carried to another signer. Here the Miner signs it itself with
+// `walletprocesspsbt`. `--psbt-in <path>` instead resumes from a PSBT signed
+// elsewhere. Either way `finalizepsbt` extracts the transaction and
+// `sendrawtransaction` broadcasts it, and the run goes on with its txid.
+use crate::amount_serde::deserialize_amount;
+use crate::rpc_call;
+use crate::wallet_lock::Wallet;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Error, RpcApi};
+use serde::Deserialize;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+pub const PSBT_PATH: &str = "psbt.b64";
+
+// A `walletcreatefundedpsbt` result
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FundedPsbt {
+    pub psbt: String,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub fee: Amount,
+    // -1 without a change output
+    pub changepos: i64,
+}
+
+// A `walletprocesspsbt` result
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProcessedPsbt {
+    pub psbt: String,
+    pub complete: bool,
+}
+
+// A `finalizepsbt` result: the network transaction once every input is
+// signed, the PSBT as far as it got otherwise
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FinalizedPsbt {
+    pub complete: bool,
+    pub hex: Option<String>,
+    pub psbt: Option<String>,
+}
+
+impl FinalizedPsbt {
+    // The transaction to broadcast
+    pub fn into_hex(self) -> Result<String, String> {
+        if !self.complete {
+            return Err("the PSBT is not fully signed, so it cannot be finalized".to_string());
+        }
+        self.hex
+            .ok_or_else(|| "finalizepsbt extracted no transaction".to_string())
+    }
+}
+
+// The base64 PSBT in a `--psbt-in` file
+pub fn read_psbt_file(path: &Path) -> Result<String, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let psbt = text.trim();
+    if psbt.is_empty() {
+        return Err(format!("{} holds no PSBT", path.display()));
+    }
+    Ok(psbt.to_string())
+}
+
+// Helper function to fund a payment of `amount` to `address` from `wallet`,
+// write the unsigned PSBT to `path` and sign it with the wallet. Returns the
+// signed PSBT.
+pub fn create_signed_psbt(
+    wallet: &Wallet<impl RpcApi>,
+    address: &str,
+    amount: Amount,
+    path: &Path,
+) -> bitcoincore_rpc::Result<String> {
+    // inputs, outputs, locktime, options
+    let args = [
+        json!([]),
+        json!([{ address: amount.to_btc() }]),
+        json!(0),
+        json!({ "lockUnspents": true }),
+    ];
+    let funded = wallet.call::<FundedPsbt>("walletcreatefundedpsbt", &args)?;
+    fs::write(path, format!("{}\n", funded.psbt))?;
+    log::info!(
+        "Funded PSBT paying a {} sat fee written to {}",
+        funded.fee.to_sat(),
+        path.display()
+    );
+
+    let processed = wallet.call::<ProcessedPsbt>("walletprocesspsbt", &[json!(funded.psbt)])?;
+    if !processed.complete {
+        return Err(Error::ReturnedError(
+            "walletprocesspsbt could not sign every input".to_string(),
+        ));
+    }
+    Ok(processed.psbt)
+}
+
+// Helper function to finalize the signed `psbt` and broadcast its transaction.
+// Returns the txid.
+pub fn finalize_and_send(rpc: &impl RpcApi, psbt: &str) -> bitcoincore_rpc::Result<String> {
+    let finalized = rpc_call::<FinalizedPsbt>(rpc, "finalizepsbt", &[json!(psbt)])?;
+    let hex = finalized.into_hex().map_err(Error::ReturnedError)?;
+    rpc_call::<String>(rpc, "sendrawtransaction", &[json!(hex)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_complete_psbts_yield_a_transaction() {
+        let finalized: FinalizedPsbt =
+            serde_json::from_value(json!({ "hex": "0200", "complete": true })).unwrap();
+        assert_eq!(finalized.into_hex().unwrap(), "0200");
+
+        let partial: FinalizedPsbt =
+            serde_json::from_value(json!({ "psbt": "cHNidP8B", "complete": false })).unwrap();
+        assert!(partial.into_hex().unwrap_err().contains("not fully signed"));
+
+        let empty: FinalizedPsbt = serde_json::from_value(json!({ "complete": true })).unwrap();
+        assert!(empty.into_hex().is_err());
+    }
+
+    #[test]
+    fn funded_psbts_deserialize_with_their_fee() {
+        let funded: FundedPsbt = serde_json::from_value(
+            json!({ "psbt": "cHNidP8B", "fee": 0.00000141, "changepos": 1 }),
+        )
+        .unwrap();
+        assert_eq!(funded.fee, Amount::from_sat(141));
+        assert_eq!(funded.changepos, 1);
+    }
+
+    #[test]
+    fn psbt_files_are_trimmed_and_must_not_be_empty() {
+        let path = std::env::temp_dir().join(format!("psbt-{}.b64", std::process::id()));
+        fs::write(&path, "  cHNidP8B\n").unwrap();
+        assert_eq!(read_psbt_file(&path).unwrap(), "cHNidP8B");
+        fs::write(&path, "\n").unwrap();
+        assert!(read_psbt_file(&path)
+            .unwrap_err()
+            .ends_with("holds no PSBT"));
+        fs::remove_file(&path).unwrap();
+        assert!(read_psbt_file(&path)
+            .unwrap_err()
+            .starts_with("cannot read"));
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn a_signed_psbt_file_can_be_resumed() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_transaction, get_wallet_client, mine_blocks};
+        use bitcoincore_rpc::RpcApi;
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks(&rpc, &address, 101).unwrap();
+
+        let path = std::env::temp_dir().join(format!("psbt-flow-{}.b64", std::process::id()));
+        let amount = Amount::from_int_btc(20);
+        let wallet = Wallet::new("Miner", &miner);
+        let signed = create_signed_psbt(&wallet, &address.to_string(), amount, &path).unwrap();
+        // The file holds the unsigned PSBT, which cannot be finalized yet
+        let unsigned = read_psbt_file(&path).unwrap();
+        assert_ne!(unsigned, signed);
+        assert!(finalize_and_send(&rpc, &unsigned).is_err());
+
+        // Resuming from the signed PSBT, as --psbt-in would
+        fs::write(&path, &signed).unwrap();
+        let txid = finalize_and_send(&rpc, &read_psbt_file(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let tx = get_transaction(&rpc, &txid).unwrap();
+        assert!(tx.vout.iter().any(|output| output.value == amount));
+    }
+}
@@ -0,0 +1,194 @@
+// Sending the payment by hand, `--send-raw`, instead of with `sendtoaddress`.
+//
+// The wallet's automatic funding is skipped: the Miner's UTXOs are listed with
+// `listunspent` and the smallest block reward that covers the payment is
+// spent alone. `createrawtransaction` pays the Trader and sends the rest to a
+// fresh Miner change address, sized so that what is left over is a fee of
+// `RAW_SEND_FEE_RATE` (or more, above a full mempool's minimum fee);
+// `signrawtransactionwithwallet` signs it and
+// `sendrawtransaction` broadcasts it. The fee the node then reports for the
+// mempool entry must be exactly the one chosen here.
+use crate::coin_selection::Utxo;
+use crate::coinbase_inputs::{wallet_coinbase_utxos, COINBASE_FEE_MARGIN};
+use crate::cpfp::sign_at_rate;
+use crate::rpc_helpers::MempoolEntry;
+use crate::{get_mempool_entry, rpc_call};
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Error, RpcApi};
+use serde_json::json;
+
+// sat/vB
+pub const RAW_SEND_FEE_RATE: f64 = 2.0;
+
+// What `send_raw` spent and paid
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawSend {
+    pub txid: String,
+    pub input: Utxo,
+    pub change_address: String,
+    pub fee: Amount,
+}
+
+// The smallest of the `coinbases` that covers `needed` on its own. `spendable`
+// counts every spendable UTXO, to tell the user what is there instead.
+pub fn pick_coinbase_utxo(
+    coinbases: &[(Utxo, u64)],
+    spendable: usize,
+    needed: Amount,
+) -> Result<Utxo, String> {
+    coinbases
+        .iter()
+        .map(|(utxo, _)| utxo)
+        .filter(|utxo| utxo.amount >= needed)
+        .min_by(|a, b| {
+            a.amount
+                .cmp(&b.amount)
+                .then_with(|| (&a.txid, a.vout).cmp(&(&b.txid, b.vout)))
+        })
+        .cloned()
+        .ok_or_else(|| {
+            format!(
+                "--send-raw: none of the {} block reward(s) among {} spendable UTXO(s) holds \
+                 {} BTC (payment plus fee margin)",
+                coinbases.len(),
+                spendable,
+                needed.to_btc()
+            )
+        })
+}
+
+// Check that the node charges the fee the change output was sized for
+pub fn check_fee(txid: &str, fee: Amount, entry: &MempoolEntry) -> Result<(), String> {
+    if entry.fees.base != fee {
+        return Err(format!(
+            "{} was built to pay {} sat, but its mempool entry pays {} sat",
+            txid,
+            fee.to_sat(),
+            entry.fees.base.to_sat()
+        ));
+    }
+    Ok(())
+}
+
+// Helper function to pay `amount` to `address` from `wallet` with a hand-built
+// transaction spending one block reward, at `fee_rate` sat/vB
+pub fn send_raw(
+    wallet: &impl RpcApi,
+    address: &str,
+    amount: Amount,
+    fee_rate: f64,
+) -> bitcoincore_rpc::Result<RawSend> {
+    let (coinbases, spendable) = wallet_coinbase_utxos(wallet)?;
+    let input = pick_coinbase_utxo(&coinbases, spendable, amount + COINBASE_FEE_MARGIN)
+        .map_err(Error::ReturnedError)?;
+    let change_address = rpc_call::<String>(wallet, "getrawchangeaddress", &[])?;
+    let (hex, _, fee) = sign_at_rate(
+        wallet,
+        (&input.txid, input.vout),
+        input.amount,
+        (address, amount),
+        &change_address,
+        fee_rate,
+    )?;
+    let txid = rpc_call::<String>(wallet, "sendrawtransaction", &[json!(hex)])?;
+    check_fee(&txid, fee, &get_mempool_entry(wallet, &txid)?).map_err(Error::ReturnedError)?;
+    Ok(RawSend {
+        txid,
+        input,
+        change_address,
+        fee,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(txid: &str, btc: u64) -> Utxo {
+        Utxo {
+            txid: txid.to_string(),
+            vout: 0,
+            amount: Amount::from_int_btc(btc),
+        }
+    }
+
+    #[test]
+    fn the_smallest_covering_reward_is_spent() {
+        let coinbases = [
+            (utxo("large", 50), 1),
+            (utxo("halved", 25), 2),
+            (utxo("small", 10), 3),
+        ];
+        let needed = Amount::from_int_btc(20) + COINBASE_FEE_MARGIN;
+        assert_eq!(
+            pick_coinbase_utxo(&coinbases, 5, needed).unwrap().txid,
+            "halved"
+        );
+        // Exactly enough is enough
+        let needed = Amount::from_int_btc(25);
+        assert_eq!(
+            pick_coinbase_utxo(&coinbases, 5, needed).unwrap().txid,
+            "halved"
+        );
+    }
+
+    #[test]
+    fn no_single_reward_covering_the_payment_is_an_error() {
+        let coinbases = [(utxo("a", 10), 1), (utxo("b", 10), 2)];
+        let err = pick_coinbase_utxo(&coinbases, 4, Amount::from_int_btc(15)).unwrap_err();
+        assert_eq!(
+            err,
+            "--send-raw: none of the 2 block reward(s) among 4 spendable UTXO(s) holds \
+             15 BTC (payment plus fee margin)"
+        );
+        assert!(pick_coinbase_utxo(&[], 0, Amount::ONE_SAT).is_err());
+    }
+
+    #[test]
+    fn the_mempool_fee_must_be_the_chosen_one() {
+        let entry = |base: f64| -> MempoolEntry {
+            serde_json::from_value(json!({
+                "fees": { "base": base, "modified": base, "ancestor": base, "descendant": base },
+                "vsize": 141,
+                "weight": 561,
+                "time": 1_700_000_000,
+                "height": 101,
+                "depends": [],
+            }))
+            .unwrap()
+        };
+        let fee = Amount::from_sat(282);
+        assert!(check_fee("aa", fee, &entry(0.00000282)).is_ok());
+        assert_eq!(
+            check_fee("aa", fee, &entry(0.00000283)).unwrap_err(),
+            "aa was built to pay 282 sat, but its mempool entry pays 283 sat"
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn raw_payments_pay_the_chosen_fee() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_transaction, get_wallet_client, mine_blocks};
+        use bitcoincore_rpc::RpcApi;
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks(&rpc, &address, 101).unwrap();
+
+        let recipient = miner.get_new_address(None, None).unwrap().assume_checked();
+        let amount = Amount::from_int_btc(20);
+        let sent = send_raw(&miner, &recipient.to_string(), amount, RAW_SEND_FEE_RATE).unwrap();
+        let tx = get_transaction(&rpc, &sent.txid).unwrap();
+        assert_eq!(tx.vin.len(), 1);
+        assert_eq!(tx.vin[0].txid.as_deref(), Some(sent.input.txid.as_str()));
+        assert_eq!(tx.vout[0].value, amount);
+        assert_eq!(tx.vout[1].address(), Some(sent.change_address.as_str()));
+        assert_eq!(tx.vout[1].value, sent.input.amount - amount - sent.fee);
+        let rate = sent.fee.to_sat() as f64 / tx.vsize as f64;
+        assert!((RAW_SEND_FEE_RATE..RAW_SEND_FEE_RATE + 0.1).contains(&rate));
+    }
+}
@@ -0,0 +1,268 @@
+// The RBF scenario, `--rbf`: send the payment replaceable, then bump its fee.
+//
+// The Miner sends with `replaceable=true`, so its mempool entry must show
+// `bip125-replaceable`. `bumpfee` then replaces it at a higher fee rate
+// (`--bump-fee-rate`, or the wallet's own pick without it), after which the
+// original must be gone from the mempool and the replacement in it; the run
+// goes on to confirm the replacement like any payment. When a block confirms
+// the original first, the wallet refuses the bump. That race is reported,
+// with the block that won it, and the run carries on with the original.
+use crate::get_mempool_entry;
+use crate::rpc_helpers::MempoolEntry;
+use crate::wallet_lock::Wallet;
+use bitcoincore_rpc::bitcoin::BlockHash;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+
+// What `bumpfee` says about a transaction a block has already confirmed
+const ALREADY_MINED: &str = "has been mined";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeeBump {
+    pub original_txid: String,
+    // sat/vB
+    pub original_fee_rate: f64,
+    // Both unset when the original confirmed before it could be replaced
+    pub replacement_txid: Option<String>,
+    pub replacement_fee_rate: Option<f64>,
+    // Set in that case: the block that confirmed the original
+    pub original_confirmed_in: Option<BlockHash>,
+}
+
+impl FeeBump {
+    // The transaction that pays the Trader: the replacement, unless the
+    // original confirmed first
+    pub fn payment_txid(&self) -> &str {
+        self.replacement_txid
+            .as_deref()
+            .unwrap_or(&self.original_txid)
+    }
+}
+
+// Fee rate in sat/vB of a mempool entry
+pub fn entry_fee_rate(entry: &MempoolEntry) -> Result<f64, String> {
+    if entry.vsize == 0 {
+        return Err("the mempool entry has no vsize".to_string());
+    }
+    Ok(entry.fees.base.to_sat() as f64 / entry.vsize as f64)
+}
+
+// Check that the mempool entry of `txid` signals BIP 125 replaceability
+pub fn check_replaceable(txid: &str, entry: &MempoolEntry) -> Result<(), String> {
+    match entry.bip125_replaceable {
+        Some(true) => Ok(()),
+        Some(false) => Err(format!("{} is not bip125-replaceable", txid)),
+        None => Err(format!(
+            "the mempool entry of {} does not say whether it is bip125-replaceable",
+            txid
+        )),
+    }
+}
+
+// Whether `bumpfee` failed because a block already confirmed the transaction
+pub fn is_already_mined(error: &Error) -> bool {
+    error.to_string().contains(ALREADY_MINED)
+}
+
+// Check that the replacement took the original's place in `mempool`
+pub fn check_replaced(mempool: &[String], original: &str, replacement: &str) -> Result<(), String> {
+    if mempool.iter().any(|txid| txid == original) {
+        return Err(format!(
+            "the original {} is still in the mempool after the bump",
+            original
+        ));
+    }
+    if !mempool.iter().any(|txid| txid == replacement) {
+        return Err(format!(
+            "the replacement {} is not in the mempool",
+            replacement
+        ));
+    }
+    Ok(())
+}
+
+// Helper function to bump the fee of `txid`, sent replaceable from `wallet`,
+// to `fee_rate` sat/vB or, without one, to what the wallet picks
+pub fn bump_fee(
+    rpc: &impl RpcApi,
+    wallet: &Wallet<impl RpcApi>,
+    txid: &str,
+    fee_rate: Option<f64>,
+) -> bitcoincore_rpc::Result<FeeBump> {
+    let entry = get_mempool_entry(rpc, txid)?;
+    check_replaceable(txid, &entry).map_err(Error::ReturnedError)?;
+    let original_fee_rate = entry_fee_rate(&entry).map_err(Error::ReturnedError)?;
+    info!(
+        "{} is bip125-replaceable, paying {:.3} sat/vB",
+        txid, original_fee_rate
+    );
+
+    let mut args = vec![json!(txid)];
+    if let Some(rate) = fee_rate {
+        args.push(json!({ "fee_rate": rate }));
+    }
+    let bumped = match wallet.call::<serde_json::Value>("bumpfee", &args) {
+        Ok(bumped) => bumped,
+        Err(e) if is_already_mined(&e) => {
+            let tx = wallet.call::<serde_json::Value>("gettransaction", &[json!(txid)])?;
+            let block =
+                serde_json::from_value::<BlockHash>(tx["blockhash"].clone()).map_err(|_| {
+                    Error::ReturnedError(format!(
+                        "bumpfee says {} was mined, but the wallet knows no block for it",
+                        txid
+                    ))
+                })?;
+            warn!(
+                "The original {} confirmed in block {} before it could be bumped",
+                txid, block
+            );
+            return Ok(FeeBump {
+                original_txid: txid.to_string(),
+                original_fee_rate,
+                replacement_txid: None,
+                replacement_fee_rate: None,
+                original_confirmed_in: Some(block),
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    let replacement = bumped["txid"]
+        .as_str()
+        .ok_or_else(|| Error::ReturnedError("bumpfee returned no txid".to_string()))?
+        .to_string();
+
+    let mempool: Vec<String> = rpc
+        .get_raw_mempool()?
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    check_replaced(&mempool, txid, &replacement).map_err(Error::ReturnedError)?;
+    let replacement_fee_rate =
+        entry_fee_rate(&get_mempool_entry(rpc, &replacement)?).map_err(Error::ReturnedError)?;
+    Ok(FeeBump {
+        original_txid: txid.to_string(),
+        original_fee_rate,
+        replacement_txid: Some(replacement),
+        replacement_fee_rate: Some(replacement_fee_rate),
+        original_confirmed_in: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "aa";
+    const REPLACEMENT: &str = "bb";
+
+    // A mempool entry paying `base` BTC for `vsize` vbytes
+    fn entry(base: f64, vsize: u64, replaceable: Option<bool>) -> MempoolEntry {
+        let fees = json!({ "base": base, "modified": base, "ancestor": base, "descendant": base });
+        serde_json::from_value(json!({
+            "fees": fees,
+            "vsize": vsize,
+            "weight": vsize * 4,
+            "time": 1_700_000_000,
+            "height": 101,
+            "depends": [],
+            "bip125-replaceable": replaceable,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn fee_rates_come_from_the_base_fee_and_vsize() {
+        assert_eq!(entry_fee_rate(&entry(0.00002820, 141, None)).unwrap(), 20.0);
+        assert_eq!(entry_fee_rate(&entry(0.00000141, 141, None)).unwrap(), 1.0);
+        assert!(entry_fee_rate(&entry(0.0001, 0, None)).is_err());
+    }
+
+    #[test]
+    fn only_signalling_entries_are_replaceable() {
+        assert!(check_replaceable(ORIGINAL, &entry(0.0001, 141, Some(true))).is_ok());
+        let err = check_replaceable(ORIGINAL, &entry(0.0001, 141, Some(false)));
+        assert_eq!(err.unwrap_err(), "aa is not bip125-replaceable");
+        assert!(check_replaceable(ORIGINAL, &entry(0.0001, 141, None)).is_err());
+    }
+
+    #[test]
+    fn the_replacement_takes_the_originals_place() {
+        let mempool = |txids: &[&str]| txids.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert!(check_replaced(&mempool(&["cc", REPLACEMENT]), ORIGINAL, REPLACEMENT).is_ok());
+        let err = check_replaced(&mempool(&[ORIGINAL, REPLACEMENT]), ORIGINAL, REPLACEMENT);
+        assert!(err.unwrap_err().contains("still in the mempool"));
+        let err = check_replaced(&mempool(&[]), ORIGINAL, REPLACEMENT);
+        assert!(err.unwrap_err().contains("not in the mempool"));
+    }
+
+    #[test]
+    fn a_lost_race_with_mining_is_recognised() {
+        let mined = Error::ReturnedError(
+            "RPC 'bumpfee' failed with code -4 (RPC_WALLET_ERROR): Transaction has been \
+             mined, or is conflicted with a mined transaction"
+                .to_string(),
+        );
+        assert!(is_already_mined(&mined));
+        let other = Error::ReturnedError(
+            "RPC 'bumpfee' failed with code -8 (RPC_INVALID_PARAMETER): Insufficient total \
+             fee 0.00000141"
+                .to_string(),
+        );
+        assert!(!is_already_mined(&other));
+    }
+
+    #[test]
+    fn the_payment_is_the_transaction_that_confirms() {
+        let mut bump = FeeBump {
+            original_txid: ORIGINAL.to_string(),
+            original_fee_rate: 1.0,
+            replacement_txid: Some(REPLACEMENT.to_string()),
+            replacement_fee_rate: Some(10.0),
+            original_confirmed_in: None,
+        };
+        assert_eq!(bump.payment_txid(), REPLACEMENT);
+        bump.replacement_txid = None;
+        bump.replacement_fee_rate = None;
+        assert_eq!(bump.payment_txid(), ORIGINAL);
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn bumping_replaces_the_payment_in_the_mempool() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks, send_to_address};
+        use bitcoincore_rpc::bitcoin::Amount;
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks(&rpc, &address, 101).unwrap();
+        let original = send_to_address(
+            &miner,
+            &address.to_string(),
+            Amount::from_int_btc(1),
+            None,
+            false,
+            true,
+            6,
+        )
+        .unwrap()
+        .to_string();
+
+        let wallet = Wallet::new("Miner", &miner);
+        let bump = bump_fee(&rpc, &wallet, &original, Some(50.0)).unwrap();
+        assert!(bump.replacement_fee_rate.unwrap() >= 50.0);
+        assert!(bump.replacement_fee_rate.unwrap() > bump.original_fee_rate);
+        let replacement = bump.payment_txid().to_string();
+        assert_ne!(replacement, original);
+
+        // Once the replacement is mined there is nothing left to bump
+        mine_blocks(&rpc, &address, 1).unwrap();
+        let err = bump_fee(&rpc, &wallet, &replacement, None).unwrap_err();
+        assert!(err.to_string().contains("getmempoolentry"), "{}", err);
+    }
+}
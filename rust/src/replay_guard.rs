@@ -0,0 +1,427 @@
+// Protection against paying twice when a send is repeated after a crash.
+//
+// If the process dies after `sendtoaddress` reached the node but before the
+// txid was recorded, blindly sending again pays the recipient a second time.
+// `find_existing_payment` looks through the wallet's transactions since a
+// known block for a live send of exactly `amount` to `address`, and returns
+// its txid so the caller can adopt it instead of sending again. The run's
+// payment goes through `send_or_adopt`: when the connection fails after the
+// request may have reached the node, the payment is looked up before the
+// error is reported.
+//
+// A crash can also take the process down with the send, so `send_once` first
+// writes the intent (wallet, address, amount and the tip before sending) to a
+// state file and removes it once the send is settled. A run that finds the
+// file reuses its address and looks for the payment since its tip before
+// paying again.
+use crate::tx_category::TxCategory;
+use crate::tx_history::WalletTx;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::jsonrpc;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const PENDING_SEND_PATH: &str = "pendingsend.json";
+
+// A payment about to be made, and the best block before it was: a send that
+// reached the node is in `listsinceblock` from `since` on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingSend {
+    pub wallet: String,
+    pub address: String,
+    pub amount_sat: u64,
+    pub since: String,
+}
+
+impl PendingSend {
+    pub fn amount(&self) -> Amount {
+        Amount::from_sat(self.amount_sat)
+    }
+
+    // Whether this is the payment of `amount` from `wallet` (to any address)
+    pub fn is_for(&self, wallet: &str, amount: Amount) -> bool {
+        self.wallet == wallet && self.amount() == amount
+    }
+}
+
+// The send an earlier run recorded and did not settle, if any
+pub fn load_pending(path: &Path) -> Result<Option<PendingSend>, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("cannot read {}: {}", path.display(), e)),
+    }
+}
+
+fn save_pending(path: &Path, pending: &PendingSend) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(pending).map_err(|e| e.to_string())?;
+    fs::write(path, json + "\n").map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+fn clear_pending(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(format!("cannot remove {}: {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+// A `listsinceblock` transaction entry; the wallet marks sends it gave up on
+// as abandoned
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SinceBlockEntry {
+    #[serde(flatten)]
+    pub tx: WalletTx,
+    #[serde(default)]
+    pub abandoned: bool,
+}
+
+#[derive(Deserialize)]
+struct SinceBlock {
+    transactions: Vec<SinceBlockEntry>,
+}
+
+// Sends of exactly `amount` to `address` that may still confirm, newest
+// first. Conflicted (negative confirmations) and abandoned sends are skipped.
+pub fn matching_payments<'a>(
+    entries: &'a [SinceBlockEntry],
+    address: &str,
+    amount: Amount,
+) -> Vec<&'a WalletTx> {
+    let sats = i64::try_from(amount.to_sat()).unwrap_or(i64::MAX);
+    let mut matches: Vec<&WalletTx> = entries
+        .iter()
+        .filter(|entry| !entry.abandoned)
+        .map(|entry| &entry.tx)
+        .filter(|tx| {
+            tx.tx_category() == TxCategory::Send
+                && tx.confirmations >= 0
+                && tx.address.as_deref() == Some(address)
+                && tx.amount.to_sat() == -sats
+        })
+        .collect();
+    matches.sort_by_key(|tx| Reverse((tx.time, tx.blockheight, tx.txid.clone())));
+    matches.dedup_by(|a, b| a.txid == b.txid);
+    matches
+}
+
+// Helper function to find a payment of `amount` to `address` the wallet
+// already made since block `since` (the whole history when `None`). With
+// several candidates the newest wins and the others are logged.
+pub fn find_existing_payment(
+    wallet: &impl RpcApi,
+    address: &str,
+    amount: Amount,
+    since: Option<&str>,
+) -> bitcoincore_rpc::Result<Option<String>> {
+    // blockhash, target_confirmations, include_watchonly
+    let args = [json!(since.unwrap_or("")), json!(1), json!(false)];
+    let since_block: SinceBlock = wallet.call("listsinceblock", &args)?;
+    let matches = matching_payments(&since_block.transactions, address, amount);
+    if matches.len() > 1 {
+        let txids: Vec<&str> = matches.iter().map(|tx| tx.txid.as_str()).collect();
+        warn!(
+            "{} payments of {} BTC to {} found ({}); adopting the newest",
+            matches.len(),
+            amount.to_btc(),
+            address,
+            txids.join(", ")
+        );
+    }
+    Ok(matches.first().map(|tx| tx.txid.clone()))
+}
+
+// Whether a failed call may still have been carried out by the node: the
+// connection broke, rather than the node answering with an error
+pub fn may_have_reached_node(error: &Error) -> bool {
+    matches!(error, Error::JsonRpc(jsonrpc::Error::Transport(_)))
+}
+
+// Helper function to make the payment of `amount` to `address` with `send`.
+// If the connection fails in a way the node may still have sent it, the
+// wallet's sends since block `since` are searched and a matching payment is
+// adopted; otherwise the original error is returned.
+pub fn send_or_adopt(
+    wallet: &impl RpcApi,
+    address: &str,
+    amount: Amount,
+    since: &str,
+    send: impl FnOnce() -> bitcoincore_rpc::Result<String>,
+) -> bitcoincore_rpc::Result<String> {
+    match send() {
+        Err(e) if may_have_reached_node(&e) => {
+            warn!(
+                "The payment failed ({}); checking whether it was sent anyway",
+                e
+            );
+            match find_existing_payment(wallet, address, amount, Some(since)) {
+                Ok(Some(txid)) => {
+                    info!("Adopting {}, already paying {}", txid, address);
+                    Ok(txid)
+                }
+                _ => Err(e),
+            }
+        }
+        sent => sent,
+    }
+}
+
+// Helper function to make the payment of `amount` from `wallet_name` to
+// `address` at most once across runs. The intent is kept in the state file at
+// `state` while the send is in doubt: an earlier run's intent for the same
+// payment is searched for from its own tip before anything is sent, and a new
+// one starts from `tip`, the best block now.
+pub fn send_once(
+    wallet: &impl RpcApi,
+    state: &Path,
+    wallet_name: &str,
+    address: &str,
+    amount: Amount,
+    tip: &str,
+    send: impl FnOnce() -> bitcoincore_rpc::Result<String>,
+) -> bitcoincore_rpc::Result<String> {
+    let state_error = Error::ReturnedError;
+    let earlier = load_pending(state)
+        .map_err(state_error)?
+        .filter(|pending| pending.is_for(wallet_name, amount) && pending.address == address);
+    let pending = match earlier {
+        Some(pending) => {
+            info!(
+                "Resuming the send recorded in {} (tip {})",
+                state.display(),
+                pending.since
+            );
+            if let Some(txid) =
+                find_existing_payment(wallet, address, amount, Some(&pending.since))?
+            {
+                info!("Adopting {}, already paying {}", txid, address);
+                clear_pending(state).map_err(state_error)?;
+                return Ok(txid);
+            }
+            pending
+        }
+        None => {
+            let pending = PendingSend {
+                wallet: wallet_name.to_string(),
+                address: address.to_string(),
+                amount_sat: amount.to_sat(),
+                since: tip.to_string(),
+            };
+            save_pending(state, &pending).map_err(state_error)?;
+            pending
+        }
+    };
+    let result = send_or_adopt(wallet, address, amount, &pending.since, send);
+    // A send that may have reached the node stays in doubt for the next run
+    match &result {
+        Err(e) if may_have_reached_node(e) => {}
+        _ => clear_pending(state).map_err(state_error)?,
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRADER: &str = "bcrt1qtrader";
+
+    fn entry(txid: &str, address: &str, sats: i64, time: u64) -> SinceBlockEntry {
+        serde_json::from_value(json!({
+            "txid": txid,
+            "category": if sats < 0 { "send" } else { "receive" },
+            "amount": sats as f64 / 100_000_000.0,
+            "confirmations": 0,
+            "address": address,
+            "time": time,
+        }))
+        .unwrap()
+    }
+
+    fn state_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn pending(address: &str) -> PendingSend {
+        PendingSend {
+            wallet: "Miner".to_string(),
+            address: address.to_string(),
+            amount_sat: 2_000_000_000,
+            since: "00ff".to_string(),
+        }
+    }
+
+    // A wallet whose `listsinceblock` returns `transactions`
+    struct Since(serde_json::Value);
+
+    impl RpcApi for Since {
+        fn call<T: for<'a> serde::de::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            _: &[serde_json::Value],
+        ) -> bitcoincore_rpc::Result<T> {
+            match cmd {
+                "listsinceblock" => Ok(serde_json::from_value(
+                    json!({ "transactions": self.0.clone() }),
+                )?),
+                _ => Err(Error::ReturnedError(format!("unexpected call to {}", cmd))),
+            }
+        }
+    }
+
+    fn transport_error() -> Error {
+        Error::JsonRpc(jsonrpc::Error::Transport("reset by peer".into()))
+    }
+
+    fn txids(matches: Vec<&WalletTx>) -> Vec<&str> {
+        matches.into_iter().map(|tx| tx.txid.as_str()).collect()
+    }
+
+    #[test]
+    fn finds_the_interrupted_payment() {
+        let entries = vec![
+            entry("other", "bcrt1qsomeone", -2_000_000_000, 10),
+            entry("paid", TRADER, -2_000_000_000, 20),
+        ];
+        let found = matching_payments(&entries, TRADER, Amount::from_int_btc(20));
+        assert_eq!(txids(found), vec!["paid"]);
+    }
+
+    #[test]
+    fn amount_must_match_to_the_sat() {
+        let entries = vec![
+            entry("short", TRADER, -1_999_999_999, 10),
+            entry("incoming", TRADER, 2_000_000_000, 20),
+        ];
+        assert!(matching_payments(&entries, TRADER, Amount::from_int_btc(20)).is_empty());
+    }
+
+    #[test]
+    fn conflicted_and_abandoned_sends_do_not_count() {
+        let mut replaced = entry("replaced", TRADER, -2_000_000_000, 10);
+        replaced.tx.confirmations = -1;
+        let mut abandoned = entry("abandoned", TRADER, -2_000_000_000, 20);
+        abandoned.abandoned = true;
+        let entries = vec![replaced, abandoned];
+        assert!(matching_payments(&entries, TRADER, Amount::from_int_btc(20)).is_empty());
+    }
+
+    #[test]
+    fn several_candidates_newest_first() {
+        let entries = vec![
+            entry("first", TRADER, -2_000_000_000, 10),
+            entry("third", TRADER, -2_000_000_000, 30),
+            entry("second", TRADER, -2_000_000_000, 20),
+            entry("third", TRADER, -2_000_000_000, 30),
+        ];
+        let found = matching_payments(&entries, TRADER, Amount::from_int_btc(20));
+        assert_eq!(txids(found), vec!["third", "second", "first"]);
+    }
+
+    #[test]
+    fn only_broken_connections_may_have_sent() {
+        let transport = Error::JsonRpc(jsonrpc::Error::Transport("reset by peer".into()));
+        assert!(may_have_reached_node(&transport));
+        let refused = Error::ReturnedError("Insufficient funds".to_string());
+        assert!(!may_have_reached_node(&refused));
+    }
+
+    #[test]
+    fn parses_abandoned_flag() {
+        let parsed: SinceBlockEntry = serde_json::from_value(json!({
+            "txid": "aa",
+            "category": "send",
+            "amount": -20.0,
+            "confirmations": 0,
+            "abandoned": true,
+            "time": 1,
+        }))
+        .unwrap();
+        assert!(parsed.abandoned);
+        assert_eq!(parsed.tx.txid, "aa");
+    }
+
+    #[test]
+    fn pending_send_round_trips() {
+        let path = state_path("pending-send");
+        assert_eq!(load_pending(&path).unwrap(), None);
+        save_pending(&path, &pending(TRADER)).unwrap();
+        assert_eq!(load_pending(&path).unwrap(), Some(pending(TRADER)));
+        clear_pending(&path).unwrap();
+        assert_eq!(load_pending(&path).unwrap(), None);
+        // Clearing twice is fine
+        clear_pending(&path).unwrap();
+    }
+
+    #[test]
+    fn a_broken_pending_file_is_an_error() {
+        let path = state_path("pending-send-broken");
+        fs::write(&path, "{").unwrap();
+        assert!(load_pending(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pending_send_matches_wallet_and_amount() {
+        let pending = pending(TRADER);
+        assert!(pending.is_for("Miner", Amount::from_int_btc(20)));
+        assert!(!pending.is_for("Trader", Amount::from_int_btc(20)));
+        assert!(!pending.is_for("Miner", Amount::from_int_btc(10)));
+    }
+
+    #[test]
+    fn an_interrupted_send_is_adopted_by_the_next_run() {
+        let path = state_path("pending-send-adopt");
+        let amount = Amount::from_int_btc(20);
+        // The first run dies with the send in doubt; its intent stays behind
+        let wallet = Since(json!([]));
+        let err = send_once(&wallet, &path, "Miner", TRADER, amount, "00ff", || {
+            Err(transport_error())
+        })
+        .unwrap_err();
+        assert!(may_have_reached_node(&err));
+        assert_eq!(load_pending(&path).unwrap().unwrap().since, "00ff");
+        // The node did make it; the next run finds it from the first run's tip
+        let wallet = Since(json!([{
+            "txid": "paid",
+            "category": "send",
+            "amount": -20.0,
+            "confirmations": 1,
+            "address": TRADER,
+            "time": 10,
+        }]));
+        let txid = send_once(&wallet, &path, "Miner", TRADER, amount, "11ee", || {
+            panic!("paid twice")
+        })
+        .unwrap();
+        assert_eq!(txid, "paid");
+        assert_eq!(load_pending(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn a_refused_send_is_not_kept() {
+        let path = state_path("pending-send-refused");
+        let refused = send_once(
+            &Since(json!([])),
+            &path,
+            "Miner",
+            TRADER,
+            Amount::from_int_btc(20),
+            "00ff",
+            || Err(Error::ReturnedError("Insufficient funds".to_string())),
+        );
+        assert!(refused.is_err());
+        assert_eq!(load_pending(&path).unwrap(), None);
+    }
+}
@@ -0,0 +1,594 @@
+// The ten-line out.txt report: canonical writer plus strict and lenient parsers.
+//
+// Our own writer always emits amounts in the canonical 8-decimal form.
+// `Report::parse_strict` only accepts exactly that layout and is used to
+// self-validate the file we just wrote. `Report::parse_lenient` accepts the
+// many shapes seen in hand-made or third-party files ("20", "20.0",
+// "2e1", stray whitespace, CRLF line endings, a negative fee, ...) and
+// normalizes them, for comparing or verifying (`report verify`) reports we
+// did not produce.
+//
+// With `--format json` the same report is written as a JSON document instead
+// (`Report::write_json`). Each amount is an object holding the exact
+// 8-decimal string and the number of satoshis (`"fee": {"btc": "0.00001410",
+// "sat": 1410}`), never a float. It also carries the confirmation time, vsize,
+// every recipient output, the `--rbf` fee bump, the `--multisig` round trip
+// and the chain state fingerprint, which out.txt has no line for. With
+// `--format csv` it is a header of the ten field names over one row of the
+// out.txt values (`Report::to_csv`).
+use crate::fingerprint::Fingerprint;
+use crate::multisig::MultisigDemo;
+use crate::rbf::FeeBump;
+use bitcoincore_rpc::bitcoin::{Amount, SignedAmount};
+use serde::{Serialize, Serializer};
+use std::fmt::Write as _;
+use std::io;
+
+const SATS_PER_BTC: i128 = 100_000_000;
+const MAX_SATS: i128 = 21_000_000 * SATS_PER_BTC;
+const REPORT_LINES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub txid: String,
+    pub miner_input_address: String,
+    #[serde(serialize_with = "btc_and_sat")]
+    pub miner_input_amount: Amount,
+    pub trader_output_address: String,
+    #[serde(serialize_with = "btc_and_sat")]
+    pub trader_output_amount: Amount,
+    pub miner_change_address: String,
+    #[serde(serialize_with = "btc_and_sat")]
+    pub miner_change_amount: Amount,
+    #[serde(serialize_with = "btc_and_sat")]
+    pub fee: Amount,
+    pub block_height: u64,
+    pub block_hash: String,
+    // JSON only: the confirming block's timestamp and the payment's vsize.
+    // Unknown (null) for reports read back from text.
+    pub confirmation_time: Option<u64>,
+    pub vsize: Option<u64>,
+    // JSON only: every output paying a recipient, the Trader's first. Empty
+    // for reports read back from text.
+    pub recipients: Vec<Recipient>,
+    // JSON only: with --rbf, the original and replacement payments
+    pub fee_bump: Option<FeeBump>,
+    // JSON only: with --multisig, the 2-of-2 address and its two transactions
+    pub multisig: Option<MultisigDemo>,
+    // JSON only: the chain state the run ended in
+    pub fingerprint: Option<Fingerprint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Recipient {
+    pub address: String,
+    #[serde(serialize_with = "btc_and_sat")]
+    pub amount: Amount,
+}
+
+// How `--format` writes the report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            other => Err(format!(
+                "invalid --format value '{}': expected text, json or csv",
+                other
+            )),
+        }
+    }
+}
+
+// The CSV header: the out.txt fields, in out.txt order
+const CSV_COLUMNS: [&str; REPORT_LINES] = [
+    "txid",
+    "miner_input_address",
+    "miner_input_amount_btc",
+    "trader_output_address",
+    "trader_output_amount_btc",
+    "miner_change_address",
+    "miner_change_amount_btc",
+    "fee_btc",
+    "block_height",
+    "block_hash",
+];
+
+// A JSON amount: the exact 8-decimal string and the number of satoshis. A
+// float could not always hold the BTC value exactly; the satoshi integer can.
+#[derive(Serialize)]
+struct BtcAndSat {
+    btc: String,
+    sat: u64,
+}
+
+fn btc_and_sat<S: Serializer>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error> {
+    BtcAndSat {
+        btc: format_btc(*amount),
+        sat: amount.to_sat(),
+    }
+    .serialize(serializer)
+}
+
+// Parse a BTC amount written in decimal or scientific notation into an exact
+// satoshi value. Surrounding whitespace is ignored; more than 8 decimal places
+// of precision is rejected rather than rounded.
+pub fn parse_btc(s: &str) -> Result<SignedAmount, String> {
+    let s = s.trim();
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(pos) => {
+            let exp = unsigned[pos + 1..]
+                .parse::<i32>()
+                .map_err(|_| format!("invalid exponent in amount '{}'", s))?;
+            (&unsigned[..pos], exp)
+        }
+        None => (unsigned, 0),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_part.is_empty() && frac_part.is_empty()
+        || !int_part
+            .bytes()
+            .chain(frac_part.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(format!("invalid amount '{}'", s));
+    }
+
+    // value = digits * 10^(exponent - frac_len) BTC = digits * 10^shift sats
+    let digits = format!("{}{}", int_part, frac_part);
+    let digits = digits.trim_start_matches('0');
+    let shift = i32::try_from(frac_part.len())
+        .ok()
+        .and_then(|frac_len| exponent.checked_add(8)?.checked_sub(frac_len))
+        .ok_or_else(|| format!("invalid exponent in amount '{}'", s))?;
+    let (digits, shift) = if shift < 0 {
+        let cut = digits.len().saturating_sub(shift.unsigned_abs() as usize);
+        if digits[cut..].bytes().any(|b| b != b'0') {
+            return Err(format!("amount '{}' has more than 8 decimal places", s));
+        }
+        (&digits[..cut], 0)
+    } else {
+        (digits, shift)
+    };
+    if digits.len() + shift as usize > 16 {
+        return Err(format!("amount '{}' exceeds the 21M BTC supply", s));
+    }
+    let sats = if digits.is_empty() {
+        0
+    } else {
+        digits.parse::<i128>().unwrap() * 10i128.pow(shift as u32)
+    };
+    if sats > MAX_SATS {
+        return Err(format!("amount '{}' exceeds the 21M BTC supply", s));
+    }
+    let sats = if negative { -sats } else { sats };
+    Ok(SignedAmount::from_sat(sats as i64))
+}
+
+// Format an amount in the canonical 8-decimal BTC form, e.g. `20.00000000`.
+pub fn format_btc(amount: Amount) -> String {
+    let sats = amount.to_sat();
+    format!(
+        "{}.{:08}",
+        sats / SATS_PER_BTC as u64,
+        sats % SATS_PER_BTC as u64
+    )
+}
+
+fn unsigned_btc(s: &str, field: &str) -> Result<Amount, String> {
+    let amount = parse_btc(s).map_err(|e| format!("{}: {}", field, e))?;
+    amount
+        .to_unsigned()
+        .map_err(|_| format!("{}: amount '{}' must not be negative", field, s.trim()))
+}
+
+fn is_hash(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl Report {
+    // The ten out.txt values, in order
+    fn fields(&self) -> [String; REPORT_LINES] {
+        [
+            self.txid.clone(),
+            self.miner_input_address.clone(),
+            format_btc(self.miner_input_amount),
+            self.trader_output_address.clone(),
+            format_btc(self.trader_output_amount),
+            self.miner_change_address.clone(),
+            format_btc(self.miner_change_amount),
+            format_btc(self.fee),
+            self.block_height.to_string(),
+            self.block_hash.clone(),
+        ]
+    }
+
+    // Render the report in the canonical out.txt layout.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for line in self.fields() {
+            writeln!(out, "{}", line).unwrap();
+        }
+        out
+    }
+
+    // Render the report as a CSV header and one row. No field can hold a
+    // comma, quote or newline, so none is quoted.
+    pub fn to_csv(&self) -> String {
+        format!("{}\n{}\n", CSV_COLUMNS.join(","), self.fields().join(","))
+    }
+
+    pub fn write_text(&self, out: &mut impl io::Write) -> io::Result<()> {
+        out.write_all(self.to_text().as_bytes())
+    }
+
+    // Pretty-printed JSON with a final newline.
+    pub fn write_json(&self, out: &mut impl io::Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, self)?;
+        writeln!(out)
+    }
+
+    pub fn write(&self, format: ReportFormat, out: &mut impl io::Write) -> io::Result<()> {
+        match format {
+            ReportFormat::Text => self.write_text(out),
+            ReportFormat::Json => self.write_json(out),
+            ReportFormat::Csv => out.write_all(self.to_csv().as_bytes()),
+        }
+    }
+
+    // Accept only the exact layout produced by `to_text`.
+    pub fn parse_strict(text: &str) -> Result<Report, String> {
+        let body = text
+            .strip_suffix('\n')
+            .ok_or("report must end with a newline")?;
+        let lines: Vec<&str> = body.split('\n').collect();
+        if lines.len() != REPORT_LINES {
+            return Err(format!(
+                "expected {} lines, found {}",
+                REPORT_LINES,
+                lines.len()
+            ));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            if line.is_empty() || line.trim() != *line {
+                return Err(format!(
+                    "line {} is empty or has surrounding whitespace",
+                    i + 1
+                ));
+            }
+        }
+
+        let report = Self::from_fields(&lines)?;
+        for (i, field) in [
+            (2, "miner input amount"),
+            (4, "trader output amount"),
+            (6, "miner change amount"),
+            (7, "fee"),
+        ] {
+            let canonical = format_btc(unsigned_btc(lines[i], field)?);
+            if lines[i] != canonical {
+                return Err(format!(
+                    "{}: '{}' is not in canonical form '{}'",
+                    field, lines[i], canonical
+                ));
+            }
+        }
+        if report.txid.bytes().any(|b| b.is_ascii_uppercase())
+            || report.block_hash.bytes().any(|b| b.is_ascii_uppercase())
+        {
+            return Err("hashes must be lowercase hex".to_string());
+        }
+        if lines[8] != report.block_height.to_string() {
+            return Err(format!("block height '{}' is not canonical", lines[8]));
+        }
+        Ok(report)
+    }
+
+    // Accept any reasonable rendering of the ten fields and normalize it.
+    pub fn parse_lenient(text: &str) -> Result<Report, String> {
+        let text = text.trim_start_matches('\u{feff}');
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.len() != REPORT_LINES {
+            return Err(format!(
+                "expected {} non-empty lines, found {}",
+                REPORT_LINES,
+                lines.len()
+            ));
+        }
+        let mut report = Self::from_fields(&lines)?;
+        report.txid.make_ascii_lowercase();
+        report.block_hash.make_ascii_lowercase();
+        Ok(report)
+    }
+
+    fn from_fields(lines: &[&str]) -> Result<Report, String> {
+        if !is_hash(lines[0]) {
+            return Err(format!(
+                "txid '{}' is not a 64-character hex string",
+                lines[0]
+            ));
+        }
+        if !is_hash(lines[9]) {
+            return Err(format!(
+                "block hash '{}' is not a 64-character hex string",
+                lines[9]
+            ));
+        }
+        // Some tools report the fee as a negative wallet delta; only its size matters.
+        let fee = parse_btc(lines[7]).map_err(|e| format!("fee: {}", e))?;
+        Ok(Report {
+            txid: lines[0].to_string(),
+            miner_input_address: lines[1].to_string(),
+            miner_input_amount: unsigned_btc(lines[2], "miner input amount")?,
+            trader_output_address: lines[3].to_string(),
+            trader_output_amount: unsigned_btc(lines[4], "trader output amount")?,
+            miner_change_address: lines[5].to_string(),
+            miner_change_amount: unsigned_btc(lines[6], "miner change amount")?,
+            fee: fee.abs().to_unsigned().unwrap(),
+            block_height: lines[8]
+                .parse()
+                .map_err(|_| format!("block height '{}' is not an integer", lines[8]))?,
+            block_hash: lines[9].to_string(),
+            confirmation_time: None,
+            vsize: None,
+            recipients: Vec::new(),
+            fee_bump: None,
+            multisig: None,
+            fingerprint: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "33b2570db2b2f18f03b44eba7f19efb686978ad22a242a78c03d049364196db9";
+
+    fn sample() -> Report {
+        Report {
+            txid: TXID.to_string(),
+            miner_input_address: "bcrt1q85jd3kzk2mahxkuzsptcn7sm7dmre6wuffvjye".to_string(),
+            miner_input_amount: Amount::from_int_btc(50),
+            trader_output_address: "bcrt1qywl0kv3kfprpl5y4r8dkgmds6xte4qp5crg9yr".to_string(),
+            trader_output_amount: Amount::from_int_btc(20),
+            miner_change_address: "bcrt1qgamkk9c6mzguq0eecnsje2mlf8xc4z86a9gxhr".to_string(),
+            miner_change_amount: Amount::from_sat(2_999_999_859),
+            fee: Amount::from_sat(141),
+            block_height: 102,
+            block_hash: "044f4aa26f7b069f4e78dd53eb8952e29b3714eedc55f35d1f3325cb4abd0912"
+                .to_string(),
+            confirmation_time: None,
+            vsize: None,
+            recipients: Vec::new(),
+            fee_bump: None,
+            multisig: None,
+            fingerprint: None,
+        }
+    }
+
+    fn golden_file(name: &str) -> String {
+        let path = format!("{}/testdata/golden/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    // The sample as the run reports it, with the JSON-only fields known
+    fn confirmed_sample() -> Report {
+        Report {
+            confirmation_time: Some(1_700_000_600),
+            vsize: Some(141),
+            recipients: vec![Recipient {
+                address: sample().trader_output_address,
+                amount: sample().trader_output_amount,
+            }],
+            fingerprint: Some(Fingerprint {
+                tip_hash: sample().block_hash.parse().unwrap(),
+                tip_height: 102,
+                confirmation_block_hash: sample().block_hash.parse().unwrap(),
+                wallet_txcounts: [("Miner".to_string(), 103), ("Trader".to_string(), 1)].into(),
+                // The change of the payment is the Miner's only UTXO
+                miner_utxos_sha256: crate::fingerprint::outpoints_digest(&[(TXID.to_string(), 1)]),
+            }),
+            ..sample()
+        }
+    }
+
+    fn written(report: &Report, format: ReportFormat) -> String {
+        let mut out = Vec::new();
+        report.write(format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse_btc_accepts_common_forms() {
+        for (input, sats) in [
+            ("20", 2_000_000_000),
+            ("20.0", 2_000_000_000),
+            ("20.00000000", 2_000_000_000),
+            ("  20 \t", 2_000_000_000),
+            ("0.00000141", 141),
+            (".5", 50_000_000),
+            ("5.", 500_000_000),
+            ("-1.41e-05", -1_410),
+            ("1.41E-6", 141),
+            ("2e1", 2_000_000_000),
+            ("0", 0),
+        ] {
+            assert_eq!(
+                parse_btc(input).unwrap().to_sat(),
+                sats,
+                "input {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parse_btc_rejects_bad_input() {
+        for input in [
+            "",
+            ".",
+            "abc",
+            "1.2.3",
+            "0.000000001",
+            "21000001",
+            "1e",
+            "--1",
+        ] {
+            assert!(parse_btc(input).is_err(), "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_btc_rejects_extreme_exponents() {
+        assert_eq!(
+            parse_btc("1e2147483647").unwrap_err(),
+            "invalid exponent in amount '1e2147483647'"
+        );
+        assert!(parse_btc("1e-2147483648").is_err());
+        assert!(parse_btc("1.5e-2147483648").is_err());
+    }
+
+    #[test]
+    fn canonical_text_round_trips_strictly() {
+        let text = sample().to_text();
+        assert!(text.contains("\n50.00000000\n"));
+        assert!(text.contains("\n29.99999859\n"));
+        assert!(text.contains("\n0.00000141\n"));
+        assert_eq!(Report::parse_strict(&text).unwrap(), sample());
+    }
+
+    // A corpus of out.txt files in the shapes students actually submit, with
+    // whether each parser accepts them.
+    const CORPUS: &[(&str, bool, bool)] = &[
+        ("canonical.txt", true, true),
+        ("integer-amounts.txt", false, true),
+        ("one-decimal.txt", false, true),
+        ("trailing-whitespace.txt", false, true),
+        ("crlf.txt", false, true),
+        ("blank-lines.txt", false, true),
+        ("negative-fee.txt", false, true),
+        ("scientific-fee.txt", false, true),
+        ("uppercase-hashes.txt", false, true),
+        ("no-final-newline.txt", false, true),
+        ("missing-line.txt", false, false),
+        ("too-precise.txt", false, false),
+    ];
+
+    fn corpus_file(name: &str) -> String {
+        let path = format!("{}/testdata/reports/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    #[test]
+    fn corpus_acceptance() {
+        for &(name, strict, lenient) in CORPUS {
+            let text = corpus_file(name);
+            assert_eq!(
+                Report::parse_strict(&text).is_ok(),
+                strict,
+                "strict: {}",
+                name
+            );
+            assert_eq!(
+                Report::parse_lenient(&text).is_ok(),
+                lenient,
+                "lenient: {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn corpus_normalizes_to_same_report() {
+        for &(name, _, lenient) in CORPUS {
+            if lenient {
+                assert_eq!(
+                    Report::parse_lenient(&corpus_file(name)).unwrap(),
+                    sample(),
+                    "{}",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn text_output_matches_golden_file() {
+        assert_eq!(
+            written(&confirmed_sample(), ReportFormat::Text),
+            golden_file("report.txt")
+        );
+        assert_eq!(golden_file("report.txt"), corpus_file("canonical.txt"));
+    }
+
+    #[test]
+    fn json_output_matches_golden_file() {
+        assert_eq!(
+            written(&confirmed_sample(), ReportFormat::Json),
+            golden_file("report.json")
+        );
+    }
+
+    #[test]
+    fn csv_output_matches_golden_file() {
+        assert_eq!(
+            written(&confirmed_sample(), ReportFormat::Csv),
+            golden_file("report.csv")
+        );
+    }
+
+    #[test]
+    fn csv_row_holds_the_text_lines() {
+        let csv = written(&sample(), ReportFormat::Csv);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].split(',').count(), REPORT_LINES);
+        let text = written(&sample(), ReportFormat::Text);
+        assert_eq!(
+            rows[1].split(',').collect::<Vec<_>>(),
+            text.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn json_amounts_are_exact_strings_and_satoshi_numbers() {
+        let json: serde_json::Value =
+            serde_json::from_str(&written(&sample(), ReportFormat::Json)).unwrap();
+        assert_eq!(json["fee"]["btc"], "0.00000141");
+        assert_eq!(json["fee"]["sat"], 141);
+        assert_eq!(json["trader_output_amount"]["btc"], "20.00000000");
+        assert_eq!(json["miner_input_amount"]["sat"], 5_000_000_000u64);
+        assert_eq!(json["miner_change_amount"]["sat"], 2_999_999_859u64);
+        assert_eq!(json["block_height"], 102);
+        assert!(json["confirmation_time"].is_null());
+        assert!(json.get("fee_btc").is_none());
+    }
+
+    #[test]
+    fn report_format_parses_text_and_json() {
+        assert_eq!(ReportFormat::parse("text"), Ok(ReportFormat::Text));
+        assert_eq!(ReportFormat::parse("json"), Ok(ReportFormat::Json));
+        assert_eq!(ReportFormat::parse("csv"), Ok(ReportFormat::Csv));
+        assert!(ReportFormat::parse("JSON").is_err());
+        assert_eq!(ReportFormat::default(), ReportFormat::Text);
+    }
+}
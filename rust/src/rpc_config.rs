@@ -0,0 +1,386 @@
+// Where the node's RPC interface is and which credentials to use.
+//
+// Settings come, from highest to lowest precedence, from the command line
+// (`--rpc-url`, `--rpc-user`, `--rpc-pass`, `--cookie-file`, applied with
+// `with_args`), from the environment (`BITCOIN_RPC_URL`, `BITCOIN_RPC_USER`,
+// `BITCOIN_RPC_PASS`), from an optional `.env`-style file named by
+// `BITCOIN_RPC_CONFIG` using the same keys, and finally from the regtest
+// defaults this project was written for.
+//
+// The node's `.cookie` file is used instead of a user and password when one is
+// given with `--cookie-file`, or when the credentials are explicitly blanked
+// (`--rpc-user= --rpc-pass=`); the latter probes the default regtest datadirs.
+use crate::arg_value;
+use crate::node_connection::NodeConnection;
+use crate::{RPC_PASS, RPC_URL, RPC_USER};
+use bitcoincore_rpc::{Auth, Client};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+pub const URL_VAR: &str = "BITCOIN_RPC_URL";
+pub const USER_VAR: &str = "BITCOIN_RPC_USER";
+pub const PASS_VAR: &str = "BITCOIN_RPC_PASS";
+pub const CONFIG_VAR: &str = "BITCOIN_RPC_CONFIG";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcConfig {
+    pub url: String,
+    // Both empty to authenticate with a cookie file
+    pub user: String,
+    pub pass: String,
+    pub cookie_file: Option<PathBuf>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            url: RPC_URL.to_string(),
+            user: RPC_USER.to_string(),
+            pass: RPC_PASS.to_string(),
+            cookie_file: None,
+        }
+    }
+}
+
+impl RpcConfig {
+    // Load the settings from the process environment over the regtest defaults.
+    pub fn load() -> Result<Self, String> {
+        Self::load_with_defaults(RpcConfig::default())
+    }
+
+    // Same as `load`, over caller-supplied defaults (e.g. another chain's port)
+    pub fn load_with_defaults(defaults: RpcConfig) -> Result<Self, String> {
+        resolve(defaults, |name| std::env::var(name).ok())
+    }
+
+    // Apply the `--rpc-url`, `--rpc-user`, `--rpc-pass` and `--cookie-file`
+    // flags of `args`
+    pub fn with_args(mut self, args: &[String]) -> Self {
+        for (flag, field) in [
+            ("--rpc-url", &mut self.url),
+            ("--rpc-user", &mut self.user),
+            ("--rpc-pass", &mut self.pass),
+        ] {
+            if let Some(value) = arg_value(args, flag) {
+                *field = value.to_string();
+            }
+        }
+        if let Some(path) = arg_value(args, "--cookie-file") {
+            self.cookie_file = Some(path.into());
+        }
+        self
+    }
+
+    // Whether `auth` picks a cookie file rather than the user and password
+    pub fn uses_cookie(&self) -> bool {
+        self.cookie_file.is_some() || (self.user.is_empty() && self.pass.is_empty())
+    }
+
+    // How to authenticate: the cookie file if given, else the user and
+    // password, else (both blank) the first cookie in the default regtest
+    // datadirs
+    pub fn auth(&self) -> Result<Auth, String> {
+        self.select_auth(&default_cookie_paths(|name| std::env::var(name).ok()))
+    }
+
+    fn select_auth(&self, default_cookies: &[PathBuf]) -> Result<Auth, String> {
+        match (
+            &self.cookie_file,
+            self.user.is_empty(),
+            self.pass.is_empty(),
+        ) {
+            (Some(path), _, _) if path.is_file() => Ok(Auth::CookieFile(path.clone())),
+            (Some(path), _, _) => Err(format!("cookie file {} does not exist", path.display())),
+            (None, false, false) => Ok(Auth::UserPass(self.user.clone(), self.pass.clone())),
+            (None, false, true) => Err(format!(
+                "RPC user '{}' is set without a password",
+                self.user
+            )),
+            (None, true, false) => Err("RPC password is set without a user".to_string()),
+            (None, true, true) => default_cookies
+                .iter()
+                .find(|path| path.is_file())
+                .map(|path| Auth::CookieFile(path.clone()))
+                .ok_or_else(|| {
+                    format!(
+                        "no RPC credentials: either pass --rpc-user and --rpc-pass (or set {} \
+                         and {}), or point --cookie-file at the node's .cookie; none was \
+                         found in the default regtest datadirs",
+                        USER_VAR, PASS_VAR
+                    )
+                }),
+        }
+    }
+
+    // The node's URL with the chosen authentication
+    pub fn connection(&self) -> bitcoincore_rpc::Result<NodeConnection> {
+        let auth = self.auth().map_err(bitcoincore_rpc::Error::ReturnedError)?;
+        NodeConnection::builder()
+            .url(self.url.as_str())
+            .auth(auth)
+            .build()
+            .map_err(bitcoincore_rpc::Error::ReturnedError)
+    }
+
+    // Helper function to connect to the node
+    pub fn client(&self) -> bitcoincore_rpc::Result<Client> {
+        self.connection()?.client()
+    }
+
+    fn apply(&mut self, settings: &HashMap<String, String>) {
+        for (name, field) in [
+            (URL_VAR, &mut self.url),
+            (USER_VAR, &mut self.user),
+            (PASS_VAR, &mut self.pass),
+        ] {
+            if let Some(value) = settings.get(name) {
+                *field = value.clone();
+            }
+        }
+    }
+}
+
+// The regtest `.cookie` in the default datadir on Linux, macOS and Windows
+fn default_cookie_paths(env: impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(home) = env("HOME").map(PathBuf::from) {
+        paths.push(home.join(".bitcoin"));
+        paths.push(home.join("Library/Application Support/Bitcoin"));
+    }
+    if let Some(appdata) = env("APPDATA") {
+        paths.push(PathBuf::from(appdata).join("Bitcoin"));
+    }
+    paths
+        .into_iter()
+        .map(|datadir| datadir.join("regtest").join(".cookie"))
+        .collect()
+}
+
+// Apply the config file and then the variables from `env` over `defaults`.
+// Empty variables count as unset.
+fn resolve(defaults: RpcConfig, env: impl Fn(&str) -> Option<String>) -> Result<RpcConfig, String> {
+    let env = |name: &str| env(name).filter(|value| !value.is_empty());
+    let mut config = defaults;
+    if let Some(path) = env(CONFIG_VAR) {
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("cannot read {} file '{}': {}", CONFIG_VAR, path, e))?;
+        let settings = parse_env_file(&text).map_err(|e| format!("{}: {}", path, e))?;
+        config.apply(&settings);
+    }
+    let overrides: HashMap<String, String> = [URL_VAR, USER_VAR, PASS_VAR]
+        .into_iter()
+        .filter_map(|name| env(name).map(|value| (name.to_string(), value)))
+        .collect();
+    config.apply(&overrides);
+    Ok(config)
+}
+
+// Parse `KEY=value` lines. Blank lines, `#` comments and a leading `export`
+// are skipped, and one pair of matching quotes around a value is removed.
+fn parse_env_file(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut settings = HashMap::new();
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected 'KEY=value', found '{}'", i + 1, line))?;
+        let value = value.trim();
+        let value = ['"', '\'']
+            .into_iter()
+            .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+            .unwrap_or(value);
+        settings.insert(key.trim().to_string(), value.to_string());
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_of(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn env_file(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.env", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn defaults_when_nothing_is_set() {
+        let config = resolve(RpcConfig::default(), env_of(&[])).unwrap();
+        assert_eq!(config.url, RPC_URL);
+        assert_eq!(config.user, RPC_USER);
+        assert_eq!(config.pass, RPC_PASS);
+        assert_eq!(config.cookie_file, None);
+        assert_eq!(
+            config.select_auth(&[]),
+            Ok(Auth::UserPass(RPC_USER.to_string(), RPC_PASS.to_string()))
+        );
+    }
+
+    #[test]
+    fn env_vars_take_precedence_over_defaults() {
+        let env = env_of(&[
+            (URL_VAR, "http://10.0.0.5:18443"),
+            (USER_VAR, "bob"),
+            (PASS_VAR, "hunter2"),
+        ]);
+        let config = resolve(RpcConfig::default(), env).unwrap();
+        assert_eq!(
+            config,
+            RpcConfig {
+                url: "http://10.0.0.5:18443".to_string(),
+                user: "bob".to_string(),
+                pass: "hunter2".to_string(),
+                cookie_file: None,
+            }
+        );
+    }
+
+    #[test]
+    fn unset_and_empty_vars_keep_defaults() {
+        let env = env_of(&[(USER_VAR, "bob"), (PASS_VAR, "")]);
+        let config = resolve(RpcConfig::default(), env).unwrap();
+        assert_eq!(config.url, RPC_URL);
+        assert_eq!(config.user, "bob");
+        assert_eq!(config.pass, RPC_PASS);
+    }
+
+    #[test]
+    fn env_vars_take_precedence_over_config_file() {
+        let path = env_file(
+            "rpc-config-precedence",
+            "# node in the lab\nexport BITCOIN_RPC_URL=\"http://lab:18443\"\n\
+             BITCOIN_RPC_USER='carol'\n\nBITCOIN_RPC_PASS = secret\n",
+        );
+        let env = env_of(&[(CONFIG_VAR, &path), (USER_VAR, "bob")]);
+        let config = resolve(RpcConfig::default(), env).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.url, "http://lab:18443");
+        assert_eq!(config.user, "bob");
+        assert_eq!(config.pass, "secret");
+    }
+
+    #[test]
+    fn broken_or_missing_config_file_is_an_error() {
+        let path = env_file("rpc-config-broken", "BITCOIN_RPC_URL\n");
+        let err = resolve(RpcConfig::default(), env_of(&[(CONFIG_VAR, &path)])).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(err.contains("line 1"), "{}", err);
+
+        let missing = env_of(&[(CONFIG_VAR, "/nonexistent/rpc.env")]);
+        assert!(resolve(RpcConfig::default(), missing).is_err());
+    }
+
+    #[test]
+    fn command_line_takes_precedence_over_env_vars() {
+        let env = env_of(&[(URL_VAR, "http://10.0.0.5:18443"), (USER_VAR, "bob")]);
+        let args: Vec<String> = ["--rpc-user", "carol", "--rpc-pass=hunter2"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let config = resolve(RpcConfig::default(), env).unwrap().with_args(&args);
+        assert_eq!(config.url, "http://10.0.0.5:18443");
+        assert_eq!(config.user, "carol");
+        assert_eq!(config.pass, "hunter2");
+    }
+
+    #[test]
+    fn other_defaults_are_overridden_too() {
+        let signet = RpcConfig {
+            url: "http://127.0.0.1:38332".to_string(),
+            ..RpcConfig::default()
+        };
+        let config = resolve(signet.clone(), env_of(&[(USER_VAR, "bob")])).unwrap();
+        assert_eq!(config.url, signet.url);
+        assert_eq!(config.user, "bob");
+    }
+
+    #[test]
+    fn cookie_file_wins_over_credentials() {
+        let cookie = PathBuf::from(env_file("rpc-cookie-unused", "__cookie__:abc\n"));
+        let config = RpcConfig {
+            user: "bob".to_string(),
+            pass: "hunter2".to_string(),
+            cookie_file: Some(cookie.clone()),
+            ..RpcConfig::default()
+        };
+        let auth = config.select_auth(&[]);
+        fs::remove_file(&cookie).unwrap();
+        assert_eq!(auth, Ok(Auth::CookieFile(cookie)));
+        let half = RpcConfig {
+            pass: String::new(),
+            cookie_file: None,
+            ..config
+        };
+        assert!(half.select_auth(&[]).is_err());
+    }
+
+    // The credentials blanked on the command line, as `--rpc-user= --rpc-pass=`
+    fn blank_credentials() -> RpcConfig {
+        let args: Vec<String> = vec!["--rpc-user=".to_string(), "--rpc-pass=".to_string()];
+        RpcConfig::default().with_args(&args)
+    }
+
+    #[test]
+    fn cookie_file_flag_or_first_default_cookie() {
+        let cookie = PathBuf::from(env_file("rpc-cookie", "__cookie__:abc\n"));
+        let missing = PathBuf::from("/nonexistent/regtest/.cookie");
+        let args: Vec<String> = vec!["--cookie-file".to_string(), cookie.display().to_string()];
+        let explicit = RpcConfig::default().with_args(&args).select_auth(&[]);
+        let probed = blank_credentials().select_auth(&[missing.clone(), cookie.clone()]);
+        let (user, pass) = probed.clone().unwrap().get_user_pass().unwrap();
+        fs::remove_file(&cookie).unwrap();
+        assert_eq!(explicit, Ok(Auth::CookieFile(cookie.clone())));
+        assert_eq!(probed, Ok(Auth::CookieFile(cookie)));
+        assert_eq!(
+            (user.unwrap(), pass.unwrap()),
+            ("__cookie__".into(), "abc".into())
+        );
+
+        let config = RpcConfig {
+            cookie_file: Some(missing),
+            ..RpcConfig::default()
+        };
+        assert!(config.select_auth(&[]).is_err());
+    }
+
+    #[test]
+    fn no_credentials_explains_both_options() {
+        assert!(!RpcConfig::default().uses_cookie());
+        assert!(blank_credentials().uses_cookie());
+        let err = blank_credentials()
+            .select_auth(&[PathBuf::from("/nonexistent/regtest/.cookie")])
+            .unwrap_err();
+        assert!(err.contains("--rpc-user"), "{}", err);
+        assert!(err.contains("--cookie-file"), "{}", err);
+    }
+
+    #[test]
+    fn default_cookies_in_each_platforms_datadir() {
+        let paths =
+            default_cookie_paths(env_of(&[("HOME", "/home/bob"), ("APPDATA", "C:\\AppData")]));
+        assert_eq!(
+            paths[0],
+            PathBuf::from("/home/bob/.bitcoin/regtest/.cookie")
+        );
+        assert_eq!(
+            paths[1],
+            PathBuf::from("/home/bob/Library/Application Support/Bitcoin/regtest/.cookie")
+        );
+        assert!(paths[2].starts_with("C:\\AppData"));
+        assert!(default_cookie_paths(env_of(&[])).is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+// Node and wallet helpers shared by the binary, the integration tests in
+// `tests/` and any other tool built on this crate.
+//
+// Wallet clients are reached through the node's `/wallet/<name>` endpoint with
+// the `NodeConnection` of an `RpcConfig`; every call goes through `rpc_call`,
+// so errors returned by the node carry its code and method.
+use crate::amount_serde::deserialize_amount;
+use crate::node_rpc::NodeRpc;
+use crate::rpc_config::RpcConfig;
+use crate::{rpc_call, wallet_manager, wallet_ready, WALLET_SCAN_TIMEOUT};
+use bitcoincore_rpc::bitcoin::address::NetworkUnchecked;
+use bitcoincore_rpc::bitcoin::{Address, Amount, BlockHash, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+// Helper function to create or load a wallet and wait for it to finish scanning
+// before it is used. With `descriptor` a wallet that has to be created is
+// explicitly a descriptor wallet; otherwise it gets the node's default type.
+pub fn create_or_load_wallet<R: NodeRpc + RpcApi>(
+    rpc: &R,
+    config: &RpcConfig,
+    wallet_name: &str,
+    descriptor: bool,
+) -> bitcoincore_rpc::Result<()> {
+    wallet_manager::load_or_create_wallet(rpc, wallet_name, descriptor)?;
+    let wallet = rpc.wallet(config, wallet_name)?;
+    wallet_ready::wait_for_wallet_ready(&wallet, WALLET_SCAN_TIMEOUT)?;
+    Ok(())
+}
+
+// Helper function to get wallet client
+pub fn get_wallet_client(config: &RpcConfig, wallet_name: &str) -> bitcoincore_rpc::Result<Client> {
+    config.connection()?.wallet_client(wallet_name)
+}
+
+// Helper function to build the wallet-scoped RPC URL from a base node URL.
+// Any existing path prefix (e.g. a reverse proxy mounted at `/btc/`), port and
+// userinfo are preserved, and the wallet name is percent-encoded as a single
+// path segment.
+pub(crate) fn wallet_url(base_url: &str, wallet_name: &str) -> bitcoincore_rpc::Result<String> {
+    if wallet_name.contains('/') {
+        return Err(bitcoincore_rpc::Error::ReturnedError(format!(
+            "invalid wallet name '{}': wallet names used in RPC URLs must not contain '/'",
+            wallet_name
+        )));
+    }
+
+    let mut url = Url::parse(base_url).map_err(|e| {
+        bitcoincore_rpc::Error::ReturnedError(format!("invalid RPC URL '{}': {}", base_url, e))
+    })?;
+    url.path_segments_mut()
+        .map_err(|_| {
+            bitcoincore_rpc::Error::ReturnedError(format!(
+                "invalid RPC URL '{}': cannot be used as a base URL",
+                base_url
+            ))
+        })?
+        .pop_if_empty()
+        .push("wallet")
+        .push(wallet_name);
+    Ok(url.to_string())
+}
+
+/// Helper function to mine `num_blocks` blocks to an address, returning their
+/// hashes.
+///
+/// ```no_run
+/// # fn main() -> bitcoincore_rpc::Result<()> {
+/// use bitcoincore_rpc::RpcApi;
+/// use rust::rpc_config::RpcConfig;
+/// use rust::rpc_helpers::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+///
+/// let config = RpcConfig::load().map_err(bitcoincore_rpc::Error::ReturnedError)?;
+/// let rpc = config.client()?;
+/// create_or_load_wallet(&rpc, &config, "Miner", true)?;
+/// let miner = get_wallet_client(&config, "Miner")?;
+/// let address = miner.get_new_address(None, None)?.assume_checked();
+/// assert_eq!(mine_blocks_to_address(&rpc, &address, 101)?.len(), 101);
+/// # Ok(())
+/// # }
+/// ```
+pub fn mine_blocks_to_address(
+    rpc: &impl RpcApi,
+    address: &Address,
+    num_blocks: u64,
+) -> bitcoincore_rpc::Result<Vec<String>> {
+    let args = [json!(num_blocks), json!(address.to_string())];
+    rpc_call(rpc, "generatetoaddress", &args)
+}
+
+/// Helper function to mine `count` blocks to an address, returning their
+/// hashes as `BlockHash`es rather than the node's hex strings.
+pub fn mine_blocks(
+    rpc: &impl RpcApi,
+    address: &Address,
+    count: u64,
+) -> bitcoincore_rpc::Result<Vec<BlockHash>> {
+    mine_blocks_to_address(rpc, address, count)?
+        .iter()
+        .map(|hash| parse_block_hash(hash))
+        .collect()
+}
+
+// Helper function to parse a block hash returned by the node
+pub(crate) fn parse_block_hash(hash: &str) -> bitcoincore_rpc::Result<BlockHash> {
+    hash.parse().map_err(|e| {
+        bitcoincore_rpc::Error::ReturnedError(format!("invalid block hash '{}': {}", hash, e))
+    })
+}
+
+// The positional `sendtoaddress` arguments for a payment, after checking that
+// the recipient is an address, the amount is positive and the confirmation
+// target is one the node accepts (1 to 1008 blocks)
+pub(crate) fn send_to_address_args(
+    recipient: &str,
+    amount: Amount,
+    comment: Option<&str>,
+    subtract_fee_from_amount: bool,
+    replaceable: bool,
+    conf_target: u32,
+) -> Result<Vec<serde_json::Value>, String> {
+    recipient
+        .parse::<Address<NetworkUnchecked>>()
+        .map_err(|e| format!("invalid recipient address '{}': {}", recipient, e))?;
+    if amount == Amount::ZERO {
+        return Err("the payment amount must be positive".to_string());
+    }
+    if !(1..=1008).contains(&conf_target) {
+        return Err(format!(
+            "invalid confirmation target {}: expected 1 to 1008 blocks",
+            conf_target
+        ));
+    }
+    // address, amount, comment, comment_to, subtractfeefromamount, replaceable,
+    // conf_target, estimate_mode, avoid_reuse
+    Ok(vec![
+        json!(recipient),
+        json!(amount.to_btc()),
+        json!(comment),
+        json!(null),
+        json!(subtract_fee_from_amount),
+        json!(replaceable),
+        json!(conf_target),
+        json!("unset"),
+        json!(false),
+    ])
+}
+
+// Helper function to pay `amount` to `recipient` from wallet client `rpc` with
+// `sendtoaddress`, returning the txid
+pub fn send_to_address(
+    rpc: &impl RpcApi,
+    recipient: &str,
+    amount: Amount,
+    comment: Option<&str>,
+    subtract_fee_from_amount: bool,
+    replaceable: bool,
+    conf_target: u32,
+) -> bitcoincore_rpc::Result<Txid> {
+    let args = send_to_address_args(
+        recipient,
+        amount,
+        comment,
+        subtract_fee_from_amount,
+        replaceable,
+        conf_target,
+    )
+    .map_err(bitcoincore_rpc::Error::ReturnedError)?;
+    rpc_call(rpc, "sendtoaddress", &args)
+}
+
+// The positional `sendmany` arguments for paying several recipients in one
+// transaction, after checking each address and amount, that no address is
+// listed twice (the node rejects duplicates) and the confirmation target
+pub(crate) fn send_many_args(
+    recipients: &[(&str, Amount)],
+    comment: Option<&str>,
+    conf_target: u32,
+) -> Result<Vec<serde_json::Value>, String> {
+    if recipients.is_empty() {
+        return Err("sendmany needs at least one recipient".to_string());
+    }
+    let mut amounts = serde_json::Map::new();
+    for &(recipient, amount) in recipients {
+        recipient
+            .parse::<Address<NetworkUnchecked>>()
+            .map_err(|e| format!("invalid recipient address '{}': {}", recipient, e))?;
+        if amount == Amount::ZERO {
+            return Err(format!("the amount paid to {} must be positive", recipient));
+        }
+        if amounts
+            .insert(recipient.to_string(), json!(amount.to_btc()))
+            .is_some()
+        {
+            return Err(format!(
+                "{} is listed more than once; sendmany pays each address at most once, \
+                 so give every recipient output its own address",
+                recipient
+            ));
+        }
+    }
+    if !(1..=1008).contains(&conf_target) {
+        return Err(format!(
+            "invalid confirmation target {}: expected 1 to 1008 blocks",
+            conf_target
+        ));
+    }
+    // dummy, amounts, minconf, comment, subtractfeefrom, replaceable,
+    // conf_target, estimate_mode
+    Ok(vec![
+        json!(""),
+        json!(amounts),
+        json!(null),
+        json!(comment),
+        json!(null),
+        json!(null),
+        json!(conf_target),
+        json!("unset"),
+    ])
+}
+
+// Helper function to pay every one of `recipients` from wallet client `rpc` in
+// one transaction with `sendmany`, returning the txid
+pub fn send_many(
+    rpc: &impl RpcApi,
+    recipients: &[(&str, Amount)],
+    comment: Option<&str>,
+    conf_target: u32,
+) -> bitcoincore_rpc::Result<Txid> {
+    let args = send_many_args(recipients, comment, conf_target)
+        .map_err(bitcoincore_rpc::Error::ReturnedError)?;
+    rpc_call(rpc, "sendmany", &args)
+}
+
+// A transaction as verbose `getrawtransaction` returns it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RawTx {
+    pub txid: String,
+    pub vin: Vec<TxInput>,
+    pub vout: Vec<TxOutput>,
+    pub size: u64,
+    pub vsize: u64,
+    pub weight: u64,
+    pub locktime: u32,
+    // Both unset while the transaction is unconfirmed
+    pub blockhash: Option<String>,
+    pub confirmations: Option<u32>,
+}
+
+// An input: either the prevout it spends or, in a coinbase, the coinbase data
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TxInput {
+    pub txid: Option<String>,
+    pub vout: Option<u32>,
+    pub coinbase: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TxOutput {
+    pub n: u32,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub value: Amount,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: ScriptPubKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub struct ScriptPubKey {
+    #[serde(default)]
+    pub hex: String,
+    #[serde(rename = "type", default)]
+    pub script_type: String,
+    pub address: Option<String>,
+    // Nodes before v22 list the address here instead
+    #[serde(default)]
+    pub addresses: Vec<String>,
+}
+
+// A block as `getblock` returns it at the default verbosity, with txids only
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BlockDetails {
+    pub hash: String,
+    pub height: u64,
+    pub time: u64,
+    pub mediantime: u64,
+    pub nonce: u64,
+    pub difficulty: f64,
+    pub tx: Vec<String>,
+    pub size: u64,
+    pub weight: u64,
+    // Unset for the genesis block
+    pub previousblockhash: Option<String>,
+}
+
+// A transaction's entry in the mempool, as `getmempoolentry` returns it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MempoolEntry {
+    pub fees: MempoolFees,
+    pub vsize: u64,
+    pub weight: u64,
+    // When it entered the mempool, and the chain height then
+    pub time: u64,
+    pub height: u64,
+    // Unconfirmed parents
+    pub depends: Vec<String>,
+    // Unset on nodes that no longer report BIP 125 signalling
+    #[serde(rename = "bip125-replaceable")]
+    pub bip125_replaceable: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MempoolFees {
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub base: Amount,
+    // With any `prioritisetransaction` delta
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub modified: Amount,
+    // Of the entry with its unconfirmed ancestors, and with its descendants
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub ancestor: Amount,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub descendant: Amount,
+}
+
+impl TxInput {
+    pub fn is_coinbase(&self) -> bool {
+        self.coinbase.is_some()
+    }
+}
+
+impl TxOutput {
+    // The address the output pays, if its script has one
+    pub fn address(&self) -> Option<&str> {
+        let spk = &self.script_pub_key;
+        spk.address
+            .as_deref()
+            .or(spk.addresses.first().map(String::as_str))
+    }
+}
+
+impl RawTx {
+    // Whether the transaction is a coinbase: its only input creates coins
+    pub fn is_coinbase(&self) -> bool {
+        self.vin.first().is_some_and(TxInput::is_coinbase)
+    }
+
+    // Check that each of `recipients` has an output paying it exactly its
+    // amount, and return what they were paid together
+    pub fn check_payments(&self, recipients: &[(&str, Amount)]) -> Result<Amount, String> {
+        let mut total = Amount::ZERO;
+        for &(recipient, amount) in recipients {
+            let paid = self
+                .vout
+                .iter()
+                .filter(|output| output.address() == Some(recipient))
+                .fold(Amount::ZERO, |sum, output| sum + output.value);
+            if paid != amount {
+                return Err(format!(
+                    "{} pays {} {} BTC, expected {} BTC",
+                    self.txid,
+                    recipient,
+                    paid.to_btc(),
+                    amount.to_btc()
+                ));
+            }
+            total += paid;
+        }
+        Ok(total)
+    }
+}
+
+// Helper function to get a transaction with its inputs and outputs decoded
+pub fn get_transaction(rpc: &impl RpcApi, txid: &str) -> bitcoincore_rpc::Result<RawTx> {
+    let args = [json!(txid), json!(true)]; // true for verbose output
+    rpc_call(rpc, "getrawtransaction", &args)
+}
@@ -0,0 +1,355 @@
+// Time spent per RPC method, for `--profile`.
+//
+// Every client is built on `ProfiledTransport`, which wraps the HTTP transport
+// and, once profiling is enabled, records the method and duration of each call.
+// It also refuses wallet RPCs on the node's base client (see `wallet_routing`).
+// Each thread accumulates into its own table, registered on first use, so
+// recording only ever takes an uncontended lock; `snapshot` merges the tables
+// into one profile, slowest method first.
+use crate::wallet_routing;
+use bitcoincore_rpc::jsonrpc::{self, Request, Response, Transport};
+use bitcoincore_rpc::{Auth, Client};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Running totals for one method
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MethodStats {
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl MethodStats {
+    fn record(&mut self, duration: Duration) {
+        self.calls += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    fn merge(&mut self, other: &MethodStats) {
+        self.calls += other.calls;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+}
+
+type Table = HashMap<String, MethodStats>;
+type ThreadTable = Arc<Mutex<Table>>;
+
+fn thread_tables() -> &'static Mutex<Vec<ThreadTable>> {
+    static TABLES: OnceLock<Mutex<Vec<ThreadTable>>> = OnceLock::new();
+    TABLES.get_or_init(Mutex::default)
+}
+
+thread_local! {
+    static LOCAL: ThreadTable = {
+        let table = ThreadTable::default();
+        let mut tables = thread_tables().lock().unwrap_or_else(|e| e.into_inner());
+        tables.push(Arc::clone(&table));
+        table
+    };
+}
+
+// Record one call of `method` on this thread, if profiling is enabled.
+pub fn record(method: &str, duration: Duration) {
+    if !is_enabled() {
+        return;
+    }
+    LOCAL.with(|table| {
+        let mut table = table.lock().unwrap_or_else(|e| e.into_inner());
+        match table.get_mut(method) {
+            Some(stats) => stats.record(duration),
+            None => {
+                let mut stats = MethodStats::default();
+                stats.record(duration);
+                table.insert(method.to_string(), stats);
+            }
+        }
+    });
+}
+
+// One row of the profile, in whole microseconds so reports compare exactly
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct MethodProfile {
+    pub method: String,
+    pub calls: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+}
+
+// Merge per-thread tables into one profile, by cumulative time descending
+// (ties by method name).
+pub fn merge_tables<'a>(tables: impl IntoIterator<Item = &'a Table>) -> Vec<MethodProfile> {
+    let mut merged = Table::new();
+    for table in tables {
+        for (method, stats) in table {
+            merged.entry(method.clone()).or_default().merge(stats);
+        }
+    }
+    let mut profile: Vec<MethodProfile> = merged
+        .into_iter()
+        .map(|(method, stats)| MethodProfile {
+            method,
+            calls: stats.calls,
+            total_us: stats.total.as_micros() as u64,
+            max_us: stats.max.as_micros() as u64,
+        })
+        .collect();
+    profile.sort_by(|a, b| {
+        b.total_us
+            .cmp(&a.total_us)
+            .then_with(|| a.method.cmp(&b.method))
+    });
+    profile
+}
+
+// The profile of every call recorded so far, on any thread
+pub fn snapshot() -> Vec<MethodProfile> {
+    let tables = thread_tables().lock().unwrap_or_else(|e| e.into_inner());
+    let tables: Vec<Table> = tables
+        .iter()
+        .map(|table| table.lock().unwrap_or_else(|e| e.into_inner()).clone())
+        .collect();
+    merge_tables(&tables)
+}
+
+fn millis(us: u64) -> String {
+    format!("{:.1}", us as f64 / 1000.0)
+}
+
+// The profile as an aligned text table.
+pub fn render_table(profile: &[MethodProfile]) -> String {
+    let width = profile
+        .iter()
+        .map(|row| row.method.len())
+        .chain(["method".len()])
+        .max()
+        .unwrap_or_default();
+    let mut lines = vec![format!(
+        "{:<width$}  {:>6}  {:>10}  {:>9}",
+        "method", "calls", "total ms", "max ms"
+    )];
+    for row in profile {
+        lines.push(format!(
+            "{:<width$}  {:>6}  {:>10}  {:>9}",
+            row.method,
+            row.calls,
+            millis(row.total_us),
+            millis(row.max_us)
+        ));
+    }
+    lines.join("\n")
+}
+
+// A transport that times every request it forwards to `inner`
+pub struct ProfiledTransport<T> {
+    inner: T,
+    // Whether `inner` reaches a wallet's URL rather than the node's base URL
+    wallet_scoped: bool,
+}
+
+impl<T> ProfiledTransport<T> {
+    fn check_routing(&self, method: &str) -> Result<(), jsonrpc::Error> {
+        if self.wallet_scoped {
+            return Ok(());
+        }
+        wallet_routing::check_base_call(method).map_err(jsonrpc::Error::Rpc)
+    }
+}
+
+impl<T: Transport> Transport for ProfiledTransport<T> {
+    fn send_request(&self, request: Request) -> Result<Response, jsonrpc::Error> {
+        self.check_routing(request.method)?;
+        let started = Instant::now();
+        let method = request.method;
+        let response = self.inner.send_request(request);
+        record(method, started.elapsed());
+        response
+    }
+
+    // A batch is one round trip, so its time is shared evenly by its requests
+    fn send_batch(&self, requests: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+        for request in requests {
+            self.check_routing(request.method)?;
+        }
+        let started = Instant::now();
+        let responses = self.inner.send_batch(requests);
+        let share = started.elapsed() / requests.len().max(1) as u32;
+        for request in requests {
+            record(request.method, share);
+        }
+        responses
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_target(f)
+    }
+}
+
+// Helper function to connect to `url`, a wallet's URL when `wallet_scoped`,
+// through a profiled HTTP transport. A cookie file is read now, so a client
+// made after a node restart picks up the new cookie.
+pub fn profiled_client(
+    url: &str,
+    auth: Auth,
+    wallet_scoped: bool,
+) -> bitcoincore_rpc::Result<Client> {
+    let mut builder = jsonrpc::simple_http::SimpleHttpTransport::builder()
+        .url(url)
+        .map_err(|e| bitcoincore_rpc::Error::JsonRpc(e.into()))?;
+    if let (Some(user), pass) = auth.get_user_pass()? {
+        builder = builder.auth(user, pass);
+    }
+    let http = builder.build();
+    let transport = ProfiledTransport {
+        inner: http,
+        wallet_scoped,
+    };
+    Ok(Client::from_jsonrpc(jsonrpc::Client::with_transport(
+        transport,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn table(records: &[(&str, u64)]) -> Table {
+        let mut table = Table::new();
+        for &(method, ms) in records {
+            table
+                .entry(method.to_string())
+                .or_default()
+                .record(Duration::from_millis(ms));
+        }
+        table
+    }
+
+    #[test]
+    fn merges_threads_and_sorts_by_total_time() {
+        let first = table(&[("getblock", 5), ("sendtoaddress", 40), ("getblock", 7)]);
+        let second = table(&[("getblock", 30), ("getbalance", 1)]);
+        let profile = merge_tables([&first, &second]);
+        let rows: Vec<(&str, u64, u64, u64)> = profile
+            .iter()
+            .map(|row| (row.method.as_str(), row.calls, row.total_us, row.max_us))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("getblock", 3, 42_000, 30_000),
+                ("sendtoaddress", 1, 40_000, 40_000),
+                ("getbalance", 1, 1_000, 1_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn ties_are_ordered_by_method() {
+        let profile = merge_tables([&table(&[("b", 2), ("a", 2)])]);
+        let methods: Vec<&str> = profile.iter().map(|row| row.method.as_str()).collect();
+        assert_eq!(methods, vec!["a", "b"]);
+        assert!(merge_tables([]).is_empty());
+    }
+
+    #[test]
+    fn renders_an_aligned_table() {
+        let profile = merge_tables([&table(&[("getrawtransaction", 12), ("getblock", 3)])]);
+        assert_eq!(
+            render_table(&profile),
+            "method              calls    total ms     max ms\n\
+             getrawtransaction       1        12.0       12.0\n\
+             getblock                1         3.0        3.0"
+        );
+    }
+
+    // Stands in for the node: records each method that reaches it and fails
+    // the call
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Transport for Recorder {
+        fn send_request(&self, request: Request) -> Result<Response, jsonrpc::Error> {
+            self.0.lock().unwrap().push(request.method.to_string());
+            Err(jsonrpc::Error::Transport("no node".into()))
+        }
+
+        fn send_batch(&self, _: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+            Err(jsonrpc::Error::Transport("batches are not recorded".into()))
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("recorder")
+        }
+    }
+
+    #[test]
+    fn the_base_client_refuses_wallet_methods() {
+        use bitcoincore_rpc::RpcApi;
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let client = |wallet_scoped| {
+            Client::from_jsonrpc(jsonrpc::Client::with_transport(ProfiledTransport {
+                inner: Recorder(Arc::clone(&sent)),
+                wallet_scoped,
+            }))
+        };
+        let base = client(false);
+        match base.get_new_address(None, None) {
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e))) => {
+                assert_eq!(e.code, wallet_routing::RPC_WALLET_NOT_SPECIFIED);
+                assert!(e.message.contains("'getnewaddress'"), "{}", e.message);
+            }
+            other => panic!("expected a routing error, got {:?}", other),
+        }
+        assert!(base.get_balances().is_err());
+        assert!(sent.lock().unwrap().is_empty());
+
+        // Node methods on the base client, and wallet methods on a wallet
+        // client, reach the node
+        assert!(base.get_blockchain_info().is_err());
+        assert!(client(true).get_new_address(None, None).is_err());
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec!["getblockchaininfo", "getnewaddress"]
+        );
+    }
+
+    #[test]
+    fn records_from_every_thread_are_merged() {
+        enable();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..10 {
+                        record("test.synthetic", Duration::from_micros(100));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let row = snapshot()
+            .into_iter()
+            .find(|row| row.method == "test.synthetic")
+            .unwrap();
+        assert_eq!((row.calls, row.total_us, row.max_us), (40, 4_000, 100));
+    }
+}
@@ -0,0 +1,228 @@
+// Retrying RPC calls that fail only because the node is not ready yet.
+//
+// Right after bitcoind starts, connections are refused until it listens, and
+// once it does it answers every call with RPC_IN_WARMUP (-28, "Loading
+// block index...", "Loading wallet...") until startup is done. `retry_for`
+// repeats a call on such errors with exponential backoff until a timeout runs
+// out; any other error is returned at once. `wait_for_node` uses it to wait for
+// the node itself, and wallet loading also retries the internal errors (-32603)
+// a wallet can briefly answer with while the node is still settling.
+use bitcoincore_rpc::json::GetBlockchainInfoResult;
+use bitcoincore_rpc::jsonrpc::error::Error as JsonRpcError;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::warn;
+use std::time::{Duration, Instant};
+
+const RPC_IN_WARMUP: i32 = -28;
+const RPC_INTERNAL_ERROR: i32 = -32603;
+
+// How long to wait for the node unless --node-timeout says otherwise
+pub const DEFAULT_NODE_TIMEOUT: Duration = Duration::from_secs(60);
+// The pause before the first retry; each following one is twice as long, up
+// to `MAX_RETRY_DELAY`
+pub const FIRST_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+
+// Whether `e` means the node is not reachable or still starting up. Errors
+// from `rpc_call` arrive as described text, so the code is looked for there too.
+pub fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::JsonRpc(JsonRpcError::Rpc(rpc_error)) => rpc_error.code == RPC_IN_WARMUP,
+        Error::JsonRpc(JsonRpcError::Transport(_)) => true,
+        Error::Io(_) => true,
+        Error::ReturnedError(message) => message.contains("failed with code -28"),
+        _ => false,
+    }
+}
+
+// Whether a wallet call failed transiently: as `is_transient`, or with the
+// internal error a wallet being loaded can answer with
+pub fn is_transient_wallet_error(e: &Error) -> bool {
+    is_transient(e)
+        || match e {
+            Error::JsonRpc(JsonRpcError::Rpc(rpc_error)) => rpc_error.code == RPC_INTERNAL_ERROR,
+            Error::ReturnedError(message) => message.contains("failed with code -32603"),
+            _ => false,
+        }
+}
+
+// Run `f` while it fails with an error `retryable` accepts and `timeout` has
+// not run out, sleeping `delay` before the first retry and twice as long
+// (at most `MAX_RETRY_DELAY`) before each following one. Returns the first
+// success or the last error.
+pub fn retry_for<T, F, P>(
+    timeout: Duration,
+    delay: Duration,
+    retryable: P,
+    mut f: F,
+) -> bitcoincore_rpc::Result<T>
+where
+    F: FnMut() -> bitcoincore_rpc::Result<T>,
+    P: Fn(&Error) -> bool,
+{
+    let deadline = Instant::now() + timeout;
+    let mut delay = delay;
+    loop {
+        match f() {
+            Err(e) if retryable(&e) && Instant::now() < deadline => {
+                let pause = delay.min(deadline.saturating_duration_since(Instant::now()));
+                warn!("Node not ready ({}), retrying in {:?}", e, pause);
+                std::thread::sleep(pause);
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+// Helper function to wait up to `timeout` for the node to answer
+// `getblockchaininfo`, returning its answer
+pub fn wait_for_node(
+    rpc: &impl RpcApi,
+    timeout: Duration,
+) -> bitcoincore_rpc::Result<GetBlockchainInfoResult> {
+    retry_for(timeout, FIRST_RETRY_DELAY, is_transient, || {
+        rpc.get_blockchain_info()
+    })
+    .map_err(|e| {
+        if !is_transient(&e) {
+            return e;
+        }
+        Error::ReturnedError(format!(
+            "the node was not ready after {:?} ({}); check that bitcoind is running at the \
+             RPC URL, or wait longer with --node-timeout <seconds>",
+            timeout, e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::jsonrpc::error::RpcError;
+    use bitcoincore_rpc::Client;
+    use std::io;
+
+    fn warmup() -> Error {
+        Error::JsonRpc(JsonRpcError::Rpc(RpcError {
+            code: RPC_IN_WARMUP,
+            message: "Loading block index...".to_string(),
+            data: None,
+        }))
+    }
+
+    fn refused() -> Error {
+        Error::JsonRpc(JsonRpcError::Transport(Box::new(io::Error::from(
+            io::ErrorKind::ConnectionRefused,
+        ))))
+    }
+
+    #[test]
+    fn succeeds_after_transient_failures() {
+        let mut calls = 0;
+        let result = retry_for(
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+            is_transient,
+            || {
+                calls += 1;
+                match calls {
+                    1 => Err(refused()),
+                    2 | 3 => Err(warmup()),
+                    _ => Ok(calls),
+                }
+            },
+        );
+        assert_eq!(result.unwrap(), 4);
+        assert_eq!(calls, 4);
+    }
+
+    #[test]
+    fn gives_up_with_the_last_error_when_the_timeout_runs_out() {
+        let started = Instant::now();
+        let mut calls = 0;
+        let result: bitcoincore_rpc::Result<()> = retry_for(
+            Duration::from_millis(50),
+            Duration::from_millis(1),
+            is_transient,
+            || {
+                calls += 1;
+                Err(if calls % 2 == 1 { refused() } else { warmup() })
+            },
+        );
+        assert!(calls > 2, "{}", calls);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(matches!(
+            result,
+            Err(Error::JsonRpc(JsonRpcError::Rpc(RpcError {
+                code: -28,
+                ..
+            }))) | Err(Error::JsonRpc(JsonRpcError::Transport(_)))
+        ));
+    }
+
+    #[test]
+    fn backoff_doubles_the_delay_until_the_deadline() {
+        let start = Instant::now();
+        let mut calls = 0;
+        let _ = retry_for(
+            Duration::from_millis(65),
+            Duration::from_millis(10),
+            is_transient,
+            || {
+                calls += 1;
+                Err::<(), _>(warmup())
+            },
+        );
+        // 10 and 20 ms of sleeping, then the 35 ms left instead of 40
+        assert_eq!(calls, 4);
+        assert!(start.elapsed() >= Duration::from_millis(65));
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let mut calls = 0;
+        let result: bitcoincore_rpc::Result<()> = retry_for(
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+            is_transient,
+            || {
+                calls += 1;
+                Err(Error::ReturnedError("wallet not found".to_string()))
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        assert!(is_transient(&Error::ReturnedError(
+            "RPC 'loadwallet' failed with code -28 (RPC_IN_WARMUP): Loading wallet...".to_string()
+        )));
+    }
+
+    #[test]
+    fn wallet_loading_also_retries_internal_errors() {
+        let internal = Error::JsonRpc(JsonRpcError::Rpc(RpcError {
+            code: RPC_INTERNAL_ERROR,
+            message: "Wallet file verification failed".to_string(),
+            data: None,
+        }));
+        assert!(!is_transient(&internal));
+        assert!(is_transient_wallet_error(&internal));
+        assert!(is_transient_wallet_error(&Error::ReturnedError(
+            "RPC 'loadwallet' failed with code -32603: Wallet file verification failed".to_string()
+        )));
+        assert!(is_transient_wallet_error(&warmup()));
+        assert!(!is_transient_wallet_error(&Error::ReturnedError(
+            "RPC 'loadwallet' failed with code -18: Wallet not found".to_string()
+        )));
+    }
+
+    #[test]
+    fn an_unreachable_node_times_out_with_a_clear_message() {
+        // Nothing listens on port 1
+        let rpc = Client::new("http://127.0.0.1:1", bitcoincore_rpc::Auth::None).unwrap();
+        let err = wait_for_node(&rpc, Duration::from_millis(200)).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("not ready after 200ms"), "{}", message);
+        assert!(message.contains("--node-timeout"), "{}", message);
+    }
+}
@@ -0,0 +1,303 @@
+// Offline check of the signature on a P2WPKH input.
+//
+// A P2WPKH input's witness is `<signature> <pubkey>`. The signature commits to
+// the BIP-143 sighash, which covers the amount of the spent output, so the
+// caller supplies that amount along with the pubkey it expects. DER ECDSA
+// signatures carry no recovery id, so instead of recovering a key from the
+// signature the witness pubkey is compared with the expected one and the
+// signature is verified against it with libsecp256k1.
+// `verify_wallet_signatures` runs the check on every input of a wallet
+// transaction (`--verify-signatures`), expecting the key the wallet holds for
+// each spent output's address.
+use crate::rpc_call;
+use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+use bitcoincore_rpc::bitcoin::ecdsa::Signature;
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoincore_rpc::bitcoin::sighash::SighashCache;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, PublicKey, ScriptBuf, Transaction};
+use bitcoincore_rpc::{Error, RpcApi};
+use serde_json::json;
+use std::str::FromStr;
+
+// Whether input `input_index` of the signed transaction `tx_hex` carries a
+// valid P2WPKH signature by `pubkey_hex` for an output of `amount_sat`. Errors
+// mean the input cannot be checked at all (no such input, not a P2WPKH
+// witness, malformed data); a signature that does not verify is `Ok(false)`.
+pub fn verify_p2wpkh_signature(
+    tx_hex: &str,
+    input_index: usize,
+    pubkey_hex: &str,
+    amount_sat: u64,
+) -> Result<bool, String> {
+    let bytes =
+        Vec::<u8>::from_hex(tx_hex).map_err(|e| format!("invalid transaction hex: {}", e))?;
+    let tx: Transaction = deserialize(&bytes).map_err(|e| format!("invalid transaction: {}", e))?;
+    let expected = PublicKey::from_str(pubkey_hex).map_err(|e| format!("invalid pubkey: {}", e))?;
+    let input = tx.input.get(input_index).ok_or_else(|| {
+        format!(
+            "transaction has {} inputs, no input {}",
+            tx.input.len(),
+            input_index
+        )
+    })?;
+    let (Some(sig_bytes), Some(key_bytes), 2) = (
+        input.witness.nth(0),
+        input.witness.nth(1),
+        input.witness.len(),
+    ) else {
+        return Err(format!(
+            "input {} has {} witness items, a P2WPKH spend has 2",
+            input_index,
+            input.witness.len()
+        ));
+    };
+    let witness_key = PublicKey::from_slice(key_bytes)
+        .map_err(|e| format!("input {} witness pubkey: {}", input_index, e))?;
+    if witness_key != expected {
+        return Ok(false);
+    }
+    let signature = Signature::from_slice(sig_bytes)
+        .map_err(|e| format!("input {} signature: {}", input_index, e))?;
+
+    // The spent output's script; the BIP-143 script code is derived from it
+    let wpubkey_hash = expected
+        .wpubkey_hash()
+        .ok_or_else(|| "P2WPKH only allows compressed pubkeys".to_string())?;
+    let script_pubkey = ScriptBuf::new_p2wpkh(&wpubkey_hash);
+    let sighash = SighashCache::new(&tx)
+        .p2wpkh_signature_hash(
+            input_index,
+            &script_pubkey,
+            Amount::from_sat(amount_sat),
+            signature.hash_ty,
+        )
+        .map_err(|e| format!("cannot compute the sighash of input {}: {}", input_index, e))?;
+    let message = Message::from_digest_slice(&sighash[..]).map_err(|e| e.to_string())?;
+    Ok(Secp256k1::verification_only()
+        .verify_ecdsa(&message, &signature.sig, &expected.inner)
+        .is_ok())
+}
+
+// Helper function to fetch the hex of wallet transaction `txid`
+fn wallet_tx_hex(wallet: &impl RpcApi, txid: &str) -> bitcoincore_rpc::Result<String> {
+    let tx = rpc_call::<serde_json::Value>(wallet, "gettransaction", &[json!(txid)])?;
+    tx["hex"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| Error::ReturnedError(format!("transaction {} has no hex", txid)))
+}
+
+// Helper function to verify the signature of every input of `wallet`'s
+// transaction `txid`, each against the pubkey `getaddressinfo` gives for the
+// address of the spent output. Returns the number of inputs checked.
+pub fn verify_wallet_signatures(
+    wallet: &impl RpcApi,
+    txid: &str,
+    network: Network,
+) -> bitcoincore_rpc::Result<usize> {
+    let hex = wallet_tx_hex(wallet, txid)?;
+    let tx: Transaction = deserialize(&Vec::<u8>::from_hex(&hex)?)
+        .map_err(|e| Error::ReturnedError(format!("invalid transaction {}: {}", txid, e)))?;
+    for (index, input) in tx.input.iter().enumerate() {
+        let prevout = input.previous_output;
+        let prev: Transaction = deserialize(&Vec::<u8>::from_hex(&wallet_tx_hex(
+            wallet,
+            &prevout.txid.to_string(),
+        )?)?)
+        .map_err(|e| {
+            Error::ReturnedError(format!("invalid transaction {}: {}", prevout.txid, e))
+        })?;
+        let spent = prev.output.get(prevout.vout as usize).ok_or_else(|| {
+            Error::ReturnedError(format!(
+                "transaction {} has no output {}",
+                prevout.txid, prevout.vout
+            ))
+        })?;
+        let address = Address::from_script(&spent.script_pubkey, network).map_err(|e| {
+            Error::ReturnedError(format!("input {} spends no address: {}", index, e))
+        })?;
+        let info = rpc_call::<serde_json::Value>(wallet, "getaddressinfo", &[json!(address)])?;
+        let pubkey = info["pubkey"].as_str().ok_or_else(|| {
+            Error::ReturnedError(format!("the wallet knows no pubkey for {}", address))
+        })?;
+        let valid = verify_p2wpkh_signature(&hex, index, pubkey, spent.value.to_sat())
+            .map_err(Error::ReturnedError)?;
+        if !valid {
+            return Err(Error::ReturnedError(format!(
+                "input {} of {} is not signed by {}",
+                index, txid, pubkey
+            )));
+        }
+    }
+    Ok(tx.input.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::absolute::LockTime;
+    use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+    use bitcoincore_rpc::bitcoin::secp256k1::SecretKey;
+    use bitcoincore_rpc::bitcoin::sighash::EcdsaSighashType;
+    use bitcoincore_rpc::bitcoin::transaction::Version;
+    use bitcoincore_rpc::bitcoin::{OutPoint, Sequence, TxIn, TxOut, Witness};
+
+    const AMOUNT: u64 = 5_000_000_000;
+
+    fn key(byte: u8) -> (SecretKey, PublicKey) {
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        let public = PublicKey::new(secret.public_key(&Secp256k1::new()));
+        (secret, public)
+    }
+
+    // A one-input transaction spending a P2WPKH output of `amount` held by
+    // `secret`, signed the way a wallet would sign it
+    fn signed_tx(secret: &SecretKey, amount: u64) -> Transaction {
+        let secp = Secp256k1::new();
+        let public = PublicKey::new(secret.public_key(&secp));
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::from_str(
+                    "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098:0",
+                )
+                .unwrap(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(amount - 1_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(&public.wpubkey_hash().unwrap()),
+            }],
+        };
+        let sighash = SighashCache::new(&tx)
+            .p2wpkh_signature_hash(
+                0,
+                &ScriptBuf::new_p2wpkh(&public.wpubkey_hash().unwrap()),
+                Amount::from_sat(amount),
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let message = Message::from_digest_slice(&sighash[..]).unwrap();
+        let signature = Signature::sighash_all(secp.sign_ecdsa(&message, secret));
+        tx.input[0].witness = Witness::p2wpkh(&signature, &public.inner);
+        tx
+    }
+
+    #[test]
+    fn valid_signature_verifies() {
+        let (secret, public) = key(1);
+        let hex = serialize_hex(&signed_tx(&secret, AMOUNT));
+        assert_eq!(
+            verify_p2wpkh_signature(&hex, 0, &public.to_string(), AMOUNT),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn wrong_amount_or_pubkey_does_not_verify() {
+        let (secret, public) = key(1);
+        let (_, other) = key(2);
+        let hex = serialize_hex(&signed_tx(&secret, AMOUNT));
+        assert_eq!(
+            verify_p2wpkh_signature(&hex, 0, &public.to_string(), AMOUNT - 1),
+            Ok(false)
+        );
+        assert_eq!(
+            verify_p2wpkh_signature(&hex, 0, &other.to_string(), AMOUNT),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn tampered_transaction_does_not_verify() {
+        let (secret, public) = key(1);
+        let mut tx = signed_tx(&secret, AMOUNT);
+        tx.output[0].value = Amount::from_sat(AMOUNT - 500);
+        let hex = serialize_hex(&tx);
+        assert_eq!(
+            verify_p2wpkh_signature(&hex, 0, &public.to_string(), AMOUNT),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn uncheckable_inputs_are_errors() {
+        let (secret, public) = key(1);
+        let mut tx = signed_tx(&secret, AMOUNT);
+        let hex = serialize_hex(&tx);
+        let err = verify_p2wpkh_signature(&hex, 1, &public.to_string(), AMOUNT).unwrap_err();
+        assert!(err.contains("no input 1"), "{}", err);
+
+        tx.input[0].witness = Witness::new();
+        let err = verify_p2wpkh_signature(&serialize_hex(&tx), 0, &public.to_string(), AMOUNT)
+            .unwrap_err();
+        assert!(err.contains("0 witness items"), "{}", err);
+        assert!(verify_p2wpkh_signature("zz", 0, &public.to_string(), AMOUNT).is_err());
+    }
+
+    // The Miner's payment to the Trader from the capstone flow: every input
+    // spends one of the Miner's P2WPKH outputs.
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn capstone_payment_signatures_verify() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+        use bitcoincore_rpc::bitcoin::hex::DisplayHex;
+        use bitcoincore_rpc::RpcApi;
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        create_or_load_wallet(&rpc, &config, "Trader", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let trader = get_wallet_client(&config, "Trader").unwrap();
+        let address = miner
+            .get_new_address(None, Some(bitcoincore_rpc::json::AddressType::Bech32))
+            .unwrap()
+            .assume_checked();
+        mine_blocks_to_address(&rpc, &address, 101).unwrap();
+        let recipient = trader.get_new_address(None, None).unwrap().assume_checked();
+        let txid = miner
+            .send_to_address(
+                &recipient,
+                Amount::from_int_btc(20),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        let tx = miner.get_raw_transaction(&txid, None).unwrap();
+        let hex = serialize_hex(&tx);
+        for (index, input) in tx.input.iter().enumerate() {
+            // Without -txindex only the wallet can still return the spent
+            // (confirmed) transaction
+            let prev = miner
+                .get_transaction(&input.previous_output.txid, None)
+                .unwrap()
+                .transaction()
+                .unwrap();
+            let amount = prev.output[input.previous_output.vout as usize].value;
+            let pubkey = input.witness.nth(1).unwrap().to_lower_hex_string();
+            assert_eq!(
+                verify_p2wpkh_signature(&hex, index, &pubkey, amount.to_sat()),
+                Ok(true)
+            );
+            assert_eq!(
+                verify_p2wpkh_signature(&hex, index, &pubkey, amount.to_sat() + 1),
+                Ok(false)
+            );
+        }
+        assert_eq!(
+            verify_wallet_signatures(&miner, &txid.to_string(), Network::Regtest).unwrap(),
+            tx.input.len()
+        );
+    }
+}
@@ -0,0 +1,142 @@
+// Pre-validate transactions with `testmempoolaccept` before broadcasting them.
+use bitcoincore_rpc::RpcApi;
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt;
+
+// Outcome of a `testmempoolaccept` batch: `(txid, accepted, reject_reason)` per
+// transaction, ordered by fee rate with the highest first and rejected ones last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptanceSimulation {
+    pub txs: Vec<(String, bool, Option<String>)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MempoolAcceptResult {
+    txid: String,
+    allowed: Option<bool>,
+    vsize: Option<u64>,
+    fees: Option<MempoolAcceptFees>,
+    #[serde(rename = "reject-reason")]
+    reject_reason: Option<String>,
+    // Set instead of `allowed` when another transaction in the package failed.
+    #[serde(rename = "package-error")]
+    package_error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MempoolAcceptFees {
+    base: f64,
+}
+
+impl MempoolAcceptResult {
+    // Fee rate in sat/vB, only known for accepted transactions.
+    fn fee_rate(&self) -> Option<f64> {
+        let vsize = self.vsize.filter(|v| *v > 0)?;
+        Some(self.fees.as_ref()?.base * 100_000_000.0 / vsize as f64)
+    }
+}
+
+impl AcceptanceSimulation {
+    fn from_results(mut results: Vec<MempoolAcceptResult>) -> Self {
+        results.sort_by(|a, b| {
+            let (a, b) = (a.fee_rate(), b.fee_rate());
+            b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let txs = results
+            .into_iter()
+            .map(|r| {
+                let accepted = r.allowed.unwrap_or(false);
+                let reason = if accepted {
+                    None
+                } else {
+                    r.reject_reason.or(r.package_error)
+                };
+                (r.txid, accepted, reason)
+            })
+            .collect();
+        AcceptanceSimulation { txs }
+    }
+
+    pub fn all_accepted(&self) -> bool {
+        self.txs.iter().all(|(_, accepted, _)| *accepted)
+    }
+}
+
+impl fmt::Display for AcceptanceSimulation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (txid, accepted, reason) in &self.txs {
+            match (accepted, reason) {
+                (true, _) => writeln!(f, "{} accepted", txid)?,
+                (false, Some(reason)) => writeln!(f, "{} rejected: {}", txid, reason)?,
+                (false, None) => writeln!(f, "{} rejected", txid)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Helper function to run `testmempoolaccept` over a batch of raw transactions at once
+pub fn simulate_batch_acceptance(
+    rpc: &impl RpcApi,
+    tx_hexes: &[&str],
+) -> bitcoincore_rpc::Result<AcceptanceSimulation> {
+    let results = rpc.call::<Vec<MempoolAcceptResult>>("testmempoolaccept", &[json!(tx_hexes)])?;
+    Ok(AcceptanceSimulation::from_results(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulate(results: serde_json::Value) -> AcceptanceSimulation {
+        AcceptanceSimulation::from_results(serde_json::from_value(results).unwrap())
+    }
+
+    #[test]
+    fn mixed_batch_sorted_by_fee_rate() {
+        let simulation = simulate(json!([
+            { "txid": "low", "wtxid": "low", "allowed": true, "vsize": 141,
+              "fees": { "base": 0.00000141 } },
+            { "txid": "bad", "wtxid": "bad", "allowed": false,
+              "reject-reason": "bad-txns-inputs-missingorspent" },
+            { "txid": "high", "wtxid": "high", "allowed": true, "vsize": 110,
+              "fees": { "base": 0.00001100 } },
+        ]));
+        assert_eq!(
+            simulation.txs,
+            vec![
+                ("high".to_string(), true, None),
+                ("low".to_string(), true, None),
+                (
+                    "bad".to_string(),
+                    false,
+                    Some("bad-txns-inputs-missingorspent".to_string())
+                ),
+            ]
+        );
+        assert!(!simulation.all_accepted());
+    }
+
+    #[test]
+    fn package_error_reported_as_reason() {
+        let simulation = simulate(json!([
+            { "txid": "a", "wtxid": "a", "package-error": "package-not-sorted" },
+            { "txid": "b", "wtxid": "b", "package-error": "package-not-sorted" },
+        ]));
+        assert!(simulation
+            .txs
+            .iter()
+            .all(|(_, accepted, reason)| !accepted
+                && reason.as_deref() == Some("package-not-sorted")));
+    }
+
+    #[test]
+    fn all_accepted_batch() {
+        let simulation = simulate(json!([
+            { "txid": "a", "wtxid": "a", "allowed": true, "vsize": 100, "fees": { "base": 0.000001 } },
+        ]));
+        assert!(simulation.all_accepted());
+        assert_eq!(simulation.to_string(), "a accepted\n");
+    }
+}
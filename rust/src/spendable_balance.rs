@@ -0,0 +1,92 @@
+// What a wallet can spend, by how many confirmations its coins must have.
+//
+// `getbalance` also counts coins that are not confirmed yet. The spendable
+// balance at `min_conf` is the sum of the outputs `listunspent` returns with
+// at least that many confirmations; immature block rewards are never among
+// them. `spendable_by_conf` shows how the balance shrinks as the requirement
+// grows, which is what the capstone prints after confirming its payment.
+use crate::coin_selection::Utxo;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::RpcApi;
+use serde_json::json;
+use std::collections::HashMap;
+
+// Confirmation requirements `spendable_by_conf` reports on
+pub const CONF_LEVELS: [u32; 4] = [0, 1, 2, 6];
+
+pub fn total(utxos: &[Utxo]) -> Amount {
+    utxos.iter().map(|utxo| utxo.amount).sum()
+}
+
+// Helper function to sum the wallet's UTXOs with at least `min_conf`
+// confirmations
+pub fn spendable_balance(rpc: &impl RpcApi, min_conf: u32) -> bitcoincore_rpc::Result<Amount> {
+    let utxos: Vec<Utxo> = rpc.call("listunspent", &[json!(min_conf)])?;
+    Ok(total(&utxos))
+}
+
+// The balance at every level of `CONF_LEVELS`, from `balance(min_conf)`
+fn by_conf<F>(mut balance: F) -> bitcoincore_rpc::Result<HashMap<u32, Amount>>
+where
+    F: FnMut(u32) -> bitcoincore_rpc::Result<Amount>,
+{
+    CONF_LEVELS
+        .iter()
+        .map(|&min_conf| Ok((min_conf, balance(min_conf)?)))
+        .collect()
+}
+
+// Helper function to compute the wallet's spendable balance at each of
+// `CONF_LEVELS`
+pub fn spendable_by_conf(rpc: &impl RpcApi) -> bitcoincore_rpc::Result<HashMap<u32, Amount>> {
+    by_conf(|min_conf| spendable_balance(rpc, min_conf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(vout: u32, sats: u64) -> Utxo {
+        Utxo {
+            txid: "aa".repeat(32),
+            vout,
+            amount: Amount::from_sat(sats),
+        }
+    }
+
+    #[test]
+    fn total_sums_every_utxo() {
+        assert_eq!(total(&[]), Amount::ZERO);
+        assert_eq!(
+            total(&[utxo(0, 5_000_000_000), utxo(1, 2_999_999_859)]),
+            Amount::from_sat(7_999_999_859)
+        );
+    }
+
+    #[test]
+    fn every_conf_level_is_reported() {
+        // A reward with 101 confirmations and change with one
+        let utxos = [(101, 5_000_000_000), (1, 2_999_999_859)];
+        let mut asked = Vec::new();
+        let balances = by_conf(|min_conf| {
+            asked.push(min_conf);
+            Ok(utxos
+                .iter()
+                .filter(|(confirmations, _)| *confirmations >= min_conf)
+                .map(|&(_, sats)| Amount::from_sat(sats))
+                .sum())
+        })
+        .unwrap();
+        assert_eq!(asked, CONF_LEVELS);
+        assert_eq!(balances[&0], Amount::from_sat(7_999_999_859));
+        assert_eq!(balances[&1], Amount::from_sat(7_999_999_859));
+        assert_eq!(balances[&2], Amount::from_int_btc(50));
+        assert_eq!(balances[&6], Amount::from_int_btc(50));
+    }
+
+    #[test]
+    fn rpc_errors_are_passed_through() {
+        let result = by_conf(|_| Err(bitcoincore_rpc::Error::ReturnedError("down".into())));
+        assert!(result.is_err());
+    }
+}
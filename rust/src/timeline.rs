@@ -0,0 +1,474 @@
+// Wallet activity timeline, from its creation to now.
+//
+// Built from the wallet's `listtransactions` history plus block timestamps:
+// wallet creation, the first mining reward, each reward reaching maturity,
+// sends and receives, their confirmations, and fee bumps/replacements. Nothing
+// reports when a coinbase matures, so maturity events are synthesized at the
+// coinbase height plus the maturity and timed with that block's timestamp.
+// Each event shows the time and block deltas from the event before it.
+use crate::report::format_btc;
+use crate::tx_category::TxCategory;
+use crate::tx_history::{get_all_transactions, WalletTx};
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::RpcApi;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventKind {
+    WalletCreated,
+    MiningReward,
+    RewardMatured,
+    Sent,
+    Received,
+    Bumped,
+    Replaced,
+    Confirmed,
+}
+
+impl EventKind {
+    fn label(self) -> &'static str {
+        match self {
+            EventKind::WalletCreated => "wallet created",
+            EventKind::MiningReward => "first mining reward",
+            EventKind::RewardMatured => "reward matured",
+            EventKind::Sent => "sent",
+            EventKind::Received => "received",
+            EventKind::Bumped => "fee bumped",
+            EventKind::Replaced => "replaced",
+            EventKind::Confirmed => "confirmed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEvent {
+    pub kind: EventKind,
+    pub height: Option<u64>,
+    pub time: u64,
+    pub detail: String,
+}
+
+// Absolute value of the entry's amount; sends are negative
+fn btc(tx: &WalletTx) -> String {
+    format_btc(Amount::from_sat(tx.amount.to_sat().unsigned_abs()))
+}
+
+// Mined rewards as (coinbase height, tx), lowest first
+fn coinbases(history: &[WalletTx]) -> Vec<(u64, &WalletTx)> {
+    let mut rewards: Vec<_> = history
+        .iter()
+        .filter(|tx| tx.tx_category().is_coinbase())
+        .filter_map(|tx| tx.blockheight.map(|height| (height, tx)))
+        .collect();
+    rewards.sort_by_key(|&(height, _)| height);
+    rewards
+}
+
+// Heights at which rewards in `history` matured, up to the chain tip. These
+// are the block timestamps `build_timeline` needs.
+pub fn maturity_heights(history: &[WalletTx], maturity: u64, tip_height: u64) -> Vec<u64> {
+    coinbases(history)
+        .into_iter()
+        .map(|(height, _)| height + maturity)
+        .filter(|&height| height <= tip_height)
+        .collect()
+}
+
+// Merge the wallet history, its birth time and the timestamps of the
+// maturity blocks into a chronological timeline.
+pub fn build_timeline(
+    birth_time: Option<u64>,
+    history: &[WalletTx],
+    maturity: u64,
+    tip_height: u64,
+    block_times: &BTreeMap<u64, u64>,
+) -> Vec<TimelineEvent> {
+    let mut events = Vec::new();
+    if let Some(time) = birth_time {
+        events.push(TimelineEvent {
+            kind: EventKind::WalletCreated,
+            height: None,
+            time,
+            detail: String::new(),
+        });
+    }
+
+    let rewards = coinbases(history);
+    if let Some(&(height, tx)) = rewards.first() {
+        events.push(TimelineEvent {
+            kind: EventKind::MiningReward,
+            height: Some(height),
+            time: tx.blocktime.unwrap_or(tx.time),
+            detail: format!("{} BTC in {}", btc(tx), tx.txid),
+        });
+    }
+    for (height, tx) in rewards {
+        let matured_at = height + maturity;
+        if let (true, Some(&time)) = (matured_at <= tip_height, block_times.get(&matured_at)) {
+            events.push(TimelineEvent {
+                kind: EventKind::RewardMatured,
+                height: Some(matured_at),
+                time,
+                detail: format!("{} BTC mined at height {} is spendable", btc(tx), height),
+            });
+        }
+    }
+
+    let seen_at: BTreeMap<&str, u64> = history
+        .iter()
+        .map(|tx| (tx.txid.as_str(), tx.time))
+        .collect();
+    for tx in history {
+        let kind = match tx.tx_category() {
+            TxCategory::Send => EventKind::Sent,
+            TxCategory::Receive => EventKind::Received,
+            _ => continue,
+        };
+        let amount = btc(tx);
+        let to = tx
+            .address
+            .as_deref()
+            .map(|address| {
+                format!(
+                    " {} {}",
+                    if kind == EventKind::Sent { "to" } else { "at" },
+                    address
+                )
+            })
+            .unwrap_or_default();
+        events.push(TimelineEvent {
+            kind,
+            height: None,
+            time: tx.time,
+            detail: format!("{} BTC{} in {}", amount, to, tx.txid),
+        });
+        if let Some(original) = &tx.replaces_txid {
+            events.push(TimelineEvent {
+                kind: EventKind::Bumped,
+                height: None,
+                time: tx.time,
+                detail: format!("{} replaces {}", tx.txid, original),
+            });
+        }
+        if let Some(replacement) = &tx.replaced_by_txid {
+            // The replacement happened when the wallet saw the new transaction
+            let time = seen_at
+                .get(replacement.as_str())
+                .copied()
+                .unwrap_or(tx.time);
+            events.push(TimelineEvent {
+                kind: EventKind::Replaced,
+                height: None,
+                time,
+                detail: format!("{} replaced by {}", tx.txid, replacement),
+            });
+        }
+        if let (Some(height), Some(time)) = (tx.blockheight, tx.blocktime) {
+            events.push(TimelineEvent {
+                kind: EventKind::Confirmed,
+                height: Some(height),
+                time,
+                detail: tx.txid.clone(),
+            });
+        }
+    }
+
+    events.sort_by_key(|event| (event.time, event.height, event.kind));
+    events
+}
+
+// Format a unix timestamp as `YYYY-MM-DD HH:MM:SS` UTC.
+pub fn format_utc(secs: u64) -> String {
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+// (time delta, block delta) of every event from the one before it. The block
+// delta is measured from the last event that had a height.
+fn deltas(events: &[TimelineEvent]) -> Vec<(Option<u64>, Option<u64>)> {
+    let mut last_time = None;
+    let mut last_height = None;
+    events
+        .iter()
+        .map(|event| {
+            let dt = last_time.map(|t: u64| event.time.saturating_sub(t));
+            let dh = match (last_height, event.height) {
+                (Some(prev), Some(height)) => Some(height.saturating_sub(prev)),
+                _ => None,
+            };
+            last_time = Some(event.time);
+            if event.height.is_some() {
+                last_height = event.height;
+            }
+            (dt, dh)
+        })
+        .collect()
+}
+
+fn or_dash(value: Option<u64>, prefix: &str, suffix: &str) -> String {
+    value
+        .map(|v| format!("{}{}{}", prefix, v, suffix))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn describe(event: &TimelineEvent) -> String {
+    if event.detail.is_empty() {
+        event.kind.label().to_string()
+    } else {
+        format!("{}: {}", event.kind.label(), event.detail)
+    }
+}
+
+// One line per event.
+pub fn render_text(events: &[TimelineEvent]) -> String {
+    events
+        .iter()
+        .zip(deltas(events))
+        .map(|(event, (dt, dh))| {
+            format!(
+                "{}  height {:>6}  {:>9} {:>12}  {}\n",
+                format_utc(event.time),
+                or_dash(event.height, "", ""),
+                or_dash(dt, "+", "s"),
+                or_dash(dh, "+", " blocks"),
+                describe(event)
+            )
+        })
+        .collect()
+}
+
+// A Markdown table for the report appendix.
+pub fn render_markdown(events: &[TimelineEvent]) -> String {
+    let mut out = String::from(
+        "| Time (UTC) | Height | Δ time | Δ blocks | Event |\n|---|---|---|---|---|\n",
+    );
+    for (event, (dt, dh)) in events.iter().zip(deltas(events)) {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            format_utc(event.time),
+            or_dash(event.height, "", ""),
+            or_dash(dt, "+", "s"),
+            or_dash(dh, "+", ""),
+            describe(event).replace('|', "\\|")
+        ));
+    }
+    out
+}
+
+// Helper function to build the timeline of the wallet behind `wallet`, with
+// block timestamps looked up on `rpc`
+pub fn fetch_timeline(
+    rpc: &impl RpcApi,
+    wallet: &impl RpcApi,
+    maturity: u64,
+) -> bitcoincore_rpc::Result<Vec<TimelineEvent>> {
+    let info = wallet.call::<serde_json::Value>("getwalletinfo", &[])?;
+    let history = get_all_transactions(wallet, 1000)?;
+    let tip_height = rpc.get_block_count()?;
+    let mut block_times = BTreeMap::new();
+    for height in maturity_heights(&history, maturity, tip_height) {
+        let hash = rpc.call::<String>("getblockhash", &[json!(height)])?;
+        let header = rpc.call::<serde_json::Value>("getblockheader", &[json!(hash)])?;
+        if let Some(time) = header["time"].as_u64() {
+            block_times.insert(height, time);
+        }
+    }
+    Ok(build_timeline(
+        info["birthtime"].as_u64(),
+        &history,
+        maturity,
+        tip_height,
+        &block_times,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::SignedAmount;
+
+    const T0: u64 = 1_700_000_000;
+
+    fn tx(txid: &str, category: &str, sats: i64, time: u64, block: Option<(u64, u64)>) -> WalletTx {
+        WalletTx {
+            txid: txid.to_string(),
+            category: category.to_string(),
+            amount: SignedAmount::from_sat(sats),
+            confirmations: if block.is_some() { 1 } else { 0 },
+            address: None,
+            blockhash: block.map(|(height, _)| format!("hash{}", height)),
+            blockheight: block.map(|(height, _)| height),
+            blocktime: block.map(|(_, time)| time),
+            time,
+            replaced_by_txid: None,
+            replaces_txid: None,
+            generated: matches!(category, "generate" | "immature" | "orphan"),
+            trusted: block.map_or(Some(true), |_| None),
+        }
+    }
+
+    // A regtest run with a maturity of 3: rewards at heights 1..=4, a send
+    // that gets fee-bumped, and the bump confirming at height 5
+    fn history() -> Vec<WalletTx> {
+        let mut history: Vec<WalletTx> = (1..=4)
+            .map(|h| {
+                tx(
+                    &format!("cb{}", h),
+                    if h <= 2 { "generate" } else { "immature" },
+                    5_000_000_000,
+                    T0 + 10 * h,
+                    Some((h, T0 + 10 * h)),
+                )
+            })
+            .collect();
+        let mut original = tx("send1", "send", -2_000_000_000, T0 + 60, None);
+        original.address = Some("bcrt1qtrader".to_string());
+        original.replaced_by_txid = Some("send2".to_string());
+        let mut bump = tx("send2", "send", -2_000_000_000, T0 + 70, Some((5, T0 + 90)));
+        bump.address = Some("bcrt1qtrader".to_string());
+        bump.replaces_txid = Some("send1".to_string());
+        history.extend([bump, original]);
+        history
+    }
+
+    fn block_times() -> BTreeMap<u64, u64> {
+        (1..=5).map(|h| (h, T0 + 10 * h)).collect()
+    }
+
+    #[test]
+    fn maturity_is_synthesized_up_to_the_tip() {
+        assert_eq!(maturity_heights(&history(), 3, 5), vec![4, 5]);
+        assert_eq!(maturity_heights(&history(), 3, 3), Vec::<u64>::new());
+        assert_eq!(maturity_heights(&history(), 100, 100), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn covers_every_event_kind_in_order() {
+        let events = build_timeline(Some(T0), &history(), 3, 5, &block_times());
+        let kinds: Vec<EventKind> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::WalletCreated,
+                EventKind::MiningReward,
+                EventKind::RewardMatured,
+                EventKind::RewardMatured,
+                EventKind::Sent,
+                EventKind::Sent,
+                EventKind::Bumped,
+                EventKind::Replaced,
+                EventKind::Confirmed,
+            ]
+        );
+        assert_eq!(events[1].height, Some(1));
+        assert_eq!(events[2].height, Some(4));
+        assert_eq!(events[2].time, T0 + 40);
+        assert_eq!(
+            events[2].detail,
+            "50.00000000 BTC mined at height 1 is spendable"
+        );
+        assert_eq!(events[4].detail, "20.00000000 BTC to bcrt1qtrader in send1");
+        // The replacement is timed when the wallet saw the bump, not the original
+        assert_eq!(events[7].time, T0 + 70);
+        assert_eq!(events[8].height, Some(5));
+        assert_eq!(events[8].time, T0 + 90);
+    }
+
+    #[test]
+    fn missing_block_time_skips_maturity() {
+        let mut times = block_times();
+        times.remove(&5);
+        let events = build_timeline(None, &history(), 3, 5, &times);
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| event.kind == EventKind::RewardMatured)
+                .count(),
+            1
+        );
+        assert!(events
+            .iter()
+            .all(|event| event.kind != EventKind::WalletCreated));
+    }
+
+    #[test]
+    fn receiving_wallet_sees_received_and_confirmed() {
+        let mut received = tx("pay", "receive", 2_000_000_000, T0 + 5, Some((7, T0 + 9)));
+        received.address = Some("bcrt1qme".to_string());
+        let events = build_timeline(Some(T0), &[received], 100, 7, &BTreeMap::new());
+        let kinds: Vec<EventKind> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::WalletCreated,
+                EventKind::Received,
+                EventKind::Confirmed
+            ]
+        );
+        assert_eq!(events[1].detail, "20.00000000 BTC at bcrt1qme in pay");
+    }
+
+    #[test]
+    fn deltas_use_the_previous_event_and_last_height() {
+        let events = build_timeline(Some(T0), &history(), 3, 5, &block_times());
+        let deltas = deltas(&events);
+        assert_eq!(deltas[0], (None, None));
+        assert_eq!(deltas[1], (Some(10), None));
+        assert_eq!(deltas[2], (Some(30), Some(3)));
+        // Sends have no height, so the confirmation is measured from height 5's
+        // maturity event
+        assert_eq!(deltas[8], (Some(20), Some(0)));
+    }
+
+    #[test]
+    fn formats_utc_dates() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00");
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_utc(T0), "2023-11-14 22:13:20");
+    }
+
+    #[test]
+    fn renders_text_and_markdown() {
+        let events = build_timeline(Some(T0), &history(), 3, 5, &block_times());
+        let text = render_text(&events);
+        assert_eq!(text.lines().count(), events.len());
+        assert!(text
+            .lines()
+            .next()
+            .unwrap()
+            .starts_with("2023-11-14 22:13:20  height      -"));
+        assert!(text.contains("+30s    +3 blocks  reward matured"));
+
+        let markdown = render_markdown(&events);
+        let lines: Vec<&str> = markdown.lines().collect();
+        assert_eq!(lines.len(), events.len() + 2);
+        assert_eq!(
+            lines[0],
+            "| Time (UTC) | Height | Δ time | Δ blocks | Event |"
+        );
+        assert_eq!(
+            lines[3],
+            "| 2023-11-14 22:13:30 | 1 | +10s | - | first mining reward: 50.00000000 BTC in cb1 |"
+        );
+    }
+}
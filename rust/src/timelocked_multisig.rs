@@ -0,0 +1,322 @@
+// 2-of-3 multisig with a CSV (BIP-112) recovery path, as a P2WSH output.
+//
+// The witness script is
+// `OP_IF 2 <pk1> <pk2> <pk3> 3 OP_CHECKMULTISIG OP_ELSE <csv_blocks> OP_CSV OP_DROP <pk1> OP_CHECKSIG OP_ENDIF`:
+// any two of the three keys can spend at any time, and once the output is
+// `csv_blocks` blocks deep the first key can recover it alone.
+// `demonstrate` (`--timelocked-multisig`) funds one from the Miner and takes
+// the recovery path back once the delay has passed.
+use crate::cltv::random_key;
+use crate::mine_blocks_to_address;
+use bitcoincore_rpc::bitcoin::blockdata::opcodes::all::{
+    OP_CHECKMULTISIG, OP_CHECKSIG, OP_CSV, OP_DROP, OP_ELSE, OP_ENDIF, OP_IF,
+};
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::script::Builder;
+use bitcoincore_rpc::bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoincore_rpc::bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoincore_rpc::bitcoin::{
+    absolute, ecdsa, transaction, Address, Amount, Network, OutPoint, PrivateKey, PublicKey,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+use std::str::FromStr;
+
+// The recovery delay `demonstrate` uses, what it locks and the fee of its spend
+const DEMO_CSV_BLOCKS: u32 = 30;
+const DEMO_AMOUNT: Amount = Amount::from_sat(100_000_000);
+const DEMO_SPEND_FEE: Amount = Amount::from_sat(10_000);
+
+// A relative lock in blocks is a 16-bit field of nSequence (BIP-68)
+fn check_csv_blocks(csv_blocks: u32) -> bitcoincore_rpc::Result<u16> {
+    u16::try_from(csv_blocks)
+        .ok()
+        .filter(|&blocks| blocks > 0)
+        .ok_or_else(|| {
+            Error::ReturnedError(format!(
+                "CSV delay of {} blocks is out of range (1 to {})",
+                csv_blocks,
+                u16::MAX
+            ))
+        })
+}
+
+// Build the witness script; `public_keys[0]` is also the recovery key.
+pub fn timelocked_multisig_script(public_keys: &[PublicKey; 3], csv_blocks: u16) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(OP_IF)
+        .push_int(2)
+        .push_key(&public_keys[0])
+        .push_key(&public_keys[1])
+        .push_key(&public_keys[2])
+        .push_int(3)
+        .push_opcode(OP_CHECKMULTISIG)
+        .push_opcode(OP_ELSE)
+        .push_int(i64::from(csv_blocks))
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_key(&public_keys[0])
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_ENDIF)
+        .into_script()
+}
+
+// Helper function to build the P2WSH address of the timelocked multisig, checked
+// against the node's own `decodescript` result
+pub fn create_timelocked_multisig(
+    rpc: &impl RpcApi,
+    public_keys: &[&str; 3],
+    csv_blocks: u32,
+) -> bitcoincore_rpc::Result<String> {
+    let csv_blocks = check_csv_blocks(csv_blocks)?;
+    let mut keys = Vec::with_capacity(3);
+    for key in public_keys {
+        let key = PublicKey::from_str(key)
+            .map_err(|e| Error::ReturnedError(format!("invalid public key '{}': {}", key, e)))?;
+        if !key.compressed {
+            return Err(Error::ReturnedError(format!(
+                "public key {} is uncompressed; segwit requires compressed keys",
+                key
+            )));
+        }
+        keys.push(key);
+    }
+    let keys: [PublicKey; 3] = [keys[0], keys[1], keys[2]];
+
+    let witness_script = timelocked_multisig_script(&keys, csv_blocks);
+    let address = Address::p2wsh(&witness_script, Network::Regtest).to_string();
+    let decoded =
+        rpc.call::<serde_json::Value>("decodescript", &[json!(witness_script.to_hex_string())])?;
+    let node_address = decoded["segwit"]["address"].as_str().unwrap_or_default();
+    if node_address != address {
+        return Err(Error::ReturnedError(format!(
+            "node derives P2WSH address '{}' for the script, expected {}",
+            node_address, address
+        )));
+    }
+    Ok(address)
+}
+
+// Helper function to spend a timelocked multisig output through its recovery
+// path, signed locally with the first key and broadcast through `rpc`. The node
+// rejects it until the output is `csv_blocks` deep.
+#[allow(clippy::too_many_arguments)]
+pub fn spend_recovery_path(
+    rpc: &impl RpcApi,
+    outpoint: OutPoint,
+    amount: Amount,
+    witness_script: &ScriptBuf,
+    csv_blocks: u32,
+    key: &PrivateKey,
+    destination: &Address,
+    fee: Amount,
+) -> bitcoincore_rpc::Result<String> {
+    let csv_blocks = check_csv_blocks(csv_blocks)?;
+    let value = amount.checked_sub(fee).ok_or_else(|| {
+        Error::ReturnedError(format!("fee {} exceeds locked amount {}", fee, amount))
+    })?;
+
+    let mut tx = Transaction {
+        // BIP-68 relative locks only apply to version 2 transactions
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::from_height(csv_blocks),
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: destination.script_pubkey(),
+        }],
+    };
+
+    let sighash = SighashCache::new(&tx)
+        .p2wsh_signature_hash(0, witness_script, amount, EcdsaSighashType::All)
+        .map_err(|e| Error::ReturnedError(format!("cannot compute sighash: {}", e)))?;
+    let secp = Secp256k1::new();
+    let signature = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &key.inner);
+    let signature = ecdsa::Signature::sighash_all(signature);
+
+    // The empty element selects the OP_ELSE branch (MINIMALIF requires it empty)
+    let mut witness = Witness::new();
+    witness.push(signature.serialize());
+    witness.push([]);
+    witness.push(witness_script.as_bytes());
+    tx.input[0].witness = witness;
+
+    Ok(rpc.send_raw_transaction(&tx)?.to_string())
+}
+
+// Helper function to fund a timelocked multisig over three throwaway keys with
+// 1 BTC of `miner`, check that the node refuses the recovery spend while the
+// output is younger than the delay, then mine until it is old enough and
+// recover it to `miner`. Blocks are mined to `mining_address`. Returns the
+// funding and the recovery txid.
+pub fn demonstrate(
+    rpc: &impl RpcApi,
+    miner: &impl RpcApi,
+    mining_address: &Address,
+) -> bitcoincore_rpc::Result<(String, String)> {
+    let secp = Secp256k1::new();
+    let keys = [(); 3].map(|_| random_key(Network::Regtest));
+    let public_keys = keys.each_ref().map(|key| key.public_key(&secp));
+    let key_strings = public_keys.map(|key| key.to_string());
+    let address = create_timelocked_multisig(
+        rpc,
+        &key_strings.each_ref().map(String::as_str),
+        DEMO_CSV_BLOCKS,
+    )?;
+    let address = Address::from_str(&address)
+        .map_err(|e| Error::ReturnedError(format!("invalid address '{}': {}", address, e)))?
+        .assume_checked();
+    let txid = miner.send_to_address(&address, DEMO_AMOUNT, None, None, None, None, None, None)?;
+    let vout = rpc
+        .get_raw_transaction(&txid, None)?
+        .output
+        .iter()
+        .position(|out| out.script_pubkey == address.script_pubkey())
+        .ok_or_else(|| {
+            Error::ReturnedError(format!("transaction {} has no output to {}", txid, address))
+        })?;
+    mine_blocks_to_address(rpc, mining_address, 1)?;
+    info!(
+        "Locked {} BTC at {} with a {}-block recovery delay",
+        DEMO_AMOUNT.to_btc(),
+        address,
+        DEMO_CSV_BLOCKS
+    );
+
+    let witness_script =
+        timelocked_multisig_script(&public_keys, check_csv_blocks(DEMO_CSV_BLOCKS)?);
+    let destination = miner.get_new_address(None, None)?.assume_checked();
+    let recover = || {
+        spend_recovery_path(
+            rpc,
+            OutPoint::new(txid, vout as u32),
+            DEMO_AMOUNT,
+            &witness_script,
+            DEMO_CSV_BLOCKS,
+            &keys[0],
+            &destination,
+            DEMO_SPEND_FEE,
+        )
+    };
+    if recover().is_ok() {
+        return Err(Error::ReturnedError(format!(
+            "the node accepted the recovery spend of {} after 1 confirmation",
+            address
+        )));
+    }
+    info!("Recovery with 1 confirmation is rejected");
+
+    // The output is DEMO_CSV_BLOCKS deep once that many blocks include it
+    mine_blocks_to_address(rpc, mining_address, u64::from(DEMO_CSV_BLOCKS) - 1)?;
+    let recovery_txid = recover()?;
+    mine_blocks_to_address(rpc, mining_address, 1)?;
+    info!(
+        "Recovered to the Miner with the first key: {}",
+        recovery_txid
+    );
+    Ok((txid.to_string(), recovery_txid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_wallet_client;
+    use crate::rpc_config::RpcConfig;
+    use bitcoincore_rpc::bitcoin::secp256k1::SecretKey;
+
+    fn test_keys() -> [PrivateKey; 3] {
+        [0x21, 0x22, 0x23].map(|byte| {
+            PrivateKey::new(
+                SecretKey::from_slice(&[byte; 32]).unwrap(),
+                Network::Regtest,
+            )
+        })
+    }
+
+    fn public_keys() -> [PublicKey; 3] {
+        let secp = Secp256k1::new();
+        test_keys().map(|key| key.public_key(&secp))
+    }
+
+    #[test]
+    fn witness_script_layout() {
+        let keys = public_keys();
+        let asm = timelocked_multisig_script(&keys, 30).to_asm_string();
+        let expected = format!(
+            "OP_IF OP_PUSHNUM_2 OP_PUSHBYTES_33 {0} OP_PUSHBYTES_33 {1} OP_PUSHBYTES_33 {2} \
+             OP_PUSHNUM_3 OP_CHECKMULTISIG OP_ELSE OP_PUSHBYTES_1 1e OP_CSV OP_DROP \
+             OP_PUSHBYTES_33 {0} OP_CHECKSIG OP_ENDIF",
+            keys[0], keys[1], keys[2]
+        );
+        assert_eq!(asm, expected);
+    }
+
+    #[test]
+    fn csv_delay_must_fit_a_relative_lock() {
+        assert!(check_csv_blocks(0).is_err());
+        assert_eq!(check_csv_blocks(30).unwrap(), 30);
+        assert_eq!(check_csv_blocks(65_535).unwrap(), 65_535);
+        assert!(check_csv_blocks(65_536).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn recovery_path_spendable_after_csv_delay() {
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let mining_address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &mining_address, 101).unwrap();
+
+        let csv_blocks = 30;
+        let keys: Vec<String> = public_keys().iter().map(PublicKey::to_string).collect();
+        let address = create_timelocked_multisig(
+            &rpc,
+            &[keys[0].as_str(), keys[1].as_str(), keys[2].as_str()],
+            csv_blocks,
+        )
+        .unwrap();
+        let address = Address::from_str(&address).unwrap().assume_checked();
+        let amount = Amount::from_btc(1.0).unwrap();
+        let txid = miner
+            .send_to_address(&address, amount, None, None, None, None, None, None)
+            .unwrap();
+        let vout = rpc
+            .get_raw_transaction(&txid, None)
+            .unwrap()
+            .output
+            .iter()
+            .position(|out| out.script_pubkey == address.script_pubkey())
+            .unwrap();
+        mine_blocks_to_address(&rpc, &mining_address, 1).unwrap();
+
+        let witness_script = timelocked_multisig_script(&public_keys(), csv_blocks as u16);
+        let destination = miner.get_new_address(None, None).unwrap().assume_checked();
+        let spend = || {
+            spend_recovery_path(
+                &rpc,
+                OutPoint::new(txid, vout as u32),
+                amount,
+                &witness_script,
+                csv_blocks,
+                &test_keys()[0],
+                &destination,
+                Amount::from_sat(10_000),
+            )
+        };
+
+        // One confirmation is not enough (non-BIP68-final)
+        assert!(spend().is_err());
+        mine_blocks_to_address(&rpc, &mining_address, u64::from(csv_blocks)).unwrap();
+        let spend_txid = spend().unwrap();
+        let spend_txid = bitcoincore_rpc::bitcoin::Txid::from_str(&spend_txid).unwrap();
+        assert!(rpc.get_mempool_entry(&spend_txid).is_ok());
+    }
+}
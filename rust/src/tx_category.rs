@@ -0,0 +1,156 @@
+// What a wallet transaction entry is, normalized from the node's strings.
+//
+// `listtransactions`, `listsinceblock` and the `details` of `gettransaction`
+// name a category per entry, and the wallet adds `"generated": true` to
+// coinbase transactions and `trusted` to unconfirmed ones. A coinbase is
+// always one of generate (mature), immature or orphan (its block left the
+// chain); `TxCategory::normalize` is the one place those rules live. Categories
+// this code does not know are kept as `Other` instead of failing.
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TxCategory {
+    Send,
+    Receive,
+    // A spendable block reward
+    Generate,
+    // A block reward still waiting for coinbase maturity
+    Immature,
+    // A block reward whose block is no longer in the best chain
+    Orphan,
+    Other(String),
+}
+
+impl TxCategory {
+    // Normalize an RPC `category` with the entry's `generated` and `trusted`
+    // flags. `generated` marks a coinbase whatever the category says, and a
+    // coinbase the wallet does not trust is unconfirmed, which for a coinbase
+    // means orphaned.
+    pub fn normalize(category: &str, generated: bool, trusted: Option<bool>) -> Self {
+        let coinbase = generated || matches!(category, "generate" | "immature" | "orphan");
+        if coinbase {
+            return match (category, trusted) {
+                (_, Some(false)) | ("orphan", _) => TxCategory::Orphan,
+                ("immature", _) => TxCategory::Immature,
+                _ => TxCategory::Generate,
+            };
+        }
+        match category {
+            "send" => TxCategory::Send,
+            "receive" => TxCategory::Receive,
+            other => TxCategory::Other(other.to_string()),
+        }
+    }
+
+    // Whether the entry is a block reward, mature or not
+    pub fn is_coinbase(&self) -> bool {
+        matches!(
+            self,
+            TxCategory::Generate | TxCategory::Immature | TxCategory::Orphan
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CATEGORIES: [&str; 7] = [
+        "send", "receive", "generate", "immature", "orphan", "move", "",
+    ];
+    const TRUST: [Option<bool>; 3] = [None, Some(true), Some(false)];
+
+    // The expected normalization of every category string and flag combination
+    fn expected(category: &str, generated: bool, trusted: Option<bool>) -> TxCategory {
+        use TxCategory::*;
+        match (category, generated, trusted) {
+            ("send", false, _) => Send,
+            ("receive", false, _) => Receive,
+            ("move", false, _) => Other("move".to_string()),
+            ("", false, _) => Other(String::new()),
+            (_, _, Some(false)) => Orphan,
+            ("orphan", _, _) => Orphan,
+            ("immature", _, _) => Immature,
+            _ => Generate,
+        }
+    }
+
+    #[test]
+    fn every_category_and_flag_combination() {
+        for category in CATEGORIES {
+            for generated in [false, true] {
+                for trusted in TRUST {
+                    assert_eq!(
+                        TxCategory::normalize(category, generated, trusted),
+                        expected(category, generated, trusted),
+                        "category {:?}, generated {}, trusted {:?}",
+                        category,
+                        generated,
+                        trusted
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn plain_categories() {
+        assert_eq!(TxCategory::normalize("send", false, None), TxCategory::Send);
+        assert_eq!(
+            TxCategory::normalize("receive", false, Some(false)),
+            TxCategory::Receive
+        );
+        assert_eq!(
+            TxCategory::normalize("generate", true, None),
+            TxCategory::Generate
+        );
+        assert_eq!(
+            TxCategory::normalize("immature", true, None),
+            TxCategory::Immature
+        );
+        assert_eq!(
+            TxCategory::normalize("orphan", true, Some(false)),
+            TxCategory::Orphan
+        );
+    }
+
+    #[test]
+    fn unknown_categories_are_kept() {
+        assert_eq!(
+            TxCategory::normalize("Send", false, None),
+            TxCategory::Other("Send".to_string())
+        );
+        assert!(!TxCategory::normalize("move", false, None).is_coinbase());
+        // Without a category, `generated` alone marks a block reward
+        assert_eq!(TxCategory::normalize("", true, None), TxCategory::Generate);
+    }
+
+    #[test]
+    fn untrusted_coinbases_are_orphaned() {
+        assert_eq!(
+            TxCategory::normalize("immature", true, Some(false)),
+            TxCategory::Orphan
+        );
+        assert_eq!(
+            TxCategory::normalize("generate", false, Some(false)),
+            TxCategory::Orphan
+        );
+        assert_eq!(
+            TxCategory::normalize("immature", true, Some(true)),
+            TxCategory::Immature
+        );
+    }
+
+    #[test]
+    fn only_block_rewards_are_coinbases() {
+        for category in CATEGORIES {
+            for generated in [false, true] {
+                for trusted in TRUST {
+                    let normalized = TxCategory::normalize(category, generated, trusted);
+                    let reward =
+                        generated || matches!(category, "generate" | "immature" | "orphan");
+                    assert_eq!(normalized.is_coinbase(), reward, "{:?}", normalized);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,342 @@
+// TxDiffTool: what changed between a transaction and its replacement, e.g.
+// an original send and its RBF fee bump.
+//
+// Inputs are compared as a set of outpoints. Outputs are compared position by
+// position, so a bumped fee shows up as a changed value on the change vout.
+// The replaced transaction is no longer in the mempool, so when the node
+// cannot serve it `getrawtransaction`, it is taken from the wallet instead.
+use crate::report::format_btc;
+use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, OutPoint, Transaction, TxOut};
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxInput {
+    pub txid: String,
+    pub vout: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDiff {
+    pub vout: u32,
+    pub old_value: Option<Amount>,
+    pub new_value: Option<Amount>,
+    pub old_address: Option<String>,
+    pub new_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxDiff {
+    pub added_inputs: Vec<TxInput>,
+    pub removed_inputs: Vec<TxInput>,
+    pub changed_outputs: Vec<OutputDiff>,
+}
+
+impl TxDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_inputs.is_empty()
+            && self.removed_inputs.is_empty()
+            && self.changed_outputs.is_empty()
+    }
+}
+
+fn tx_input(outpoint: &OutPoint) -> TxInput {
+    TxInput {
+        txid: outpoint.txid.to_string(),
+        vout: outpoint.vout,
+    }
+}
+
+fn output_address(output: &TxOut) -> Option<String> {
+    Address::from_script(&output.script_pubkey, Network::Regtest)
+        .ok()
+        .map(|address| address.to_string())
+}
+
+// Diff two decoded transactions.
+pub fn diff(original: &Transaction, replacement: &Transaction) -> TxDiff {
+    let outpoints = |tx: &Transaction| -> Vec<OutPoint> {
+        tx.input.iter().map(|input| input.previous_output).collect()
+    };
+    let (old_inputs, new_inputs) = (outpoints(original), outpoints(replacement));
+    let added_inputs = new_inputs
+        .iter()
+        .filter(|outpoint| !old_inputs.contains(outpoint))
+        .map(tx_input)
+        .collect();
+    let removed_inputs = old_inputs
+        .iter()
+        .filter(|outpoint| !new_inputs.contains(outpoint))
+        .map(tx_input)
+        .collect();
+
+    let vouts = original.output.len().max(replacement.output.len());
+    let changed_outputs = (0..vouts)
+        .filter_map(|vout| {
+            let old = original.output.get(vout);
+            let new = replacement.output.get(vout);
+            if old == new {
+                return None;
+            }
+            Some(OutputDiff {
+                vout: vout as u32,
+                old_value: old.map(|output| output.value),
+                new_value: new.map(|output| output.value),
+                old_address: old.and_then(output_address),
+                new_address: new.and_then(output_address),
+            })
+        })
+        .collect();
+
+    TxDiff {
+        added_inputs,
+        removed_inputs,
+        changed_outputs,
+    }
+}
+
+// Helper function to fetch a transaction from the node, falling back to the
+// wallet's copy (which survives the transaction being replaced)
+fn fetch_transaction(rpc: &impl RpcApi, txid: &str) -> bitcoincore_rpc::Result<Transaction> {
+    let hex = match rpc.call::<String>("getrawtransaction", &[json!(txid)]) {
+        Ok(hex) => hex,
+        Err(_) => {
+            let wallet_tx = rpc.call::<serde_json::Value>("gettransaction", &[json!(txid)])?;
+            wallet_tx["hex"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    Error::ReturnedError(format!("wallet transaction {} has no hex", txid))
+                })?
+        }
+    };
+    Ok(deserialize(&Vec::<u8>::from_hex(&hex)?)?)
+}
+
+// Helper function to diff two transactions by txid. `rpc` should be a wallet
+// client when the original may already have been replaced.
+pub fn diff_transactions(
+    rpc: &impl RpcApi,
+    original_txid: &str,
+    replacement_txid: &str,
+) -> bitcoincore_rpc::Result<TxDiff> {
+    let original = fetch_transaction(rpc, original_txid)?;
+    let replacement = fetch_transaction(rpc, replacement_txid)?;
+    Ok(diff(&original, &replacement))
+}
+
+fn describe_output(value: Option<Amount>, address: &Option<String>) -> String {
+    match value {
+        Some(value) => format!(
+            "{} BTC to {}",
+            format_btc(value),
+            address.as_deref().unwrap_or("<non-standard script>")
+        ),
+        None => "none".to_string(),
+    }
+}
+
+// `diff-tx <original-txid> <replacement-txid>`; `args` excludes "diff-tx".
+pub fn run_command(rpc: &impl RpcApi, args: &[String]) -> bitcoincore_rpc::Result<()> {
+    let (original, replacement) = match args {
+        [original, replacement, ..] => (original, replacement),
+        _ => {
+            return Err(Error::ReturnedError(
+                "usage: diff-tx <original-txid> <replacement-txid>".to_string(),
+            ))
+        }
+    };
+    let diff = diff_transactions(rpc, original, replacement)?;
+    if diff.is_empty() {
+        info!("No differences");
+    }
+    for input in &diff.added_inputs {
+        info!("+ input {}:{}", input.txid, input.vout);
+    }
+    for input in &diff.removed_inputs {
+        info!("- input {}:{}", input.txid, input.vout);
+    }
+    for output in &diff.changed_outputs {
+        info!(
+            "~ vout {}: {} -> {}",
+            output.vout,
+            describe_output(output.old_value, &output.old_address),
+            describe_output(output.new_value, &output.new_address)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::absolute::LockTime;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::transaction::Version;
+    use bitcoincore_rpc::bitcoin::{ScriptBuf, Sequence, TxIn, Txid, WPubkeyHash, Witness};
+
+    fn outpoint(byte: u8, vout: u32) -> OutPoint {
+        OutPoint {
+            txid: Txid::from_byte_array([byte; 32]),
+            vout,
+        }
+    }
+
+    fn p2wpkh(byte: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([byte; 20]))
+    }
+
+    fn tx(inputs: &[OutPoint], outputs: &[(ScriptBuf, u64)]) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|&previous_output| TxIn {
+                    previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|(script, sats)| TxOut {
+                    value: Amount::from_sat(*sats),
+                    script_pubkey: script.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn identical_transactions_have_no_diff() {
+        let original = tx(&[outpoint(1, 0)], &[(p2wpkh(1), 1_000)]);
+        assert!(diff(&original, &original.clone()).is_empty());
+    }
+
+    #[test]
+    fn fee_bump_changes_only_the_change_value() {
+        let original = tx(
+            &[outpoint(1, 0)],
+            &[(p2wpkh(1), 2_000_000_000), (p2wpkh(2), 2_999_998_590)],
+        );
+        let bumped = tx(
+            &[outpoint(1, 0)],
+            &[(p2wpkh(1), 2_000_000_000), (p2wpkh(2), 2_999_997_180)],
+        );
+        let diff = diff(&original, &bumped);
+        assert!(diff.added_inputs.is_empty());
+        assert!(diff.removed_inputs.is_empty());
+        let change_address = output_address(&original.output[1]);
+        assert!(change_address.as_deref().unwrap().starts_with("bcrt1q"));
+        assert_eq!(
+            diff.changed_outputs,
+            vec![OutputDiff {
+                vout: 1,
+                old_value: Some(Amount::from_sat(2_999_998_590)),
+                new_value: Some(Amount::from_sat(2_999_997_180)),
+                old_address: change_address.clone(),
+                new_address: change_address,
+            }]
+        );
+    }
+
+    #[test]
+    fn added_and_removed_inputs() {
+        let original = tx(&[outpoint(1, 0), outpoint(2, 1)], &[(p2wpkh(1), 1_000)]);
+        let replacement = tx(&[outpoint(2, 1), outpoint(3, 0)], &[(p2wpkh(1), 1_000)]);
+        let diff = diff(&original, &replacement);
+        assert_eq!(diff.added_inputs, vec![tx_input(&outpoint(3, 0))]);
+        assert_eq!(diff.removed_inputs, vec![tx_input(&outpoint(1, 0))]);
+        assert!(diff.changed_outputs.is_empty());
+    }
+
+    #[test]
+    fn dropped_and_new_outputs() {
+        let original = tx(&[outpoint(1, 0)], &[(p2wpkh(1), 1_000), (p2wpkh(2), 500)]);
+        let replacement = tx(&[outpoint(1, 0)], &[(p2wpkh(3), 1_400)]);
+        let diff = diff(&original, &replacement);
+        assert_eq!(diff.changed_outputs.len(), 2);
+        assert_eq!(
+            diff.changed_outputs[0].old_value,
+            Some(Amount::from_sat(1_000))
+        );
+        assert_eq!(
+            diff.changed_outputs[0].new_value,
+            Some(Amount::from_sat(1_400))
+        );
+        assert_ne!(
+            diff.changed_outputs[0].old_address,
+            diff.changed_outputs[0].new_address
+        );
+        assert_eq!(diff.changed_outputs[1].vout, 1);
+        assert_eq!(diff.changed_outputs[1].new_value, None);
+        assert_eq!(diff.changed_outputs[1].new_address, None);
+    }
+
+    #[test]
+    fn script_without_address_has_none() {
+        let original = tx(
+            &[outpoint(1, 0)],
+            &[(ScriptBuf::new_op_return([1u8; 4]), 0)],
+        );
+        let replacement = tx(
+            &[outpoint(1, 0)],
+            &[(ScriptBuf::new_op_return([2u8; 4]), 0)],
+        );
+        let diff = diff(&original, &replacement);
+        assert_eq!(diff.changed_outputs[0].old_address, None);
+        assert_eq!(diff.changed_outputs[0].new_address, None);
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn rbf_fee_bump_lowers_the_change_output() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        create_or_load_wallet(&rpc, &config, "Trader", true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let trader = get_wallet_client(&config, "Trader").unwrap();
+        let mining_address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &mining_address, 101).unwrap();
+
+        let recipient = trader.get_new_address(None, None).unwrap().assume_checked();
+        let original = miner
+            .call::<String>(
+                "sendtoaddress",
+                &[
+                    json!(recipient.to_string()),
+                    json!(20),
+                    json!(""),
+                    json!(""),
+                    json!(false),
+                    json!(true),
+                ],
+            )
+            .unwrap();
+        let bumped = miner
+            .call::<serde_json::Value>("bumpfee", &[json!(original)])
+            .unwrap();
+        let replacement = bumped["txid"].as_str().unwrap();
+
+        let diff = diff_transactions(&miner, &original, replacement).unwrap();
+        assert!(diff.added_inputs.is_empty());
+        assert!(diff.removed_inputs.is_empty());
+        assert_eq!(diff.changed_outputs.len(), 1);
+        let change = &diff.changed_outputs[0];
+        assert_ne!(
+            change.old_address.as_deref(),
+            Some(recipient.to_string().as_str())
+        );
+        assert!(change.new_value.unwrap() < change.old_value.unwrap());
+        run_command(&miner, &[original, replacement.to_string()]).unwrap();
+    }
+}
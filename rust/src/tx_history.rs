@@ -0,0 +1,213 @@
+// Full wallet transaction history, paged through `listtransactions`.
+//
+// `listtransactions` returns at most `count` entries per call and one entry per
+// wallet-relevant output, so a transaction with several outputs shows up
+// several times. `get_all_transactions` keeps asking for the next page until a
+// short page comes back, then keeps the first entry seen for each txid.
+use crate::tx_category::TxCategory;
+use bitcoincore_rpc::bitcoin::SignedAmount;
+use bitcoincore_rpc::{Error, RpcApi};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+
+// One `listtransactions` entry. Amounts are signed: sends are negative.
+// `time` is when the wallet first saw the transaction; the block fields are
+// only set once it is confirmed. Read the category through `tx_category`,
+// which also takes the `generated` and `trusted` flags into account.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WalletTx {
+    pub txid: String,
+    pub category: String,
+    #[serde(with = "bitcoincore_rpc::bitcoin::amount::serde::as_btc")]
+    pub amount: SignedAmount,
+    pub confirmations: i64,
+    pub address: Option<String>,
+    pub blockhash: Option<String>,
+    #[serde(default)]
+    pub blockheight: Option<u64>,
+    #[serde(default)]
+    pub blocktime: Option<u64>,
+    #[serde(default)]
+    pub time: u64,
+    #[serde(default)]
+    pub replaced_by_txid: Option<String>,
+    #[serde(default)]
+    pub replaces_txid: Option<String>,
+    #[serde(default)]
+    pub generated: bool,
+    #[serde(default)]
+    pub trusted: Option<bool>,
+}
+
+impl WalletTx {
+    pub fn tx_category(&self) -> TxCategory {
+        TxCategory::normalize(&self.category, self.generated, self.trusted)
+    }
+}
+
+// Collect every page returned by `fetch(count, skip)` and drop repeated txids.
+fn collect_pages<F>(batch_size: usize, mut fetch: F) -> bitcoincore_rpc::Result<Vec<WalletTx>>
+where
+    F: FnMut(usize, usize) -> bitcoincore_rpc::Result<Vec<WalletTx>>,
+{
+    if batch_size == 0 {
+        return Err(Error::ReturnedError(
+            "batch size must be at least 1".to_string(),
+        ));
+    }
+    let mut seen = HashSet::new();
+    let mut history = Vec::new();
+    let mut skip = 0;
+    loop {
+        let page = fetch(batch_size, skip)?;
+        let last_page = page.len() < batch_size;
+        history.extend(page.into_iter().filter(|tx| seen.insert(tx.txid.clone())));
+        if last_page {
+            return Ok(history);
+        }
+        skip += batch_size;
+    }
+}
+
+// Helper function to fetch a wallet's whole deduplicated history, `batch_size`
+// entries per `listtransactions` call
+pub fn get_all_transactions(
+    rpc: &impl RpcApi,
+    batch_size: usize,
+) -> bitcoincore_rpc::Result<Vec<WalletTx>> {
+    collect_pages(batch_size, |count, skip| {
+        rpc.call("listtransactions", &[json!("*"), json!(count), json!(skip)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallet_tx(txid: &str) -> WalletTx {
+        WalletTx {
+            txid: txid.to_string(),
+            category: "receive".to_string(),
+            amount: SignedAmount::from_sat(1_000),
+            confirmations: 1,
+            address: None,
+            blockhash: None,
+            blockheight: None,
+            blocktime: None,
+            time: 0,
+            replaced_by_txid: None,
+            replaces_txid: None,
+            generated: false,
+            trusted: None,
+        }
+    }
+
+    // A wallet history of `entries`, served like `listtransactions` does
+    fn serve(
+        entries: &[WalletTx],
+    ) -> impl FnMut(usize, usize) -> bitcoincore_rpc::Result<Vec<WalletTx>> + '_ {
+        move |count, skip| Ok(entries.iter().skip(skip).take(count).cloned().collect())
+    }
+
+    #[test]
+    fn pages_through_250_transactions() {
+        let entries: Vec<WalletTx> = (0..250).map(|i| wallet_tx(&format!("tx{}", i))).collect();
+        let mut calls = 0;
+        let mut fetch = serve(&entries);
+        let history = collect_pages(100, |count, skip| {
+            calls += 1;
+            fetch(count, skip)
+        })
+        .unwrap();
+        assert_eq!(history, entries);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn exact_multiple_needs_a_final_empty_page() {
+        let entries: Vec<WalletTx> = (0..200).map(|i| wallet_tx(&format!("tx{}", i))).collect();
+        assert_eq!(collect_pages(100, serve(&entries)).unwrap().len(), 200);
+    }
+
+    #[test]
+    fn repeated_txids_are_dropped_across_pages() {
+        let entries = vec![
+            wallet_tx("a"),
+            wallet_tx("b"),
+            wallet_tx("a"),
+            wallet_tx("c"),
+        ];
+        let txids: Vec<String> = collect_pages(2, serve(&entries))
+            .unwrap()
+            .into_iter()
+            .map(|tx| tx.txid)
+            .collect();
+        assert_eq!(txids, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn zero_batch_size_is_rejected() {
+        assert!(collect_pages(0, serve(&[])).is_err());
+    }
+
+    #[test]
+    fn parses_listtransactions_entry() {
+        let entry: WalletTx = serde_json::from_value(json!({
+            "address": "bcrt1qywl0kv3kfprpl5y4r8dkgmds6xte4qp5crg9yr",
+            "category": "send",
+            "amount": -20.00000000,
+            "vout": 0,
+            "fee": -0.00000141,
+            "confirmations": 1,
+            "blockheight": 102,
+            "blocktime": 1_700_000_600,
+            "time": 1_700_000_000,
+            "txid": "33b2570db2b2f18f03b44eba7f19efb686978ad22a242a78c03d049364196db9",
+        }))
+        .unwrap();
+        assert_eq!(entry.amount, SignedAmount::from_sat(-2_000_000_000));
+        assert_eq!(entry.blockhash, None);
+        assert_eq!(entry.blockheight, Some(102));
+        assert_eq!(entry.time, 1_700_000_000);
+        assert_eq!(entry.replaced_by_txid, None);
+        assert_eq!(entry.tx_category(), TxCategory::Send);
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn returns_all_250_wallet_transactions() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+        use bitcoincore_rpc::bitcoin::Amount;
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let name = format!("History-test-{}", std::process::id());
+        create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+        create_or_load_wallet(&rpc, &config, &name, true).unwrap();
+        let miner = get_wallet_client(&config, "Miner").unwrap();
+        let wallet = get_wallet_client(&config, &name).unwrap();
+        let mining_address = miner.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &mining_address, 101).unwrap();
+
+        for _ in 0..250 {
+            let address = wallet.get_new_address(None, None).unwrap().assume_checked();
+            miner
+                .send_to_address(
+                    &address,
+                    Amount::from_sat(10_000),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let history = get_all_transactions(&wallet, 100).unwrap();
+        assert_eq!(history.len(), 250);
+        rpc.unload_wallet(Some(&name)).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+// Free-form notes attached to transactions, kept in a local SQLite database.
+//
+// Bitcoin Core only stores one comment per wallet transaction, so notes and
+// their tags live in two tables keyed by txid: `tx_notes` holds the notes, and
+// `tx_note_tags` one row per tag so notes can be searched by tag.
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const TX_NOTES_PATH: &str = "txnotes.db";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS tx_notes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        txid TEXT NOT NULL,
+        note TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS tx_notes_txid ON tx_notes (txid);
+    CREATE TABLE IF NOT EXISTS tx_note_tags (
+        note_id INTEGER NOT NULL REFERENCES tx_notes (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        UNIQUE (note_id, tag)
+    );
+    CREATE INDEX IF NOT EXISTS tx_note_tags_tag ON tx_note_tags (tag);
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxNote {
+    pub txid: String,
+    pub note: String,
+    pub created_at: u64,
+    pub tags: Vec<String>,
+}
+
+impl TxNote {
+    // A note created now.
+    pub fn new(txid: &str, note: &str, tags: &[&str]) -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        TxNote {
+            txid: txid.to_string(),
+            note: note.to_string(),
+            created_at,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+}
+
+pub struct TransactionNoteStore {
+    conn: Connection,
+}
+
+impl TransactionNoteStore {
+    // Open (creating if needed) the note database at `path`.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    // A store that lives only as long as the value, for tests.
+    #[cfg(test)]
+    pub fn in_memory() -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(TransactionNoteStore { conn })
+    }
+
+    // Store a note and its tags; returns the new note's id. Repeated tags are
+    // stored once.
+    pub fn add_note(&self, note: TxNote) -> rusqlite::Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO tx_notes (txid, note, created_at) VALUES (?1, ?2, ?3)",
+            params![note.txid, note.note, note.created_at as i64],
+        )?;
+        let id = tx.last_insert_rowid();
+        for tag in &note.tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO tx_note_tags (note_id, tag) VALUES (?1, ?2)",
+                params![id, tag],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    // Every note on `txid`, oldest first.
+    pub fn get_notes(&self, txid: &str) -> rusqlite::Result<Vec<TxNote>> {
+        self.query_notes(
+            "SELECT id, txid, note, created_at FROM tx_notes WHERE txid = ?1 ORDER BY id",
+            txid,
+        )
+    }
+
+    // Every note carrying `tag`, on any transaction, oldest first.
+    pub fn search_by_tag(&self, tag: &str) -> rusqlite::Result<Vec<TxNote>> {
+        self.query_notes(
+            "SELECT n.id, n.txid, n.note, n.created_at FROM tx_notes n \
+             JOIN tx_note_tags t ON t.note_id = n.id WHERE t.tag = ?1 ORDER BY n.id",
+            tag,
+        )
+    }
+
+    fn query_notes(&self, sql: &str, param: &str) -> rusqlite::Result<Vec<TxNote>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([param], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                TxNote {
+                    txid: row.get(1)?,
+                    note: row.get(2)?,
+                    created_at: row.get::<_, i64>(3)? as u64,
+                    tags: Vec::new(),
+                },
+            ))
+        })?;
+        rows.map(|row| {
+            let (id, mut note) = row?;
+            note.tags = self.tags(id)?;
+            Ok(note)
+        })
+        .collect()
+    }
+
+    // Tags of note `id` in the order they were added
+    fn tags(&self, id: i64) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag FROM tx_note_tags WHERE note_id = ?1 ORDER BY rowid")?;
+        let tags = stmt.query_map([id], |row| row.get(0))?;
+        tags.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(txid: &str, text: &str, tags: &[&str]) -> TxNote {
+        TxNote {
+            txid: txid.to_string(),
+            note: text.to_string(),
+            created_at: 1_700_000_000,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn notes_round_trip_by_txid() {
+        let store = TransactionNoteStore::in_memory().unwrap();
+        let first = note("aa", "capstone payment to Trader", &["capstone", "payment"]);
+        let second = note("aa", "confirmed in block 102", &[]);
+        let first_id = store.add_note(first.clone()).unwrap();
+        let second_id = store.add_note(second.clone()).unwrap();
+        store
+            .add_note(note("bb", "unrelated", &["payment"]))
+            .unwrap();
+        assert!(second_id > first_id);
+
+        assert_eq!(store.get_notes("aa").unwrap(), vec![first, second]);
+        assert_eq!(store.get_notes("cc").unwrap(), Vec::<TxNote>::new());
+    }
+
+    #[test]
+    fn search_by_tag_spans_transactions() {
+        let store = TransactionNoteStore::in_memory().unwrap();
+        store
+            .add_note(note(
+                "aa",
+                "capstone payment to Trader",
+                &["capstone", "payment"],
+            ))
+            .unwrap();
+        store.add_note(note("bb", "refund", &["payment"])).unwrap();
+        store.add_note(note("cc", "untagged", &[])).unwrap();
+
+        let txids = |tag: &str| -> Vec<String> {
+            store
+                .search_by_tag(tag)
+                .unwrap()
+                .into_iter()
+                .map(|note| note.txid)
+                .collect()
+        };
+        assert_eq!(txids("payment"), vec!["aa", "bb"]);
+        assert_eq!(txids("capstone"), vec!["aa"]);
+        assert_eq!(txids("Capstone"), Vec::<String>::new());
+        // Found notes come back with all of their tags, not just the match
+        assert_eq!(
+            store.search_by_tag("capstone").unwrap()[0].tags,
+            vec!["capstone", "payment"]
+        );
+    }
+
+    #[test]
+    fn repeated_tags_are_stored_once() {
+        let store = TransactionNoteStore::in_memory().unwrap();
+        store
+            .add_note(note("aa", "twice", &["payment", "capstone", "payment"]))
+            .unwrap();
+        assert_eq!(
+            store.get_notes("aa").unwrap()[0].tags,
+            vec!["payment", "capstone"]
+        );
+        assert_eq!(store.search_by_tag("payment").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn notes_persist_across_opens() {
+        let path = std::env::temp_dir().join(format!("txnotes-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let store = TransactionNoteStore::open(&path).unwrap();
+            store.add_note(note("aa", "kept", &["capstone"])).unwrap();
+        }
+        let store = TransactionNoteStore::open(&path).unwrap();
+        assert_eq!(store.get_notes("aa").unwrap()[0].note, "kept");
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+// Pre-flight checks on a wallet before trusting it with a large payment.
+//
+// Each check covers a way a send can go wrong without the node refusing it:
+// a nearly empty keypool (change addresses run out), a rescan still in
+// progress (balances incomplete), a locked encrypted wallet (signing fails
+// halfway), dust UTXOs (uneconomical inputs the coin selector may pick up),
+// and balance RPCs that disagree (see `balance_reconciler`).
+use crate::balance_reconciler::{reconcile_wallet_balance, ReconciliationResult};
+use crate::wallet_ready::WalletInfo;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::RpcApi;
+
+// The keypool must hold more keys than this
+pub const MIN_KEYPOOL_SIZE: u64 = 20;
+// Outputs below this many sats are dust (P2PKH dust limit at 3 sat/vB)
+pub const DUST_LIMIT: Amount = Amount::from_sat(546);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletHealthReport {
+    pub keypool_adequate: bool,
+    pub not_scanning: bool,
+    pub unlocked_or_unencrypted: bool,
+    pub no_dust_utxos: bool,
+    pub balance_reconciled: bool,
+}
+
+impl WalletHealthReport {
+    pub fn from_facts(
+        info: &WalletInfo,
+        utxo_amounts: &[Amount],
+        reconciliation: &ReconciliationResult,
+    ) -> Self {
+        WalletHealthReport {
+            keypool_adequate: info.keypoolsize > MIN_KEYPOOL_SIZE,
+            not_scanning: !info.is_scanning(),
+            unlocked_or_unencrypted: info.unlocked_until.is_none_or(|until| until > 0),
+            no_dust_utxos: utxo_amounts.iter().all(|&amount| amount >= DUST_LIMIT),
+            balance_reconciled: reconciliation.all_agree,
+        }
+    }
+
+    // Names of the checks that failed.
+    pub fn failures(&self) -> Vec<&'static str> {
+        [
+            ("keypool_adequate", self.keypool_adequate),
+            ("not_scanning", self.not_scanning),
+            ("unlocked_or_unencrypted", self.unlocked_or_unencrypted),
+            ("no_dust_utxos", self.no_dust_utxos),
+            ("balance_reconciled", self.balance_reconciled),
+        ]
+        .into_iter()
+        .filter(|&(_, passed)| !passed)
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.failures().is_empty()
+    }
+}
+
+// Helper function to run every health check on the wallet behind `rpc`
+pub fn check_wallet_health(rpc: &impl RpcApi) -> bitcoincore_rpc::Result<WalletHealthReport> {
+    let info: WalletInfo = rpc.call("getwalletinfo", &[])?;
+    let utxo_amounts: Vec<Amount> = rpc
+        .list_unspent(Some(0), None, None, Some(true), None)?
+        .into_iter()
+        .map(|utxo| utxo.amount)
+        .collect();
+    let reconciliation = reconcile_wallet_balance(rpc)?;
+    Ok(WalletHealthReport::from_facts(
+        &info,
+        &utxo_amounts,
+        &reconciliation,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn info(extra: serde_json::Value) -> WalletInfo {
+        let mut value = json!({ "walletname": "Miner", "txcount": 101, "keypoolsize": 1000 });
+        for (key, field) in extra.as_object().unwrap() {
+            value[key] = field.clone();
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn reconciled(all_agree: bool) -> ReconciliationResult {
+        ReconciliationResult {
+            getbalances_total: Amount::from_int_btc(50),
+            listunspent_total: Amount::from_int_btc(50),
+            getwalletinfo_total: Amount::from_int_btc(50),
+            all_agree,
+            discrepancies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn fresh_regtest_miner_is_healthy() {
+        let report = WalletHealthReport::from_facts(
+            &info(json!({ "scanning": false })),
+            &[Amount::from_int_btc(50)],
+            &reconciled(true),
+        );
+        assert!(report.is_healthy(), "{:?}", report);
+    }
+
+    #[test]
+    fn each_check_fails_on_its_own() {
+        let utxos = [Amount::from_int_btc(50)];
+        let cases = [
+            (info(json!({ "keypoolsize": 20 })), "keypool_adequate"),
+            (
+                info(json!({ "scanning": { "duration": 3, "progress": 0.5 } })),
+                "not_scanning",
+            ),
+            (
+                info(json!({ "unlocked_until": 0 })),
+                "unlocked_or_unencrypted",
+            ),
+        ];
+        for (info, failed) in cases {
+            let report = WalletHealthReport::from_facts(&info, &utxos, &reconciled(true));
+            assert_eq!(report.failures(), vec![failed]);
+        }
+
+        let dust = [Amount::from_int_btc(50), Amount::from_sat(545)];
+        let report = WalletHealthReport::from_facts(&info(json!({})), &dust, &reconciled(true));
+        assert_eq!(report.failures(), vec!["no_dust_utxos"]);
+
+        let report = WalletHealthReport::from_facts(&info(json!({})), &utxos, &reconciled(false));
+        assert_eq!(report.failures(), vec!["balance_reconciled"]);
+    }
+
+    #[test]
+    fn unlocked_encrypted_wallet_and_dust_limit_pass() {
+        let report = WalletHealthReport::from_facts(
+            &info(json!({ "unlocked_until": 1_700_000_600 })),
+            &[DUST_LIMIT],
+            &reconciled(true),
+        );
+        assert!(report.is_healthy(), "{:?}", report);
+    }
+}
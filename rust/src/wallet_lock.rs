@@ -0,0 +1,260 @@
+// Client-side serialization of mutating wallet RPCs, per wallet name.
+//
+// Bitcoin Core serializes wallet RPCs itself, but our own multi-step logic
+// (retrying a send, bumping a fee, locking coins) can interleave badly when
+// two threads work on the same wallet. `Wallet::call` therefore holds the
+// wallet's lock around every mutating method; read-only calls pass straight
+// through. The lock is chosen by the method name that is actually sent, so a
+// send cannot slip past it under a read-only name.
+use crate::rpc_call;
+use bitcoincore_rpc::RpcApi;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+
+// Wallet RPCs that spend, reserve or re-key funds, or change the wallet's
+// encryption state
+const MUTATING_METHODS: &[&str] = &[
+    "abandontransaction",
+    "bumpfee",
+    "encryptwallet",
+    "fundrawtransaction",
+    "importdescriptors",
+    "lockunspent",
+    "psbtbumpfee",
+    "send",
+    "sendall",
+    "sendmany",
+    "sendtoaddress",
+    "walletcreatefundedpsbt",
+    "walletlock",
+    "walletpassphrase",
+    "walletpassphrasechange",
+];
+
+pub fn is_mutating(method: &str) -> bool {
+    MUTATING_METHODS.contains(&method)
+}
+
+// One wallet's lock: a held flag and a condvar to wait on it
+#[derive(Default)]
+struct WalletMutex {
+    held: Mutex<bool>,
+    released: Condvar,
+}
+
+// Registry of per-wallet locks, created on first use.
+#[derive(Default)]
+pub struct WalletLocks {
+    locks: Mutex<HashMap<String, Arc<WalletMutex>>>,
+}
+
+// Holds a wallet's lock until dropped.
+pub struct WalletGuard {
+    mutex: Arc<WalletMutex>,
+}
+
+impl Drop for WalletGuard {
+    fn drop(&mut self) {
+        let mut held = self.mutex.held.lock().unwrap_or_else(|e| e.into_inner());
+        *held = false;
+        self.mutex.released.notify_one();
+    }
+}
+
+impl WalletLocks {
+    pub fn new() -> Self {
+        WalletLocks::default()
+    }
+
+    // Block until wallet `name` is free, then hold it.
+    pub fn lock(&self, name: &str) -> WalletGuard {
+        let mutex = {
+            let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(locks.entry(name.to_string()).or_default())
+        };
+        {
+            // A panic while holding the lock still releases it in Drop, so a
+            // poisoned std mutex carries no broken state here
+            let mut held = mutex.held.lock().unwrap_or_else(|e| e.into_inner());
+            while *held {
+                held = mutex.released.wait(held).unwrap_or_else(|e| e.into_inner());
+            }
+            *held = true;
+        }
+        WalletGuard { mutex }
+    }
+}
+
+// The process-wide registry used by `Wallet`
+pub fn global_locks() -> &'static WalletLocks {
+    static LOCKS: OnceLock<WalletLocks> = OnceLock::new();
+    LOCKS.get_or_init(WalletLocks::new)
+}
+
+// A wallet client together with the wallet's name, so its mutating calls can
+// be serialized. Helpers that send from a wallet take a `Wallet` and make
+// every wallet RPC through `call`.
+pub struct Wallet<'a, R: RpcApi> {
+    name: String,
+    client: &'a R,
+}
+
+impl<'a, R: RpcApi> Wallet<'a, R> {
+    pub fn new(name: &str, client: &'a R) -> Self {
+        Wallet {
+            name: name.to_string(),
+            client,
+        }
+    }
+
+    // Call RPC `method` on this wallet, holding its lock if the method mutates
+    // the wallet.
+    pub fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        args: &[serde_json::Value],
+    ) -> bitcoincore_rpc::Result<T> {
+        serialized_in(global_locks(), &self.name, method, || {
+            rpc_call(self.client, method, args)
+        })
+    }
+}
+
+fn serialized_in<T>(locks: &WalletLocks, wallet: &str, method: &str, f: impl FnOnce() -> T) -> T {
+    if !is_mutating(method) {
+        return f();
+    }
+    let _guard = locks.lock(wallet);
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    // Request log of a fake node: (wallet, method, "start" | "end")
+    type RequestLog = Arc<Mutex<Vec<(String, String, &'static str)>>>;
+
+    fn fake_rpc(log: &RequestLog, wallet: &str, method: &str) {
+        let entry = |phase| (wallet.to_string(), method.to_string(), phase);
+        log.lock().unwrap().push(entry("start"));
+        thread::sleep(Duration::from_millis(5));
+        log.lock().unwrap().push(entry("end"));
+    }
+
+    fn race(locks: &Arc<WalletLocks>, log: &RequestLog, calls: &[(&str, &str)]) {
+        let handles: Vec<_> = calls
+            .iter()
+            .map(|&(wallet, method)| {
+                let (locks, log) = (Arc::clone(locks), Arc::clone(log));
+                let (wallet, method) = (wallet.to_string(), method.to_string());
+                thread::spawn(move || {
+                    for _ in 0..5 {
+                        serialized_in(&locks, &wallet, &method, || {
+                            fake_rpc(&log, &wallet, &method)
+                        });
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    // Whether any two requests matching `filter` overlapped in the log
+    fn overlapped(log: &RequestLog, filter: impl Fn(&str, &str) -> bool) -> bool {
+        let mut open = 0;
+        for (wallet, method, phase) in log.lock().unwrap().iter() {
+            if !filter(wallet, method) {
+                continue;
+            }
+            match *phase {
+                "start" => open += 1,
+                _ => open -= 1,
+            }
+            if open > 1 {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn classifies_methods() {
+        assert!(is_mutating("sendtoaddress"));
+        assert!(is_mutating("bumpfee"));
+        assert!(is_mutating("lockunspent"));
+        assert!(is_mutating("walletpassphrase"));
+        assert!(!is_mutating("getbalance"));
+        assert!(!is_mutating("listunspent"));
+        assert!(!is_mutating("gettransaction"));
+    }
+
+    #[test]
+    fn racing_sends_on_one_wallet_are_serialized() {
+        let locks = Arc::new(WalletLocks::new());
+        let log = RequestLog::default();
+        race(
+            &locks,
+            &log,
+            &[("Miner", "sendtoaddress"), ("Miner", "sendtoaddress")],
+        );
+        assert_eq!(log.lock().unwrap().len(), 20);
+        assert!(!overlapped(&log, |_, _| true));
+        // Strictly alternating start/end
+        for pair in log.lock().unwrap().chunks(2) {
+            assert_eq!((pair[0].2, pair[1].2), ("start", "end"));
+            assert_eq!(pair[0].1, pair[1].1);
+        }
+    }
+
+    #[test]
+    fn bumpfee_and_send_are_serialized_but_reads_pass_through() {
+        let locks = Arc::new(WalletLocks::new());
+        let log = RequestLog::default();
+        race(
+            &locks,
+            &log,
+            &[
+                ("Miner", "bumpfee"),
+                ("Miner", "sendtoaddress"),
+                ("Miner", "getbalance"),
+                ("Miner", "getbalance"),
+            ],
+        );
+        assert!(!overlapped(&log, |_, method| is_mutating(method)));
+        assert_eq!(log.lock().unwrap().len(), 40);
+    }
+
+    #[test]
+    fn reads_do_not_wait_for_the_lock() {
+        let locks = WalletLocks::new();
+        let _held = locks.lock("Miner");
+        // Would block forever if reads took the lock
+        assert_eq!(serialized_in(&locks, "Miner", "getbalance", || 7), 7);
+    }
+
+    #[test]
+    fn different_wallets_do_not_block_each_other() {
+        let locks = WalletLocks::new();
+        let _miner = locks.lock("Miner");
+        // Would block forever if the wallets shared a lock
+        let _trader = locks.lock("Trader");
+    }
+
+    #[test]
+    fn lock_is_released_after_a_panic() {
+        let locks = Arc::new(WalletLocks::new());
+        let panicking = Arc::clone(&locks);
+        let result = thread::spawn(move || {
+            serialized_in(&panicking, "Miner", "bumpfee", || panic!("node went away"))
+        })
+        .join();
+        assert!(result.is_err());
+        drop(locks.lock("Miner"));
+    }
+}
@@ -0,0 +1,249 @@
+// Opening wallets once and keeping their clients.
+//
+// `WalletManager::open` loads a wallet, creating it as a descriptor wallet
+// when it does not exist yet, waits for its initial scan and keeps the wallet
+// client, so later calls for the same wallet reuse it instead of loading and
+// connecting again.
+use crate::node_connection::NodeConnection;
+use crate::node_rpc::NodeRpc;
+use crate::{wallet_ready, WALLET_SCAN_TIMEOUT};
+use bitcoincore_rpc::Client;
+use log::info;
+use std::collections::HashMap;
+
+pub struct WalletManager<'a> {
+    node: &'a NodeConnection,
+    wallets: HashMap<String, Client>,
+}
+
+impl<'a> WalletManager<'a> {
+    pub fn new(node: &'a NodeConnection) -> Self {
+        WalletManager {
+            node,
+            wallets: HashMap::new(),
+        }
+    }
+
+    // Helper function to create or load wallet `name` and return its client,
+    // the cached one if the wallet is already open
+    pub fn open(&mut self, name: &str) -> bitcoincore_rpc::Result<&Client> {
+        self.open_with(name, |node, name| {
+            load_or_create_wallet(&node.client()?, name, true)?;
+            let wallet = node.wallet_client(name)?;
+            wallet_ready::wait_for_wallet_ready(&wallet, WALLET_SCAN_TIMEOUT)?;
+            Ok(wallet)
+        })
+    }
+
+    // Open `name` with `connect` unless it is already open
+    fn open_with<F>(&mut self, name: &str, connect: F) -> bitcoincore_rpc::Result<&Client>
+    where
+        F: FnOnce(&NodeConnection, &str) -> bitcoincore_rpc::Result<Client>,
+    {
+        if !self.wallets.contains_key(name) {
+            let wallet = connect(self.node, name)?;
+            self.wallets.insert(name.to_string(), wallet);
+        }
+        Ok(&self.wallets[name])
+    }
+
+    // The client of wallet `name` if it is open
+    pub fn get(&self, name: &str) -> Option<&Client> {
+        self.wallets.get(name)
+    }
+}
+
+// Helper function to load wallet `name`, creating it (as a descriptor wallet
+// with `descriptor`) if loading fails. Loading works the same for every wallet
+// type. When creating fails too, loading is tried once more in case another
+// process created the wallet in between.
+pub fn load_or_create_wallet(
+    rpc: &impl NodeRpc,
+    name: &str,
+    descriptor: bool,
+) -> bitcoincore_rpc::Result<()> {
+    if rpc.load_wallet(name).is_ok() {
+        info!("Wallet '{}' loaded successfully", name);
+        return Ok(());
+    }
+    info!("Creating new wallet '{}'", name);
+    match rpc.create_wallet(name, descriptor) {
+        Ok(()) => {
+            info!("Wallet '{}' created successfully", name);
+            Ok(())
+        }
+        // If both creation and loading fail, return the creation error
+        Err(e) => rpc.load_wallet(name).map_err(|_| e).map(|()| {
+            info!(
+                "Wallet '{}' loaded successfully after creation attempt",
+                name
+            );
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_config::RpcConfig;
+    use crate::rpc_helpers::{BlockDetails, MempoolEntry, RawTx};
+    use bitcoincore_rpc::bitcoin::{Amount, BlockHash};
+    use bitcoincore_rpc::jsonrpc::Response;
+    use bitcoincore_rpc::Error;
+    use serde_json::value::RawValue;
+    use std::cell::RefCell;
+
+    // A node whose `loadwallet` answers come from `loads` in order and whose
+    // `createwallet` answers `create`; every call is recorded, and so is the
+    // descriptor flag of every `createwallet`
+    struct MockRpc {
+        loads: RefCell<Vec<bool>>,
+        create: bool,
+        calls: RefCell<Vec<&'static str>>,
+        descriptor_flags: RefCell<Vec<bool>>,
+    }
+
+    impl MockRpc {
+        fn new(loads: &[bool], create: bool) -> Self {
+            MockRpc {
+                loads: RefCell::new(loads.to_vec()),
+                create,
+                calls: RefCell::new(Vec::new()),
+                descriptor_flags: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn answer(&self, call: &'static str, ok: bool) -> bitcoincore_rpc::Result<()> {
+            self.calls.borrow_mut().push(call);
+            match ok {
+                true => Ok(()),
+                false => Err(Error::ReturnedError(format!("{} failed", call))),
+            }
+        }
+    }
+
+    // Calls the tests do not expect fail with an error naming the RPC
+    fn not_mocked<T>(call: &str) -> bitcoincore_rpc::Result<T> {
+        Err(Error::ReturnedError(format!("{} is not mocked", call)))
+    }
+
+    impl NodeRpc for MockRpc {
+        fn wallet(&self, _: &RpcConfig, _: &str) -> bitcoincore_rpc::Result<Self> {
+            not_mocked("wallet")
+        }
+        fn send_batch(
+            &self,
+            method: &str,
+            _: &[Vec<Box<RawValue>>],
+        ) -> bitcoincore_rpc::Result<Vec<Option<Response>>> {
+            not_mocked(method)
+        }
+        fn create_wallet(&self, _: &str, descriptor: bool) -> bitcoincore_rpc::Result<()> {
+            self.descriptor_flags.borrow_mut().push(descriptor);
+            self.answer("createwallet", self.create)
+        }
+        fn load_wallet(&self, _: &str) -> bitcoincore_rpc::Result<()> {
+            let ok = self.loads.borrow_mut().remove(0);
+            self.answer("loadwallet", ok)
+        }
+        fn get_new_address(&self, _: &str) -> bitcoincore_rpc::Result<String> {
+            not_mocked("getnewaddress")
+        }
+        fn generate_to_address(&self, _: u64, _: &str) -> bitcoincore_rpc::Result<Vec<String>> {
+            not_mocked("generatetoaddress")
+        }
+        fn send_to_address(&self, _: &str, _: Amount) -> bitcoincore_rpc::Result<String> {
+            not_mocked("sendtoaddress")
+        }
+        fn get_raw_transaction(&self, _: &str) -> bitcoincore_rpc::Result<RawTx> {
+            not_mocked("getrawtransaction")
+        }
+        fn get_block(&self, _: &BlockHash) -> bitcoincore_rpc::Result<BlockDetails> {
+            not_mocked("getblock")
+        }
+        fn get_mempool_entry(&self, _: &str) -> bitcoincore_rpc::Result<MempoolEntry> {
+            not_mocked("getmempoolentry")
+        }
+        fn get_address_info(&self, _: &str) -> bitcoincore_rpc::Result<serde_json::Value> {
+            not_mocked("getaddressinfo")
+        }
+    }
+
+    fn node() -> NodeConnection {
+        NodeConnection::builder()
+            .url("http://127.0.0.1:18443")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn open_twice_reuses_the_cached_client() {
+        let node = node();
+        let mut wallets = WalletManager::new(&node);
+        let mut connects = 0;
+        for _ in 0..2 {
+            wallets
+                .open_with("Miner", |node, name| {
+                    connects += 1;
+                    node.wallet_client(name)
+                })
+                .unwrap();
+        }
+        assert_eq!(connects, 1);
+        assert!(wallets.get("Miner").is_some());
+        assert!(wallets.get("Trader").is_none());
+    }
+
+    #[test]
+    fn failed_opens_are_not_cached() {
+        let node = node();
+        let mut wallets = WalletManager::new(&node);
+        let refused = wallets.open_with("Miner", |_, _| {
+            Err(Error::ReturnedError("no such wallet".to_string()))
+        });
+        assert!(refused.is_err());
+        assert!(wallets.get("Miner").is_none());
+    }
+
+    #[test]
+    fn existing_wallets_are_loaded() {
+        let rpc = MockRpc::new(&[true], false);
+        load_or_create_wallet(&rpc, "Miner", true).unwrap();
+        assert_eq!(*rpc.calls.borrow(), ["loadwallet"]);
+    }
+
+    #[test]
+    fn missing_wallets_are_created() {
+        let rpc = MockRpc::new(&[false], true);
+        load_or_create_wallet(&rpc, "Miner", true).unwrap();
+        assert_eq!(*rpc.calls.borrow(), ["loadwallet", "createwallet"]);
+        assert_eq!(*rpc.descriptor_flags.borrow(), [true]);
+    }
+
+    #[test]
+    fn both_wallet_types_load_first() {
+        for descriptor in [false, true] {
+            let rpc = MockRpc::new(&[true], true);
+            load_or_create_wallet(&rpc, "Trader", descriptor).unwrap();
+            assert_eq!(*rpc.calls.borrow(), ["loadwallet"]);
+
+            let rpc = MockRpc::new(&[false], true);
+            load_or_create_wallet(&rpc, "Trader", descriptor).unwrap();
+            assert_eq!(*rpc.descriptor_flags.borrow(), [descriptor]);
+        }
+    }
+
+    #[test]
+    fn loading_is_retried_after_a_failed_create() {
+        let rpc = MockRpc::new(&[false, true], false);
+        load_or_create_wallet(&rpc, "Miner", true).unwrap();
+        assert_eq!(
+            *rpc.calls.borrow(),
+            ["loadwallet", "createwallet", "loadwallet"]
+        );
+
+        let rpc = MockRpc::new(&[false, false], false);
+        let err = load_or_create_wallet(&rpc, "Miner", true).unwrap_err();
+        assert!(err.to_string().contains("createwallet failed"), "{}", err);
+    }
+}
@@ -0,0 +1,382 @@
+// `wallets prune`: remove stale wallets left behind by test runs.
+//
+// Candidates are the wallets in `listwalletdir` whose name starts with the
+// given prefix. Each is loaded just long enough to read its newest transaction
+// time (or its birth time when it has none) and its balance, then unloaded
+// again. Wallets idle for longer than `--older-than` days are deleted from the
+// node's wallets directory (`--walletdir`; without it the run is a dry run),
+// but only after the plan has been printed and confirmed (or `--yes`), and
+// never while they hold coins unless `--force-nonzero` is given. Wallets
+// another user of the node has loaded are left alone.
+use crate::arg_value;
+use crate::rpc_config::RpcConfig;
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+// What the prune decision needs to know about one wallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletSummary {
+    pub name: String,
+    // Already loaded by someone else before we looked
+    pub loaded: bool,
+    pub last_activity: Option<u64>,
+    pub balance: Amount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepReason {
+    Recent,
+    NonzeroBalance,
+    Loaded,
+    NoTimestamp,
+}
+
+impl KeepReason {
+    fn describe(self) -> &'static str {
+        match self {
+            KeepReason::Recent => "recently used",
+            KeepReason::NonzeroBalance => "holds coins (use --force-nonzero)",
+            KeepReason::Loaded => "loaded on the node",
+            KeepReason::NoTimestamp => "no transaction or birth time",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrunePlan {
+    pub delete: Vec<String>,
+    pub keep: Vec<(String, KeepReason)>,
+}
+
+// Wallet names from `listwalletdir` that start with `prefix`. An empty prefix
+// would select every wallet on the node and is refused.
+pub fn matching_wallets<'a>(names: &'a [String], prefix: &str) -> Result<Vec<&'a str>, String> {
+    if prefix.is_empty() {
+        return Err("--prefix must not be empty".to_string());
+    }
+    Ok(names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| name.starts_with(prefix))
+        .collect())
+}
+
+// Decide which wallets to delete: idle for at least `older_than_secs` as of
+// `now`, not loaded elsewhere, and empty unless `force_nonzero`.
+pub fn plan_prune(
+    wallets: &[WalletSummary],
+    older_than_secs: u64,
+    now: u64,
+    force_nonzero: bool,
+) -> PrunePlan {
+    let mut plan = PrunePlan::default();
+    for wallet in wallets {
+        let reason = if wallet.loaded {
+            Some(KeepReason::Loaded)
+        } else if wallet.balance > Amount::ZERO && !force_nonzero {
+            Some(KeepReason::NonzeroBalance)
+        } else {
+            match wallet.last_activity {
+                None => Some(KeepReason::NoTimestamp),
+                Some(time) if now.saturating_sub(time) < older_than_secs => {
+                    Some(KeepReason::Recent)
+                }
+                Some(_) => None,
+            }
+        };
+        match reason {
+            Some(reason) => plan.keep.push((wallet.name.clone(), reason)),
+            None => plan.delete.push(wallet.name.clone()),
+        }
+    }
+    plan
+}
+
+// Directory of wallet `name` under `walletdir`, refusing names that could
+// point anywhere else.
+pub fn wallet_path(walletdir: &Path, name: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(name);
+    if name.is_empty()
+        || !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(format!("refusing to delete unsafe wallet path '{}'", name));
+    }
+    Ok(walletdir.join(relative))
+}
+
+// Delete the directory of wallet `name`, but only if it really is a wallet
+// directory (it holds a wallet.dat).
+pub fn delete_wallet_dir(walletdir: &Path, name: &str) -> io::Result<()> {
+    let path =
+        wallet_path(walletdir, name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if !path.join("wallet.dat").is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a wallet directory", path.display()),
+        ));
+    }
+    fs::remove_dir_all(&path)
+}
+
+// Helper function to load wallet `name` if needed, read its activity and
+// balance, and unload it again
+pub fn summarize_wallet(
+    rpc: &impl RpcApi,
+    config: &RpcConfig,
+    name: &str,
+    loaded_wallets: &[String],
+) -> bitcoincore_rpc::Result<WalletSummary> {
+    let loaded = loaded_wallets.iter().any(|loaded| loaded == name);
+    if !loaded {
+        // load_on_startup=false so the node does not remember it
+        rpc.call::<serde_json::Value>("loadwallet", &[json!(name), json!(false)])?;
+    }
+    let summary = (|| {
+        let wallet = crate::get_wallet_client(config, name)?;
+        let newest =
+            wallet.call::<Vec<serde_json::Value>>("listtransactions", &[json!("*"), json!(1)])?;
+        let info = wallet.call::<serde_json::Value>("getwalletinfo", &[])?;
+        let balances = wallet.get_balances()?.mine;
+        Ok(WalletSummary {
+            name: name.to_string(),
+            loaded,
+            last_activity: newest
+                .last()
+                .and_then(|tx| tx["time"].as_u64())
+                .or_else(|| info["birthtime"].as_u64()),
+            balance: balances.trusted + balances.untrusted_pending + balances.immature,
+        })
+    })();
+    if !loaded {
+        rpc.unload_wallet(Some(name))?;
+    }
+    summary
+}
+
+fn confirmed_on_stdin(count: usize) -> io::Result<bool> {
+    info!("Delete {} wallet(s)? Type 'yes' to confirm:", count);
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim() == "yes")
+}
+
+// `wallets prune --prefix <p> --older-than <days> [--walletdir <dir>] [--yes]
+// [--force-nonzero]`; `args` excludes "wallets".
+pub fn run_command(
+    rpc: &impl RpcApi,
+    config: &RpcConfig,
+    args: &[String],
+) -> bitcoincore_rpc::Result<()> {
+    let usage = || {
+        Error::ReturnedError(
+            "usage: wallets prune --prefix <p> --older-than <days> [--walletdir <dir>] \
+             [--yes] [--force-nonzero]"
+                .to_string(),
+        )
+    };
+    if args.first().map(String::as_str) != Some("prune") {
+        return Err(usage());
+    }
+    let prefix = arg_value(args, "--prefix").ok_or_else(usage)?;
+    let older_than_days: u64 = arg_value(args, "--older-than")
+        .ok_or_else(usage)?
+        .parse()
+        .map_err(|e| Error::ReturnedError(format!("invalid --older-than: {}", e)))?;
+    let force_nonzero = args.iter().any(|arg| arg == "--force-nonzero");
+
+    let listed = rpc.call::<serde_json::Value>("listwalletdir", &[])?;
+    let names: Vec<String> = listed["wallets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|wallet| wallet["name"].as_str().map(str::to_string))
+        .collect();
+    let matching = matching_wallets(&names, prefix).map_err(Error::ReturnedError)?;
+    let loaded_wallets = rpc.list_wallets()?;
+    let mut summaries = Vec::new();
+    for name in matching {
+        summaries.push(summarize_wallet(rpc, config, name, &loaded_wallets)?);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let plan = plan_prune(
+        &summaries,
+        older_than_days.saturating_mul(SECS_PER_DAY),
+        now,
+        force_nonzero,
+    );
+    for (name, reason) in &plan.keep {
+        info!("keep    {} ({})", name, reason.describe());
+    }
+    for name in &plan.delete {
+        info!("delete  {}", name);
+    }
+    if plan.delete.is_empty() {
+        info!("Nothing to prune");
+        return Ok(());
+    }
+
+    // The node does not report where its wallets directory is
+    let Some(walletdir) = arg_value(args, "--walletdir") else {
+        info!("Dry run: pass --walletdir <node wallets directory> to delete");
+        return Ok(());
+    };
+    let confirmed = args.iter().any(|arg| arg == "--yes") || confirmed_on_stdin(plan.delete.len())?;
+    if !confirmed {
+        info!("Not confirmed: nothing was deleted");
+        return Ok(());
+    }
+    for name in &plan.delete {
+        delete_wallet_dir(Path::new(walletdir), name)?;
+        info!("Deleted wallet {}", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const WEEK: u64 = 7 * SECS_PER_DAY;
+
+    fn summary(name: &str, idle_secs: Option<u64>, sats: u64) -> WalletSummary {
+        WalletSummary {
+            name: name.to_string(),
+            loaded: false,
+            last_activity: idle_secs.map(|idle| NOW - idle),
+            balance: Amount::from_sat(sats),
+        }
+    }
+
+    #[test]
+    fn prefix_selects_wallets() {
+        let names: Vec<String> = ["Miner", "Miner-test-1", "Miner-test-2", "Trader"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            matching_wallets(&names, "Miner-test-").unwrap(),
+            vec!["Miner-test-1", "Miner-test-2"]
+        );
+        assert!(matching_wallets(&names, "").is_err());
+    }
+
+    #[test]
+    fn plan_deletes_only_stale_empty_wallets() {
+        let mut loaded = summary("Miner-test-loaded", Some(2 * WEEK), 0);
+        loaded.loaded = true;
+        let wallets = vec![
+            summary("Miner-test-old", Some(2 * WEEK), 0),
+            summary("Miner-test-new", Some(SECS_PER_DAY), 0),
+            summary("Miner-test-funded", Some(2 * WEEK), 5_000_000_000),
+            summary("Miner-test-unknown", None, 0),
+            summary("Miner-test-exact", Some(WEEK), 0),
+            loaded,
+        ];
+        let plan = plan_prune(&wallets, WEEK, NOW, false);
+        assert_eq!(plan.delete, vec!["Miner-test-old", "Miner-test-exact"]);
+        assert_eq!(
+            plan.keep,
+            vec![
+                ("Miner-test-new".to_string(), KeepReason::Recent),
+                ("Miner-test-funded".to_string(), KeepReason::NonzeroBalance),
+                ("Miner-test-unknown".to_string(), KeepReason::NoTimestamp),
+                ("Miner-test-loaded".to_string(), KeepReason::Loaded),
+            ]
+        );
+    }
+
+    #[test]
+    fn force_nonzero_allows_funded_wallets() {
+        let wallets = vec![
+            summary("Miner-test-funded", Some(2 * WEEK), 1),
+            summary("Miner-test-recent", Some(60), 1),
+        ];
+        let plan = plan_prune(&wallets, WEEK, NOW, true);
+        assert_eq!(plan.delete, vec!["Miner-test-funded"]);
+        assert_eq!(plan.keep[0].1, KeepReason::Recent);
+    }
+
+    #[test]
+    fn unsafe_wallet_paths_are_refused() {
+        let dir = Path::new("/node/wallets");
+        assert_eq!(
+            wallet_path(dir, "Miner-test-1").unwrap(),
+            dir.join("Miner-test-1")
+        );
+        assert_eq!(wallet_path(dir, "a/b").unwrap(), dir.join("a/b"));
+        for name in ["", "..", "../wallets", "/etc", "a/../../b", "./x"] {
+            assert!(wallet_path(dir, name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn deletes_only_real_wallet_directories() {
+        let walletdir = std::env::temp_dir().join(format!("prune-test-{}", std::process::id()));
+        fs::create_dir_all(walletdir.join("Miner-test-1")).unwrap();
+        fs::write(walletdir.join("Miner-test-1/wallet.dat"), b"").unwrap();
+        fs::create_dir_all(walletdir.join("not-a-wallet")).unwrap();
+
+        delete_wallet_dir(&walletdir, "Miner-test-1").unwrap();
+        assert!(!walletdir.join("Miner-test-1").exists());
+        assert!(delete_wallet_dir(&walletdir, "not-a-wallet").is_err());
+        assert!(walletdir.join("not-a-wallet").exists());
+        fs::remove_dir_all(&walletdir).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn plans_pruning_of_disposable_wallets() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let prefix = format!("Prune-test-{}-", std::process::id());
+        let names: Vec<String> = (0..3).map(|i| format!("{}{}", prefix, i)).collect();
+        for name in &names {
+            create_or_load_wallet(&rpc, &config, name, true).unwrap();
+        }
+        // Give the last wallet a block reward so it must be kept
+        let funded = get_wallet_client(&config, &names[2]).unwrap();
+        let address = funded.get_new_address(None, None).unwrap().assume_checked();
+        mine_blocks_to_address(&rpc, &address, 1).unwrap();
+        for name in &names {
+            rpc.unload_wallet(Some(name)).unwrap();
+        }
+
+        let loaded = rpc.list_wallets().unwrap();
+        let summaries: Vec<WalletSummary> = names
+            .iter()
+            .map(|name| summarize_wallet(&rpc, &config, name, &loaded).unwrap())
+            .collect();
+        // Summarizing leaves the wallets unloaded as it found them
+        let still_loaded = rpc.list_wallets().unwrap();
+        assert!(names.iter().all(|name| !still_loaded.contains(name)));
+
+        let now = summaries
+            .iter()
+            .filter_map(|summary| summary.last_activity)
+            .max()
+            .unwrap();
+        let plan = plan_prune(&summaries, 0, now, false);
+        assert_eq!(plan.delete, names[..2].to_vec());
+        assert_eq!(
+            plan.keep,
+            vec![(names[2].clone(), KeepReason::NonzeroBalance)]
+        );
+    }
+}
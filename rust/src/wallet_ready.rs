@@ -0,0 +1,153 @@
+// Waiting for a freshly created or loaded wallet to finish its initial scan.
+//
+// While a wallet rescans the chain, `getwalletinfo` reports
+// `"scanning": {"duration": N, "progress": P}`, and balances and transaction
+// lists are incomplete. Once the scan is done the field is `false` (or absent
+// on older nodes). `wait_for_wallet_ready` polls until then.
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// The `scanning` field of `getwalletinfo`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum WalletScan {
+    InProgress { duration: u64, progress: f64 },
+    Idle(bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WalletInfo {
+    pub walletname: String,
+    pub txcount: u64,
+    #[serde(default)]
+    pub keypoolsize: u64,
+    // Only present on encrypted wallets; 0 while locked
+    #[serde(default)]
+    pub unlocked_until: Option<u64>,
+    #[serde(default)]
+    pub scanning: Option<WalletScan>,
+}
+
+impl WalletInfo {
+    pub fn is_scanning(&self) -> bool {
+        matches!(self.scanning, Some(WalletScan::InProgress { .. }))
+    }
+}
+
+// Poll `fetch` every `interval` until it reports no scan in progress.
+fn poll_until_ready<F>(
+    timeout: Duration,
+    interval: Duration,
+    mut fetch: F,
+) -> bitcoincore_rpc::Result<WalletInfo>
+where
+    F: FnMut() -> bitcoincore_rpc::Result<WalletInfo>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let info = fetch()?;
+        let Some(WalletScan::InProgress { duration, progress }) = info.scanning else {
+            return Ok(info);
+        };
+        if Instant::now() >= deadline {
+            return Err(Error::ReturnedError(format!(
+                "wallet '{}' is still scanning after {}s ({:.0}% done)",
+                info.walletname,
+                timeout.as_secs(),
+                progress * 100.0
+            )));
+        }
+        info!(
+            "Wallet '{}' is scanning: {:.0}% after {}s",
+            info.walletname,
+            progress * 100.0,
+            duration
+        );
+        std::thread::sleep(interval);
+    }
+}
+
+// Helper function to wait until the wallet behind `rpc` (a wallet client) has
+// finished scanning
+pub fn wait_for_wallet_ready(
+    rpc: &impl RpcApi,
+    timeout: Duration,
+) -> bitcoincore_rpc::Result<WalletInfo> {
+    poll_until_ready(timeout, POLL_INTERVAL, || rpc.call("getwalletinfo", &[]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn info(scanning: serde_json::Value) -> WalletInfo {
+        let mut value = json!({ "walletname": "Miner", "txcount": 3, "keypoolsize": 1000 });
+        if !scanning.is_null() {
+            value["scanning"] = scanning;
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn parses_scanning_states() {
+        assert!(!info(json!(false)).is_scanning());
+        assert!(!info(json!(null)).is_scanning());
+        assert_eq!(info(json!(null)).scanning, None);
+        let scanning = info(json!({ "duration": 12, "progress": 0.25 }));
+        assert!(scanning.is_scanning());
+        assert_eq!(
+            scanning.scanning,
+            Some(WalletScan::InProgress {
+                duration: 12,
+                progress: 0.25
+            })
+        );
+    }
+
+    #[test]
+    fn polls_until_scan_finishes() {
+        let mut states = vec![
+            info(json!(false)),
+            info(json!({ "duration": 2, "progress": 0.9 })),
+            info(json!({ "duration": 1, "progress": 0.4 })),
+        ];
+        let mut calls = 0;
+        let ready = poll_until_ready(Duration::from_secs(60), Duration::ZERO, || {
+            calls += 1;
+            Ok(states.pop().unwrap())
+        })
+        .unwrap();
+        assert!(!ready.is_scanning());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn ready_wallet_returns_immediately() {
+        let ready =
+            poll_until_ready(Duration::ZERO, Duration::ZERO, || Ok(info(json!(false)))).unwrap();
+        assert_eq!(ready.walletname, "Miner");
+    }
+
+    #[test]
+    fn times_out_while_scanning() {
+        let err = poll_until_ready(Duration::ZERO, Duration::ZERO, || {
+            Ok(info(json!({ "duration": 5, "progress": 0.5 })))
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("still scanning"));
+        assert!(err.to_string().contains("50% done"));
+    }
+
+    #[test]
+    fn rpc_errors_are_passed_through() {
+        let result = poll_until_ready(Duration::from_secs(1), Duration::ZERO, || {
+            Err(Error::ReturnedError("wallet not loaded".to_string()))
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,130 @@
+// Keeping wallet RPCs off the node's base client.
+//
+// With exactly one wallet loaded, bitcoind answers a wallet RPC sent to the
+// base URL from that wallet, so a call on the wrong client works by accident
+// until a second wallet is loaded, when it fails with RPC_WALLET_NOT_SPECIFIED.
+// `ProfiledTransport` knows whether its client is for a wallet; on the base
+// client it refuses the wallet methods listed here before they reach the node,
+// with that same error, so the mistake shows up whatever wallets are loaded.
+use bitcoincore_rpc::jsonrpc::error::RpcError;
+
+// What bitcoind returns for a wallet RPC it cannot route to one wallet
+pub const RPC_WALLET_NOT_SPECIFIED: i32 = -19;
+
+// RPCs that bitcoind answers from a wallet. Wallet management (createwallet,
+// loadwallet, unloadwallet, listwallets) names its wallet in the arguments and
+// belongs on the base client.
+const WALLET_METHODS: &[&str] = &[
+    "abandontransaction",
+    "backupwallet",
+    "bumpfee",
+    "dumpprivkey",
+    "encryptwallet",
+    "fundrawtransaction",
+    "getaddressesbylabel",
+    "getaddressinfo",
+    "getbalance",
+    "getbalances",
+    "getnewaddress",
+    "getrawchangeaddress",
+    "getreceivedbyaddress",
+    "gettransaction",
+    "getwalletinfo",
+    "importaddress",
+    "importdescriptors",
+    "importmulti",
+    "importprivkey",
+    "importpubkey",
+    "keypoolrefill",
+    "listaddressgroupings",
+    "listdescriptors",
+    "listlabels",
+    "listlockunspent",
+    "listreceivedbyaddress",
+    "listsinceblock",
+    "listtransactions",
+    "listunspent",
+    "lockunspent",
+    "psbtbumpfee",
+    "rescanblockchain",
+    "send",
+    "sendall",
+    "sendmany",
+    "sendtoaddress",
+    "setlabel",
+    "settxfee",
+    "signmessage",
+    "signrawtransactionwithwallet",
+    "walletcreatefundedpsbt",
+    "walletlock",
+    "walletpassphrase",
+    "walletpassphrasechange",
+    "walletprocesspsbt",
+];
+
+pub fn is_wallet_method(method: &str) -> bool {
+    WALLET_METHODS.contains(&method)
+}
+
+// Check that `method` may be called on the node's base client
+pub fn check_base_call(method: &str) -> Result<(), RpcError> {
+    if !is_wallet_method(method) {
+        return Ok(());
+    }
+    Err(RpcError {
+        code: RPC_WALLET_NOT_SPECIFIED,
+        message: format!(
+            "'{}' is a wallet RPC but was called on the node's base client; call it on a \
+             wallet client (NodeConnection::wallet_client) instead",
+            method
+        ),
+        data: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wallet_methods_are_refused_on_the_base_client() {
+        for method in [
+            "getnewaddress",
+            "sendtoaddress",
+            "getbalance",
+            "getbalances",
+            "listunspent",
+            "gettransaction",
+            "bumpfee",
+        ] {
+            let err = check_base_call(method).unwrap_err();
+            assert_eq!(err.code, RPC_WALLET_NOT_SPECIFIED, "{}", method);
+            assert!(err.message.contains("wallet client"), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn node_methods_are_allowed_on_the_base_client() {
+        for method in [
+            "getblockchaininfo",
+            "getblock",
+            "getrawtransaction",
+            "getmempoolentry",
+            "getrawmempool",
+            "sendrawtransaction",
+            "generatetoaddress",
+            "deriveaddresses",
+            "createwallet",
+            "loadwallet",
+            "unloadwallet",
+            "listwallets",
+        ] {
+            assert!(check_base_call(method).is_ok(), "{}", method);
+        }
+    }
+
+    #[test]
+    fn wallet_methods_are_listed_once_in_order() {
+        assert!(WALLET_METHODS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,269 @@
+// Wallet security management: encryption, passphrase changes and unlocking.
+//
+// Passphrases are checked with `PasswordStrength` before they are handed to
+// the node, so weak ones are rejected without touching the wallet.
+// `wallet-security <wallet> <action>` reads them from stdin, one per line, so
+// they never show up in the process list.
+use crate::arg_value;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde_json::json;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+const MIN_PASSPHRASE_LEN: usize = 12;
+const MIN_ENTROPY_BITS: f64 = 50.0;
+
+// How long to wait for the wallet to answer again after `encryptwallet`.
+const ENCRYPT_RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const ENCRYPT_RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+// How long `wallet-security <wallet> unlock` unlocks for without --timeout
+const DEFAULT_UNLOCK_SECS: u64 = 60;
+
+// Estimated strength of a passphrase, based on its length and the character
+// classes it draws from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PasswordStrength {
+    pub length: usize,
+    pub entropy_bits: f64,
+}
+
+impl PasswordStrength {
+    pub fn assess(passphrase: &str) -> Self {
+        let has = |pred: fn(&char) -> bool| passphrase.chars().any(|c| pred(&c));
+        let mut pool = 0u32;
+        if has(char::is_ascii_lowercase) {
+            pool += 26;
+        }
+        if has(char::is_ascii_uppercase) {
+            pool += 26;
+        }
+        if has(char::is_ascii_digit) {
+            pool += 10;
+        }
+        if has(char::is_ascii_punctuation) || has(|c| *c == ' ') {
+            pool += 33;
+        }
+        if has(|c| !c.is_ascii()) {
+            pool += 100;
+        }
+
+        let length = passphrase.chars().count();
+        let entropy_bits = if pool == 0 {
+            0.0
+        } else {
+            length as f64 * f64::from(pool).log2()
+        };
+        PasswordStrength {
+            length,
+            entropy_bits,
+        }
+    }
+
+    // Reject passphrases shorter than 12 characters or below 50 bits of entropy.
+    pub fn validate(passphrase: &str) -> Result<Self, String> {
+        let strength = Self::assess(passphrase);
+        if strength.length < MIN_PASSPHRASE_LEN {
+            return Err(format!(
+                "passphrase is {} characters long, at least {} are required",
+                strength.length, MIN_PASSPHRASE_LEN
+            ));
+        }
+        if strength.entropy_bits < MIN_ENTROPY_BITS {
+            return Err(format!(
+                "passphrase has an estimated {:.1} bits of entropy, at least {} are required",
+                strength.entropy_bits, MIN_ENTROPY_BITS
+            ));
+        }
+        Ok(strength)
+    }
+}
+
+fn validate_passphrase(passphrase: &str) -> bitcoincore_rpc::Result<()> {
+    PasswordStrength::validate(passphrase)
+        .map(|_| ())
+        .map_err(|e| Error::ReturnedError(format!("weak passphrase: {}", e)))
+}
+
+// Helper function to encrypt a wallet. Older Bitcoin Core versions shut the
+// node down after encrypting, so wait until the wallet answers RPCs again.
+pub fn encrypt_wallet(rpc: &impl RpcApi, passphrase: &str) -> bitcoincore_rpc::Result<()> {
+    validate_passphrase(passphrase)?;
+    rpc.call::<serde_json::Value>("encryptwallet", &[json!(passphrase)])?;
+
+    let deadline = Instant::now() + ENCRYPT_RECONNECT_TIMEOUT;
+    loop {
+        match rpc.call::<serde_json::Value>("getwalletinfo", &[]) {
+            Ok(_) => return Ok(()),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => std::thread::sleep(ENCRYPT_RECONNECT_INTERVAL),
+        }
+    }
+}
+
+// Helper function to change the passphrase of an encrypted wallet
+pub fn change_passphrase(rpc: &impl RpcApi, old: &str, new: &str) -> bitcoincore_rpc::Result<()> {
+    validate_passphrase(new)?;
+    rpc.call::<serde_json::Value>("walletpassphrasechange", &[json!(old), json!(new)])?;
+    Ok(())
+}
+
+// Helper function to unlock an encrypted wallet for `timeout_secs` seconds
+pub fn unlock_wallet(
+    rpc: &impl RpcApi,
+    passphrase: &str,
+    timeout_secs: u64,
+) -> bitcoincore_rpc::Result<()> {
+    rpc.call::<serde_json::Value>(
+        "walletpassphrase",
+        &[json!(passphrase), json!(timeout_secs)],
+    )?;
+    Ok(())
+}
+
+// Helper function to read the next line of `input` as a passphrase named `what`
+fn read_passphrase(
+    input: &mut impl BufRead,
+    what: &str,
+) -> bitcoincore_rpc::Result<Zeroizing<String>> {
+    let mut line = Zeroizing::new(String::new());
+    input.read_line(&mut line)?;
+    let passphrase = Zeroizing::new(line.trim_end_matches(['\r', '\n']).to_string());
+    if passphrase.is_empty() {
+        return Err(Error::ReturnedError(format!("no {} on stdin", what)));
+    }
+    Ok(passphrase)
+}
+
+// `wallet-security <wallet> <encrypt|change-passphrase|unlock> [--timeout <secs>]`
+// on `wallet`; `args` starts at the action. The passphrases come from `input`,
+// the old one first when changing it.
+pub fn run_command(
+    wallet: &impl RpcApi,
+    args: &[String],
+    input: &mut impl BufRead,
+) -> bitcoincore_rpc::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("encrypt") => {
+            encrypt_wallet(wallet, &read_passphrase(input, "passphrase")?)?;
+            info!("Wallet encrypted");
+        }
+        Some("change-passphrase") => {
+            let old = read_passphrase(input, "old passphrase")?;
+            change_passphrase(wallet, &old, &read_passphrase(input, "new passphrase")?)?;
+            info!("Wallet passphrase changed");
+        }
+        Some("unlock") => {
+            let timeout_secs = match arg_value(args, "--timeout") {
+                Some(secs) => secs.parse().map_err(|_| {
+                    Error::ReturnedError(format!(
+                        "invalid --timeout value '{}': expected seconds",
+                        secs
+                    ))
+                })?,
+                None => DEFAULT_UNLOCK_SECS,
+            };
+            unlock_wallet(wallet, &read_passphrase(input, "passphrase")?, timeout_secs)?;
+            info!("Wallet unlocked for {}s", timeout_secs);
+        }
+        _ => {
+            return Err(Error::ReturnedError(
+                "usage: wallet-security <wallet> <encrypt|change-passphrase|unlock> \
+                 [--timeout <secs>]"
+                    .to_string(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_short_passphrase() {
+        let err = PasswordStrength::validate("Ab1!xyz").unwrap_err();
+        assert!(err.contains("at least 12"));
+    }
+
+    #[test]
+    fn rejects_low_entropy_passphrase() {
+        // 12 digits: 12 * log2(10) ~ 39.9 bits
+        let err = PasswordStrength::validate("123456789012").unwrap_err();
+        assert!(err.contains("entropy"));
+    }
+
+    #[test]
+    fn accepts_mixed_passphrase() {
+        let strength = PasswordStrength::validate("correct-Horse-42").unwrap();
+        assert_eq!(strength.length, 16);
+        assert!(strength.entropy_bits > 100.0);
+    }
+
+    #[test]
+    fn long_lowercase_passphrase_is_enough() {
+        // 12 lowercase letters: 12 * log2(26) ~ 56.4 bits
+        assert!(PasswordStrength::validate("abcdefghijkl").is_ok());
+    }
+
+    // A wallet that fails every call, for paths that must not reach the node
+    struct NoWallet;
+
+    impl RpcApi for NoWallet {
+        fn call<T: for<'a> serde::de::Deserialize<'a>>(
+            &self,
+            cmd: &str,
+            _: &[serde_json::Value],
+        ) -> bitcoincore_rpc::Result<T> {
+            Err(Error::ReturnedError(format!("unexpected call to {}", cmd)))
+        }
+    }
+
+    fn run(action: &str, stdin: &str) -> String {
+        let args = [action.to_string()];
+        run_command(&NoWallet, &args, &mut stdin.as_bytes())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn command_checks_passphrases_before_calling_the_node() {
+        assert!(run("encrypt", "Ab1!xyz\n").contains("weak passphrase"));
+        assert!(run("encrypt", "").contains("no passphrase on stdin"));
+        let err = run("change-passphrase", "old-Passphrase-1\n");
+        assert!(err.contains("no new passphrase"), "{}", err);
+        assert!(run("rotate", "").contains("usage"));
+        // A strong passphrase gets as far as the node
+        assert!(run("unlock", "old-Passphrase-1\r\n").contains("walletpassphrase"));
+    }
+
+    #[test]
+    #[ignore = "requires a running regtest bitcoind"]
+    fn encrypt_change_and_unlock() {
+        use crate::rpc_config::RpcConfig;
+        use crate::{create_or_load_wallet, get_wallet_client};
+
+        let config = RpcConfig::load().unwrap();
+        let rpc = config.client().unwrap();
+        let name = format!("Encrypt-test-{}", std::process::id());
+        create_or_load_wallet(&rpc, &config, &name, true).unwrap();
+        let wallet = get_wallet_client(&config, &name).unwrap();
+
+        let first = "first-Passphrase-1";
+        let second = "second-Passphrase-2";
+        encrypt_wallet(&wallet, first).unwrap();
+        assert!(change_passphrase(&wallet, first, "weak").is_err());
+        change_passphrase(&wallet, first, second).unwrap();
+        assert!(unlock_wallet(&wallet, first, 10).is_err());
+        unlock_wallet(&wallet, second, 10).unwrap();
+
+        let info = wallet
+            .call::<serde_json::Value>("getwalletinfo", &[])
+            .unwrap();
+        assert!(info["unlocked_until"].as_u64().unwrap() > 0);
+        rpc.unload_wallet(Some(&name)).unwrap();
+    }
+}
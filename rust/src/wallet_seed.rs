@@ -0,0 +1,262 @@
+// Reproducible Miner and Trader wallets from a fixed BIP-39 mnemonic.
+//
+// With `--seed-mnemonic <words>` (or `--seed-file <path>` holding the words)
+// each wallet is created blank and given the BIP-84 descriptors of its own
+// account of the mnemonic's seed, Miner account 0 and Trader account 1, so
+// every fresh regtest node hands out the same addresses. The first receive
+// address of each wallet is recorded in a state file the first time and
+// checked on every later run. The mnemonic, seed and descriptors are zeroized
+// once used and never logged; prefer `--seed-file`, as command-line words are
+// visible to other processes.
+use crate::descriptor_wallet::{bip84_address, create_bip84_wallet, verify_address_derivation};
+use crate::node_rpc::NodeRpc;
+use crate::rpc_config::RpcConfig;
+use crate::{wallet_ready, WALLET_SCAN_TIMEOUT};
+use bip39::Mnemonic;
+use bitcoincore_rpc::bitcoin::Network;
+use bitcoincore_rpc::{Error, RpcApi};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+pub const SEED_STATE_PATH: &str = "seedstate.json";
+pub const MINER_ACCOUNT: u32 = 0;
+pub const TRADER_ACCOUNT: u32 = 1;
+
+// The BIP-39 seed of a mnemonic, without a passphrase
+pub struct WalletSeed {
+    seed: Zeroizing<[u8; 64]>,
+}
+
+impl fmt::Debug for WalletSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("WalletSeed(..)")
+    }
+}
+
+impl WalletSeed {
+    // Errors name the offending word's position, never the word itself.
+    pub fn from_mnemonic(words: &str) -> Result<Self, String> {
+        let mnemonic =
+            Mnemonic::parse(words).map_err(|e| format!("invalid seed mnemonic: {}", e))?;
+        Ok(WalletSeed {
+            seed: Zeroizing::new(mnemonic.to_seed("")),
+        })
+    }
+
+    // First address of the external chain of `account`
+    pub fn first_receive_address(&self, account: u32) -> Result<String, String> {
+        bip84_address(&*self.seed, Network::Regtest, account, false, 0)
+    }
+}
+
+// The seed of `--seed-mnemonic <words>` or `--seed-file <path>`; at most one of them.
+pub fn read_seed(
+    mnemonic: Option<&str>,
+    file: Option<&Path>,
+) -> Result<Option<WalletSeed>, String> {
+    match (mnemonic, file) {
+        (Some(_), Some(_)) => {
+            Err("--seed-mnemonic and --seed-file are mutually exclusive".to_string())
+        }
+        (Some(words), None) => WalletSeed::from_mnemonic(words).map(Some),
+        (None, Some(path)) => {
+            let words = Zeroizing::new(
+                fs::read_to_string(path)
+                    .map_err(|e| format!("cannot read seed file '{}': {}", path.display(), e))?,
+            );
+            WalletSeed::from_mnemonic(words.trim()).map(Some)
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+// First receive address of every seeded wallet, by wallet name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct SeedState {
+    first_addresses: BTreeMap<String, String>,
+}
+
+// Check `address` against the one recorded for `wallet` in the state file at
+// `path`, recording it if there is none yet. Returns whether it was recorded.
+pub fn check_first_address(path: &Path, wallet: &str, address: &str) -> Result<bool, String> {
+    let mut state: SeedState = match fs::read_to_string(path) {
+        Ok(text) => {
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => SeedState::default(),
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e)),
+    };
+    match state.first_addresses.get(wallet) {
+        Some(recorded) if recorded == address => Ok(false),
+        Some(recorded) => Err(format!(
+            "wallet '{}' derives {} first, but {} records {}; \
+             the mnemonic differs from the one the state was recorded with",
+            wallet,
+            address,
+            path.display(),
+            recorded
+        )),
+        None => {
+            state
+                .first_addresses
+                .insert(wallet.to_string(), address.to_string());
+            let json = serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?;
+            fs::write(path, json + "\n")
+                .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+            Ok(true)
+        }
+    }
+}
+
+// Helper function to load wallet `name`, or create it blank with the
+// descriptors of `account` of `seed`, and check that its first receive
+// address is the seed's and the one in the state file. Returns that address.
+pub fn create_or_load_seeded_wallet<R: NodeRpc + RpcApi>(
+    rpc: &R,
+    config: &RpcConfig,
+    name: &str,
+    seed: &WalletSeed,
+    account: u32,
+) -> bitcoincore_rpc::Result<String> {
+    let loaded = rpc.list_wallets()?.iter().any(|wallet| wallet == name);
+    if loaded || NodeRpc::load_wallet(rpc, name).is_ok() {
+        info!("Wallet '{}' loaded successfully", name);
+    } else {
+        info!("Creating new wallet '{}' from the seed mnemonic", name);
+        create_bip84_wallet(rpc, config, name, &*seed.seed, Network::Regtest, account)?;
+    }
+    let wallet = rpc.wallet(config, name)?;
+    wallet_ready::wait_for_wallet_ready(&wallet, WALLET_SCAN_TIMEOUT)?;
+
+    let expected = seed
+        .first_receive_address(account)
+        .map_err(Error::ReturnedError)?;
+    if !verify_address_derivation(rpc, &wallet, &[&expected])? {
+        return Err(Error::ReturnedError(format!(
+            "wallet '{}' was not created from this mnemonic; \
+             use a fresh node or other wallet names",
+            name
+        )));
+    }
+    check_first_address(Path::new(SEED_STATE_PATH), name, &expected)
+        .map_err(Error::ReturnedError)?;
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_wallet_client;
+
+    // The BIP-84 test vector mnemonic
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon \
+                            abandon abandon abandon abandon abandon about";
+
+    fn state_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn mnemonic_gives_the_bip39_seed() {
+        let seed = WalletSeed::from_mnemonic(MNEMONIC).unwrap();
+        let hex: String = seed.seed.iter().map(|b| format!("{:02x}", b)).collect();
+        assert!(hex.starts_with("5eb00bbddcf069084889a8ab9155568165f5c453"));
+        assert!(hex.ends_with("0f20ad3d8d48b2d2ce9e38e4"));
+    }
+
+    #[test]
+    fn miner_and_trader_get_fixed_distinct_addresses() {
+        let seed = WalletSeed::from_mnemonic(MNEMONIC).unwrap();
+        let again = WalletSeed::from_mnemonic(MNEMONIC).unwrap();
+        let miner = seed.first_receive_address(MINER_ACCOUNT).unwrap();
+        assert!(miner.starts_with("bcrt1q"), "{}", miner);
+        assert_eq!(miner, again.first_receive_address(MINER_ACCOUNT).unwrap());
+        assert_ne!(miner, seed.first_receive_address(TRADER_ACCOUNT).unwrap());
+    }
+
+    #[test]
+    fn bad_mnemonics_are_rejected_without_echoing_words() {
+        let wrong_checksum = MNEMONIC.replace("about", "abandon");
+        let err = WalletSeed::from_mnemonic(&wrong_checksum).unwrap_err();
+        assert!(err.contains("checksum"), "{}", err);
+        let err = WalletSeed::from_mnemonic(&MNEMONIC.replacen("abandon", "zzzzq", 1)).unwrap_err();
+        assert!(err.contains("unknown word"), "{}", err);
+        assert!(!err.contains("zzzzq"), "{}", err);
+        let seed = WalletSeed::from_mnemonic(MNEMONIC).unwrap();
+        assert_eq!(format!("{:?}", seed), "WalletSeed(..)");
+    }
+
+    #[test]
+    fn seed_comes_from_one_flag_or_the_file() {
+        assert!(read_seed(None, None).unwrap().is_none());
+        let path = state_path("seed-words");
+        fs::write(&path, format!("  {}\n", MNEMONIC)).unwrap();
+        let from_file = read_seed(None, Some(&path)).unwrap();
+        let both = read_seed(Some(MNEMONIC), Some(&path));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            from_file.unwrap().first_receive_address(0),
+            WalletSeed::from_mnemonic(MNEMONIC)
+                .unwrap()
+                .first_receive_address(0)
+        );
+        assert!(both.is_err());
+    }
+
+    #[test]
+    fn state_file_records_then_checks_first_addresses() {
+        let path = state_path("seed-state");
+        assert!(check_first_address(&path, "Miner", "bcrt1qaaa").unwrap());
+        assert!(check_first_address(&path, "Trader", "bcrt1qbbb").unwrap());
+        assert!(!check_first_address(&path, "Miner", "bcrt1qaaa").unwrap());
+        let err = check_first_address(&path, "Trader", "bcrt1qccc").unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(err.contains("bcrt1qbbb"), "{}", err);
+    }
+
+    // Seed the same wallet on the main node and on a second fresh regtest node
+    // (`BITCOIN_RPC_PEER_URL`, same credentials): both hand out the same
+    // addresses, the ones derived locally.
+    #[test]
+    #[ignore = "requires two fresh regtest bitcoinds"]
+    fn seeded_wallets_match_across_nodes() {
+        let config = RpcConfig::load().unwrap();
+        let peer_config = RpcConfig {
+            url: std::env::var("BITCOIN_RPC_PEER_URL")
+                .unwrap_or_else(|_| "http://127.0.0.1:18453".to_string()),
+            ..config.clone()
+        };
+        let seed = WalletSeed::from_mnemonic(MNEMONIC).unwrap();
+        let name = format!("Seeded-{}", std::process::id());
+        let mut addresses = Vec::new();
+        for config in [&config, &peer_config] {
+            let rpc = config.client().unwrap();
+            create_bip84_wallet(
+                &rpc,
+                config,
+                &name,
+                &*seed.seed,
+                Network::Regtest,
+                TRADER_ACCOUNT,
+            )
+            .unwrap();
+            let wallet = get_wallet_client(config, &name).unwrap();
+            let derived: Vec<String> = (0..3)
+                .map(|_| NodeRpc::get_new_address(&wallet, "").unwrap())
+                .collect();
+            rpc.unload_wallet(Some(&name)).unwrap();
+            addresses.push(derived);
+        }
+        assert_eq!(addresses[0], addresses[1]);
+        assert_eq!(
+            addresses[0][0],
+            seed.first_receive_address(TRADER_ACCOUNT).unwrap()
+        );
+    }
+}
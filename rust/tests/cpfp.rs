@@ -0,0 +1,95 @@
+// Integration tests for three-generation CPFP packages.
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::RpcApi;
+use rust::cpfp::{
+    build_chain, check_chain_relatives, check_confirmed_together, mempool_relatives,
+    package_fee_rate, render_package,
+};
+use rust::rpc_config::RpcConfig;
+use rust::rpc_helpers::{
+    create_or_load_wallet, get_transaction, get_wallet_client, mine_blocks_to_address,
+};
+
+// Grandparent, parent and child, in sat/vB
+const FEE_RATES: [f64; 3] = [1.0, 5.0, 25.0];
+
+#[test]
+#[ignore = "requires a running regtest bitcoind"]
+fn three_generations_confirm_as_one_package() {
+    let config = RpcConfig::load().unwrap();
+    let rpc = config.client().unwrap();
+    create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+    let miner = get_wallet_client(&config, "Miner").unwrap();
+    let mining_address = miner.get_new_address(None, None).unwrap().assume_checked();
+    mine_blocks_to_address(&rpc, &mining_address, 101).unwrap();
+
+    let funding = miner
+        .list_unspent(Some(1), None, None, None, None)
+        .unwrap()
+        .into_iter()
+        .find(|utxo| utxo.amount >= Amount::from_int_btc(1))
+        .unwrap();
+    let recipient = miner.get_new_address(None, None).unwrap().assume_checked();
+    let chain = build_chain(
+        &miner,
+        (&funding.txid.to_string(), funding.vout),
+        (&recipient.to_string(), Amount::from_btc(0.1).unwrap()),
+        &FEE_RATES,
+    )
+    .unwrap();
+    assert_eq!(chain.len(), 3);
+    // A header, one line per generation and the package total
+    let table = render_package(&chain);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 5);
+    for (line, tx) in lines[1..4].iter().zip(&chain) {
+        assert!(line.contains(&tx.txid), "{}", table);
+    }
+    assert!(
+        lines[4].trim_start().starts_with("all  package"),
+        "{}",
+        table
+    );
+    for (tx, rate) in chain.iter().zip(FEE_RATES) {
+        // The fee is rounded up to a whole satoshi
+        assert!(
+            (rate..rate + 0.1).contains(&tx.fee_rate()),
+            "{} pays {} sat/vB, expected {}",
+            tx.txid,
+            tx.fee_rate(),
+            rate
+        );
+    }
+
+    let counts: Vec<(usize, usize)> = chain
+        .iter()
+        .map(|tx| mempool_relatives(&rpc, &tx.txid).unwrap())
+        .collect();
+    assert_eq!(counts, vec![(0, 2), (1, 1), (2, 0)]);
+    check_chain_relatives(&counts).unwrap();
+
+    // The node's view of the child's ancestor package matches ours
+    let child = rpc
+        .call::<serde_json::Value>("getmempoolentry", &[serde_json::json!(chain[2].txid)])
+        .unwrap();
+    let ancestor_fee = Amount::from_btc(child["fees"]["ancestor"].as_f64().unwrap()).unwrap();
+    let ancestor_rate = ancestor_fee.to_sat() as f64 / child["ancestorsize"].as_f64().unwrap();
+    assert!((ancestor_rate - package_fee_rate(&chain)).abs() < 0.001);
+
+    let mined = mine_blocks_to_address(&rpc, &mining_address, 1).unwrap();
+    let blocks: Vec<(String, Option<String>)> = chain
+        .iter()
+        .map(|tx| {
+            (
+                tx.txid.clone(),
+                get_transaction(&rpc, &tx.txid).unwrap().blockhash,
+            )
+        })
+        .collect();
+    let blocks: Vec<(&str, Option<&str>)> = blocks
+        .iter()
+        .map(|(txid, block)| (txid.as_str(), block.as_deref()))
+        .collect();
+    let block = check_confirmed_together(&blocks).unwrap();
+    assert_eq!(block, mined[0]);
+}
@@ -0,0 +1,41 @@
+// Round trip of an encrypted descriptor backup through a regtest node.
+use bitcoincore_rpc::RpcApi;
+use rust::descriptor_backup::{backup_descriptors, restore_from_descriptor_backup};
+use rust::rpc_config::RpcConfig;
+use rust::rpc_helpers::{create_or_load_wallet, get_wallet_client};
+
+#[test]
+#[ignore = "requires a running regtest bitcoind"]
+fn restored_wallet_derives_the_same_addresses() {
+    let config = RpcConfig::load().unwrap();
+    let rpc = config.client().unwrap();
+    let source_name = format!("Backup-Source-{}", std::process::id());
+    let restored_name = format!("Backup-Restored-{}", std::process::id());
+    create_or_load_wallet(&rpc, &config, &source_name, true).unwrap();
+    let source = get_wallet_client(&config, &source_name).unwrap();
+    source.get_new_address(None, None).unwrap();
+
+    let path = std::env::temp_dir().join(format!("{}.json", source_name));
+    let count = backup_descriptors(&source, &path, "correct horse").unwrap();
+    assert!(count > 0);
+    assert!(
+        restore_from_descriptor_backup(&rpc, &config, &path, "wrong horse", &restored_name)
+            .is_err()
+    );
+    restore_from_descriptor_backup(&rpc, &config, &path, "correct horse", &restored_name).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let restored = get_wallet_client(&config, &restored_name).unwrap();
+    for _ in 0..3 {
+        assert_eq!(
+            restored.get_new_address(None, None).unwrap(),
+            source.get_new_address(None, None).unwrap()
+        );
+    }
+    let info = restored
+        .call::<serde_json::Value>("getwalletinfo", &[])
+        .unwrap();
+    assert_eq!(info["private_keys_enabled"], true);
+    rpc.unload_wallet(Some(&source_name)).unwrap();
+    rpc.unload_wallet(Some(&restored_name)).unwrap();
+}
@@ -0,0 +1,119 @@
+// Integration tests for `fees preview`.
+use bitcoincore_rpc::bitcoin::Amount;
+use bitcoincore_rpc::RpcApi;
+use rust::fee_preview::{
+    locked_outputs, parse_rates, preview_fees, preview_from_funded, render_table, FeePreview,
+    PREVIEW_RECIPIENT,
+};
+use rust::rpc_config::RpcConfig;
+use rust::rpc_helpers::{create_or_load_wallet, get_wallet_client, mine_blocks_to_address};
+use serde_json::json;
+
+// A `walletcreatefundedpsbt` result and its decoded PSBT: one 50 BTC reward
+// paying 20 BTC with the change at `changepos`
+fn funded(fee: f64, changepos: i64) -> (serde_json::Value, serde_json::Value) {
+    let change = json!({ "value": 30.0 - fee, "scriptPubKey": { "address": "bcrt1qchange" } });
+    let payment = json!({ "value": 20.0, "scriptPubKey": { "address": PREVIEW_RECIPIENT } });
+    let vout = match changepos {
+        -1 => vec![payment],
+        0 => vec![change, payment],
+        _ => vec![payment, change],
+    };
+    (
+        json!({ "psbt": "cHNidP8B", "fee": fee, "changepos": changepos }),
+        json!({ "tx": { "vin": [{ "txid": "aa", "vout": 0 }], "vout": vout } }),
+    )
+}
+
+#[test]
+fn previews_read_fee_change_and_inputs() {
+    let (result, decoded) = funded(0.00000141, 1);
+    assert_eq!(
+        preview_from_funded(1.0, &result, &decoded).unwrap(),
+        FeePreview {
+            fee_rate: 1.0,
+            fee: Amount::from_sat(141),
+            change: Some(Amount::from_sat(2_999_999_859)),
+            inputs: 1,
+        }
+    );
+    let (result, decoded) = funded(0.0000141, 0);
+    let preview = preview_from_funded(10.0, &result, &decoded).unwrap();
+    assert_eq!(preview.change, Some(Amount::from_sat(2_999_998_590)));
+    let (result, decoded) = funded(0.0000141, -1);
+    assert_eq!(
+        preview_from_funded(10.0, &result, &decoded).unwrap().change,
+        None
+    );
+    assert!(preview_from_funded(1.0, &json!({}), &decoded).is_err());
+}
+
+#[test]
+fn table_has_a_row_per_rate() {
+    let previews: Vec<FeePreview> = [(1.0, 141), (25.0, 3_525)]
+        .into_iter()
+        .map(|(fee_rate, sats)| FeePreview {
+            fee_rate,
+            fee: Amount::from_sat(sats),
+            change: Some(Amount::from_sat(3_000_000_000 - sats)),
+            inputs: 1,
+        })
+        .chain([FeePreview {
+            fee_rate: 2.5,
+            fee: Amount::from_sat(353),
+            change: None,
+            inputs: 3,
+        }])
+        .collect();
+    assert_eq!(
+        render_table(&previews),
+        [
+            "    sat/vB       fee BTC      change BTC  inputs",
+            "         1    0.00000141     29.99999859       1",
+            "        25    0.00003525     29.99996475       1",
+            "       2.5    0.00000353               -       3",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn rates_must_be_positive_numbers() {
+    assert_eq!(parse_rates("1,5, 10,25").unwrap(), [1.0, 5.0, 10.0, 25.0]);
+    assert_eq!(parse_rates("2.5").unwrap(), [2.5]);
+    for bad in ["", "1,,5", "0", "-1", "fast", "NaN", "inf"] {
+        assert!(parse_rates(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+#[ignore = "requires a running regtest bitcoind"]
+fn previews_leave_no_trace() {
+    let config = RpcConfig::load().unwrap();
+    let rpc = config.client().unwrap();
+    let name = format!("Fee-preview-{}", std::process::id());
+    create_or_load_wallet(&rpc, &config, &name, true).unwrap();
+    let wallet = get_wallet_client(&config, &name).unwrap();
+    let address = wallet.get_new_address(None, None).unwrap().assume_checked();
+    mine_blocks_to_address(&rpc, &address, 102).unwrap();
+    // One coin locked by hand, which the preview must neither use nor release
+    let reward = &wallet.list_unspent(None, None, None, None, None).unwrap()[0];
+    let outpoint = json!([{ "txid": reward.txid.to_string(), "vout": reward.vout }]);
+    assert!(wallet
+        .call::<bool>("lockunspent", &[json!(false), outpoint])
+        .unwrap());
+
+    let locked = locked_outputs(&wallet).unwrap();
+    let mempool = rpc.get_raw_mempool().unwrap();
+    let txcount = wallet.get_wallet_info().unwrap().tx_count;
+    let rates = [1.0, 5.0, 10.0, 25.0];
+    let previews =
+        preview_fees(&wallet, PREVIEW_RECIPIENT, Amount::from_int_btc(20), &rates).unwrap();
+
+    assert_eq!(previews.len(), rates.len());
+    assert!(previews.windows(2).all(|pair| pair[0].fee < pair[1].fee));
+    assert_eq!(locked_outputs(&wallet).unwrap(), locked);
+    assert_eq!(rpc.get_raw_mempool().unwrap(), mempool);
+    assert_eq!(wallet.get_wallet_info().unwrap().tx_count, txcount);
+    rpc.unload_wallet(Some(&name)).unwrap();
+}
@@ -0,0 +1,211 @@
+// Integration tests against the library's public helpers.
+use bitcoincore_rpc::bitcoin::{self, Amount};
+use bitcoincore_rpc::json::AddressType;
+use bitcoincore_rpc::RpcApi;
+use rust::rpc_config::RpcConfig;
+use rust::rpc_helpers::{
+    create_or_load_wallet, get_transaction, get_wallet_client, mine_blocks, mine_blocks_to_address,
+    BlockDetails, MempoolEntry, RawTx,
+};
+use serde_json::json;
+
+#[test]
+fn verbose_transactions_deserialize() {
+    let tx: RawTx = serde_json::from_value(json!({
+        "txid": "aa".repeat(32),
+        "hash": "bb".repeat(32),
+        "version": 2,
+        "size": 222,
+        "vsize": 141,
+        "weight": 561,
+        "locktime": 101,
+        "vin": [{
+            "txid": "cc".repeat(32),
+            "vout": 0,
+            "scriptSig": { "asm": "", "hex": "" },
+            "txinwitness": ["30440220", "02"],
+            "sequence": 4294967293u32
+        }],
+        "vout": [
+            {
+                "value": 20.0,
+                "n": 0,
+                "scriptPubKey": {
+                    "asm": "0 751e76e8199196d454941c45d1b3a323f1433bd6",
+                    "hex": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                    "address": "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080",
+                    "type": "witness_v0_keyhash"
+                }
+            },
+            {
+                "value": 29.9999859,
+                "n": 1,
+                "scriptPubKey": {
+                    "hex": "00140000000000000000000000000000000000000000",
+                    "addresses": ["bcrt1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq"],
+                    "type": "witness_v0_keyhash"
+                }
+            }
+        ],
+        "hex": "02000000",
+        "blockhash": "dd".repeat(32),
+        "confirmations": 1,
+        "time": 1_700_000_600,
+        "blocktime": 1_700_000_600
+    }))
+    .unwrap();
+    assert_eq!(
+        (tx.size, tx.vsize, tx.weight, tx.locktime),
+        (222, 141, 561, 101)
+    );
+    assert!(!tx.is_coinbase());
+    assert_eq!(tx.vin[0].vout, Some(0));
+    assert_eq!(tx.vout[1].value, Amount::from_sat(2_999_998_590));
+    assert_eq!(
+        tx.vout[0].address(),
+        Some("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080")
+    );
+    // Nodes before v22 list the address in `addresses`
+    assert_eq!(
+        tx.vout[1].address(),
+        Some("bcrt1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq")
+    );
+    assert_eq!(tx.confirmations, Some(1));
+
+    // A coinbase in the mempool: no prevout, no block yet
+    let coinbase: RawTx = serde_json::from_value(json!({
+        "txid": "ee".repeat(32),
+        "size": 168,
+        "vsize": 141,
+        "weight": 564,
+        "locktime": 0,
+        "vin": [{ "coinbase": "5100", "sequence": 4294967295u32 }],
+        "vout": [{ "value": 50.0, "n": 0, "scriptPubKey": { "hex": "6a", "type": "nulldata" } }]
+    }))
+    .unwrap();
+    assert!(coinbase.is_coinbase());
+    assert_eq!(coinbase.vout[0].address(), None);
+    assert_eq!((coinbase.blockhash, coinbase.confirmations), (None, None));
+
+    // A missing field is a deserialization error, not a panic later on
+    assert!(serde_json::from_value::<RawTx>(json!({ "txid": "ff".repeat(32) })).is_err());
+}
+
+#[test]
+fn blocks_deserialize() {
+    let block = json!({
+        "hash": "dd".repeat(32),
+        "confirmations": 1,
+        "height": 102,
+        "version": 536870912,
+        "versionHex": "20000000",
+        "merkleroot": "ee".repeat(32),
+        "time": 1_700_000_600,
+        "mediantime": 1_700_000_000,
+        "nonce": 1,
+        "bits": "207fffff",
+        "difficulty": 4.656542373906925e-10,
+        "chainwork": "00000000000000000000000000000000000000000000000000000000000000ce",
+        "nTx": 2,
+        "previousblockhash": "ff".repeat(32),
+        "strippedsize": 243,
+        "size": 389,
+        "weight": 1118,
+        "tx": ["aa".repeat(32), "bb".repeat(32)]
+    });
+    let details: BlockDetails = serde_json::from_value(block.clone()).unwrap();
+    assert_eq!((details.height, details.time), (102, 1_700_000_600));
+    assert_eq!(details.tx.len(), 2);
+    assert_eq!(details.previousblockhash, Some("ff".repeat(32)));
+
+    // Without a height the call fails instead of reporting block 0
+    let mut headless = block;
+    headless.as_object_mut().unwrap().remove("height");
+    assert!(serde_json::from_value::<BlockDetails>(headless).is_err());
+}
+
+#[test]
+fn mempool_entries_deserialize() {
+    let entry: MempoolEntry = serde_json::from_value(json!({
+        "vsize": 141,
+        "weight": 561,
+        "time": 1_700_000_000,
+        "height": 101,
+        "descendantcount": 1,
+        "descendantsize": 141,
+        "ancestorcount": 2,
+        "ancestorsize": 282,
+        "wtxid": "aa".repeat(32),
+        "fees": {
+            "base": 0.00000141,
+            "modified": 0.00000141,
+            "ancestor": 0.00000423,
+            "descendant": 0.00000141
+        },
+        "depends": ["bb".repeat(32)],
+        "spentby": [],
+        "bip125-replaceable": true,
+        "unbroadcast": false
+    }))
+    .unwrap();
+    assert_eq!(entry.fees.base, Amount::from_sat(141));
+    assert_eq!(entry.fees.ancestor, Amount::from_sat(423));
+    assert_eq!((entry.vsize, entry.weight, entry.height), (141, 561, 101));
+    assert_eq!(entry.depends, vec!["bb".repeat(32)]);
+    assert_eq!(entry.bip125_replaceable, Some(true));
+}
+
+#[test]
+#[ignore = "requires a running regtest bitcoind"]
+fn payment_details_are_fetched_after_mining() {
+    let config = RpcConfig::load().unwrap();
+    let rpc = config.client().unwrap();
+    create_or_load_wallet(&rpc, &config, "Miner", true).unwrap();
+    let miner = get_wallet_client(&config, "Miner").unwrap();
+    let address = miner.get_new_address(None, None).unwrap().assume_checked();
+    mine_blocks_to_address(&rpc, &address, 101).unwrap();
+
+    let recipient = miner.get_new_address(None, None).unwrap().assume_checked();
+    let txid = miner
+        .send_to_address(
+            &recipient,
+            Amount::from_int_btc(1),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    let hashes = mine_blocks(&rpc, &address, 1).unwrap();
+
+    let details = get_transaction(&rpc, &txid.to_string()).unwrap();
+    assert_eq!(details.txid, txid.to_string());
+    assert_eq!(details.blockhash, Some(hashes[0].to_string()));
+    assert_eq!(details.confirmations, Some(1));
+}
+
+#[test]
+#[ignore = "requires a running regtest bitcoind"]
+fn descriptor_wallets_are_created_as_descriptor_wallets() {
+    let config = RpcConfig::load().unwrap();
+    let rpc = config.client().unwrap();
+    let name = format!("Descriptor-{}", std::process::id());
+    create_or_load_wallet(&rpc, &config, &name, true).unwrap();
+    let wallet = get_wallet_client(&config, &name).unwrap();
+    let info: serde_json::Value = wallet.call("getwalletinfo", &[]).unwrap();
+    assert_eq!(info["descriptors"], true);
+
+    // Only descriptor wallets hand out taproot addresses
+    let address = wallet
+        .get_new_address(None, Some(AddressType::Bech32m))
+        .unwrap()
+        .assume_checked();
+    assert_eq!(address.address_type(), Some(bitcoin::AddressType::P2tr));
+
+    // Loading the existing wallet again does not create anything
+    rpc.unload_wallet(Some(&name)).unwrap();
+    create_or_load_wallet(&rpc, &config, &name, true).unwrap();
+    rpc.unload_wallet(Some(&name)).unwrap();
+}